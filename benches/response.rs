@@ -0,0 +1,57 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+use web::alloc::{AllocStats, CountingAllocator};
+use web::models::headers::{valid_header_name, valid_header_value};
+use web::models::http::{HTTPHeaderType, HTTPResponse, HTTPStatus};
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator;
+
+/// a typical JSON API response
+fn response() -> HTTPResponse {
+    let mut res = HTTPResponse::error(HTTPStatus::Ok, "");
+    res.set_header(HTTPHeaderType::ContentType, "application/json");
+    res.set_header(HTTPHeaderType::CacheControl, "no-store");
+    res.set_header(HTTPHeaderType::Other(String::from("X-Request-Id")), "f3a1");
+    res.set_content_length(2);
+    res
+}
+
+/// how heads were built before the status line and header name tables:
+/// every line checked, then formatted
+fn formatted(res: &HTTPResponse) -> Vec<u8> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", res.status.code(), res.status);
+    for (key, value) in &res.headers {
+        let name = key.to_string();
+        if valid_header_name(&name) && valid_header_value(value) {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    head.push_str("\r\n");
+    head.into_bytes()
+}
+
+fn head(c: &mut Criterion) {
+    let res = response();
+    let mut group = c.benchmark_group("response_head");
+    group.bench_function("tables", |b| {
+        b.iter(|| {
+            let mut head = Vec::with_capacity(256);
+            black_box(&res).write_head(&mut head);
+            head
+        })
+    });
+    group.bench_function("formatting", |b| b.iter(|| formatted(black_box(&res))));
+    group.finish();
+
+    let (_, tables) = AllocStats::measure(|| {
+        let mut head = Vec::with_capacity(256);
+        res.write_head(&mut head);
+        head
+    });
+    let (_, formatting) = AllocStats::measure(|| formatted(&res));
+    println!("allocations: tables {}, formatting {}", tables.allocations, formatting.allocations);
+}
+
+criterion_group!(benches, head);
+criterion_main!(benches);
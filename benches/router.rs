@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+use web::alloc::{AllocStats, CountingAllocator};
+use web::models::http::{HTTPMethod, HTTPRequest};
+use web::router::Router;
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator;
+
+/// `count` routes like an API's: literal paths, parameters and a constrained
+/// parameter in turn, each under its own resource
+fn router(count: usize) -> Router {
+    let mut router = Router::new();
+    for i in 0..count {
+        let pattern = match i % 3 {
+            0 => format!("/resource{}/items", i),
+            1 => format!("/resource{}/items/{{id}}", i),
+            _ => format!("/resource{}/items/{{id:\\d+}}/history", i),
+        };
+        router.bind((HTTPMethod::GET, pattern), |_req, res, params| {
+            res.body = params.get("id").map(str::to_string);
+        });
+    }
+    router
+}
+
+fn routing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("router");
+    for count in [10, 100, 1000] {
+        let router = router(count);
+        // the last parameter route, so every candidate is looked at
+        let last = (0..count).rev().find(|i| i % 3 == 1).unwrap();
+        let hit = HTTPRequest::new(format!("GET /resource{}/items/7 HTTP/1.1\r\n\r\n", last));
+        let miss = HTTPRequest::new(String::from("GET /nowhere HTTP/1.1\r\n\r\n"));
+        group.bench_with_input(BenchmarkId::new("hit", count), &hit, |b, req| b.iter(|| router.route(black_box(req))));
+        group.bench_with_input(BenchmarkId::new("miss", count), &miss, |b, req| b.iter(|| router.route(black_box(req))));
+        let (_, stats) = AllocStats::measure(|| router.route(&hit));
+        println!("allocations: {} routes, hit {}", count, stats.allocations);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, routing);
+criterion_main!(benches);
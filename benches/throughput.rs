@@ -0,0 +1,57 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use web::alloc::{AllocStats, CountingAllocator};
+use web::httpserver::HTTPServer;
+use web::models::http::HTTPMethod;
+use web::router::Router;
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator;
+
+/// one request on its own connection, as the server handles them
+async fn request(addr: SocketAddr, request: &[u8]) -> usize {
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request).await.unwrap();
+    let mut response = Vec::with_capacity(256);
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    response.len()
+}
+
+fn throughput(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/users/{id}".to_string()), |_req, res, params| {
+        res.body = Some(format!("user {}", params.get("id").unwrap_or_default()));
+    });
+    let server = HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    runtime.spawn(async move { server.start().await });
+
+    let get = b"GET /users/42 HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let mut group = c.benchmark_group("throughput");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("loopback", |b| b.iter(|| runtime.block_on(request(addr, get))));
+    group.throughput(Throughput::Elements(16));
+    group.bench_function("loopback_concurrent_16", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let requests: Vec<_> = (0..16).map(|_| tokio::spawn(request(addr, get))).collect();
+                for request in requests {
+                    request.await.unwrap();
+                }
+            })
+        })
+    });
+    group.finish();
+
+    // the client's allocations are counted too
+    let (_, stats) = AllocStats::measure(|| runtime.block_on(request(addr, get)));
+    println!("allocations: loopback request {}", stats.allocations);
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+//! Cookie-session login with a protected page.
+//!
+//! cargo run --example auth, then open http://localhost:3000/account
+use web::auth::LoginManager;
+use web::middleware::request_log::RequestLog;
+use web::models::error::HTTPError;
+use web::models::http::{HTTPHeaderType, HTTPMethod, HTTPStatus};
+use web::router::Router;
+use web::session::Sessions;
+
+const LOGIN_FORM: &str = "<form method=post><input name=user placeholder=name><button>Log in</button></form>";
+
+fn html(res: &mut web::models::http::HTTPResponse, body: String) {
+    res.body = Some(body);
+    res.set_header(HTTPHeaderType::ContentType, "text/html; charset=utf-8");
+}
+
+fn redirect(res: &mut web::models::http::HTTPResponse, location: &str) {
+    res.status = HTTPStatus::SeeOther;
+    res.body = None;
+    res.set_location(location);
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    // a real deployment loads a fixed random key; this one only lives as long as the process
+    let key: Vec<u8> = (0..32).map(|_| rand_byte()).collect();
+    let mut sessions = Sessions::new(&key);
+    sessions.secure = false; // plain http on localhost
+    let logins = LoginManager::new(sessions);
+    let mut router = Router::new();
+    router.layer(RequestLog);
+
+    router.bind((HTTPMethod::GET, String::from("/login")), |_req, res, _pattern| {
+        html(res, String::from(LOGIN_FORM));
+    });
+
+    let manager = logins.clone();
+    router.try_bind((HTTPMethod::POST, String::from("/login")), move |req, res, _pattern| {
+        let form = req.body.clone().unwrap_or_default();
+        let user = form
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("user="))
+            .filter(|user| !user.is_empty())
+            .ok_or_else(|| HTTPError::client(HTTPStatus::BadRequest, "missing user"))?;
+        manager.login(req, res, user);
+        let next = req.query_params().get("next").cloned();
+        redirect(res, next.as_deref().filter(|n| n.starts_with('/')).unwrap_or("/account"));
+        Ok(())
+    });
+
+    let manager = logins.clone();
+    router.bind((HTTPMethod::POST, String::from("/logout")), move |_req, res, _pattern| {
+        manager.logout(res);
+        redirect(res, "/login");
+    });
+
+    let manager = logins.clone();
+    router.try_bind(
+        (HTTPMethod::GET, String::from("/account")),
+        logins.require_login(move |req, res, _pattern| {
+            let user = manager.current_user(req).unwrap_or_default();
+            html(
+                res,
+                format!("<p>Signed in as {}</p><form method=post action=/logout><button>Log out</button></form>", user),
+            );
+            Ok(())
+        }),
+    );
+
+    web::httpserver::HTTPServer::new(3000, router, std::collections::HashMap::new())
+        .start()
+        .await
+}
+
+/// good enough for a throwaway demo key; use a proper RNG for real keys
+fn rand_byte() -> u8 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    hasher.finish() as u8
+}
@@ -0,0 +1,16 @@
+//! Echoes every line sent to it back, upper-cased after `shout `.
+//!
+//! cargo run --example echo, then nc 127.0.0.1 7000
+use web::tcp::{LinesCodec, Server};
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    Server::new("127.0.0.1:7000", LinesCodec::new(), |line: String, _peer| async move {
+        match line.strip_prefix("shout ") {
+            Some(words) => Some(words.to_uppercase()),
+            None => Some(line),
+        }
+    })
+    .start()
+    .await
+}
@@ -0,0 +1,90 @@
+//! A small JSON CRUD API over an in-memory `Store`.
+//!
+//! cargo run --example json_api
+//! curl -X POST localhost:3000/users -H 'Content-Type: application/json' -d '{"name":"carol","age":41}'
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use web::middleware::request_log::RequestLog;
+use web::models::error::HTTPError;
+use web::models::http::{HTTPMethod, HTTPResponse, HTTPStatus};
+use web::router::Router;
+use web::store::Store;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct User {
+    name: String,
+    age: u32,
+}
+
+fn json<T: Serialize>(res: &mut HTTPResponse, status: HTTPStatus, value: &T) {
+    res.status = status;
+    res.json(value).unwrap();
+}
+
+fn user_id(params: &web::router::PathParams) -> Result<u64, HTTPError> {
+    params
+        .get("id")
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| HTTPError::client(HTTPStatus::BadRequest, "invalid user id"))
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let users = Arc::new(Store::seeded([
+        User { name: String::from("alice"), age: 30 },
+        User { name: String::from("bob"), age: 25 },
+    ]));
+    let mut router = Router::new();
+    router.layer(RequestLog);
+
+    let store = Arc::clone(&users);
+    router.bind((HTTPMethod::GET, String::from("/users")), move |_req, res, _pattern| {
+        let list: Vec<_> = store
+            .list()
+            .into_iter()
+            .map(|(id, user)| serde_json::json!({ "id": id, "name": user.name, "age": user.age }))
+            .collect();
+        json(res, HTTPStatus::Ok, &list);
+    });
+
+    let store = Arc::clone(&users);
+    router.try_bind((HTTPMethod::GET, String::from("/users/{id}")), move |_req, res, params| {
+        let user = store
+            .get(user_id(params)?)
+            .ok_or_else(|| HTTPError::client(HTTPStatus::NotFound, "no such user"))?;
+        json(res, HTTPStatus::Ok, &user);
+        Ok(())
+    });
+
+    let store = Arc::clone(&users);
+    let create = (HTTPMethod::POST, String::from("/users"));
+    router.try_bind(create.clone(), move |req, res, _pattern| {
+        let user: User = serde_json::from_str(req.body.as_deref().unwrap_or(""))
+            .map_err(|e| HTTPError::client(HTTPStatus::UnprocessableEntity, &e.to_string()))?;
+        let id = store.insert(user);
+        res.set_location(&format!("/users/{}", id));
+        json(res, HTTPStatus::Created, &serde_json::json!({ "id": id }));
+        Ok(())
+    });
+    router.accept(create, &["application/json"]).unwrap();
+
+    let store = Arc::clone(&users);
+    router.try_bind((HTTPMethod::DELETE, String::from("/users/{id}")), move |_req, res, params| {
+        store
+            .remove(user_id(params)?)
+            .ok_or_else(|| HTTPError::client(HTTPStatus::NotFound, "no such user"))?;
+        res.status = HTTPStatus::NoContent;
+        res.body = None;
+        Ok(())
+    });
+
+    router.on_error(|_req, err| {
+        let mut res = HTTPResponse::default();
+        json(&mut res, err.status.clone(), &serde_json::json!({ "error": err.message }));
+        res
+    });
+
+    web::httpserver::HTTPServer::new(3000, router, std::collections::HashMap::new())
+        .start()
+        .await
+}
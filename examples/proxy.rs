@@ -0,0 +1,26 @@
+//! Forwards `/api/...` to the given upstreams in turn and serves everything
+//! else from the current directory.
+//!
+//! cargo run --example proxy -- http://127.0.0.1:8080 http://127.0.0.1:8081
+use web::middleware::request_log::RequestLog;
+use web::proxy::ReverseProxy;
+use web::router::Router;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let mut upstreams: Vec<String> = std::env::args().skip(1).collect();
+    if upstreams.is_empty() {
+        upstreams.push(String::from("http://127.0.0.1:8080"));
+    }
+    let proxy = ReverseProxy::new(upstreams)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    let mut router = Router::new();
+    router.layer(RequestLog);
+    router.proxy("/api", proxy);
+    router.serve_dir("/", ".");
+
+    web::httpserver::HTTPServer::new(3000, router, std::collections::HashMap::new())
+        .start()
+        .await
+}
@@ -0,0 +1,17 @@
+//! Serves a directory, with range requests for media seeking.
+//!
+//! cargo run --example static_site -- ./public
+use web::middleware::request_log::RequestLog;
+use web::router::Router;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let root = std::env::args().nth(1).unwrap_or_else(|| String::from("."));
+    let mut router = Router::new();
+    router.layer(RequestLog);
+    router.serve_dir("/", root);
+
+    web::httpserver::HTTPServer::new(3000, router, std::collections::HashMap::new())
+        .start()
+        .await
+}
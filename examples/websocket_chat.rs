@@ -0,0 +1,42 @@
+//! Chat rooms: every text message is broadcast to the sender's room.
+//!
+//! cargo run --example websocket_chat, then open several connections with a
+//! WebSocket client, e.g. `websocat 'ws://localhost:3000/chat?name=ann&room=rust'`
+use web::models::http::HTTPMethod;
+use web::router::Router;
+use web::websocket::{Hub, Message};
+
+const PAGE: &str = "<p>Connect a WebSocket client to <code>/chat?name=you&amp;room=lobby</code>.</p>";
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let hub = Hub::new();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/".to_string()), |_req, res, _pattern| {
+        res.html(PAGE);
+    });
+    router.bind_ws("/chat", move |ws, req| {
+        let hub = hub.clone();
+        async move {
+            let (_, query) = web::router::parse_url(&req.url);
+            let name = query.get("name").cloned().unwrap_or_else(|| "anonymous".to_string());
+            let room = query.get("room").cloned().unwrap_or_else(|| "lobby".to_string());
+
+            let (sender, mut receiver) = ws.split();
+            let id = hub.connect(sender);
+            hub.join(id, &room);
+            hub.broadcast_to(&room, format!("{} joined", name));
+            while let Ok(Some(message)) = receiver.recv().await {
+                if let Message::Text(text) = message {
+                    hub.broadcast_to(&room, format!("{}: {}", name, text));
+                }
+            }
+            hub.disconnect(id);
+            hub.broadcast_to(&room, format!("{} left", name));
+        }
+    });
+
+    web::httpserver::HTTPServer::new(3000, router, std::collections::HashMap::new())
+        .start()
+        .await
+}
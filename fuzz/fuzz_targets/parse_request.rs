@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use web::models::parse::{parse_request, ParseError};
+
+// run with `cargo fuzz run parse_request fuzz/corpus/parse_request fuzz/seeds/parse_request`
+fuzz_target!(|data: &[u8]| {
+    let parsed = parse_request(data);
+    // the same bytes, the same answer
+    assert_eq!(parsed, parse_request(data));
+    match parsed {
+        Ok((request, used)) => {
+            assert!(used <= data.len());
+            // the request stands on its own, whatever follows it
+            assert_eq!(parse_request(&data[..used]), Ok((request, used)));
+        }
+        Err(ParseError::Incomplete) => {}
+        // more bytes can't fix a malformed head
+        Err(e) => assert_eq!(parse_request(&[data, b"\r\n\r\n"].concat()).err(), Some(e)),
+    }
+});
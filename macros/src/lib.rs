@@ -0,0 +1,100 @@
+//! `#[get("/path")]`-style route attributes and `routes![]`, re-exported
+//! by `web` with the `macros` feature.
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, ItemFn, LitStr, Path, ReturnType, Token};
+
+/// Keep the handler function as it is and add a same-named struct in the
+/// type namespace that `routes![]` turns into a `web::router::Route`.
+fn route(method: &str, attr: TokenStream, item: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(attr as LitStr);
+    let handler = parse_macro_input!(item as ItemFn);
+    if !path.value().starts_with('/') {
+        return syn::Error::new(path.span(), "route paths start with `/`")
+            .to_compile_error()
+            .into();
+    }
+    let name = &handler.sig.ident;
+    let vis = &handler.vis;
+    let method = format_ident!("{}", method);
+    // handlers without a return type can't fail, like with `Router::bind`
+    let call = match &handler.sig.output {
+        ReturnType::Default => quote! {
+            ::std::boxed::Box::new(|req, res, params| {
+                #name(req, res, params);
+                ::std::result::Result::Ok(())
+            })
+        },
+        // through a closure, so handlers may take the params or just the pattern
+        ReturnType::Type(..) => quote! { ::std::boxed::Box::new(|req, res, params| #name(req, res, params)) },
+    };
+    quote! {
+        #handler
+
+        #[doc(hidden)]
+        #[allow(non_camel_case_types)]
+        #vis struct #name {}
+
+        impl ::std::convert::From<#name> for ::web::router::Route {
+            fn from(_: #name) -> Self {
+                ::web::router::Route {
+                    method: ::web::models::http::HTTPMethod::#method,
+                    path: ::std::string::String::from(#path),
+                    handler: #call,
+                }
+            }
+        }
+    }
+    .into()
+}
+
+/// `#[get("/posts/{id}")]` on a handler `fn(&HTTPRequest, &mut HTTPResponse, &str)`,
+/// optionally returning `Result<(), HTTPError>`
+#[proc_macro_attribute]
+pub fn get(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("GET", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn head(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("HEAD", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn post(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("POST", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn put(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("PUT", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn patch(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("PATCH", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn delete(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("DELETE", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn options(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("OPTIONS", attr, item)
+}
+
+/// `routes![show, posts::create]`: the routes of attributed handlers, as a
+/// `Vec<web::router::Route>` for `Router::mount`
+#[proc_macro]
+pub fn routes(input: TokenStream) -> TokenStream {
+    let handlers = parse_macro_input!(input with Punctuated::<Path, Token![,]>::parse_terminated);
+    let handlers = handlers.iter();
+    quote! {
+        ::std::vec![#(::web::router::Route::from(#handlers {})),*]
+    }
+    .into()
+}
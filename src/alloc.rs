@@ -0,0 +1,81 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static DEALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static REALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator, counting what goes through it, for tracking how
+/// much the parser, router and server allocate. Install it in a bench or
+/// test binary and read the counters with `AllocStats::now`:
+///
+/// `#[global_allocator] static ALLOC: web::alloc::CountingAllocator = web::alloc::CountingAllocator;`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        REALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size.saturating_sub(layout.size()) as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// What `CountingAllocator` has counted, process-wide. Only meaningful
+/// while it is the global allocator; otherwise everything stays 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub reallocations: u64,
+    /// requested by allocations and by reallocations that grew
+    pub bytes: u64,
+}
+
+impl AllocStats {
+    /// the counters so far
+    pub fn now() -> Self {
+        AllocStats {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            deallocations: DEALLOCATIONS.load(Ordering::Relaxed),
+            reallocations: REALLOCATIONS.load(Ordering::Relaxed),
+            bytes: BYTES.load(Ordering::Relaxed),
+        }
+    }
+
+    /// what was counted between `earlier` and `self`
+    pub fn since(&self, earlier: &AllocStats) -> Self {
+        AllocStats {
+            allocations: self.allocations - earlier.allocations,
+            deallocations: self.deallocations - earlier.deallocations,
+            reallocations: self.reallocations - earlier.reallocations,
+            bytes: self.bytes - earlier.bytes,
+        }
+    }
+
+    /// Run `f` and count what it allocated, along with its result. Other
+    /// threads allocating meanwhile are counted too.
+    pub fn measure<R>(f: impl FnOnce() -> R) -> (R, AllocStats) {
+        let before = AllocStats::now();
+        let result = f();
+        (result, AllocStats::now().since(&before))
+    }
+}
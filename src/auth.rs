@@ -0,0 +1,88 @@
+use crate::models::error::HTTPError;
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse, HTTPStatus};
+use crate::router::PathParams;
+use crate::session::Sessions;
+use std::sync::Arc;
+
+const USER_KEY: &str = "user_id";
+
+/// Login state on top of `Sessions`: remembers which user a browser is
+/// signed in as.
+#[derive(Clone)]
+pub struct LoginManager {
+    sessions: Arc<Sessions>,
+    /// where `require_login` sends browsers, with the original path in `?next=`
+    pub login_url: String,
+}
+
+impl LoginManager {
+    pub fn new(sessions: Sessions) -> Self {
+        LoginManager {
+            sessions: Arc::new(sessions),
+            login_url: String::from("/login"),
+        }
+    }
+
+    pub fn sessions(&self) -> &Sessions {
+        &self.sessions
+    }
+
+    pub fn login(&self, req: &HTTPRequest, res: &mut HTTPResponse, user_id: &str) {
+        let mut session = self.sessions.load(req);
+        session.insert(USER_KEY, user_id);
+        self.sessions.save(&session, res);
+    }
+
+    /// end the session entirely, not just the login
+    pub fn logout(&self, res: &mut HTTPResponse) {
+        self.sessions.save(&Default::default(), res);
+    }
+
+    pub fn current_user(&self, req: &HTTPRequest) -> Option<String> {
+        self.sessions.load(req).get(USER_KEY).map(str::to_string)
+    }
+
+    /// Guard `handler` for `Router::try_bind`. Anonymous browsers (anything
+    /// accepting `text/html`) are redirected to `login_url`, other clients get
+    /// a 401.
+    pub fn require_login<F>(
+        &self,
+        handler: F,
+    ) -> impl Fn(&HTTPRequest, &mut HTTPResponse, &PathParams) -> Result<(), HTTPError> + Send + Sync + 'static
+    where
+        F: Fn(&HTTPRequest, &mut HTTPResponse, &PathParams) -> Result<(), HTTPError> + Send + Sync + 'static,
+    {
+        let manager = self.clone();
+        move |req, res, params| {
+            if manager.current_user(req).is_some() {
+                return handler(req, res, params);
+            }
+            if !wants_html(req) {
+                return Err(HTTPError::client(HTTPStatus::Unauthorized, "login required"));
+            }
+            res.status = HTTPStatus::SeeOther;
+            res.body = None;
+            res.set_location(&format!("{}?next={}", manager.login_url, encode_query(&req.url)));
+            Ok(())
+        }
+    }
+}
+
+fn wants_html(req: &HTTPRequest) -> bool {
+    req.vary_on(&HTTPHeaderType::Accept);
+    req.header(&HTTPHeaderType::Accept)
+        .is_some_and(|accept| accept.to_ascii_lowercase().contains("text/html"))
+}
+
+/// percent-encode everything but unreserved characters (RFC 3986 2.3)
+fn encode_query(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
@@ -0,0 +1,697 @@
+pub mod multipart;
+pub mod proxy;
+pub mod redirect;
+pub mod retry;
+
+pub use multipart::Form;
+pub use proxy::Proxy;
+pub use redirect::{Redirect, RedirectPolicy};
+pub use retry::Retry;
+
+use crate::dns::Resolver;
+use crate::happy_eyeballs;
+use crate::http1;
+use crate::models::body::Chunks;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use crate::socket::SocketOptions;
+use futures_core::Stream;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// how long connecting, TLS handshake included, may take by default
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const DEFAULT_USER_AGENT: &str = concat!("web/", env!("CARGO_PKG_VERSION"));
+
+/// Why a request got no response
+#[derive(Debug)]
+pub enum ClientError {
+    /// the URL can't be requested, e.g. no host or an unsupported scheme
+    InvalidUrl(String),
+    /// connecting or the TLS handshake failed
+    Connect(io::Error),
+    /// no complete response within the timeout
+    Timeout,
+    /// the connection broke, or the server didn't answer with HTTP/1.1
+    Io(io::Error),
+    /// still redirected after following as many redirects as allowed
+    TooManyRedirects(usize),
+    /// the proxy answered `CONNECT` with this status instead of a tunnel
+    ProxyRefused(HTTPStatus),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::InvalidUrl(url) => write!(f, "invalid URL: {}", url),
+            ClientError::Connect(e) => write!(f, "connect failed: {}", e),
+            ClientError::Timeout => write!(f, "request timed out"),
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::TooManyRedirects(n) => write!(f, "more than {} redirects", n),
+            ClientError::ProxyRefused(status) => write!(f, "proxy refused the tunnel: {}", status),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Connect(e) | ClientError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// An HTTP/1.1 client for `http://` and `https://` URLs, e.g.
+/// `client.get(url).header(..).send().await`. Cheap to clone; clones
+/// share their configuration. Every request uses a new connection.
+#[derive(Clone)]
+pub struct HTTPClient {
+    config: Arc<ClientConfig>,
+}
+
+struct ClientConfig {
+    timeout: Option<Duration>,
+    redirect: RedirectPolicy,
+    retry: Option<Retry>,
+    proxy: Option<Proxy>,
+    connect_timeout: Duration,
+    default_headers: Vec<(HTTPHeaderType, String)>,
+    tls: TlsConnector,
+    resolver: Option<Resolver>,
+    socket: SocketOptions,
+}
+
+impl HTTPClient {
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> HTTPClientBuilder {
+        HTTPClientBuilder::default()
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.request(HTTPMethod::GET, url)
+    }
+
+    pub fn head(&self, url: &str) -> RequestBuilder {
+        self.request(HTTPMethod::HEAD, url)
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.request(HTTPMethod::POST, url)
+    }
+
+    pub fn put(&self, url: &str) -> RequestBuilder {
+        self.request(HTTPMethod::PUT, url)
+    }
+
+    pub fn patch(&self, url: &str) -> RequestBuilder {
+        self.request(HTTPMethod::PATCH, url)
+    }
+
+    pub fn delete(&self, url: &str) -> RequestBuilder {
+        self.request(HTTPMethod::DELETE, url)
+    }
+
+    pub fn request(&self, method: HTTPMethod, url: &str) -> RequestBuilder {
+        let mut req = HTTPRequest {
+            method,
+            ..Default::default()
+        };
+        for (header, value) in &self.config.default_headers {
+            req.headers.insert(header.clone(), value.clone());
+        }
+        RequestBuilder {
+            client: self.clone(),
+            url: url.to_string(),
+            req,
+            body: Body::Empty,
+            timeout: self.config.timeout,
+            retry: self.config.retry.clone(),
+        }
+    }
+
+    /// send the request, following redirects as the policy allows
+    async fn execute(
+        &self,
+        mut url: String,
+        mut req: HTTPRequest,
+        mut body: Body,
+        retry: Option<&Retry>,
+    ) -> Result<HTTPResponse, ClientError> {
+        let mut chain = Vec::new();
+        loop {
+            let replay = body.try_clone();
+            let mut res = self.dispatch_with_retries(&url, &req, body, retry).await?;
+            let limit = match self.config.redirect {
+                RedirectPolicy::None => return Ok(res),
+                RedirectPolicy::Limit(limit) => limit,
+            };
+            let follow_up = redirect::follow(&url, &mut req, &res);
+            // a streamed body is gone, so a redirect repeating it is the answer
+            let next_body = match (&follow_up, replay) {
+                (Some(follow_up), _) if !follow_up.keep_body => Some(Body::Empty),
+                (Some(_), replay) => replay,
+                (None, _) => None,
+            };
+            let (Some(follow_up), Some(next_body)) = (follow_up, next_body) else {
+                if !chain.is_empty() {
+                    res.extensions.insert(redirect::RedirectChain(chain));
+                }
+                return Ok(res);
+            };
+            if chain.len() == limit {
+                return Err(ClientError::TooManyRedirects(limit));
+            }
+            chain.push(Redirect {
+                url: std::mem::replace(&mut url, follow_up.url.clone()),
+                status: res.status.clone(),
+                location: follow_up.url,
+            });
+            body = next_body;
+        }
+    }
+
+    /// `dispatch`, repeated as `retry` allows
+    async fn dispatch_with_retries(
+        &self,
+        url: &str,
+        req: &HTTPRequest,
+        mut body: Body,
+        retry: Option<&Retry>,
+    ) -> Result<HTTPResponse, ClientError> {
+        let mut attempt = 0;
+        loop {
+            let replay = body.try_clone();
+            let outcome = self.dispatch(url, req.clone(), body).await;
+            let delay = retry.and_then(|retry| retry.delay(&req.method, attempt, outcome.as_ref()));
+            match (delay, replay) {
+                (Some(delay), Some(replay)) => {
+                    tokio::time::sleep(delay).await;
+                    body = replay;
+                    attempt += 1;
+                }
+                _ => return outcome,
+            }
+        }
+    }
+
+    /// connect to `url`'s origin and send the request, returning once the
+    /// response head has arrived
+    async fn dispatch(&self, url: &str, mut req: HTTPRequest, body: Body) -> Result<HTTPResponse, ClientError> {
+        let target = Target::parse(url)?;
+        req.url = target.path.clone();
+        req.headers
+            .entry(HTTPHeaderType::Host)
+            .or_insert_with(|| target.authority());
+        req.headers.insert(HTTPHeaderType::Connection, String::from("close"));
+        match &body {
+            Body::Empty if !matches!(req.method, HTTPMethod::POST | HTTPMethod::PUT | HTTPMethod::PATCH) => {}
+            Body::Empty => {
+                req.headers.insert(HTTPHeaderType::ContentLength, String::from("0"));
+            }
+            Body::Bytes(bytes) => {
+                req.headers.insert(HTTPHeaderType::ContentLength, bytes.len().to_string());
+            }
+            Body::Reader(_, Some(length)) => {
+                req.headers.insert(HTTPHeaderType::ContentLength, length.to_string());
+            }
+            Body::Stream(_) | Body::Reader(_, None) => {
+                req.headers.remove(&HTTPHeaderType::ContentLength);
+                req.headers.insert(HTTPHeaderType::TransferEncoding, String::from("chunked"));
+            }
+        }
+
+        let proxy = self.config.proxy.as_ref().filter(|proxy| !proxy.bypasses(&target.host));
+        let Some(proxy) = proxy else {
+            let tcp = self.connect(&target).await?;
+            return match target.https {
+                true => exchange(self.handshake(&target, tcp).await?, &req, body).await,
+                false => exchange(tcp, &req, body).await,
+            }
+            .map_err(ClientError::Io);
+        };
+        let tcp = self.connect(&proxy.target).await?;
+        let mut stream: Box<dyn Connection> = match proxy.target.https {
+            true => Box::new(self.handshake(&proxy.target, tcp).await?),
+            false => Box::new(tcp),
+        };
+        if !target.https {
+            req.url = target.absolute();
+            if let Some(auth) = &proxy.auth {
+                req.headers.insert(HTTPHeaderType::ProxyAuthorization, auth.clone());
+            }
+            return exchange(stream, &req, body).await.map_err(ClientError::Io);
+        }
+        let tunnel = tokio::time::timeout(self.config.connect_timeout, open_tunnel(&mut stream, proxy, &target));
+        match tunnel.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(ClientError::Timeout),
+        }
+        exchange(self.handshake(&target, stream).await?, &req, body)
+            .await
+            .map_err(ClientError::Io)
+    }
+
+    async fn connect(&self, target: &Target) -> Result<TcpStream, ClientError> {
+        let host = target.host.trim_start_matches('[').trim_end_matches(']');
+        let connect = async {
+            let addrs: Vec<_> = match &self.config.resolver {
+                Some(resolver) => {
+                    let ips = resolver.lookup_ip(host).await?.into_iter();
+                    ips.map(|ip| std::net::SocketAddr::new(ip, target.port)).collect()
+                }
+                None => tokio::net::lookup_host((host, target.port)).await?.collect(),
+            };
+            let delay = happy_eyeballs::CONNECTION_ATTEMPT_DELAY;
+            happy_eyeballs::connect_with(addrs, delay, self.config.socket).await
+        };
+        match tokio::time::timeout(self.config.connect_timeout, connect).await {
+            Ok(Ok(tcp)) => Ok(tcp),
+            Ok(Err(e)) => Err(ClientError::Connect(e)),
+            Err(_) => Err(ClientError::Timeout),
+        }
+    }
+
+    /// TLS to `target` over `stream`, checking its certificate
+    async fn handshake<S>(&self, target: &Target, stream: S) -> Result<TlsStream<S>, ClientError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let host = target.host.trim_start_matches('[').trim_end_matches(']').to_string();
+        let name = ServerName::try_from(host).map_err(|_| ClientError::InvalidUrl(target.absolute()))?;
+        let handshake = self.config.tls.connect(name, stream);
+        match tokio::time::timeout(self.config.connect_timeout, handshake).await {
+            Ok(Ok(tls)) => Ok(tls),
+            Ok(Err(e)) => Err(ClientError::Connect(e)),
+            Err(_) => Err(ClientError::Timeout),
+        }
+    }
+}
+
+/// a connection to a proxy, over TLS or not
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Connection for S {}
+
+/// ask the proxy for a tunnel to `target`
+async fn open_tunnel(stream: &mut Box<dyn Connection>, proxy: &Proxy, target: &Target) -> Result<(), ClientError> {
+    let authority = format!("{}:{}", target.host, target.port);
+    let mut head = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+    if let Some(auth) = &proxy.auth {
+        head.push_str(&format!("Proxy-Authorization: {}\r\n", auth));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await.map_err(ClientError::Connect)?;
+    stream.flush().await.map_err(ClientError::Connect)?;
+    let (res, early, _) = http1::read_response(stream, &HTTPMethod::CONNECT)
+        .await
+        .map_err(ClientError::Connect)?;
+    if !res.status.is_success() {
+        return Err(ClientError::ProxyRefused(res.status));
+    }
+    // the server speaks first in TLS, so nothing may come before our hello
+    if !early.is_empty() {
+        return Err(ClientError::Connect(io::ErrorKind::InvalidData.into()));
+    }
+    Ok(())
+}
+
+impl Default for HTTPClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Settings for an `HTTPClient`
+pub struct HTTPClientBuilder {
+    timeout: Option<Duration>,
+    redirect: RedirectPolicy,
+    retry: Option<Retry>,
+    proxy: Option<Proxy>,
+    connect_timeout: Duration,
+    default_headers: Vec<(HTTPHeaderType, String)>,
+    roots: rustls::RootCertStore,
+    tls: Option<Arc<rustls::ClientConfig>>,
+    resolver: Option<Resolver>,
+    socket: SocketOptions,
+}
+
+impl Default for HTTPClientBuilder {
+    fn default() -> Self {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        HTTPClientBuilder {
+            timeout: None,
+            redirect: RedirectPolicy::default(),
+            retry: None,
+            proxy: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            default_headers: vec![
+                (HTTPHeaderType::UserAgent, String::from(DEFAULT_USER_AGENT)),
+                (HTTPHeaderType::Accept, String::from("*/*")),
+            ],
+            roots,
+            tls: None,
+            resolver: None,
+            socket: SocketOptions::default(),
+        }
+    }
+}
+
+impl HTTPClientBuilder {
+    /// limit on a whole request, until the full body has arrived with
+    /// `send` or the response head with `send_streaming`; none by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// follow up to 10 redirects by default
+    pub fn redirect(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect = policy;
+        self
+    }
+
+    /// retry failed requests, see `Retry`; off by default
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// send requests through `proxy`, except to the hosts it bypasses
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn user_agent(self, user_agent: impl Into<String>) -> Self {
+        self.default_header(HTTPHeaderType::UserAgent, user_agent)
+    }
+
+    /// sent with every request unless the request sets it itself
+    pub fn default_header(mut self, header: HTTPHeaderType, value: impl Into<String>) -> Self {
+        self.default_headers.retain(|(h, _)| *h != header);
+        self.default_headers.push((header, value.into()));
+        self
+    }
+
+    /// also trust the CA certificates in `pem`, e.g. a private CA
+    pub fn add_root_certificates_pem(mut self, pem: &[u8]) -> io::Result<Self> {
+        for cert in CertificateDer::pem_slice_iter(pem) {
+            let cert = cert.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            self.roots
+                .add(cert)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        Ok(self)
+    }
+
+    /// use `config` for HTTPS instead of the bundled roots
+    pub fn tls_config(mut self, config: Arc<rustls::ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// look hosts up with `resolver` instead of the system's resolver
+    pub fn resolver(mut self, resolver: Resolver) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// look hosts up over DNS-over-HTTPS at `url`, e.g.
+    /// `https://1.1.1.1/dns-query`, see `Resolver::dns_over_https`
+    pub fn dns_over_https(self, url: &str) -> Self {
+        self.resolver(Resolver::dns_over_https(url))
+    }
+
+    /// TCP options for every connection, e.g. `nodelay` or `keepalive`
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket = options;
+        self
+    }
+
+    pub fn build(self) -> HTTPClient {
+        let tls = self.tls.unwrap_or_else(|| {
+            let mut config = rustls::ClientConfig::builder()
+                .with_root_certificates(self.roots)
+                .with_no_client_auth();
+            config.alpn_protocols = vec![b"http/1.1".to_vec()];
+            Arc::new(config)
+        });
+        HTTPClient {
+            config: Arc::new(ClientConfig {
+                timeout: self.timeout,
+                redirect: self.redirect,
+                retry: self.retry,
+                proxy: self.proxy,
+                connect_timeout: self.connect_timeout,
+                default_headers: self.default_headers,
+                tls: TlsConnector::from(tls),
+                resolver: self.resolver,
+                socket: self.socket,
+            }),
+        }
+    }
+}
+
+enum Body {
+    Empty,
+    Bytes(Vec<u8>),
+    /// sent with chunked transfer coding
+    Stream(Chunks),
+    /// read until its end, with `Content-Length` if the length is known
+    /// and chunked otherwise
+    Reader(Pin<Box<dyn AsyncRead + Send>>, Option<u64>),
+}
+
+impl Body {
+    /// a copy to send again, e.g. after a redirect; streams can't be replayed
+    fn try_clone(&self) -> Option<Body> {
+        match self {
+            Body::Empty => Some(Body::Empty),
+            Body::Bytes(bytes) => Some(Body::Bytes(bytes.clone())),
+            Body::Stream(_) | Body::Reader(..) => None,
+        }
+    }
+}
+
+/// A request being put together, sent with `send` or `send_streaming`
+pub struct RequestBuilder {
+    client: HTTPClient,
+    url: String,
+    req: HTTPRequest,
+    body: Body,
+    timeout: Option<Duration>,
+    retry: Option<Retry>,
+}
+
+impl RequestBuilder {
+    pub fn header(mut self, header: HTTPHeaderType, value: impl Into<String>) -> Self {
+        self.req.headers.insert(header, value.into());
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    /// `value` as a JSON body
+    pub fn json<T: serde::Serialize>(self, value: &T) -> Result<Self, serde_json::Error> {
+        let body = serde_json::to_vec(value)?;
+        Ok(self
+            .header(HTTPHeaderType::ContentType, crate::mime::Mime::APPLICATION_JSON.to_string())
+            .body(body))
+    }
+
+    /// a body sent as it is produced, e.g. a large upload
+    pub fn body_stream<S>(mut self, chunks: S) -> Self
+    where
+        S: Stream<Item = Vec<u8>> + Send + 'static,
+    {
+        self.body = Body::Stream(Box::pin(chunks));
+        self
+    }
+
+    /// a body read from `reader` as it is sent, chunked
+    pub fn body_reader<R>(mut self, reader: R) -> Self
+    where
+        R: AsyncRead + Send + 'static,
+    {
+        self.body = Body::Reader(Box::pin(reader), None);
+        self
+    }
+
+    /// a `multipart/form-data` body, see `Form`
+    pub fn multipart(mut self, form: Form) -> Self {
+        self.req.headers.insert(HTTPHeaderType::ContentType, form.content_type());
+        let (reader, length) = form.into_reader();
+        self.body = Body::Reader(reader, Some(length));
+        self
+    }
+
+    /// override the client's timeout for this request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// override the client's retries for this request
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Send the request and read the whole response. Text bodies end up in
+    /// `body`, anything else in `raw_body`.
+    pub async fn send(self) -> Result<HTTPResponse, ClientError> {
+        let timeout = self.timeout;
+        with_timeout(timeout, async move {
+            let mut res = self.client.execute(self.url, self.req, self.body, self.retry.as_ref()).await?;
+            res.collect_body().await;
+            if let Some(raw) = res.raw_body.take() {
+                match String::from_utf8(raw) {
+                    Ok(text) => res.body = Some(text),
+                    Err(e) => res.raw_body = Some(e.into_bytes()),
+                }
+            }
+            Ok(res)
+        })
+        .await
+    }
+
+    /// Send the request and return as soon as the response head arrived;
+    /// the body follows through `HTTPResponse::chunk`
+    pub async fn send_streaming(self) -> Result<HTTPResponse, ClientError> {
+        with_timeout(self.timeout, self.client.execute(self.url, self.req, self.body, self.retry.as_ref())).await
+    }
+}
+
+async fn with_timeout<F>(timeout: Option<Duration>, request: F) -> Result<HTTPResponse, ClientError>
+where
+    F: Future<Output = Result<HTTPResponse, ClientError>>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, request)
+            .await
+            .unwrap_or(Err(ClientError::Timeout)),
+        None => request.await,
+    }
+}
+
+/// write `req` and `body`, then read the response head
+async fn exchange<S>(mut stream: S, req: &HTTPRequest, body: Body) -> io::Result<HTTPResponse>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    stream.write_all(req.head_to_string().as_bytes()).await?;
+    match body {
+        Body::Empty => {}
+        Body::Bytes(bytes) => stream.write_all(&bytes).await?,
+        Body::Stream(mut chunks) => {
+            while let Some(chunk) = crate::models::body::next_chunk(&mut chunks).await {
+                if chunk.is_empty() {
+                    continue;
+                }
+                stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes()).await?;
+                stream.write_all(&chunk).await?;
+                stream.write_all(b"\r\n").await?;
+            }
+            stream.write_all(b"0\r\n\r\n").await?;
+        }
+        Body::Reader(reader, Some(length)) => {
+            let sent = tokio::io::copy(&mut reader.take(length), &mut stream).await?;
+            if sent < length {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Body::Reader(mut reader, None) => {
+            let mut buf = vec![0; 16 * 1024];
+            loop {
+                let n = reader.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                stream.write_all(format!("{:x}\r\n", n).as_bytes()).await?;
+                stream.write_all(&buf[..n]).await?;
+                stream.write_all(b"\r\n").await?;
+            }
+            stream.write_all(b"0\r\n\r\n").await?;
+        }
+    }
+    stream.flush().await?;
+    let (mut res, buffered, framing) = http1::read_response(&mut stream, &req.method).await?;
+    http1::stream_body(&mut res, stream, buffered, framing, ());
+    Ok(res)
+}
+
+/// where a URL points
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Target {
+    pub(crate) https: bool,
+    /// brackets included for IPv6
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    /// path and query, at least `/`
+    pub(crate) path: String,
+}
+
+impl Target {
+    pub(crate) fn parse(url: &str) -> Result<Self, ClientError> {
+        let invalid = || ClientError::InvalidUrl(url.to_string());
+        let parsed = crate::models::url::Url::parse(url).map_err(|_| invalid())?;
+        let https = match parsed.scheme.as_deref() {
+            Some("http") => false,
+            Some("https") => true,
+            _ => return Err(invalid()),
+        };
+        let (Some(host), Some(port)) = (parsed.host.clone(), parsed.port_or_default()) else {
+            return Err(invalid());
+        };
+        let mut path = parsed.path_and_query();
+        if !path.starts_with('/') {
+            path.insert(0, '/');
+        }
+        Ok(Target { https, host, port, path })
+    }
+
+    /// scheme, host and port; requests may only share credentials within one
+    pub(crate) fn origin(&self) -> (bool, String, u16) {
+        (self.https, self.host.to_ascii_lowercase(), self.port)
+    }
+
+    /// the URL again, without fragment, as sent to a proxy
+    pub(crate) fn absolute(&self) -> String {
+        let scheme = if self.https { "https" } else { "http" };
+        format!("{}://{}{}", scheme, self.authority(), self.path)
+    }
+
+    /// `Host` header value, leaving out the scheme's default port
+    pub(crate) fn authority(&self) -> String {
+        if self.port == if self.https { 443 } else { 80 } {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
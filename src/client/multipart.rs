@@ -0,0 +1,113 @@
+use crate::mime::Mime;
+use std::hash::BuildHasher;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// A `multipart/form-data` body for `RequestBuilder::multipart`. Files
+/// are opened when added and read while the request is sent, so they are
+/// never held in memory; the total length is known up front and sent as
+/// `Content-Length`.
+pub struct Form {
+    boundary: String,
+    parts: Vec<(String, Content)>,
+}
+
+enum Content {
+    Bytes(Vec<u8>),
+    File(tokio::fs::File, u64),
+}
+
+impl Form {
+    pub fn new() -> Self {
+        let random = std::collections::hash_map::RandomState::new();
+        Form {
+            boundary: format!(
+                "web-{:016x}{:016x}",
+                random.hash_one(std::time::SystemTime::now()),
+                random.hash_one(std::process::id())
+            ),
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn text(self, name: &str, value: impl Into<String>) -> Self {
+        self.part(name, None, None, Content::Bytes(value.into().into_bytes()))
+    }
+
+    /// in-memory file contents, typed by `filename`'s extension
+    pub fn bytes(self, name: &str, filename: &str, data: impl Into<Vec<u8>>) -> Self {
+        let mime = Mime::from_path(filename);
+        self.part(name, Some(filename), Some(mime), Content::Bytes(data.into()))
+    }
+
+    /// the file at `path`, named and typed after it
+    pub async fn file(self, name: &str, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = tokio::fs::File::open(path).await?;
+        let length = file.metadata().await?.len();
+        let filename = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+        Ok(self.part(name, Some(&filename), Some(Mime::from_path(path)), Content::File(file, length)))
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// `Content-Type` header value
+    pub fn content_type(&self) -> String {
+        format!("{}; boundary={}", Mime::MULTIPART_FORM_DATA, self.boundary)
+    }
+
+    fn part(mut self, name: &str, filename: Option<&str>, mime: Option<Mime>, content: Content) -> Self {
+        let mut head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape(name)
+        );
+        if let Some(filename) = filename {
+            head.push_str(&format!("; filename=\"{}\"", escape(filename)));
+        }
+        if let Some(mime) = mime {
+            head.push_str(&format!("\r\nContent-Type: {}", mime));
+        }
+        head.push_str("\r\n\r\n");
+        self.parts.push((head, content));
+        self
+    }
+
+    /// the encoded form and its length
+    pub(crate) fn into_reader(self) -> (Pin<Box<dyn AsyncRead + Send>>, u64) {
+        let closing = format!("--{}--\r\n", self.boundary).into_bytes();
+        let mut length = closing.len() as u64;
+        let mut reader: Pin<Box<dyn AsyncRead + Send>> = Box::pin(tokio::io::empty());
+        for (head, content) in self.parts {
+            length += head.len() as u64 + 2;
+            reader = Box::pin(reader.chain(io::Cursor::new(head.into_bytes())));
+            reader = match content {
+                Content::Bytes(bytes) => {
+                    length += bytes.len() as u64;
+                    Box::pin(reader.chain(io::Cursor::new(bytes)))
+                }
+                Content::File(file, file_length) => {
+                    length += file_length;
+                    Box::pin(reader.chain(file.take(file_length)))
+                }
+            };
+            reader = Box::pin(reader.chain(&b"\r\n"[..]));
+        }
+        (Box::pin(reader.chain(io::Cursor::new(closing))), length)
+    }
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// quoted-string content as browsers send it
+fn escape(value: &str) -> String {
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
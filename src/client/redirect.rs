@@ -0,0 +1,115 @@
+use super::Target;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+
+/// How many redirects a client follows before giving up with
+/// `ClientError::TooManyRedirects`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    /// hand redirect responses back as they are
+    None,
+    Limit(usize),
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        RedirectPolicy::Limit(10)
+    }
+}
+
+/// one redirect followed on the way to a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    /// the URL that answered with the redirect
+    pub url: String,
+    pub status: HTTPStatus,
+    /// where it pointed, resolved against `url`
+    pub location: String,
+}
+
+/// kept in the final response's extensions
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RedirectChain(pub(crate) Vec<Redirect>);
+
+impl HTTPResponse {
+    /// the redirects a client followed to get this response, oldest first
+    pub fn redirects(&self) -> &[Redirect] {
+        self.extensions
+            .get::<RedirectChain>()
+            .map_or(&[], |chain| chain.0.as_slice())
+    }
+}
+
+/// what to send after a redirect
+pub(crate) struct FollowUp {
+    pub(crate) url: String,
+    /// the method and body stay (307/308); otherwise the body is dropped
+    pub(crate) keep_body: bool,
+}
+
+/// Where `res`, the answer to `req` at `url`, redirects to, with `req`
+/// rewritten for the next hop. `None` when it isn't a redirect to follow.
+///
+/// 301 and 302 turn POST into GET and 303 turns everything but HEAD into
+/// GET, as browsers do; 307 and 308 repeat the request as it was.
+/// Credentials and cookies are not sent on to another origin.
+pub(crate) fn follow(url: &str, req: &mut HTTPRequest, res: &HTTPResponse) -> Option<FollowUp> {
+    let to_get = match res.status.code() {
+        301 | 302 => req.method == HTTPMethod::POST,
+        303 => req.method != HTTPMethod::HEAD,
+        307 | 308 => false,
+        _ => return None,
+    };
+    let location = res.header(&HTTPHeaderType::Location)?;
+    let next = resolve(url, location)?;
+    let (from, to) = (Target::parse(url).ok()?, Target::parse(&next).ok()?);
+    if to_get {
+        req.method = HTTPMethod::GET;
+        req.headers.retain(|header, _| {
+            !matches!(
+                header,
+                HTTPHeaderType::ContentType
+                    | HTTPHeaderType::ContentLength
+                    | HTTPHeaderType::ContentEncoding
+                    | HTTPHeaderType::TransferEncoding
+            )
+        });
+    }
+    if from.origin() != to.origin() {
+        req.headers.retain(|header, _| {
+            !matches!(
+                header,
+                HTTPHeaderType::Authorization
+                    | HTTPHeaderType::ProxyAuthorization
+                    | HTTPHeaderType::Cookie
+                    | HTTPHeaderType::Host
+            )
+        });
+    }
+    Some(FollowUp {
+        url: next,
+        keep_body: !to_get,
+    })
+}
+
+/// `location` as an absolute URL, relative to `base`
+fn resolve(base: &str, location: &str) -> Option<String> {
+    let location = location.trim();
+    if location.contains("://") {
+        return Some(location.to_string());
+    }
+    let base = Target::parse(base).ok()?;
+    let scheme = if base.https { "https" } else { "http" };
+    if let Some(rest) = location.strip_prefix("//") {
+        return Some(format!("{}://{}", scheme, rest));
+    }
+    let root = format!("{}://{}", scheme, base.authority());
+    if location.starts_with('/') {
+        return Some(format!("{}{}", root, location));
+    }
+    let path = base.path.split(['?', '#']).next().unwrap_or("/");
+    if location.starts_with('?') {
+        return Some(format!("{}{}{}", root, path, location));
+    }
+    let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+    Some(format!("{}{}{}", root, dir, location))
+}
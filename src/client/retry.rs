@@ -0,0 +1,93 @@
+use super::ClientError;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPResponse};
+use std::hash::BuildHasher;
+use std::time::{Duration, SystemTime};
+
+/// Retries for failed client requests, waiting `base_delay`, then twice
+/// that and so on up to `max_delay`, each wait shortened by a random part
+/// of up to half so clients don't retry in lockstep.
+///
+/// Connect errors are always retried, as nothing was sent yet. 5xx and
+/// 429 responses and broken connections are only retried for idempotent
+/// methods unless `retry_non_idempotent` opts in. A `Retry-After` longer
+/// than `max_delay` ends the retries with that response. Streamed bodies
+/// can't be sent again, so their requests are never retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retry {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub non_idempotent: bool,
+}
+
+impl Retry {
+    pub fn new(max_retries: u32) -> Self {
+        Retry {
+            max_retries,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            non_idempotent: false,
+        }
+    }
+
+    pub fn with_base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// also retry e.g. POST, for endpoints known to be safe to repeat
+    pub fn retry_non_idempotent(mut self) -> Self {
+        self.non_idempotent = true;
+        self
+    }
+
+    /// how long to wait before retry number `attempt` (from 0) of a
+    /// `method` request that came to `outcome`; `None` to stop
+    pub(crate) fn delay(
+        &self,
+        method: &HTTPMethod,
+        attempt: u32,
+        outcome: Result<&HTTPResponse, &ClientError>,
+    ) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        let may_resend = self.non_idempotent || method.is_idempotent();
+        match outcome {
+            Err(ClientError::Connect(_)) => {}
+            Err(ClientError::Io(_)) if may_resend => {}
+            Ok(res) if may_resend && (res.status.code() == 429 || res.status.is_server_error()) => {
+                if let Some(after) = retry_after(res) {
+                    return (after <= self.max_delay).then_some(after);
+                }
+            }
+            _ => return None,
+        }
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_delay);
+        Some(delay / 2 + jitter(delay / 2))
+    }
+}
+
+/// `Retry-After` in seconds or as a date
+fn retry_after(res: &HTTPResponse) -> Option<Duration> {
+    let value = res.header(&HTTPHeaderType::RetryAfter)?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// a random duration up to `max`
+fn jitter(max: Duration) -> Duration {
+    let random = std::collections::hash_map::RandomState::new().hash_one(SystemTime::now());
+    Duration::from_nanos(random % (max.as_nanos() as u64 + 1))
+}
@@ -0,0 +1,122 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPStatus};
+
+/// decompressed request body size allowed when the server config sets none
+pub const DEFAULT_MAX_DECOMPRESSED_BYTES: usize = 16 * 1024 * 1024;
+
+/// A content coding this crate can produce and undo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Coding {
+    Brotli,
+    Gzip,
+    /// zlib-wrapped deflate, as `deflate` means in HTTP (RFC 9110 8.4.1.2)
+    Deflate,
+}
+
+impl Coding {
+    /// server preference when the client weighs several equally
+    pub const ALL: [Coding; 3] = [Coding::Brotli, Coding::Gzip, Coding::Deflate];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Coding::Brotli => "br",
+            Coding::Gzip => "gzip",
+            Coding::Deflate => "deflate",
+        }
+    }
+
+    /// compress `data`; `level` is 0-9 and mapped onto brotli's 0-11
+    pub fn encode(&self, data: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+        let level = level.min(9);
+        match self {
+            Coding::Brotli => {
+                let mut out = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut out, 4096, level + level / 4, 22);
+                    encoder.write_all(data)?;
+                }
+                Ok(out)
+            }
+            Coding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Coding::Deflate => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::new(level));
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    /// Decompress `data`. Fails with `FileTooLarge` once the output would
+    /// exceed `limit` bytes, so a small bomb can't exhaust memory.
+    pub fn decode(&self, data: &[u8], limit: usize) -> std::io::Result<Vec<u8>> {
+        let reader: Box<dyn Read + '_> = match self {
+            Coding::Brotli => Box::new(brotli::Decompressor::new(data, 4096)),
+            Coding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(data)),
+            Coding::Deflate => Box::new(flate2::read::ZlibDecoder::new(data)),
+        };
+        let mut out = Vec::new();
+        reader.take(limit as u64 + 1).read_to_end(&mut out)?;
+        if out.len() > limit {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::FileTooLarge,
+                "decompressed body exceeds the limit",
+            ));
+        }
+        Ok(out)
+    }
+}
+
+impl FromStr for Coding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "br" => Ok(Coding::Brotli),
+            "gzip" | "x-gzip" => Ok(Coding::Gzip),
+            "deflate" => Ok(Coding::Deflate),
+            _ => Err(format!("Unsupported content coding: {}", s)),
+        }
+    }
+}
+
+impl Display for Coding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Undo the request's `Content-Encoding` on `body`, the bytes as received,
+/// and hand the result to `req` with the header removed and
+/// `Content-Length` updated. Codings are undone in reverse order of
+/// application. Fails with 415 for unknown codings, 413 past `limit` and
+/// 400 for corrupt data.
+pub fn decode_request_body(req: &mut HTTPRequest, body: &[u8], limit: usize) -> Result<(), HTTPStatus> {
+    let Some(encoding) = req.headers.get(&HTTPHeaderType::ContentEncoding) else {
+        return Ok(());
+    };
+    let codings = encoding
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty() && !c.eq_ignore_ascii_case("identity"))
+        .map(|c| c.parse::<Coding>().map_err(|_| HTTPStatus::UnsupportedMediaType))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut decoded = body.to_vec();
+    for coding in codings.iter().rev() {
+        decoded = coding.decode(&decoded, limit).map_err(|e| match e.kind() {
+            std::io::ErrorKind::FileTooLarge => HTTPStatus::PayloadTooLarge,
+            _ => HTTPStatus::BadRequest,
+        })?;
+    }
+    req.headers.remove(&HTTPHeaderType::ContentEncoding);
+    req.headers
+        .insert(HTTPHeaderType::ContentLength, decoded.len().to_string());
+    req.set_body_bytes(decoded);
+    Ok(())
+}
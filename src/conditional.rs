@@ -0,0 +1,169 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::headers::EntityTag;
+use crate::models::http::{Buffering, HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Outcome of evaluating a request's preconditions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition {
+    /// carry on with the request
+    Proceed,
+    /// answer 304, the client's copy is current (GET and HEAD only)
+    NotModified,
+    /// answer 412
+    Failed,
+}
+
+/// Evaluate `If-Match`, `If-Unmodified-Since`, `If-None-Match` and
+/// `If-Modified-Since` against the current validators of the target
+/// resource, in the order of RFC 9110 13.2.2. Pass `None` for a validator
+/// the resource doesn't have; for state-changing requests call this before
+/// making the change.
+pub fn evaluate(req: &HTTPRequest, etag: Option<&EntityTag>, last_modified: Option<SystemTime>) -> Precondition {
+    let safe = matches!(req.method, HTTPMethod::GET | HTTPMethod::HEAD);
+    let last_modified = last_modified.map(truncate_to_seconds);
+
+    let if_match = req.if_match();
+    if !if_match.is_empty() {
+        let matches = if_match
+            .iter()
+            .any(|tag| (tag.is_any() && etag.is_some()) || etag.is_some_and(|etag| tag.strong_eq(etag)));
+        if !matches {
+            return Precondition::Failed;
+        }
+    } else if let (Some(since), Some(modified)) = (req.if_unmodified_since(), last_modified) {
+        if modified > since {
+            return Precondition::Failed;
+        }
+    }
+
+    let if_none_match = req.if_none_match();
+    if !if_none_match.is_empty() {
+        let matches = if_none_match
+            .iter()
+            .any(|tag| (tag.is_any() && etag.is_some()) || etag.is_some_and(|etag| tag.weak_eq(etag)));
+        if matches {
+            return if safe {
+                Precondition::NotModified
+            } else {
+                Precondition::Failed
+            };
+        }
+    } else if safe {
+        if let (Some(since), Some(modified)) = (req.if_modified_since(), last_modified) {
+            if modified <= since {
+                return Precondition::NotModified;
+            }
+        }
+    }
+    Precondition::Proceed
+}
+
+/// Evaluate `req` against the `ETag` / `Last-Modified` already set on `res`
+/// and turn `res` into a 304 or 412 if a precondition says so. Returns false
+/// when `res` was replaced.
+pub fn apply(req: &HTTPRequest, res: &mut HTTPResponse) -> bool {
+    match evaluate(req, res.etag().as_ref(), res.last_modified()) {
+        Precondition::Proceed => true,
+        Precondition::NotModified => {
+            res.status = HTTPStatus::NotModified;
+            res.body = None;
+            res.raw_body = None;
+            res.headers.remove(&HTTPHeaderType::ContentLength);
+            res.headers.remove(&HTTPHeaderType::ContentRange);
+            false
+        }
+        Precondition::Failed => {
+            let status = HTTPStatus::PreconditionFailed;
+            *res = HTTPResponse::error(status.clone(), &status.default_body());
+            false
+        }
+    }
+}
+
+/// Applies conditional GET to every successful `GET`/`HEAD` response that
+/// carries an `ETag` or `Last-Modified`. Requests that change state need
+/// `evaluate` before the change is made, which a layer can't do for them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConditionalGet;
+
+impl Middleware for ConditionalGet {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            if !matches!(req.method, HTTPMethod::GET | HTTPMethod::HEAD) {
+                return next.run(req).await;
+            }
+            let probe = is_conditional(&req).then(|| req.clone());
+            let mut res = next.run(req).await;
+            if let Some(req) = probe {
+                if res.status == HTTPStatus::Ok {
+                    apply(&req, &mut res);
+                }
+            }
+            res
+        })
+    }
+}
+
+/// Gives successful `GET`/`HEAD` responses that have no `ETag` a strong one
+/// hashed from the body, then applies conditional GET like
+/// `ConditionalGet`, so a client whose copy is current gets a bodiless 304.
+/// Streamed responses are left alone: their body isn't known up front.
+/// Place it inside `Compression` to tag the uncompressed body, whose tag
+/// compression then weakens.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AutoETag;
+
+impl AutoETag {
+    /// the tag for `res`'s body and `Content-Encoding`
+    pub fn tag(res: &HTTPResponse) -> EntityTag {
+        let mut hash = fnv1a(0xcbf2_9ce4_8422_2325, res.body_bytes());
+        if let Some(coding) = res.header(&HTTPHeaderType::ContentEncoding) {
+            hash = fnv1a(hash, coding.as_bytes());
+        }
+        EntityTag::strong(&format!("{:016x}", hash))
+    }
+}
+
+impl Middleware for AutoETag {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            if !matches!(req.method, HTTPMethod::GET | HTTPMethod::HEAD) {
+                return next.run(req).await;
+            }
+            let probe = is_conditional(&req).then(|| req.clone());
+            let mut res = next.run(req).await;
+            if res.status != HTTPStatus::Ok || res.buffering() != Buffering::Buffered {
+                return res;
+            }
+            if res.header(&HTTPHeaderType::ETag).is_none() {
+                res.set_etag(&AutoETag::tag(&res));
+            }
+            if let Some(req) = probe {
+                apply(&req, &mut res);
+            }
+            res
+        })
+    }
+}
+
+fn is_conditional(req: &HTTPRequest) -> bool {
+    req.header(&HTTPHeaderType::IfNoneMatch).is_some()
+        || req.header(&HTTPHeaderType::IfModifiedSince).is_some()
+        || req.header(&HTTPHeaderType::IfMatch).is_some()
+        || req.header(&HTTPHeaderType::IfUnmodifiedSince).is_some()
+}
+
+/// FNV-1a, continuing from `hash`; stable across builds, so every instance
+/// of a server tags the same body alike
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, b| (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+/// HTTP dates have whole seconds, so compare at that precision
+fn truncate_to_seconds(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => UNIX_EPOCH + Duration::from_secs(since.as_secs()),
+        Err(_) => time,
+    }
+}
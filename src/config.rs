@@ -0,0 +1,310 @@
+/// request head size allowed when the server config doesn't say otherwise
+pub const DEFAULT_MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// request body size allowed when the server config doesn't say otherwise
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Settings for `HTTPServer`. Start from `ServerConfig::default()` and
+/// override the fields you need.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// keep the original header casing and order on each request
+    /// (`HTTPRequest::raw_headers`), e.g. for proxying or diagnostics
+    pub preserve_header_case: bool,
+    /// accept HTTP/2 over plaintext, both with prior knowledge and through
+    /// `Upgrade: h2c`. Meant for internal services behind a load balancer.
+    /// Ignored without the `http2` feature
+    pub h2c: bool,
+    /// redirect (301) requests for any other host to this one, keeping path
+    /// and query, e.g. `www.example.com` -> `example.com`
+    pub canonical_host: Option<String>,
+    /// cap on connections served at once across all listeners. Further
+    /// connections wait in the accept backlog until a slot frees up
+    pub max_connections: Option<usize>,
+    /// load shedding: when at `max_connections`, answer new plaintext
+    /// connections with 503 and this `Retry-After` (seconds) instead of
+    /// making them wait. TLS connections are closed without a response
+    pub load_shed_retry_after: Option<u64>,
+    /// how long a new connection may take to send the first byte of its
+    /// request before it is closed without a response. There is no idle
+    /// timeout between requests: the built-in HTTP/1 server closes each
+    /// connection after one response, and HTTP/2 and `hyper` connections
+    /// are not bounded by this setting
+    pub first_byte_timeout: Option<std::time::Duration>,
+    /// time allowed from the first byte of a request until its head is
+    /// complete (also bounds the TLS handshake). Slower clients get 408
+    pub header_read_timeout: Option<std::time::Duration>,
+    /// time allowed for the request body once the head has arrived; 408 when
+    /// exceeded
+    pub body_read_timeout: Option<std::time::Duration>,
+    /// time allowed for writing the response before the connection is dropped
+    pub write_timeout: Option<std::time::Duration>,
+    /// largest request head (request line and headers) accepted; bigger
+    /// ones are answered with 431. `DEFAULT_MAX_HEADER_BYTES` by default;
+    /// `None` (`unlimited` in files) lifts the limit
+    pub max_header_bytes: Option<usize>,
+    /// largest request body accepted, a chunked one by its decoded size;
+    /// bigger ones are answered with 413, announced sizes before they are
+    /// read. `DEFAULT_MAX_BODY_BYTES` by default; `None` (`unlimited` in
+    /// files) lifts the limit
+    pub max_body_bytes: Option<usize>,
+    /// largest request body accepted after undoing its `Content-Encoding`;
+    /// bigger ones are answered with 413. `None` means
+    /// `compression::DEFAULT_MAX_DECOMPRESSED_BYTES`. Ignored without the
+    /// `compression` feature, where encoded bodies reach handlers as sent
+    pub max_decompressed_body_bytes: Option<usize>,
+    /// proxies whose `Forwarded` / `X-Forwarded-For` / `X-Real-IP` headers
+    /// are believed by `HTTPRequest::real_ip`
+    pub trusted_proxies: crate::middleware::real_ip::TrustedProxies,
+    /// how long background tasks get to finish once told to stop at
+    /// shutdown before they are aborted; `None` waits for them
+    pub background_shutdown_timeout: Option<std::time::Duration>,
+    /// address the primary listener binds, e.g. `0.0.0.0:8080`, instead of
+    /// the port given to `HTTPServer::new`
+    pub bind: Option<String>,
+    /// PEM certificate chain and private key to serve the primary listener
+    /// over TLS with, loaded by `HTTPServer::start`. Ignored without the
+    /// `tls` feature or when `HTTPServer::with_tls` is used
+    pub tls_cert: Option<std::path::PathBuf>,
+    pub tls_key: Option<std::path::PathBuf>,
+    /// TCP options for the listeners `HTTPServer` binds and the connections
+    /// they accept. Read when the listeners are bound, so reloads don't
+    /// change them
+    pub socket: crate::socket::SocketOptions,
+    /// accept loops per address listener, each on its own socket bound with
+    /// `SO_REUSEPORT` so the kernel balances new connections between them.
+    /// Helps at tens of thousands of connections a second, where a single
+    /// accept loop falls behind. Unix only; `None` means one
+    pub acceptors: Option<usize>,
+    /// compress responses for every route
+    #[cfg(feature = "compression")]
+    pub compression: Option<crate::middleware::compression::Compression>,
+    /// log every request with `RequestLog`
+    pub request_log: bool,
+    /// serve connections with hyper rather than the built-in HTTP/1 and
+    /// HTTP/2 code; see `hyper_server::serve_connection`
+    #[cfg(feature = "hyper")]
+    pub hyper: bool,
+    /// named lists of upstream URLs, for `HTTPServer::on_reload` hooks to
+    /// build `ReverseProxy`s from
+    pub upstreams: std::collections::BTreeMap<String, Vec<String>>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            preserve_header_case: false,
+            h2c: false,
+            canonical_host: None,
+            max_connections: None,
+            load_shed_retry_after: None,
+            first_byte_timeout: None,
+            header_read_timeout: None,
+            body_read_timeout: None,
+            write_timeout: None,
+            max_header_bytes: Some(DEFAULT_MAX_HEADER_BYTES),
+            max_body_bytes: Some(DEFAULT_MAX_BODY_BYTES),
+            max_decompressed_body_bytes: None,
+            trusted_proxies: Default::default(),
+            background_shutdown_timeout: None,
+            bind: None,
+            tls_cert: None,
+            tls_key: None,
+            socket: Default::default(),
+            acceptors: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+            request_log: false,
+            #[cfg(feature = "hyper")]
+            hyper: false,
+            upstreams: Default::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The defaults, overridden by `WEB_*` environment variables; see
+    /// `merge_env`.
+    pub fn from_env() -> Result<Self, String> {
+        ServerConfig::default().merge_env()
+    }
+
+    /// Settings from a TOML file, e.g.
+    ///
+    /// ```toml
+    /// bind = "0.0.0.0:8443"
+    /// first_byte_timeout = "30s"
+    /// max_body_bytes = 1048576
+    /// trusted_proxies = ["10.0.0.0/8"]
+    ///
+    /// [tls]
+    /// cert = "/etc/web/cert.pem"
+    /// key = "/etc/web/key.pem"
+    ///
+    /// [socket]
+    /// nodelay = true
+    /// keepalive = "60s"
+    /// backlog = 4096
+    ///
+    /// [upstreams]
+    /// api = ["http://10.0.0.1:8080", "http://10.0.0.2:8080"]
+    /// ```
+    ///
+    /// Keys are the field names, and a table's keys are joined to its name
+    /// with `_`, so `[tls] cert` sets `tls_cert`. Durations are seconds or
+    /// strings like `500ms`, `30s`, `5m` or `1h`. `compression = true`
+    /// compresses with the defaults, and `compression_level` and
+    /// `compression_min_size` tune it. Unknown keys are an error.
+    #[cfg(feature = "toml")]
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// like `from_file`, from the file's contents
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        fn flatten(prefix: &str, table: &toml::Table, out: &mut Vec<(String, String)>) -> Result<(), String> {
+            for (key, value) in table {
+                let key = if prefix.is_empty() { key.clone() } else { format!("{}_{}", prefix, key) };
+                let value = match value {
+                    toml::Value::Table(table) => {
+                        flatten(&key, table, out)?;
+                        continue;
+                    }
+                    toml::Value::String(s) => s.clone(),
+                    toml::Value::Array(items) => items
+                        .iter()
+                        .map(|item| match item {
+                            toml::Value::String(s) => s.clone(),
+                            other => other.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    other => other.to_string(),
+                };
+                out.push((key, value));
+            }
+            Ok(())
+        }
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+        let mut settings = Vec::new();
+        flatten("", &table, &mut settings)?;
+        let mut config = ServerConfig::default();
+        for (key, value) in settings {
+            if !config.set(&key, &value)? {
+                return Err(format!("Unknown setting: {}", key));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Override settings with `WEB_*` environment variables named after
+    /// the keys `from_file` takes, e.g. `WEB_BIND=0.0.0.0:8080`,
+    /// `WEB_FIRST_BYTE_TIMEOUT=30s` or `WEB_TLS_CERT=/etc/web/cert.pem`. Lists
+    /// like `WEB_TRUSTED_PROXIES` are comma-separated. Other `WEB_`
+    /// variables are ignored.
+    pub fn merge_env(mut self) -> Result<Self, String> {
+        for (name, value) in std::env::vars() {
+            if let Some(key) = name.strip_prefix("WEB_") {
+                self.set(&key.to_ascii_lowercase(), &value)
+                    .map_err(|e| format!("{}: {}", name, e))?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// apply one setting; `false` when there is no such key
+    fn set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        let invalid = || format!("Invalid value for {}: {}", key, value);
+        let value = value.trim();
+        let flag = || match value {
+            "true" | "1" | "yes" | "on" => Ok(true),
+            "false" | "0" | "no" | "off" => Ok(false),
+            _ => Err(invalid()),
+        };
+        let number = || value.parse::<usize>().map_err(|_| invalid());
+        let limit = || match value {
+            "unlimited" => Ok(None),
+            _ => number().map(Some),
+        };
+        let duration = || parse_duration(value).ok_or_else(invalid);
+        let text = || (!value.is_empty()).then(|| value.to_string());
+        match key {
+            "bind" => self.bind = text(),
+            "tls_cert" => self.tls_cert = text().map(Into::into),
+            "tls_key" => self.tls_key = text().map(Into::into),
+            "preserve_header_case" => self.preserve_header_case = flag()?,
+            "h2c" => self.h2c = flag()?,
+            "canonical_host" => self.canonical_host = text(),
+            "max_connections" => self.max_connections = Some(number()?),
+            "load_shed_retry_after" => self.load_shed_retry_after = Some(number()? as u64),
+            "first_byte_timeout" => self.first_byte_timeout = Some(duration()?),
+            "header_read_timeout" => self.header_read_timeout = Some(duration()?),
+            "body_read_timeout" => self.body_read_timeout = Some(duration()?),
+            "write_timeout" => self.write_timeout = Some(duration()?),
+            "background_shutdown_timeout" => self.background_shutdown_timeout = Some(duration()?),
+            "max_header_bytes" => self.max_header_bytes = limit()?,
+            "max_body_bytes" => self.max_body_bytes = limit()?,
+            "max_decompressed_body_bytes" => self.max_decompressed_body_bytes = Some(number()?),
+            "trusted_proxies" => {
+                let ranges: Vec<&str> = value.split(',').map(str::trim).filter(|r| !r.is_empty()).collect();
+                self.trusted_proxies = crate::middleware::real_ip::TrustedProxies::new(&ranges)?;
+            }
+            #[cfg(feature = "compression")]
+            "compression" => self.compression = flag()?.then(Default::default),
+            #[cfg(feature = "compression")]
+            "compression_level" => {
+                let level = value.parse::<u32>().ok().filter(|level| *level <= 9).ok_or_else(invalid)?;
+                self.compression.get_or_insert_with(Default::default).level = level;
+            }
+            #[cfg(feature = "compression")]
+            "compression_min_size" => self.compression.get_or_insert_with(Default::default).min_size = number()?,
+            "socket_nodelay" => self.socket.nodelay = Some(flag()?),
+            "socket_keepalive" => self.socket.keepalive.get_or_insert_with(Default::default).time = duration()?,
+            "socket_keepalive_interval" => {
+                self.socket.keepalive.get_or_insert_with(Default::default).interval = Some(duration()?)
+            }
+            "socket_keepalive_retries" => {
+                let retries = value.parse::<u32>().map_err(|_| invalid())?;
+                self.socket.keepalive.get_or_insert_with(Default::default).retries = Some(retries);
+            }
+            "socket_reuse_address" => self.socket.reuse_address = Some(flag()?),
+            "socket_reuse_port" => self.socket.reuse_port = Some(flag()?),
+            "socket_backlog" => self.socket.backlog = Some(value.parse::<u32>().map_err(|_| invalid())?),
+            "socket_send_buffer_size" => self.socket.send_buffer_size = Some(number()?),
+            "socket_recv_buffer_size" => self.socket.recv_buffer_size = Some(number()?),
+            "acceptors" => {
+                let acceptors = number().ok().filter(|n| *n > 0).ok_or_else(invalid)?;
+                self.acceptors = Some(acceptors);
+            }
+            "request_log" => self.request_log = flag()?,
+            #[cfg(feature = "hyper")]
+            "hyper" => self.hyper = flag()?,
+            key => match key.strip_prefix("upstreams_").filter(|name| !name.is_empty()) {
+                Some(name) => {
+                    let urls = value.split(',').map(str::trim).filter(|url| !url.is_empty());
+                    self.upstreams.insert(name.to_string(), urls.map(String::from).collect());
+                }
+                None => return Ok(false),
+            },
+        }
+        Ok(true)
+    }
+}
+
+/// `30`, `30s`, `500ms`, `5m` or `1h`
+fn parse_duration(value: &str) -> Option<std::time::Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let secs = match unit.trim() {
+        "ms" => return Some(std::time::Duration::from_millis(amount)),
+        "" | "s" => amount,
+        "m" => amount.checked_mul(60)?,
+        "h" => amount.checked_mul(3600)?,
+        _ => return None,
+    };
+    Some(std::time::Duration::from_secs(secs))
+}
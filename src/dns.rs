@@ -0,0 +1,529 @@
+mod message;
+
+use crate::client::{ClientError, HTTPClient, Retry};
+use crate::middleware::BoxFuture;
+use crate::models::http::{HTTPHeaderType, HTTPStatus};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+/// the media type of DNS messages over HTTPS
+const DNS_MESSAGE: &str = "application/dns-message";
+/// how many CNAMEs a lookup follows before giving up
+const MAX_CNAME_CHAIN: usize = 8;
+/// how many answers the cache holds before the soonest to expire go
+const MAX_CACHED: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    AAAA,
+    CNAME,
+    TXT,
+    SRV,
+}
+
+impl RecordType {
+    pub fn code(&self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::CNAME => 5,
+            RecordType::TXT => 16,
+            RecordType::AAAA => 28,
+            RecordType::SRV => 33,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Option<Self> {
+        match code {
+            1 => Some(RecordType::A),
+            5 => Some(RecordType::CNAME),
+            16 => Some(RecordType::TXT),
+            28 => Some(RecordType::AAAA),
+            33 => Some(RecordType::SRV),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    CNAME(String),
+    /// the record's strings, each up to 255 bytes
+    TXT(Vec<String>),
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+}
+
+impl RecordData {
+    pub fn kind(&self) -> RecordType {
+        match self {
+            RecordData::A(_) => RecordType::A,
+            RecordData::AAAA(_) => RecordType::AAAA,
+            RecordData::CNAME(_) => RecordType::CNAME,
+            RecordData::TXT(_) => RecordType::TXT,
+            RecordData::SRV { .. } => RecordType::SRV,
+        }
+    }
+}
+
+/// One resource record; `name` has no trailing dot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+/// How DNS-over-HTTPS queries are sent, see RFC 8484
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DohMethod {
+    /// the query base64url-encoded in the `dns` parameter, which caches
+    /// can store
+    #[default]
+    Get,
+    /// the query as the request body
+    Post,
+}
+
+/// A DNS-over-HTTPS endpoint, e.g. `https://1.1.1.1/dns-query`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DohServer {
+    pub url: String,
+    pub method: DohMethod,
+}
+
+impl DohServer {
+    pub fn new(url: impl Into<String>) -> Self {
+        DohServer {
+            url: url.into(),
+            method: DohMethod::Get,
+        }
+    }
+
+    pub fn method(mut self, method: DohMethod) -> Self {
+        self.method = method;
+        self
+    }
+}
+
+/// Where and how a `Resolver` asks, usually from `/etc/resolv.conf`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolverConfig {
+    pub nameservers: Vec<SocketAddr>,
+    /// ask over HTTPS here instead of the nameservers, so nothing on the
+    /// path can read or change the queries
+    pub doh: Option<DohServer>,
+    /// domains tried after names with fewer than `ndots` dots
+    pub search: Vec<String>,
+    pub ndots: usize,
+    /// how long to wait for each nameserver
+    pub timeout: Duration,
+    /// how many times to go through the nameservers
+    pub attempts: usize,
+}
+
+impl Default for ResolverConfig {
+    /// what the C library assumes without a `resolv.conf`
+    fn default() -> Self {
+        ResolverConfig {
+            nameservers: vec![SocketAddr::from(([127, 0, 0, 1], 53))],
+            doh: None,
+            search: Vec::new(),
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+        }
+    }
+}
+
+impl ResolverConfig {
+    /// Settings from the text of a `resolv.conf`: `nameserver`, `search`,
+    /// `domain` and the `ndots`, `timeout` and `attempts` options. Lines
+    /// it doesn't understand are skipped.
+    pub fn from_resolv_conf(text: &str) -> Self {
+        let mut config = ResolverConfig {
+            nameservers: Vec::new(),
+            ..Default::default()
+        };
+        for line in text.lines() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    // scoped IPv6 addresses, e.g. `fe80::1%eth0`, can't be used here
+                    if let Some(ip) = words.next().and_then(|ip| ip.parse::<IpAddr>().ok()) {
+                        config.nameservers.push(SocketAddr::new(ip, 53));
+                    }
+                }
+                // the last of `search` and `domain` wins
+                Some("search") => config.search = words.map(|domain| domain.trim_end_matches('.').to_string()).collect(),
+                Some("domain") => config.search = words.next().map(|domain| domain.trim_end_matches('.').to_string()).into_iter().collect(),
+                Some("options") => {
+                    for option in words {
+                        let (name, value) = option.split_once(':').unwrap_or((option, ""));
+                        let Ok(value) = value.parse::<u64>() else {
+                            continue;
+                        };
+                        match name {
+                            "ndots" => config.ndots = value.min(15) as usize,
+                            "timeout" => config.timeout = Duration::from_secs(value.clamp(1, 30)),
+                            "attempts" => config.attempts = value.clamp(1, 5) as usize,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if config.nameservers.is_empty() {
+            config.nameservers = ResolverConfig::default().nameservers;
+        }
+        config
+    }
+
+    /// `/etc/resolv.conf`, or the defaults when there is none
+    pub fn system() -> io::Result<Self> {
+        match std::fs::read_to_string("/etc/resolv.conf") {
+            Ok(text) => Ok(Self::from_resolv_conf(&text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+struct Cached {
+    records: Vec<Record>,
+    expires: Instant,
+}
+
+/// An async stub resolver: asks the configured nameservers over UDP,
+/// again over TCP when the answer is truncated, or a DNS-over-HTTPS
+/// server, and caches answers for their TTL. Cheap to clone; clones share
+/// the cache. Give it to `HTTPClientBuilder::resolver` to look up hosts
+/// with it instead of the system's resolver.
+///
+/// `let ips = Resolver::system()?.lookup_ip("example.com").await?;`
+#[derive(Clone)]
+pub struct Resolver {
+    config: Arc<ResolverConfig>,
+    cache: Arc<Mutex<HashMap<(String, RecordType), Cached>>>,
+    /// what DNS-over-HTTPS queries go through
+    client: Option<HTTPClient>,
+}
+
+impl Resolver {
+    pub fn new(config: ResolverConfig) -> Self {
+        let client = config.doh.as_ref().map(|_| HTTPClient::new());
+        Resolver {
+            config: Arc::new(config),
+            cache: Arc::default(),
+            client,
+        }
+    }
+
+    /// a resolver configured from `/etc/resolv.conf`
+    pub fn system() -> io::Result<Self> {
+        Ok(Self::new(ResolverConfig::system()?))
+    }
+
+    /// a resolver asking `url` over HTTPS, with `GET`
+    pub fn dns_over_https(url: &str) -> Self {
+        Self::new(ResolverConfig {
+            doh: Some(DohServer::new(url)),
+            ..Default::default()
+        })
+    }
+
+    /// Send DNS-over-HTTPS queries through `client`, e.g. one that trusts
+    /// a private CA. It must not look hosts up with this resolver; name
+    /// the server by IP address or leave the client on the system's
+    /// resolver.
+    pub fn http_client(mut self, client: HTTPClient) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn config(&self) -> &ResolverConfig {
+        &self.config
+    }
+
+    /// Records of type `kind` for `name`, trying the search domains the way
+    /// the C library does. CNAMEs on the way are followed, so the records
+    /// can be for another name. `NotFound` when there are none.
+    pub async fn lookup(&self, name: &str, kind: RecordType) -> io::Result<Vec<Record>> {
+        let mut last = None;
+        for candidate in self.candidates(name) {
+            match self.lookup_exact(&candidate, kind).await {
+                Ok(records) => return Ok(records),
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| not_found(name)))
+    }
+
+    /// The addresses of `host`, IPv6 first. IP literals and `localhost`
+    /// are answered without asking.
+    pub async fn lookup_ip(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+        if host.eq_ignore_ascii_case("localhost") {
+            return Ok(vec![IpAddr::V6(Ipv6Addr::LOCALHOST), IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+        }
+        let (v6, v4) = tokio::join!(self.lookup(host, RecordType::AAAA), self.lookup(host, RecordType::A));
+        let addresses: Vec<IpAddr> = v6
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .chain(v4.as_deref().unwrap_or_default())
+            .filter_map(|record| match record.data {
+                RecordData::A(ip) => Some(IpAddr::V4(ip)),
+                RecordData::AAAA(ip) => Some(IpAddr::V6(ip)),
+                _ => None,
+            })
+            .collect();
+        match (addresses.is_empty(), v4) {
+            (true, Err(e)) => Err(e),
+            (true, Ok(_)) => Err(not_found(host)),
+            (false, _) => Ok(addresses),
+        }
+    }
+
+    /// forget every cached answer
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// `name` as given and with each search domain, in the order to try
+    fn candidates(&self, name: &str) -> Vec<String> {
+        if let Some(absolute) = name.strip_suffix('.') {
+            return vec![absolute.to_string()];
+        }
+        let searched = self.config.search.iter().map(|domain| format!("{}.{}", name, domain));
+        if name.matches('.').count() >= self.config.ndots {
+            std::iter::once(name.to_string()).chain(searched).collect()
+        } else {
+            searched.chain(std::iter::once(name.to_string())).collect()
+        }
+    }
+
+    /// `name`'s records, from the cache if they haven't expired
+    async fn lookup_exact(&self, name: &str, kind: RecordType) -> io::Result<Vec<Record>> {
+        let mut name = name.to_ascii_lowercase();
+        for _ in 0..MAX_CNAME_CHAIN {
+            let key = (name.clone(), kind);
+            let cached = self.cache.lock().unwrap().get(&key).and_then(|cached| {
+                (cached.expires > Instant::now()).then(|| cached.records.clone())
+            });
+            let records = match cached {
+                Some(records) => records,
+                None => {
+                    let records = self.ask(&name, kind).await?;
+                    let ttl = records.iter().map(|record| record.ttl).min().unwrap_or(0);
+                    let now = Instant::now();
+                    let mut cache = self.cache.lock().unwrap();
+                    cache.retain(|_, cached| cached.expires > now);
+                    if cache.len() >= MAX_CACHED {
+                        let soonest = cache.iter().min_by_key(|(_, cached)| cached.expires);
+                        let soonest = soonest.map(|(key, _)| key.clone());
+                        if let Some(key) = soonest {
+                            cache.remove(&key);
+                        }
+                    }
+                    cache.insert(key, Cached {
+                        records: records.clone(),
+                        expires: now + Duration::from_secs(ttl.into()),
+                    });
+                    records
+                }
+            };
+            // a CNAME the nameserver didn't follow itself
+            match records.as_slice() {
+                [Record { data: RecordData::CNAME(target), .. }] if kind != RecordType::CNAME => {
+                    name = target.to_ascii_lowercase();
+                }
+                _ => return Ok(records),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, format!("CNAME chain too long for {}", name)))
+    }
+
+    /// the records of type `kind` an answer about `name` holds, the
+    /// nameserver's CNAME chain followed, or the CNAME it ends in
+    async fn ask(&self, name: &str, kind: RecordType) -> io::Result<Vec<Record>> {
+        let answer = self.exchange(name, kind).await?;
+        if answer.rcode == message::NXDOMAIN {
+            return Err(not_found(name));
+        }
+        let mut owner = name.to_string();
+        for _ in 0..MAX_CNAME_CHAIN {
+            let records: Vec<Record> = answer
+                .records
+                .iter()
+                .filter(|record| record.name.eq_ignore_ascii_case(&owner) && record.data.kind() == kind)
+                .cloned()
+                .collect();
+            if !records.is_empty() {
+                return Ok(records);
+            }
+            let alias = answer.records.iter().find(|record| {
+                record.name.eq_ignore_ascii_case(&owner) && record.data.kind() == RecordType::CNAME
+            });
+            match alias {
+                Some(Record { data: RecordData::CNAME(target), .. }) if answer.records.iter().any(|r| r.name.eq_ignore_ascii_case(target)) => {
+                    owner = target.clone();
+                }
+                Some(alias) => return Ok(vec![alias.clone()]),
+                None => return Err(not_found(name)),
+            }
+        }
+        Err(not_found(name))
+    }
+
+    /// ask each nameserver in turn until one answers
+    async fn exchange(&self, name: &str, kind: RecordType) -> io::Result<message::Answer> {
+        if let (Some(doh), Some(client)) = (&self.config.doh, &self.client) {
+            return self.exchange_doh(doh, client, name, kind).await;
+        }
+        let id = std::collections::hash_map::RandomState::new().hash_one(Instant::now()) as u16;
+        let query = message::query(id, name, kind).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut last = io::Error::new(io::ErrorKind::NotFound, "no nameservers configured");
+        for _ in 0..self.config.attempts.max(1) {
+            for server in &self.config.nameservers {
+                let exchange = async {
+                    let answer = exchange_udp(*server, &query, id, name, kind).await?;
+                    match answer.truncated {
+                        true => exchange_tcp(*server, &query, id, name, kind).await,
+                        false => Ok(answer),
+                    }
+                };
+                match tokio::time::timeout(self.config.timeout, exchange).await {
+                    Ok(Ok(answer)) if answer.rcode == 0 || answer.rcode == message::NXDOMAIN => return Ok(answer),
+                    Ok(Ok(answer)) => {
+                        last = io::Error::other(format!("{} answered with error {}", server, answer.rcode));
+                    }
+                    Ok(Err(e)) => last = e,
+                    Err(_) => last = io::Error::new(io::ErrorKind::TimedOut, format!("{} didn't answer", server)),
+                }
+            }
+        }
+        Err(last)
+    }
+
+    /// ask `doh`, as many times as `attempts` allows. Boxed, as the client
+    /// can come back to the resolver to look up a host.
+    fn exchange_doh<'a>(
+        &'a self,
+        doh: &'a DohServer,
+        client: &'a HTTPClient,
+        name: &'a str,
+        kind: RecordType,
+    ) -> BoxFuture<'a, io::Result<message::Answer>> {
+        Box::pin(async move {
+            // id 0 keeps GET answers cacheable
+            let query = message::query(0, name, kind).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let request = match doh.method {
+                DohMethod::Get => {
+                    let separator = if doh.url.contains('?') { '&' } else { '?' };
+                    client.get(&format!("{}{}dns={}", doh.url, separator, URL_SAFE_NO_PAD.encode(&query)))
+                }
+                DohMethod::Post => client
+                    .post(&doh.url)
+                    .header(HTTPHeaderType::ContentType, DNS_MESSAGE)
+                    .body(query),
+            }
+            .header(HTTPHeaderType::Accept, DNS_MESSAGE)
+            .timeout(self.config.timeout)
+            // resending a query is always safe
+            .retry(Retry::new(self.config.attempts.saturating_sub(1) as u32).retry_non_idempotent());
+            let res = request.send().await.map_err(|e| match e {
+                ClientError::Connect(e) | ClientError::Io(e) => e,
+                ClientError::Timeout => io::Error::new(io::ErrorKind::TimedOut, format!("{} didn't answer", doh.url)),
+                e => io::Error::other(e.to_string()),
+            })?;
+            if res.status != HTTPStatus::Ok {
+                return Err(io::Error::other(format!("{} answered {}", doh.url, res.status.code())));
+            }
+            let answer = message::parse(res.body_bytes()).map_err(invalid)?;
+            if !answer.answers(0, name, kind) {
+                return Err(invalid(String::from("DNS response for another query")));
+            }
+            match answer.rcode {
+                0 | message::NXDOMAIN => Ok(answer),
+                rcode => Err(io::Error::other(format!("{} answered with error {}", doh.url, rcode))),
+            }
+        })
+    }
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("no such host: {}", name))
+}
+
+fn invalid(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// send `query` over UDP and wait for the response with its id and question
+async fn exchange_udp(
+    server: SocketAddr,
+    query: &[u8],
+    id: u16,
+    name: &str,
+    kind: RecordType,
+) -> io::Result<message::Answer> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(query).await?;
+    let mut buffer = vec![0; message::EDNS_PAYLOAD as usize];
+    loop {
+        let read = socket.recv(&mut buffer).await?;
+        // anything else is stale or spoofed
+        if let Ok(answer) = message::parse(&buffer[..read]) {
+            if answer.answers(id, name, kind) {
+                return Ok(answer);
+            }
+        }
+    }
+}
+
+/// send `query` over TCP, each message after its length
+async fn exchange_tcp(
+    server: SocketAddr,
+    query: &[u8],
+    id: u16,
+    name: &str,
+    kind: RecordType,
+) -> io::Result<message::Answer> {
+    let mut stream = TcpStream::connect(server).await?;
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(query);
+    stream.write_all(&framed).await?;
+    let length = stream.read_u16().await?;
+    let mut response = vec![0; length as usize];
+    stream.read_exact(&mut response).await?;
+    let answer = message::parse(&response).map_err(invalid)?;
+    if !answer.answers(id, name, kind) {
+        return Err(invalid(String::from("DNS response for another query")));
+    }
+    Ok(answer)
+}
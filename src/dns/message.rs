@@ -0,0 +1,196 @@
+use super::{Record, RecordData, RecordType};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// the class of every record the resolver asks for
+const CLASS_IN: u16 = 1;
+const TYPE_OPT: u16 = 41;
+/// the UDP payload size advertised with EDNS, small enough not to fragment
+pub(crate) const EDNS_PAYLOAD: u16 = 1232;
+
+/// RCODE 3, the name doesn't exist
+pub(crate) const NXDOMAIN: u8 = 3;
+
+/// A query for `name`'s records of type `kind`, recursion desired, with
+/// an EDNS record allowing larger UDP answers
+pub(crate) fn query(id: u16, name: &str, kind: RecordType) -> Result<Vec<u8>, String> {
+    let mut message = Vec::with_capacity(name.len() + 30);
+    message.extend_from_slice(&id.to_be_bytes());
+    // RD
+    message.extend_from_slice(&0x0100u16.to_be_bytes());
+    for count in [1u16, 0, 0, 1] {
+        message.extend_from_slice(&count.to_be_bytes());
+    }
+    for label in name.trim_end_matches('.').split('.').filter(|label| !label.is_empty()) {
+        if label.len() > 63 {
+            return Err(format!("Label too long in {}", name));
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&kind.code().to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    // OPT: root name, payload size in place of the class
+    message.push(0);
+    message.extend_from_slice(&TYPE_OPT.to_be_bytes());
+    message.extend_from_slice(&EDNS_PAYLOAD.to_be_bytes());
+    message.extend_from_slice(&[0; 6]);
+    if message.len() > 512 {
+        return Err(format!("Name too long: {}", name));
+    }
+    Ok(message)
+}
+
+/// What a nameserver answered
+#[derive(Debug)]
+pub(crate) struct Answer {
+    pub(crate) id: u16,
+    pub(crate) truncated: bool,
+    pub(crate) rcode: u8,
+    /// the name, type and class of the first question
+    pub(crate) question: Option<(String, u16, u16)>,
+    pub(crate) records: Vec<Record>,
+}
+
+impl Answer {
+    /// whether this is the answer to query `id` for `name`'s `kind` records
+    pub(crate) fn answers(&self, id: u16, name: &str, kind: RecordType) -> bool {
+        match &self.question {
+            Some((asked, code, class)) => {
+                self.id == id
+                    && asked.eq_ignore_ascii_case(name.trim_end_matches('.'))
+                    && *code == kind.code()
+                    && *class == CLASS_IN
+            }
+            None => false,
+        }
+    }
+}
+
+/// the answer section of `message`, records of types the resolver doesn't
+/// know skipped
+pub(crate) fn parse(message: &[u8]) -> Result<Answer, String> {
+    let short = || String::from("Truncated DNS message");
+    let header = message.get(..12).ok_or_else(short)?;
+    let word = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]);
+    let flags = word(2);
+    if flags & 0x8000 == 0 {
+        return Err(String::from("Not a DNS response"));
+    }
+    let mut reader = Reader { message, at: 12 };
+    let mut question = None;
+    for _ in 0..word(4) {
+        let name = reader.name()?;
+        let (kind, class) = (reader.u16()?, reader.u16()?);
+        question.get_or_insert((name, kind, class));
+    }
+    let mut records = Vec::new();
+    for _ in 0..word(6) {
+        let name = reader.name()?;
+        let kind = reader.u16()?;
+        let _class = reader.u16()?;
+        let ttl = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+        let length = reader.u16()? as usize;
+        let end = reader.at + length;
+        if end > message.len() {
+            return Err(short());
+        }
+        let data = match RecordType::from_code(kind) {
+            Some(RecordType::A) => {
+                let bytes: [u8; 4] = reader.take(4)?.try_into().unwrap();
+                Some(RecordData::A(Ipv4Addr::from(bytes)))
+            }
+            Some(RecordType::AAAA) => {
+                let bytes: [u8; 16] = reader.take(16)?.try_into().unwrap();
+                Some(RecordData::AAAA(Ipv6Addr::from(bytes)))
+            }
+            Some(RecordType::CNAME) => Some(RecordData::CNAME(reader.name()?)),
+            Some(RecordType::TXT) => {
+                let mut strings = Vec::new();
+                while reader.at < end {
+                    let length = reader.take(1)?[0] as usize;
+                    strings.push(String::from_utf8_lossy(reader.take(length)?).to_string());
+                }
+                Some(RecordData::TXT(strings))
+            }
+            Some(RecordType::SRV) => Some(RecordData::SRV {
+                priority: reader.u16()?,
+                weight: reader.u16()?,
+                port: reader.u16()?,
+                target: reader.name()?,
+            }),
+            None => None,
+        };
+        if reader.at > end {
+            return Err(String::from("Record data overruns its length"));
+        }
+        reader.at = end;
+        if let Some(data) = data {
+            records.push(Record { name, ttl, data });
+        }
+    }
+    Ok(Answer {
+        id: word(0),
+        truncated: flags & 0x0200 != 0,
+        rcode: (flags & 0x000f) as u8,
+        question,
+        records,
+    })
+}
+
+struct Reader<'a> {
+    message: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .message
+            .get(self.at..self.at + n)
+            .ok_or_else(|| String::from("Truncated DNS message"))?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// a possibly compressed name, without the trailing dot
+    fn name(&mut self) -> Result<String, String> {
+        let mut labels = Vec::new();
+        let mut at = self.at;
+        let mut resume = None;
+        // every pointer must go backwards, so this ends
+        let mut limit = at;
+        loop {
+            let length = *self.message.get(at).ok_or("Truncated DNS name")? as usize;
+            match length {
+                0 => {
+                    at += 1;
+                    break;
+                }
+                l if l & 0xc0 == 0xc0 => {
+                    let low = *self.message.get(at + 1).ok_or("Truncated DNS name")? as usize;
+                    let target = (l & 0x3f) << 8 | low;
+                    if target >= limit {
+                        return Err(String::from("DNS name pointer loops"));
+                    }
+                    resume.get_or_insert(at + 2);
+                    limit = target;
+                    at = target;
+                }
+                l if l <= 63 => {
+                    let label = self.message.get(at + 1..at + 1 + l).ok_or("Truncated DNS name")?;
+                    labels.push(String::from_utf8_lossy(label).to_string());
+                    at += 1 + l;
+                }
+                _ => return Err(String::from("Invalid DNS label")),
+            }
+        }
+        self.at = resume.unwrap_or(at);
+        Ok(labels.join("."))
+    }
+}
@@ -0,0 +1,92 @@
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::Poll;
+use crate::socket::SocketOptions;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// how long an attempt gets before the next address is tried alongside
+/// it, RFC 8305's recommended Connection Attempt Delay
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+type Attempt = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send>>;
+
+/// `addrs` in the order to try them: alternating between IPv6 and IPv4,
+/// starting with the family of the first, each family in its own order
+pub fn interleave(addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+    let addrs: Vec<SocketAddr> = addrs.into_iter().collect();
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let (first_v6, count) = (first.is_ipv6(), addrs.len());
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|addr| addr.is_ipv6() == first_v6);
+    preferred.reverse();
+    other.reverse();
+    let mut ordered = Vec::with_capacity(count);
+    while !preferred.is_empty() || !other.is_empty() {
+        ordered.extend(preferred.pop());
+        ordered.extend(other.pop());
+    }
+    ordered
+}
+
+/// Connect to `host` on `port` with Happy Eyeballs (RFC 8305), looking it
+/// up with the system's resolver.
+pub async fn connect_host(host: &str, port: u16) -> io::Result<TcpStream> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    connect(tokio::net::lookup_host((host, port)).await?, CONNECTION_ATTEMPT_DELAY).await
+}
+
+/// Connect to the first of `addrs` to accept, in `interleave` order,
+/// starting the next attempt whenever the last has failed or has had
+/// `delay` to itself, so a broken IPv6 path costs no more than `delay`.
+/// The attempts that lose are dropped.
+pub async fn connect(addrs: impl IntoIterator<Item = SocketAddr>, delay: Duration) -> io::Result<TcpStream> {
+    connect_with(addrs, delay, SocketOptions::default()).await
+}
+
+/// like `connect`, opening every attempt with `options`
+pub async fn connect_with(
+    addrs: impl IntoIterator<Item = SocketAddr>,
+    delay: Duration,
+    options: SocketOptions,
+) -> io::Result<TcpStream> {
+    let mut pending = interleave(addrs).into_iter().peekable();
+    let mut attempts: Vec<Attempt> = Vec::new();
+    let mut timer = Box::pin(tokio::time::sleep(delay));
+    let mut last = None;
+    let mut next = pending.next();
+    loop {
+        if let Some(addr) = next.take() {
+            attempts.push(Box::pin(crate::socket::connect(addr, options)));
+            timer.as_mut().reset(tokio::time::Instant::now() + delay);
+        }
+        if attempts.is_empty() {
+            return Err(last.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to")));
+        }
+        let more = pending.peek().is_some();
+        let outcome = poll_fn(|cx| {
+            for (i, attempt) in attempts.iter_mut().enumerate() {
+                if let Poll::Ready(result) = attempt.as_mut().poll(cx) {
+                    return Poll::Ready(Some((i, result)));
+                }
+            }
+            if more && timer.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(None);
+            }
+            Poll::Pending
+        })
+        .await;
+        match outcome {
+            Some((_, Ok(stream))) => return Ok(stream),
+            Some((i, Err(e))) => {
+                drop(attempts.swap_remove(i));
+                last = Some(e);
+                next = pending.next();
+            }
+            None => next = pending.next(),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::middleware::BoxFuture;
+use crate::models::error::panic_message;
+use crate::models::http::{HTTPHeaderType, HTTPResponse, HTTPStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type Probe = Arc<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// Probes that decide whether the service is ready for traffic, e.g. a
+/// database ping or an upstream's reachability, served by
+/// `Router::health`. Probes run concurrently, each failing once it takes
+/// longer than the timeout, 2 seconds unless set.
+#[derive(Clone)]
+pub struct Health {
+    checks: Vec<(String, Probe)>,
+    timeout: Duration,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Fail,
+}
+
+/// The outcome of one probe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub status: HealthStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// What the readiness endpoint answers:
+///
+/// `{"status": "fail", "checks": {"db": {"status": "fail", "error": "timed out", "duration_ms": 2000}}}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// `ok` when every check is
+    pub status: HealthStatus,
+    pub checks: BTreeMap<String, CheckResult>,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Health {
+            checks: Vec::new(),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// add a probe reporting why the service can't take traffic, if so
+    pub fn check<F, Fut>(mut self, name: &str, probe: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.checks.push((name.to_string(), Arc::new(move || Box::pin(probe()))));
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// run every probe
+    pub async fn run(&self) -> HealthReport {
+        let timeout = self.timeout;
+        let running: Vec<_> = self
+            .checks
+            .iter()
+            .map(|(name, probe)| {
+                let probe = probe();
+                let started = Instant::now();
+                let task = tokio::spawn(async move {
+                    let outcome = match tokio::time::timeout(timeout, probe).await {
+                        Ok(outcome) => outcome,
+                        Err(_) => Err(String::from("timed out")),
+                    };
+                    (outcome, started.elapsed())
+                });
+                (name.clone(), started, task)
+            })
+            .collect();
+        let mut checks = BTreeMap::new();
+        for (name, started, task) in running {
+            let (outcome, elapsed) = match task.await {
+                Ok(finished) => finished,
+                Err(e) if e.is_panic() => (Err(panic_message(&*e.into_panic())), started.elapsed()),
+                Err(e) => (Err(e.to_string()), started.elapsed()),
+            };
+            let duration_ms = elapsed.as_millis() as u64;
+            let result = match outcome {
+                Ok(()) => CheckResult {
+                    status: HealthStatus::Ok,
+                    error: None,
+                    duration_ms,
+                },
+                Err(error) => CheckResult {
+                    status: HealthStatus::Fail,
+                    error: Some(error),
+                    duration_ms,
+                },
+            };
+            checks.insert(name, result);
+        }
+        let status = if checks.values().all(|check| check.status == HealthStatus::Ok) {
+            HealthStatus::Ok
+        } else {
+            HealthStatus::Fail
+        };
+        HealthReport { status, checks }
+    }
+
+    /// the report as a response: 200 when ready, else 503
+    pub(crate) async fn respond(&self) -> HTTPResponse {
+        let report = self.run().await;
+        let mut res = HTTPResponse::default();
+        if report.status == HealthStatus::Fail {
+            res.status = HTTPStatus::ServiceUnavailable;
+        }
+        if let Err(e) = res.json(&report) {
+            return HTTPResponse::error(HTTPStatus::InternalServerError, &e.message);
+        }
+        res.set_header(HTTPHeaderType::CacheControl, "no-store");
+        res
+    }
+}
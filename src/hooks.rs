@@ -0,0 +1,108 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::http::{HTTPRequest, HTTPResponse};
+use std::future::Future;
+use std::sync::Arc;
+
+type LifecycleHook = Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
+type RequestHook = Arc<dyn for<'a> Fn(&'a HTTPRequest) -> BoxFuture<'a, ()> + Send + Sync>;
+type ResponseHook = Arc<dyn for<'a> Fn(&'a HTTPRequest, &'a HTTPResponse) -> BoxFuture<'a, ()> + Send + Sync>;
+
+/// Callbacks an `HTTPServer` runs around its life and each request, set
+/// with `HTTPServer::on_start` and friends. Each kind runs in the order
+/// added, one after the other.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    start: Vec<LifecycleHook>,
+    shutdown: Vec<LifecycleHook>,
+    request: Vec<RequestHook>,
+    response: Vec<ResponseHook>,
+    error: Vec<ResponseHook>,
+}
+
+impl Hooks {
+    pub fn on_start<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.start.push(Arc::new(move || Box::pin(hook())));
+    }
+
+    pub fn on_shutdown<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown.push(Arc::new(move || Box::pin(hook())));
+    }
+
+    pub fn on_request<F>(&mut self, hook: F)
+    where
+        F: for<'a> Fn(&'a HTTPRequest) -> BoxFuture<'a, ()> + Send + Sync + 'static,
+    {
+        self.request.push(Arc::new(hook));
+    }
+
+    pub fn on_response<F>(&mut self, hook: F)
+    where
+        F: for<'a> Fn(&'a HTTPRequest, &'a HTTPResponse) -> BoxFuture<'a, ()> + Send + Sync + 'static,
+    {
+        self.response.push(Arc::new(hook));
+    }
+
+    pub fn on_error<F>(&mut self, hook: F)
+    where
+        F: for<'a> Fn(&'a HTTPRequest, &'a HTTPResponse) -> BoxFuture<'a, ()> + Send + Sync + 'static,
+    {
+        self.error.push(Arc::new(hook));
+    }
+
+    pub(crate) async fn started(&self) {
+        for hook in &self.start {
+            hook().await;
+        }
+    }
+
+    pub(crate) async fn shutting_down(&self) {
+        for hook in &self.shutdown {
+            hook().await;
+        }
+    }
+
+    /// whether any hook runs per request, so the layer is needed
+    pub(crate) fn per_request(&self) -> bool {
+        !self.request.is_empty() || !self.response.is_empty() || !self.error.is_empty()
+    }
+}
+
+impl Middleware for Hooks {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            for hook in &self.request {
+                hook(&req).await;
+            }
+            if self.response.is_empty() && self.error.is_empty() {
+                return next.run(req).await;
+            }
+            // the handler takes the request, so response hooks see its head
+            let head = HTTPRequest {
+                method: req.method.clone(),
+                url: req.url.clone(),
+                version: req.version.clone(),
+                headers: req.headers.clone(),
+                connection: req.connection.clone(),
+                ..Default::default()
+            };
+            let res = next.run(req).await;
+            for hook in &self.response {
+                hook(&head, &res).await;
+            }
+            if res.status.is_server_error() {
+                for hook in &self.error {
+                    hook(&head, &res).await;
+                }
+            }
+            res
+        })
+    }
+}
@@ -0,0 +1,209 @@
+use crate::models::body::ChannelStream;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPResponse, HTTPStatus, HTTPVersion};
+use std::io;
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+
+/// largest response head accepted
+pub(crate) const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// how a response marks the end of its body
+pub(crate) enum Framing {
+    Empty,
+    Length(u64),
+    Chunked,
+    Close,
+}
+
+/// Read the response to a `method` request, skipping interim responses
+/// other than 101. Returns the response, the body bytes read along with
+/// the head and the body's framing.
+pub(crate) async fn read_response<S>(stream: &mut S, method: &HTTPMethod) -> io::Result<(HTTPResponse, Vec<u8>, Framing)>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = Vec::new();
+    loop {
+        let head_end = loop {
+            if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+            if buf.len() > MAX_HEAD_BYTES || read_more(stream, &mut buf).await? == 0 {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+        };
+        let head = String::from_utf8_lossy(&buf[..head_end]).into_owned();
+        buf.drain(..head_end);
+        let (res, code) = parse_head(&head).ok_or(io::ErrorKind::InvalidData)?;
+        if (100..200).contains(&code) && code != 101 {
+            continue;
+        }
+        let framing = if *method == HTTPMethod::HEAD
+            || code == 101
+            || code == 204
+            || code == 304
+            || (*method == HTTPMethod::CONNECT && (200..300).contains(&code))
+        {
+            Framing::Empty
+        } else if res
+            .header(&HTTPHeaderType::TransferEncoding)
+            .is_some_and(|te| te.to_ascii_lowercase().contains("chunked"))
+        {
+            Framing::Chunked
+        } else if let Some(length) = res.content_length() {
+            Framing::Length(length)
+        } else {
+            Framing::Close
+        };
+        return Ok((res, buf, framing));
+    }
+}
+
+/// Status and headers of a response head. Repeated headers keep a
+/// comma-joined value, except `Set-Cookie`, which keeps the last one.
+pub(crate) fn parse_head(head: &str) -> Option<(HTTPResponse, u16)> {
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next()?.splitn(3, ' ');
+    let version = match parts.next()? {
+        "HTTP/1.0" => HTTPVersion::HTTP1_0,
+        version if version.starts_with("HTTP/1.") => HTTPVersion::HTTP1_1,
+        _ => return None,
+    };
+    let code: u16 = parts.next()?.parse().ok()?;
+    let mut res = HTTPResponse {
+        status: HTTPStatus::custom(code, parts.next().unwrap_or("")).ok()?,
+        version,
+        body: None,
+        ..Default::default()
+    };
+    let mut last = None;
+    for line in lines.filter(|line| !line.is_empty()) {
+        // obsolete line folding continues the previous value after a space
+        if line.starts_with([' ', '\t']) {
+            if let Some(value) = last.as_ref().and_then(|header| res.headers.get_mut(header)) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        let (name, value) = line.split_once(':')?;
+        let (name, value) = (name.trim(), value.trim().to_string());
+        let header = HTTPHeaderType::from_str(name).unwrap_or(HTTPHeaderType::Other(name.to_string()));
+        match res.headers.get_mut(&header) {
+            Some(existing) if header != HTTPHeaderType::SetCookie => {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            }
+            _ => {
+                res.headers.insert(header.clone(), value);
+            }
+        }
+        last = Some(header);
+    }
+    Some((res, code))
+}
+
+/// Attach the rest of the body to `res` as a stream, read by a task that
+/// holds on to `guard` until the body ends. Chunked bodies arrive decoded,
+/// so `Transfer-Encoding` is removed.
+pub(crate) fn stream_body<S, G>(res: &mut HTTPResponse, stream: S, buffered: Vec<u8>, framing: Framing, guard: G)
+where
+    S: AsyncRead + Unpin + Send + 'static,
+    G: Send + 'static,
+{
+    res.headers.remove(&HTTPHeaderType::TransferEncoding);
+    if let Framing::Empty = framing {
+        return;
+    }
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        // a failed read ends the body early, which the reader can only
+        // tell from a short Content-Length
+        let _ = copy_body(stream, buffered, framing, tx).await;
+        drop(guard);
+    });
+    res.set_body_stream(ChannelStream(rx));
+}
+
+/// pass the body into `tx` until it ends or the receiver goes away
+async fn copy_body<S>(mut stream: S, mut buf: Vec<u8>, framing: Framing, tx: mpsc::Sender<Vec<u8>>) -> io::Result<()>
+where
+    S: AsyncRead + Unpin,
+{
+    match framing {
+        Framing::Empty => Ok(()),
+        Framing::Length(mut remaining) => {
+            while remaining > 0 {
+                if buf.is_empty() && read_more(&mut stream, &mut buf).await? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let n = buf.len().min(remaining as usize);
+                remaining -= n as u64;
+                if tx.send(buf.drain(..n).collect()).await.is_err() {
+                    return Ok(());
+                }
+            }
+            Ok(())
+        }
+        Framing::Close => loop {
+            if !buf.is_empty() && tx.send(std::mem::take(&mut buf)).await.is_err() {
+                return Ok(());
+            }
+            if read_more(&mut stream, &mut buf).await? == 0 {
+                return Ok(());
+            }
+        },
+        Framing::Chunked => loop {
+            let line = read_line(&mut stream, &mut buf).await?;
+            let size = line.split(';').next().unwrap_or("").trim();
+            let mut remaining =
+                usize::from_str_radix(size, 16).map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+            if remaining == 0 {
+                // trailers are dropped
+                while !read_line(&mut stream, &mut buf).await?.is_empty() {}
+                return Ok(());
+            }
+            while remaining > 0 {
+                if buf.is_empty() && read_more(&mut stream, &mut buf).await? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let n = buf.len().min(remaining);
+                remaining -= n;
+                if tx.send(buf.drain(..n).collect()).await.is_err() {
+                    return Ok(());
+                }
+            }
+            if !read_line(&mut stream, &mut buf).await?.is_empty() {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+        },
+    }
+}
+
+/// the next CRLF-terminated line of `buf`, reading more as needed
+async fn read_line<S>(stream: &mut S, buf: &mut Vec<u8>) -> io::Result<String>
+where
+    S: AsyncRead + Unpin,
+{
+    loop {
+        if let Some(i) = buf.windows(2).position(|w| w == b"\r\n") {
+            let line = String::from_utf8_lossy(&buf[..i]).into_owned();
+            buf.drain(..i + 2);
+            return Ok(line);
+        }
+        if buf.len() > MAX_HEAD_BYTES || read_more(stream, buf).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+}
+
+async fn read_more<S>(stream: &mut S, buf: &mut Vec<u8>) -> io::Result<usize>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut chunk = [0u8; 16 * 1024];
+    let n = stream.read(&mut chunk).await?;
+    buf.extend_from_slice(&chunk[..n]);
+    Ok(n)
+}
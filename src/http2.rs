@@ -0,0 +1,346 @@
+use crate::models::http::{Buffering, HTTPHeaderType, HTTPRequest, HTTPResponse, HTTPStatus, HTTPVersion};
+use crate::config::ServerConfig;
+use crate::middleware::Pipeline;
+use crate::models::connection::ConnectionInfo;
+use crate::models::early_hints::Event;
+use crate::models::error::HTTPError;
+use bytes::Bytes;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+/// client connection preface (RFC 9113 3.4)
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// interim response sent before switching an `Upgrade: h2c` connection
+pub const SWITCHING_PROTOCOLS: &[u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+
+const FRAME_HEADER_LEN: usize = 9;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+
+/// Serve a single HTTP/2 connection. Each stream becomes its own task and is
+/// dispatched through the same `Pipeline` as HTTP/1.1 requests.
+pub async fn serve_connection<S>(io: S, pipeline: Arc<Pipeline>) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    serve_connection_with_info(io, pipeline, ConnectionInfo::default(), Default::default()).await
+}
+
+/// like `serve_connection`, attaching `info` to every request and applying
+/// the request limits in `config`
+pub async fn serve_connection_with_info<S>(
+    io: S,
+    pipeline: Arc<Pipeline>,
+    info: ConnectionInfo,
+    config: Arc<ServerConfig>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let info = ConnectionInfo {
+        version: HTTPVersion::HTTP2,
+        ..info
+    };
+    let mut connection = h2::server::handshake(io).await.map_err(h2_to_io)?;
+    while let Some(result) = connection.accept().await {
+        let (request, respond) = result.map_err(h2_to_io)?;
+        let pipeline = Arc::clone(&pipeline);
+        let info = info.clone();
+        let config = Arc::clone(&config);
+        tokio::spawn(async move {
+            if let Err(e) = handle_stream(request, respond, pipeline, info, &config).await {
+                crate::logging::log!(DEBUG, "h2 stream failed", error = e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// True for a plaintext request asking to switch to h2c (RFC 7540 3.2).
+/// Requests carrying a body are never upgraded, they are answered over HTTP/1.1.
+pub fn is_h2c_upgrade(request: &HTTPRequest) -> bool {
+    let has_token = |header: &HTTPHeaderType, token: &str| {
+        request.headers.get(header).is_some_and(|value| {
+            value
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        })
+    };
+    let has_settings = request
+        .headers
+        .keys()
+        .any(|key| key.to_string().eq_ignore_ascii_case("HTTP2-Settings"));
+    request.body.is_none()
+        && has_settings
+        && has_token(&HTTPHeaderType::Upgrade, "h2c")
+        && has_token(&HTTPHeaderType::Connection, "upgrade")
+}
+
+/// Continue an `Upgrade: h2c` connection after `SWITCHING_PROTOCOLS` was sent.
+///
+/// The upgrade request implicitly becomes stream 1, which the h2 crate has no
+/// notion of. We replay it to h2 as a HEADERS frame on stream 1, spliced in
+/// right after the client's preface, so it is answered like any other stream.
+pub async fn serve_upgraded<S>(
+    mut io: S,
+    request: &HTTPRequest,
+    pipeline: Arc<Pipeline>,
+    info: ConnectionInfo,
+    config: Arc<ServerConfig>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // client preface: magic followed by a SETTINGS frame
+    let mut buffered = Vec::new();
+    let settings_end = loop {
+        if buffered.len() >= PREFACE.len() + FRAME_HEADER_LEN {
+            let header = &buffered[PREFACE.len()..];
+            let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+            let end = PREFACE.len() + FRAME_HEADER_LEN + length;
+            if buffered.len() >= end {
+                break end;
+            }
+        }
+        let mut chunk = [0; 4096];
+        let n = io.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buffered.extend_from_slice(&chunk[..n]);
+    };
+    if !buffered.starts_with(PREFACE) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "missing HTTP/2 preface after h2c upgrade",
+        ));
+    }
+
+    let headers = upgrade_headers_frame(request).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "h2c upgrade request too large")
+    })?;
+    let mut replay = buffered[..settings_end].to_vec();
+    replay.extend_from_slice(&headers);
+    replay.extend_from_slice(&buffered[settings_end..]);
+    serve_connection_with_info(crate::models::upgrade::Rewind::new(replay, io), pipeline, info, config).await
+}
+
+/// HEADERS frame (stream 1, END_STREAM | END_HEADERS) for the upgrade request.
+/// Fields are HPACK literals without indexing so the decoder table is untouched.
+fn upgrade_headers_frame(request: &HTTPRequest) -> Option<Vec<u8>> {
+    let authority = request
+        .headers
+        .get(&HTTPHeaderType::Host)
+        .cloned()
+        .unwrap_or_default();
+    let mut block = Vec::new();
+    hpack_literal(&mut block, ":method", &request.method.to_string());
+    hpack_literal(&mut block, ":scheme", "http");
+    hpack_literal(&mut block, ":path", &request.url);
+    hpack_literal(&mut block, ":authority", &authority);
+    for (key, value) in &request.headers {
+        let name = key.to_string().to_ascii_lowercase();
+        if is_connection_specific(key)
+            || *key == HTTPHeaderType::Host
+            || *key == HTTPHeaderType::TE
+            || name == "http2-settings"
+        {
+            continue;
+        }
+        hpack_literal(&mut block, &name, value);
+    }
+    if block.len() > DEFAULT_MAX_FRAME_SIZE {
+        return None;
+    }
+
+    let length = (block.len() as u32).to_be_bytes();
+    let mut frame = vec![length[1], length[2], length[3], 0x1, 0x1 | 0x4, 0, 0, 0, 1];
+    frame.extend_from_slice(&block);
+    Some(frame)
+}
+
+fn hpack_literal(block: &mut Vec<u8>, name: &str, value: &str) {
+    block.push(0x00);
+    hpack_string(block, name.as_bytes());
+    hpack_string(block, value.as_bytes());
+}
+
+/// string literal without huffman coding, 7-bit prefix length (RFC 7541 5.1, 5.2)
+fn hpack_string(block: &mut Vec<u8>, s: &[u8]) {
+    let mut length = s.len();
+    if length < 127 {
+        block.push(length as u8);
+    } else {
+        block.push(127);
+        length -= 127;
+        while length >= 128 {
+            block.push((length % 128) as u8 | 0x80);
+            length /= 128;
+        }
+        block.push(length as u8);
+    }
+    block.extend_from_slice(s);
+}
+
+async fn handle_stream(
+    request: http::Request<h2::RecvStream>,
+    mut respond: h2::server::SendResponse<Bytes>,
+    pipeline: Arc<Pipeline>,
+    info: ConnectionInfo,
+    config: &ServerConfig,
+) -> Result<(), h2::Error> {
+    let (parts, mut body) = request.into_parts();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        data.extend_from_slice(&chunk);
+    }
+
+    let res = match to_http_request(parts, &data) {
+        Ok(mut req) => match decode_body(&mut req, &data, config) {
+            Ok(()) => {
+                req.connection = Some(info);
+                let (hints, mut hints_rx) = crate::models::early_hints::EarlyHints::channel();
+                req.extensions.insert(hints);
+                let cancellation = crate::models::cancellation::CancellationToken::new();
+                req.extensions.insert(cancellation.clone());
+                let mut dispatch = std::pin::pin!(pipeline.dispatch(req));
+                loop {
+                    // the client resets the stream, or the connection goes, when it gives up
+                    let reset = |cx: &mut std::task::Context<'_>| respond.poll_reset(cx).map(|_| ());
+                    match crate::models::early_hints::next(dispatch.as_mut(), &mut hints_rx, reset).await {
+                        Event::Response(res) => {
+                            // sent just before the handler returned
+                            while let Ok(links) = hints_rx.try_recv() {
+                                respond.send_informational(early_hints_head(&links))?;
+                            }
+                            break res;
+                        }
+                        Event::Hints(links) => respond.send_informational(early_hints_head(&links))?,
+                        Event::Disconnected => {
+                            cancellation.cancel();
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            Err(status) => pipeline.router().render_error(&HTTPRequest::default(), &HTTPError::client(status, "")),
+        },
+        Err(e) => pipeline.router().render_error(&HTTPRequest::default(), &HTTPError::client(HTTPStatus::NotImplemented, &e)),
+    };
+    send_response(res, respond, &pipeline).await
+}
+
+/// a `103 Early Hints` with a `Link` header per link
+fn early_hints_head(links: &[String]) -> http::Response<()> {
+    let mut head = http::Response::new(());
+    *head.status_mut() = http::StatusCode::EARLY_HINTS;
+    for link in links.iter().filter_map(|link| http::HeaderValue::from_str(link).ok()) {
+        head.headers_mut().append(http::header::LINK, link);
+    }
+    head
+}
+
+/// undo the request's `Content-Encoding`, with the `compression` feature
+#[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+fn decode_body(req: &mut HTTPRequest, body: &[u8], config: &ServerConfig) -> Result<(), HTTPStatus> {
+    #[cfg(feature = "compression")]
+    crate::compression::decode_request_body(
+        req,
+        body,
+        config
+            .max_decompressed_body_bytes
+            .unwrap_or(crate::compression::DEFAULT_MAX_DECOMPRESSED_BYTES),
+    )?;
+    Ok(())
+}
+
+/// h2 request head + collected body to HTTPRequest
+fn to_http_request(parts: http::request::Parts, body: &[u8]) -> Result<HTTPRequest, String> {
+    let mut req = crate::models::interop::request_from_parts(parts)?;
+    req.set_body_bytes(body.to_vec());
+    Ok(req)
+}
+
+/// Send `res` on its stream. With `metrics`, a streamed body is a
+/// long-lived connection of `pipeline`'s stats until it ends or is drained.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+async fn send_response(
+    mut res: HTTPResponse,
+    mut respond: h2::server::SendResponse<Bytes>,
+    pipeline: &Pipeline,
+) -> Result<(), h2::Error> {
+    if res.buffering() == Buffering::Buffered && res.content_length().is_none() && !res.body_bytes().is_empty() {
+        res.set_content_length(res.body_bytes().len() as u64);
+    }
+    let mut head = http::Response::new(());
+    *head.status_mut() =
+        http::StatusCode::from_u16(res.status.code()).unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
+    for (key, value) in &res.headers {
+        if is_connection_specific(key) {
+            continue;
+        }
+        let name = http::HeaderName::from_bytes(key.to_string().as_bytes());
+        let value = http::HeaderValue::from_str(value);
+        if let (Ok(name), Ok(value)) = (name, value) {
+            head.headers_mut().append(name, value);
+        }
+    }
+
+    let body = res.body_bytes().to_vec();
+    let chunks = res.body_stream.take();
+    let mut stream = respond.send_response(head, body.is_empty() && chunks.is_none())?;
+    if !body.is_empty() {
+        stream.send_data(Bytes::from(body), chunks.is_none())?;
+    }
+    if let Some(mut chunks) = chunks {
+        #[cfg(feature = "metrics")]
+        {
+            let handle = pipeline.stats().register(crate::stats::LongLivedKind::of(&res.headers));
+            let sent = send_chunks(&mut stream, &mut chunks, || handle.touch());
+            if let Some(sent) = handle.until_closed(sent).await {
+                return sent;
+            }
+            // drained mid-body
+            stream.send_reset(h2::Reason::CANCEL);
+            return Ok(());
+        }
+        #[cfg(not(feature = "metrics"))]
+        return send_chunks(&mut stream, &mut chunks, || ()).await;
+    }
+    Ok(())
+}
+
+/// send each chunk as it comes, calling `sent` after each, then end the stream
+async fn send_chunks(
+    stream: &mut h2::SendStream<Bytes>,
+    chunks: &mut crate::models::body::Chunks,
+    sent: impl Fn(),
+) -> Result<(), h2::Error> {
+    while let Some(chunk) = crate::models::body::next_chunk(chunks).await {
+        stream.send_data(Bytes::from(chunk), false)?;
+        sent();
+    }
+    stream.send_data(Bytes::new(), true)
+}
+
+/// headers that are forbidden in HTTP/2 (RFC 9113 8.2.2)
+fn is_connection_specific(header: &HTTPHeaderType) -> bool {
+    matches!(
+        header,
+        HTTPHeaderType::Connection
+            | HTTPHeaderType::KeepAlive
+            | HTTPHeaderType::TransferEncoding
+            | HTTPHeaderType::Upgrade
+    )
+}
+
+fn h2_to_io(e: h2::Error) -> std::io::Error {
+    if e.is_io() {
+        e.into_io().unwrap()
+    } else {
+        std::io::Error::other(e)
+    }
+}
@@ -1,63 +1,1076 @@
-use crate::models::http::HTTPResponse;
+use crate::config::ServerConfig;
+use crate::hooks::Hooks;
+use crate::logging::{log, Maybe};
+use crate::middleware::BoxFuture;
+use crate::middleware::canonical_host::CanonicalHost;
+use crate::middleware::https_redirect::HttpsRedirect;
+use crate::middleware::real_ip::RealIp;
+use crate::middleware::request_log::RequestLog;
+use crate::middleware::{Middleware, Pipeline};
+use crate::models::cancellation::CancellationToken;
+use crate::models::connection::ConnectionInfo;
+use crate::models::early_hints::Event;
+use crate::models::http::{HTTPRequest, HTTPResponse, HTTPStatus};
+use crate::models::parse::{body_length, parse_head, BodyLength, ChunkedDecoder, HeadScanner};
+use crate::reload::ConfigReloader;
 use crate::router;
-use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use crate::scheduler::{Job, Scheduler};
+use crate::tasks::TaskSet;
+#[cfg(feature = "metrics")]
+use crate::stats::{LongLivedKind, ServerStats};
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Semaphore;
+use crate::transport::{Stream, Transport};
+#[cfg(unix)]
+use crate::transport::UnixTransport;
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 pub struct HTTPServer {
-    port: i32,
+    listeners: Vec<ListenerSpec>,
     router: Arc<router::Router>,
+    reloader: ConfigReloader,
+    #[cfg(unix)]
+    reload_on_sighup: bool,
+    #[cfg(feature = "metrics")]
+    stats: Arc<ServerStats>,
+    hooks: Hooks,
+    shutdown: CancellationToken,
+    tasks: TaskSet,
+    scheduler: Scheduler,
     _context: std::collections::HashMap<String, String>,
 }
 
+/// where one listener accepts connections
+enum Bind {
+    Addr(String),
+    Std(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+    Transport(Arc<dyn Transport>),
+}
+
+struct ListenerSpec {
+    bind: Bind,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    /// answer everything with a redirect to HTTPS instead of routing
+    https_redirect: Option<HttpsRedirect>,
+}
+
+impl ListenerSpec {
+    fn new(bind: Bind) -> Self {
+        ListenerSpec {
+            bind,
+            #[cfg(feature = "tls")]
+            tls: None,
+            https_redirect: None,
+        }
+    }
+
+    /// the listener, or one per acceptor for an address, see
+    /// `ServerConfig::acceptors`
+    async fn bind(&self, config: &ServerConfig) -> std::io::Result<Vec<Arc<dyn Transport>>> {
+        let acceptors = config.acceptors.unwrap_or(1).max(1);
+        let listener = match &self.bind {
+            Bind::Addr(addr) => return crate::transport::bind(addr, config.socket, acceptors).await,
+            Bind::Std(listener) => crate::transport::from_std(listener, config.socket)?,
+            #[cfg(unix)]
+            Bind::Unix(path) => {
+                use std::os::unix::fs::FileTypeExt;
+                // clear a stale socket left by a previous run, but never a regular file
+                if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                    std::fs::remove_file(path)?;
+                }
+                let listener = tokio::net::UnixListener::bind(path)?;
+                Arc::new(UnixTransport(listener, path.clone()))
+            }
+            Bind::Transport(transport) => Arc::clone(transport),
+        };
+        Ok(vec![listener])
+    }
+}
+
 impl HTTPServer {
     pub fn new(
         port: i32,
         router: router::Router,
         context: std::collections::HashMap<String, String>,
+    ) -> Self {
+        Self::with_listener(Bind::Addr(format!("127.0.0.1:{}", port)), router, context)
+    }
+
+    fn with_listener(
+        bind: Bind,
+        router: router::Router,
+        context: std::collections::HashMap<String, String>,
     ) -> Self {
         Self {
-            port,
+            listeners: vec![ListenerSpec::new(bind)],
             router: Arc::new(router),
+            reloader: ConfigReloader::new(ServerConfig::default()),
+            #[cfg(unix)]
+            reload_on_sighup: false,
+            #[cfg(feature = "metrics")]
+            stats: Arc::new(ServerStats::default()),
+            hooks: Hooks::default(),
+            shutdown: CancellationToken::new(),
+            tasks: TaskSet::new(),
+            scheduler: Scheduler::new(),
             _context: context,
         }
     }
 
+    /// serve on an already bound socket, e.g. one inherited from a process
+    /// manager for zero-downtime restarts
+    pub fn from_listener(
+        listener: std::net::TcpListener,
+        router: router::Router,
+        context: std::collections::HashMap<String, String>,
+    ) -> std::io::Result<Self> {
+        Ok(Self::with_listener(Bind::Std(listener), router, context))
+    }
+
+    /// serve the connections `transport` accepts, e.g. streams handed over
+    /// by a WASM host
+    pub fn from_transport(
+        transport: impl Transport,
+        router: router::Router,
+        context: std::collections::HashMap<String, String>,
+    ) -> Self {
+        Self::with_listener(Bind::Transport(Arc::new(transport)), router, context)
+    }
+
+    /// Use the socket passed through socket activation (`LISTEN_FDS`) when
+    /// there is one, otherwise bind `port` as usual.
+    pub fn from_env_or_port(
+        port: i32,
+        router: router::Router,
+        context: std::collections::HashMap<String, String>,
+    ) -> std::io::Result<Self> {
+        match crate::listener::activated_listeners()?.into_iter().next() {
+            Some(listener) => Self::from_listener(listener, router, context),
+            None => Ok(Self::new(port, router, context)),
+        }
+    }
+
+    /// also accept plain HTTP on `addr` (e.g. `0.0.0.0:8080`), driving the same router
+    pub fn add_listener(mut self, addr: &str) -> Self {
+        self.listeners.push(ListenerSpec::new(Bind::Addr(addr.to_string())));
+        self
+    }
+
+    /// also accept HTTPS on `addr` with its own TLS configuration
+    #[cfg(feature = "tls")]
+    pub fn add_tls_listener(mut self, addr: &str, tls: TlsConfig) -> Self {
+        let mut spec = ListenerSpec::new(Bind::Addr(addr.to_string()));
+        spec.tls = Some(tls);
+        self.listeners.push(spec);
+        self
+    }
+
+    /// also accept on a Unix domain socket at `path`
+    #[cfg(unix)]
+    pub fn add_unix_listener(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.listeners.push(ListenerSpec::new(Bind::Unix(path.into())));
+        self
+    }
+
+    /// also accept the connections `transport` hands over
+    pub fn add_transport(mut self, transport: impl Transport) -> Self {
+        self.listeners.push(ListenerSpec::new(Bind::Transport(Arc::new(transport))));
+        self
+    }
+
+    /// Companion plaintext listener on `addr` that 301-redirects every request
+    /// to the `https://` equivalent on `https_port`, keeping host, path and query
+    pub fn add_https_redirect_listener(mut self, addr: &str, https_port: u16) -> Self {
+        let mut spec = ListenerSpec::new(Bind::Addr(addr.to_string()));
+        spec.https_redirect = Some(HttpsRedirect::new(https_port));
+        self.listeners.push(spec);
+        self
+    }
+
+    /// serve the primary listener over TLS. Clients negotiating `h2` via ALPN
+    /// get HTTP/2, everyone else falls back to HTTP/1.1.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.listeners[0].tls = Some(tls);
+        self
+    }
+
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        if let (Some(addr), Bind::Addr(_)) = (&config.bind, &self.listeners[0].bind) {
+            self.listeners[0].bind = Bind::Addr(addr.clone());
+        }
+        self.reloader.replace(config);
+        self
+    }
+
+    /// Use the settings in the TOML file at `path`, with the environment
+    /// on top, see `ServerConfig::from_file` and `merge_env`. Reloads read
+    /// the file again.
+    #[cfg(feature = "toml")]
+    pub fn with_config_file(self, path: impl Into<std::path::PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let config = ServerConfig::from_file(&path)?.merge_env()?;
+        let server = self.with_config(config);
+        server.reloader.set_file(path);
+        Ok(server)
+    }
+
+    /// a handle for changing the config while the server runs
+    pub fn reloader(&self) -> ConfigReloader {
+        self.reloader.clone()
+    }
+
+    /// Run `hook` with the config at start and after every reload, e.g. to
+    /// rebind proxies to the `upstreams` it names. Failures stop `start`
+    /// and are reported by reloads.
+    pub fn on_reload<F>(self, hook: F) -> Self
+    where
+        F: Fn(&ServerConfig) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.reloader.on_reload(Arc::new(hook));
+        self
+    }
+
+    /// reload the config when the process receives SIGHUP
+    #[cfg(unix)]
+    pub fn reload_on_sighup(mut self) -> Self {
+        self.reload_on_sighup = true;
+        self
+    }
+
+    /// live connection and request counters, including long-lived connections
+    /// that can be drained with `ServerStats::drain`
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> Arc<ServerStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// run `hook` once every listener is bound, before the first
+    /// connection is accepted, e.g. to warm caches
+    pub fn on_start<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.on_start(hook);
+        self
+    }
+
+    /// run `hook` on every request before it is routed, e.g.
+    /// `.on_request(|req| Box::pin(async move { count(req.path()) }))`
+    pub fn on_request<F>(mut self, hook: F) -> Self
+    where
+        F: for<'a> Fn(&'a HTTPRequest) -> BoxFuture<'a, ()> + Send + Sync + 'static,
+    {
+        self.hooks.on_request(hook);
+        self
+    }
+
+    /// run `hook` on every response before it is sent, with the head of
+    /// its request: method, URL, headers and connection
+    pub fn on_response<F>(mut self, hook: F) -> Self
+    where
+        F: for<'a> Fn(&'a HTTPRequest, &'a HTTPResponse) -> BoxFuture<'a, ()> + Send + Sync + 'static,
+    {
+        self.hooks.on_response(hook);
+        self
+    }
+
+    /// like `on_response`, for 5xx responses only: handler errors and
+    /// panics, timeouts, failed upstreams
+    pub fn on_error<F>(mut self, hook: F) -> Self
+    where
+        F: for<'a> Fn(&'a HTTPRequest, &'a HTTPResponse) -> BoxFuture<'a, ()> + Send + Sync + 'static,
+    {
+        self.hooks.on_error(hook);
+        self
+    }
+
+    /// run `hook` when `shutdown` is called, after the listeners have
+    /// stopped accepting, e.g. to flush state
+    pub fn on_shutdown<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.on_shutdown(hook);
+        self
+    }
+
+    /// Run `future` alongside the server until shutdown, see `TaskSet`.
+    /// Needs a running tokio runtime.
+    pub fn spawn_background<F>(&self, future: F) -> tokio::task::AbortHandle
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(future)
+    }
+
+    /// run `job` as a background task from `start` on, see `Scheduler`
+    pub fn schedule(mut self, job: Job) -> Self {
+        self.scheduler = self.scheduler.job(job);
+        self
+    }
+
+    /// the tasks started with `spawn_background` and scheduled jobs
+    pub fn background_tasks(&self) -> TaskSet {
+        self.tasks.clone()
+    }
+
+    /// Stop accepting connections, stop the background tasks, run the
+    /// `on_shutdown` hooks and return from `start`. Connections already
+    /// accepted are left to finish.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// a token whose `cancel` does what `shutdown` does, for when the
+    /// server has been moved into its task
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Bind every listener and serve until one of them fails or `shutdown`
+    /// is called. Fails right away if routes conflict, see
+    /// `Router::finalize`.
     pub async fn start(&self) -> std::io::Result<()> {
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", self.port)).await?;
-        println!("Server running on http://127.0.0.1:{}", self.port);
-        loop {
-            let (socket, addr) = listener.accept().await?;
-
-            let router = Arc::clone(&self.router);
-            tokio::spawn(async move {
-                if let Err(e) = handle_connection(socket, router).await {
-                    eprintln!("{}: {}", addr, e);
+        self.router
+            .finalize()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let config = self.reloader.current();
+        self.reloader
+            .run_hooks(&config)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let mut listeners = Vec::new();
+        for spec in &self.listeners {
+            let serving = Serving {
+                router: Arc::clone(&self.router),
+                hooks: self.hooks.clone(),
+                https_redirect: spec.https_redirect.clone(),
+                #[cfg(feature = "metrics")]
+                stats: Arc::clone(&self.stats),
+                #[cfg(feature = "tls")]
+                tls: spec.tls.clone(),
+                #[cfg(feature = "tls")]
+                primary: std::ptr::eq(spec, &self.listeners[0]),
+            };
+            let served = serving.build(&config)?;
+            listeners.push((serving, served, spec.bind(&config).await?));
+        }
+        self.hooks.started().await;
+        self.scheduler.start(&self.tasks);
+        #[cfg(unix)]
+        if self.reload_on_sighup {
+            self.spawn_sighup_reloads()?;
+        }
+        let mut accept_loops = tokio::task::JoinSet::new();
+        let limit = config.max_connections.map(|max| Arc::new(Semaphore::new(max)));
+        for (serving, served, acceptors) in listeners {
+            log!(INFO, "server running", scheme = served.scheme(), address = acceptors[0].address()?);
+            let served = Arc::new(RwLock::new(served));
+            let slot = Arc::clone(&served);
+            self.reloader.on_apply(Box::new(move |config| match serving.build(config) {
+                Ok(next) => *slot.write().unwrap() = next,
+                Err(e) => log!(WARN, "keeping the previous config", error = e),
+            }));
+            for listener in acceptors {
+                accept_loops.spawn(accept_loop(listener, Arc::clone(&served), limit.clone()));
+            }
+        }
+        let serve = async {
+            while let Some(result) = accept_loops.join_next().await {
+                result.map_err(std::io::Error::other)??;
+            }
+            Ok(())
+        };
+        match self.shutdown.run_until_cancelled(serve).await {
+            Some(result) => result,
+            None => {
+                accept_loops.shutdown().await;
+                self.tasks.shutdown(self.reloader.current().background_shutdown_timeout).await;
+                self.hooks.shutting_down().await;
+                Ok(())
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn spawn_sighup_reloads(&self) -> std::io::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangups = signal(SignalKind::hangup())?;
+        let reloader = self.reloader.clone();
+        let stop = self.tasks.cancellation();
+        self.tasks.spawn(async move {
+            while stop.run_until_cancelled(hangups.recv()).await.flatten().is_some() {
+                match reloader.reload() {
+                    Ok(()) => log!(INFO, "configuration reloaded"),
+                    Err(e) => log!(ERROR, "configuration reload failed", error = e),
                 }
-            });
+            }
+        });
+        Ok(())
+    }
+}
+
+/// What a listener needs to build what it serves with from a config
+struct Serving {
+    router: Arc<router::Router>,
+    hooks: Hooks,
+    https_redirect: Option<HttpsRedirect>,
+    #[cfg(feature = "metrics")]
+    stats: Arc<ServerStats>,
+    /// its own certificate, from `with_tls` or `add_tls_listener`
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    /// whether the config's certificate is for this listener
+    #[cfg(feature = "tls")]
+    primary: bool,
+}
+
+/// What a listener serves new connections with, replaced on reload
+#[derive(Clone)]
+struct Served {
+    pipeline: Arc<Pipeline>,
+    config: Arc<ServerConfig>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+}
+
+impl Serving {
+    fn build(&self, config: &Arc<ServerConfig>) -> std::io::Result<Served> {
+        #[cfg(feature = "tls")]
+        let tls = match &self.tls {
+            Some(tls) => Some(tls.clone()),
+            None if self.primary => tls_from_config(config)?,
+            None => None,
+        };
+        #[cfg(feature = "tls")]
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        #[cfg(not(feature = "tls"))]
+        let scheme = "http";
+        let pipeline = match &self.https_redirect {
+            Some(redirect) => Pipeline::with_layers(
+                Arc::new(router::Router::new()),
+                vec![Arc::new(redirect.clone())],
+            ),
+            None => Pipeline::with_layers(
+                Arc::clone(&self.router),
+                config_layers(config, &self.hooks, scheme),
+            ),
+        };
+        #[cfg(feature = "metrics")]
+        let pipeline = pipeline.with_stats(Arc::clone(&self.stats));
+        Ok(Served {
+            pipeline: Arc::new(pipeline),
+            config: Arc::clone(config),
+            #[cfg(feature = "tls")]
+            tls,
+        })
+    }
+}
+
+impl Served {
+    fn scheme(&self) -> &'static str {
+        #[cfg(feature = "tls")]
+        if self.tls.is_some() {
+            return "https";
         }
+        "http"
     }
 }
 
-async fn handle_connection(
-    mut stream: TcpStream,
-    router: Arc<crate::router::Router>,
+/// the certificate named by `tls_cert` and `tls_key`, if any
+#[cfg(feature = "tls")]
+pub(crate) fn tls_from_config(config: &ServerConfig) -> std::io::Result<Option<TlsConfig>> {
+    match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Ok(Some(TlsConfig::from_pem_files(
+            &cert.to_string_lossy(),
+            &key.to_string_lossy(),
+        )?)),
+        (None, None) => Ok(None),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "tls_cert and tls_key must be set together",
+        )),
+    }
+}
+
+/// layers implied by the config, run before the router's own
+fn config_layers(config: &ServerConfig, hooks: &Hooks, scheme: &str) -> Vec<Arc<dyn Middleware>> {
+    let mut layers: Vec<Arc<dyn Middleware>> = Vec::new();
+    if !config.trusted_proxies.is_empty() {
+        layers.push(Arc::new(RealIp::new(config.trusted_proxies.clone())));
+    }
+    // after RealIp, so hooks see the client's address
+    if hooks.per_request() {
+        layers.push(Arc::new(hooks.clone()));
+    }
+    if config.request_log {
+        layers.push(Arc::new(RequestLog));
+    }
+    if let Some(host) = &config.canonical_host {
+        layers.push(Arc::new(CanonicalHost::new(host, scheme)));
+    }
+    #[cfg(feature = "compression")]
+    if let Some(compression) = &config.compression {
+        layers.push(Arc::new(compression.clone()));
+    }
+    layers
+}
+
+async fn accept_loop(
+    listener: Arc<dyn Transport>,
+    served: Arc<RwLock<Served>>,
+    limit: Option<Arc<Semaphore>>,
 ) -> std::io::Result<()> {
+    loop {
+        let shedding = served.read().unwrap().config.load_shed_retry_after.is_some();
+        let permit = match &limit {
+            Some(limit) if !shedding => {
+                Some(Arc::clone(limit).acquire_owned().await.map_err(std::io::Error::other)?)
+            }
+            _ => None,
+        };
+        let (socket, info) = listener.accept().await?;
+        let addr = match info.peer_addr {
+            Some(peer) => peer.to_string(),
+            None => listener.address()?,
+        };
+        log!(DEBUG, "connection accepted", peer = addr, local = Maybe(&info.local_addr));
+        // what this connection keeps, whatever reloads come
+        let Served {
+            pipeline,
+            config,
+            #[cfg(feature = "tls")]
+            tls,
+        } = served.read().unwrap().clone();
+        let permit = match (&limit, config.load_shed_retry_after) {
+            (Some(limit), Some(retry_after)) => match Arc::clone(limit).try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    #[cfg(feature = "tls")]
+                    let plaintext = tls.is_none();
+                    #[cfg(not(feature = "tls"))]
+                    let plaintext = true;
+                    if plaintext {
+                        tokio::spawn(shed(socket, retry_after));
+                    }
+                    continue;
+                }
+            },
+            _ => permit,
+        };
+
+        #[cfg(feature = "metrics")]
+        let connection = pipeline.stats().connection();
+        tokio::spawn(async move {
+            let _permit = permit;
+            #[cfg(feature = "metrics")]
+            let _connection = connection;
+            #[cfg(feature = "tls")]
+            let result = match tls {
+                Some(tls) => serve_tls(socket, info, tls, pipeline, config).await,
+                None => handle_connection(socket, info, pipeline, config).await,
+            };
+            #[cfg(not(feature = "tls"))]
+            let result = handle_connection(socket, info, pipeline, config).await;
+            if let Err(e) = result {
+                log!(DEBUG, "connection failed", peer = addr, error = e);
+            }
+        });
+    }
+}
+
+/// turn a connection away with 503 while the server is saturated
+async fn shed(mut socket: Box<dyn Stream>, retry_after: u64) {
+    // read (part of) the request first so closing doesn't reset the connection
+    // before the client has seen the response
     let mut buffer = [0; 1024];
+    let _ = tokio::time::timeout(std::time::Duration::from_secs(1), socket.read(&mut buffer)).await;
+    let status = crate::models::http::HTTPStatus::ServiceUnavailable;
+    let mut res = crate::models::http::HTTPResponse::error(status.clone(), &status.default_body());
+    res.set_header(
+        crate::models::http::HTTPHeaderType::RetryAfter,
+        retry_after.to_string(),
+    );
+    res.set_header(crate::models::http::HTTPHeaderType::Connection, "close");
+    let _ = socket.write_all(&res.to_bytes()).await;
+    let _ = socket.shutdown().await;
+}
+
+#[cfg(feature = "tls")]
+async fn serve_tls(
+    socket: Box<dyn Stream>,
+    mut info: ConnectionInfo,
+    tls: TlsConfig,
+    pipeline: Arc<Pipeline>,
+    config: Arc<ServerConfig>,
+) -> std::io::Result<()> {
+    let handshake = tls.acceptor().accept(socket);
+    let stream = match with_deadline(deadline(config.header_read_timeout), handshake).await {
+        Some(stream) => stream?,
+        None => return Ok(()),
+    };
+    info.tls = Some(crate::tls::session_info(stream.get_ref().1));
+    #[cfg(feature = "hyper")]
+    if config.hyper {
+        return crate::hyper_server::serve_connection_with_info(stream, pipeline, info, config).await;
+    }
+    #[cfg(feature = "http2")]
+    if stream.get_ref().1.alpn_protocol() == Some(crate::tls::ALPN_H2) {
+        return crate::http2::serve_connection_with_info(stream, pipeline, info, config).await;
+    }
+    handle_connection(stream, info, pipeline, config).await
+}
+
+/// Serve a single plaintext connection from any byte stream, dispatching
+/// requests through `pipeline`, e.g. a stream from a WASM host where there
+/// is no listener to accept from.
+pub async fn serve_connection<S>(io: S, pipeline: Arc<Pipeline>) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    handle_connection(io, ConnectionInfo::default(), pipeline, Default::default()).await
+}
 
-    let size = stream.read(&mut buffer).await?;
+/// like `serve_connection`, under `config` and with what is known about the
+/// connection, e.g. the peer's address
+pub async fn serve_connection_with_info<S>(
+    io: S,
+    pipeline: Arc<Pipeline>,
+    info: ConnectionInfo,
+    config: Arc<ServerConfig>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    handle_connection(io, info, pipeline, config).await
+}
 
-    let s = std::str::from_utf8(&buffer[..size]).unwrap();
-    let data = crate::models::http::HTTPRequest::new(s.to_string());
+async fn handle_connection<S>(
+    mut stream: S,
+    info: ConnectionInfo,
+    pipeline: Arc<Pipeline>,
+    config: Arc<ServerConfig>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    #[cfg(feature = "hyper")]
+    if config.hyper {
+        return crate::hyper_server::serve_connection_with_info(stream, pipeline, info, config).await;
+    }
+    let reject = |status| rejection(pipeline.router(), status);
+    // the head is split off the buffer, which goes on to receive the body
+    let (head, mut buffer) = match read_head(&mut stream, &config).await? {
+        ReadOutcome::Head(head, rest) => (head, rest),
+        ReadOutcome::Closed => return Ok(()),
+        ReadOutcome::Rejected(status) => return send_response(&mut stream, reject(status), &pipeline, &config).await,
+    };
 
-    let mut res = HTTPResponse::default();
-    let hmm = router.handle(data.method.clone(), &data, &mut res);
-    if let Err(e) = hmm {
-        println!("Error: {}", e);
-        res = HTTPResponse::error(crate::models::http::HTTPStatus::InternalServerError, &e);
-        stream.write_all(res.to_string().as_bytes()).await?;
+    #[cfg(feature = "http2")]
+    if config.h2c && head.starts_with(&crate::http2::PREFACE[..14]) {
+        let io = crate::models::upgrade::Rewind::new([&head[..], &buffer[..]].concat(), stream);
+        return crate::http2::serve_connection_with_info(io, pipeline, info, config).await;
+    }
+
+    // header names and values stay slices of `head` until the request is built
+    let framed = parse_head(&head).and_then(|parsed| Ok((body_length(&parsed)?, parsed)));
+    let (framing, parsed) = match framed {
+        Ok(framed) => framed,
+        Err(e) => {
+            log!(DEBUG, "malformed request", peer = Maybe(&info.peer_addr), error = e);
+            return send_response(&mut stream, reject(HTTPStatus::BadRequest), &pipeline, &config).await;
+        }
+    };
+    // anything left in `buffer` is pipelined or belongs to an upgraded protocol
+    let body = match read_body(&mut stream, &mut buffer, framing, &config).await? {
+        Ok(body) => body,
+        Err(status) => return send_response(&mut stream, reject(status), &pipeline, &config).await,
+    };
+    let mut data = match HTTPRequest::from_head(&parsed, config.preserve_header_case) {
+        Ok(data) => data,
+        Err(e) => {
+            log!(DEBUG, "malformed request", peer = Maybe(&info.peer_addr), error = e);
+            return send_response(&mut stream, reject(HTTPStatus::BadRequest), &pipeline, &config).await;
+        }
+    };
+    data.set_body_bytes(body.to_vec());
+    data.connection = Some(ConnectionInfo {
+        version: data.version.clone(),
+        ..info.clone()
+    });
+
+    #[cfg(feature = "http2")]
+    if config.h2c && crate::http2::is_h2c_upgrade(&data) {
+        write_response(&mut stream, crate::http2::SWITCHING_PROTOCOLS, &config).await?;
+        return crate::http2::serve_upgraded(stream, &data, pipeline, info, config).await;
+    }
+
+    #[cfg(feature = "compression")]
+    {
+        let limit = config
+            .max_decompressed_body_bytes
+            .unwrap_or(crate::compression::DEFAULT_MAX_DECOMPRESSED_BYTES);
+        if let Err(status) = crate::compression::decode_request_body(&mut data, &body, limit) {
+            return send_response(&mut stream, rejection(pipeline.router(), status), &pipeline, &config).await;
+        }
+    }
+
+    // a 2xx to CONNECT turns the connection into a tunnel, like a 101
+    let connect = data.method == crate::models::http::HTTPMethod::CONNECT;
+    let http1_0 = data.version == crate::models::http::HTTPVersion::HTTP1_0;
+    let (hints, mut hints_rx) = crate::models::early_hints::EarlyHints::channel();
+    // HTTP/1.0 clients don't expect interim responses
+    if !http1_0 {
+        data.extensions.insert(hints);
+    }
+    let cancellation = crate::models::cancellation::CancellationToken::new();
+    data.extensions.insert(cancellation.clone());
+    let mut dispatch = std::pin::pin!(pipeline.dispatch(data));
+    let mut budget = EARLY_DATA_LIMIT;
+    let mut res = loop {
+        let disconnected = |cx: &mut std::task::Context<'_>| poll_disconnect(&mut stream, &mut buffer, &mut budget, cx);
+        match crate::models::early_hints::next(dispatch.as_mut(), &mut hints_rx, disconnected).await {
+            Event::Response(res) => {
+                // sent just before the handler returned
+                while let Ok(links) = hints_rx.try_recv() {
+                    write_response(&mut stream, early_hints_head(&links).as_bytes(), &config).await?;
+                }
+                break res;
+            }
+            Event::Hints(links) => write_response(&mut stream, early_hints_head(&links).as_bytes(), &config).await?,
+            // nobody to answer; dropping `dispatch` stops the work
+            Event::Disconnected => {
+                cancellation.cancel();
+                return Ok(());
+            }
+        }
+    };
+    let switched = res.status == crate::models::http::HTTPStatus::SwitchingProtocols
+        || (connect && res.status.is_success());
+    if switched && res.upgrade.is_set() {
+        let upgrade = res.upgrade.clone();
+        // neither carries a body, nor may it announce one
+        write_response(&mut stream, res.head_to_string().as_bytes(), &config).await?;
+        stream.flush().await?;
+        // bytes the client sent right behind the request belong to the new protocol
+        let early = buffer.to_vec();
+        let io = Box::new(crate::models::upgrade::Rewind::new(early, stream));
+        #[cfg(feature = "metrics")]
+        crate::stats::run_upgrade(pipeline.stats(), LongLivedKind::of(&res.headers), &upgrade, io).await;
+        #[cfg(not(feature = "metrics"))]
+        upgrade.run(io).await;
         return Ok(());
     }
-    stream.write_all(res.to_string().as_bytes()).await?;
+    if http1_0 {
+        // no chunked framing: the body ends where the connection does
+        res.version = crate::models::http::HTTPVersion::HTTP1_0;
+        res.headers.remove(&crate::models::http::HTTPHeaderType::TransferEncoding);
+        res.set_header(crate::models::http::HTTPHeaderType::Connection, "close");
+    }
+    send_response(&mut stream, res, &pipeline, &config).await
+}
+
+/// how much a client may send while its request is handled before the
+/// server stops watching for it to disconnect
+const EARLY_DATA_LIMIT: usize = 64 * 1024;
+
+/// Ready once the client has closed the connection. Bytes it sends while
+/// its request is handled are added to `buffer`, where a protocol switch
+/// finds them; `budget` counts down how many more may come before
+/// watching stops.
+fn poll_disconnect<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buffer: &mut BytesMut,
+    budget: &mut usize,
+    cx: &mut std::task::Context<'_>,
+) -> std::task::Poll<()> {
+    let mut scratch = [0; 1024];
+    while *budget > 0 {
+        let mut read = tokio::io::ReadBuf::new(&mut scratch);
+        match std::pin::Pin::new(&mut *stream).poll_read(cx, &mut read) {
+            std::task::Poll::Ready(Ok(())) if read.filled().is_empty() => return std::task::Poll::Ready(()),
+            std::task::Poll::Ready(Ok(())) => {
+                buffer.extend_from_slice(read.filled());
+                *budget = budget.saturating_sub(read.filled().len());
+            }
+            std::task::Poll::Ready(Err(_)) => return std::task::Poll::Ready(()),
+            std::task::Poll::Pending => return std::task::Poll::Pending,
+        }
+    }
+    std::task::Poll::Pending
+}
+
+/// error response for a request refused before it reached the pipeline,
+/// rendered the way `router` renders errors
+pub(crate) fn rejection(router: &router::Router, status: crate::models::http::HTTPStatus) -> crate::models::http::HTTPResponse {
+    let error = crate::models::error::HTTPError::client(status, "");
+    let mut res = router.render_error(&HTTPRequest::default(), &error);
+    res.set_header(crate::models::http::HTTPHeaderType::Connection, "close");
+    res
+}
+
+/// Write `res` according to its buffering policy. With `metrics`, a
+/// streamed body is a long-lived connection of `pipeline`'s stats until it
+/// ends or is drained.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+async fn send_response<S>(
+    stream: &mut S,
+    mut res: crate::models::http::HTTPResponse,
+    pipeline: &Pipeline,
+    config: &ServerConfig,
+) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    match res.buffering() {
+        crate::models::http::Buffering::Buffered => {
+            let bodiless = res.status.is_informational() || res.status == crate::models::http::HTTPStatus::NoContent;
+            if res.content_length().is_none() && !bodiless {
+                res.set_content_length(res.body_bytes().len() as u64);
+            }
+            let body = res.body_bytes();
+            let mut head = Vec::with_capacity(COALESCE_LIMIT.min(256 + body.len()));
+            res.write_head(&mut head);
+            if head.len() + body.len() <= COALESCE_LIMIT {
+                head.extend_from_slice(body);
+                return write_response(stream, &head, config).await;
+            }
+            write_response_parts(stream, &head, body, config).await
+        }
+        crate::models::http::Buffering::Streamed => {
+            res.set_header(crate::models::http::HTTPHeaderType::Connection, "close");
+            write_response(stream, res.head_to_string().as_bytes(), config).await?;
+            stream.flush().await?;
+            write_response(stream, res.body_bytes(), config).await?;
+            if let Some(mut chunks) = res.body_stream.take() {
+                #[cfg(feature = "metrics")]
+                {
+                    let handle = pipeline.stats().register(LongLivedKind::of(&res.headers));
+                    let sent = write_chunks(stream, &mut chunks, config, || handle.touch());
+                    // drained: the connection closes mid-body
+                    return handle.until_closed(sent).await.unwrap_or(Ok(()));
+                }
+                #[cfg(not(feature = "metrics"))]
+                return write_chunks(stream, &mut chunks, config, || ()).await;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// write each chunk as it comes, calling `sent` after each
+async fn write_chunks<S>(
+    stream: &mut S,
+    chunks: &mut crate::models::body::Chunks,
+    config: &ServerConfig,
+    sent: impl Fn(),
+) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    while let Some(chunk) = crate::models::body::next_chunk(chunks).await {
+        write_response(stream, &chunk, config).await?;
+        stream.flush().await?;
+        sent();
+    }
     Ok(())
 }
+
+/// what reading a request head off a connection came to
+enum ReadOutcome {
+    /// the head and the bytes read after it
+    Head(BytesMut, BytesMut),
+    /// closed or idle before a request started
+    Closed,
+    /// a request started but broke the configured timeouts or size limits;
+    /// answered with this status and closed
+    Rejected(crate::models::http::HTTPStatus),
+}
+
+/// how much room each read makes in the buffer
+const READ_CHUNK: usize = 4096;
+
+/// read more of the request straight into `buffer`
+async fn fill<S>(stream: &mut S, buffer: &mut BytesMut) -> std::io::Result<usize>
+where
+    S: AsyncRead + Unpin,
+{
+    buffer.reserve(READ_CHUNK);
+    stream.read_buf(buffer).await
+}
+
+/// Read one request head, enforcing the first byte and header timeouts and
+/// the header size limit of `config`
+async fn read_head<S>(stream: &mut S, config: &ServerConfig) -> std::io::Result<ReadOutcome>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buffer = BytesMut::new();
+    let n = match with_deadline(deadline(config.first_byte_timeout), fill(stream, &mut buffer)).await {
+        Some(n) => n?,
+        None => return Ok(ReadOutcome::Closed),
+    };
+    if n == 0 {
+        return Ok(ReadOutcome::Closed);
+    }
+
+    let head_deadline = deadline(config.header_read_timeout);
+    let headers_too_large = |length: usize| config.max_header_bytes.is_some_and(|max| length > max);
+    let mut scanner = HeadScanner::default();
+    let head_end = loop {
+        if let Some(end) = scanner.scan(&buffer) {
+            if headers_too_large(end) {
+                return Ok(ReadOutcome::Rejected(HTTPStatus::RequestHeaderFieldsTooLarge));
+            }
+            break end;
+        }
+        if headers_too_large(buffer.len()) {
+            return Ok(ReadOutcome::Rejected(HTTPStatus::RequestHeaderFieldsTooLarge));
+        }
+        match with_deadline(head_deadline, fill(stream, &mut buffer)).await {
+            Some(n) => {
+                // the client gave up before the head was complete
+                if n? == 0 {
+                    return Ok(ReadOutcome::Rejected(HTTPStatus::BadRequest));
+                }
+            }
+            None => return Ok(ReadOutcome::Rejected(HTTPStatus::RequestTimeout)),
+        }
+    };
+    let head = buffer.split_to(head_end);
+    Ok(ReadOutcome::Head(head, buffer))
+}
+
+/// Read the body `framing` announces off the front of `buffer`, leaving
+/// what follows it. The status to reject the request with when it can't:
+/// `413` when it's over `config`'s body limit, a chunked body by its
+/// decoded size, `408` when the body timeout ran out first, `400` when the
+/// client stopped sending early or sent malformed chunks.
+async fn read_body<S>(
+    stream: &mut S,
+    buffer: &mut BytesMut,
+    framing: BodyLength,
+    config: &ServerConfig,
+) -> std::io::Result<Result<Bytes, HTTPStatus>>
+where
+    S: AsyncRead + Unpin,
+{
+    let body_deadline = deadline(config.body_read_timeout);
+    let too_large = |length: usize| config.max_body_bytes.is_some_and(|max| length > max);
+    let mut chunks = match framing {
+        BodyLength::Fixed(length) if too_large(length) => return Ok(Err(HTTPStatus::PayloadTooLarge)),
+        BodyLength::Fixed(length) => {
+            while buffer.len() < length {
+                if let Some(status) = read_more(stream, buffer, body_deadline).await? {
+                    return Ok(Err(status));
+                }
+            }
+            return Ok(Ok(buffer.split_to(length).freeze()));
+        }
+        BodyLength::Chunked => ChunkedDecoder::default(),
+    };
+    loop {
+        match chunks.decode(buffer) {
+            Ok(used) => buffer.advance(used),
+            Err(_) => return Ok(Err(HTTPStatus::BadRequest)),
+        }
+        if too_large(chunks.body().len()) {
+            return Ok(Err(HTTPStatus::PayloadTooLarge));
+        }
+        if chunks.is_done() {
+            return Ok(Ok(Bytes::from(chunks.into_body())));
+        }
+        if let Some(status) = read_more(stream, buffer, body_deadline).await? {
+            return Ok(Err(status));
+        }
+    }
+}
+
+/// read more of the body into `buffer`; the status to reject the request
+/// with when the client stopped sending or `body_deadline` passed
+async fn read_more<S>(
+    stream: &mut S,
+    buffer: &mut BytesMut,
+    body_deadline: Option<tokio::time::Instant>,
+) -> std::io::Result<Option<HTTPStatus>>
+where
+    S: AsyncRead + Unpin,
+{
+    Ok(match with_deadline(body_deadline, fill(stream, buffer)).await {
+        Some(n) => (n? == 0).then_some(HTTPStatus::BadRequest),
+        None => Some(HTTPStatus::RequestTimeout),
+    })
+}
+
+/// a `103 Early Hints` with a `Link` header per link
+fn early_hints_head(links: &[String]) -> String {
+    let mut head = String::from("HTTP/1.1 103 Early Hints\r\n");
+    for link in links {
+        head.push_str(&format!("Link: {}\r\n", link));
+    }
+    head.push_str("\r\n");
+    head
+}
+
+/// responses up to this size are copied into one buffer and sent with a
+/// single write; bigger ones go out as head and body without the copy
+const COALESCE_LIMIT: usize = 16 * 1024;
+
+async fn write_response<S>(stream: &mut S, bytes: &[u8], config: &ServerConfig) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    write_response_parts(stream, bytes, &[], config).await
+}
+
+/// Write `head` then `body` with vectored writes, so both usually leave in
+/// one system call. Streams without vectored writes get one at a time.
+async fn write_response_parts<S>(stream: &mut S, head: &[u8], body: &[u8], config: &ServerConfig) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let write = async {
+        let (mut head, mut body) = (head, body);
+        while !head.is_empty() || !body.is_empty() {
+            let slices = [std::io::IoSlice::new(head), std::io::IoSlice::new(body)];
+            let written = stream.write_vectored(&slices).await?;
+            if written == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::WriteZero));
+            }
+            let from_head = written.min(head.len());
+            head = &head[from_head..];
+            body = &body[written - from_head..];
+        }
+        Ok(())
+    };
+    match with_deadline(deadline(config.write_timeout), write).await {
+        Some(result) => result,
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "timed out writing the response",
+        )),
+    }
+}
+
+fn deadline(timeout: Option<std::time::Duration>) -> Option<tokio::time::Instant> {
+    timeout.map(|timeout| tokio::time::Instant::now() + timeout)
+}
+
+/// run `future` to completion or until `deadline`, whichever comes first
+async fn with_deadline<F: std::future::Future>(
+    deadline: Option<tokio::time::Instant>,
+    future: F,
+) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
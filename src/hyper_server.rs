@@ -0,0 +1,208 @@
+use crate::config::ServerConfig;
+use crate::middleware::Pipeline;
+use crate::models::body::Chunks;
+use crate::models::cancellation::CancellationToken;
+use crate::models::connection::ConnectionInfo;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPResponse, HTTPStatus};
+use bytes::Bytes;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Serve a single connection with hyper's HTTP/1 and HTTP/2 server,
+/// dispatching requests through `pipeline` as the built-in code does.
+/// Connections are kept alive between requests. Early hints aren't sent,
+/// `preserve_header_case` has no effect and timeouts other than
+/// `header_read_timeout` and `body_read_timeout` are hyper's own. Upgrades
+/// count as long-lived in `ServerStats`; streamed bodies don't.
+pub async fn serve_connection<S>(io: S, pipeline: Arc<Pipeline>) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    serve_connection_with_info(io, pipeline, ConnectionInfo::default(), Default::default()).await
+}
+
+/// Like `serve_connection`, under `config`. HTTP/2 is spoken over TLS and,
+/// with `h2c`, to plaintext clients with prior knowledge.
+pub(crate) async fn serve_connection_with_info<S>(
+    io: S,
+    pipeline: Arc<Pipeline>,
+    info: ConnectionInfo,
+    config: Arc<ServerConfig>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    if info.tls.is_none() && !config.h2c {
+        builder = builder.http1_only();
+    }
+    let mut http1 = builder.http1();
+    http1.timer(TokioTimer::new()).header_read_timeout(config.header_read_timeout);
+    if let Some(max) = config.max_header_bytes {
+        // hyper won't buffer less than this
+        http1.max_buf_size(max.max(8192));
+    }
+    let mut http2 = builder.http2();
+    http2.timer(TokioTimer::new());
+    if let Some(max) = config.max_header_bytes {
+        http2.max_header_list_size(max.try_into().unwrap_or(u32::MAX));
+    }
+
+    let service = hyper::service::service_fn(move |req| {
+        let (pipeline, info, config) = (Arc::clone(&pipeline), info.clone(), Arc::clone(&config));
+        async move { Ok::<_, Infallible>(respond(req, &pipeline, &info, &config).await) }
+    });
+    builder
+        .serve_connection_with_upgrades(TokioIo::new(io), service)
+        .await
+        .map_err(std::io::Error::other)
+}
+
+async fn respond(
+    mut req: http::Request<Incoming>,
+    pipeline: &Pipeline,
+    info: &ConnectionInfo,
+    config: &ServerConfig,
+) -> http::Response<ResponseBody> {
+    let upgrade = hyper::upgrade::on(&mut req);
+    let (parts, body) = req.into_parts();
+    let collect = Limited::new(body, config.max_body_bytes.unwrap_or(usize::MAX)).collect();
+    let collected = match config.body_read_timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, collect).await {
+            Ok(collected) => collected,
+            Err(_) => return reject(pipeline, HTTPStatus::RequestTimeout),
+        },
+        None => collect.await,
+    };
+    let body = match collected {
+        Ok(body) => body.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => return reject(pipeline, HTTPStatus::PayloadTooLarge),
+        Err(_) => return reject(pipeline, HTTPStatus::BadRequest),
+    };
+    let mut data = match crate::models::interop::request_from_parts(parts) {
+        Ok(data) => data,
+        Err(_) => return reject(pipeline, HTTPStatus::NotImplemented),
+    };
+    data.set_body_bytes(body.to_vec());
+    #[cfg(feature = "compression")]
+    {
+        let limit = config
+            .max_decompressed_body_bytes
+            .unwrap_or(crate::compression::DEFAULT_MAX_DECOMPRESSED_BYTES);
+        if let Err(status) = crate::compression::decode_request_body(&mut data, &body, limit) {
+            return reject(pipeline, status);
+        }
+    }
+    data.connection = Some(ConnectionInfo {
+        version: data.version.clone(),
+        ..info.clone()
+    });
+
+    let connect = data.method == HTTPMethod::CONNECT;
+    // hyper drops this future when the client goes away
+    let mut cancel = CancelOnDrop(Some(CancellationToken::new()));
+    data.extensions.insert(cancel.0.clone().unwrap());
+    let res = pipeline.dispatch(data).await;
+    cancel.0 = None;
+
+    let switched = res.status == HTTPStatus::SwitchingProtocols || (connect && res.status.is_success());
+    if switched && res.upgrade.is_set() {
+        let handler = res.upgrade.clone();
+        #[cfg(feature = "metrics")]
+        let (stats, kind) = (Arc::clone(pipeline.stats()), crate::stats::LongLivedKind::of(&res.headers));
+        tokio::spawn(async move {
+            if let Ok(upgraded) = upgrade.await {
+                let io = Box::new(TokioIo::new(upgraded));
+                #[cfg(feature = "metrics")]
+                crate::stats::run_upgrade(&stats, kind, &handler, io).await;
+                #[cfg(not(feature = "metrics"))]
+                handler.run(io).await;
+            }
+        });
+    }
+    to_response(res)
+}
+
+/// a rejected request, rendered by the router
+fn reject(pipeline: &Pipeline, status: HTTPStatus) -> http::Response<ResponseBody> {
+    to_response(crate::httpserver::rejection(pipeline.router(), status))
+}
+
+fn to_response(res: HTTPResponse) -> http::Response<ResponseBody> {
+    let body = ResponseBody {
+        data: Bytes::copy_from_slice(res.body_bytes()),
+        chunks: res.body_stream.take(),
+    };
+    let mut head = http::Response::new(body);
+    *head.status_mut() = res.status.clone().into();
+    for (header, value) in &res.headers {
+        // hyper frames the body itself
+        if *header == HTTPHeaderType::TransferEncoding {
+            continue;
+        }
+        let name = http::HeaderName::from_bytes(header.to_string().as_bytes());
+        let value = http::HeaderValue::from_str(value);
+        if let (Ok(name), Ok(value)) = (name, value) {
+            head.headers_mut().append(name, value);
+        }
+    }
+    head
+}
+
+/// cancels the request's token unless disarmed first
+struct CancelOnDrop(Option<CancellationToken>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = &self.0 {
+            token.cancel();
+        }
+    }
+}
+
+/// A response body for hyper: the buffered part, then any stream
+pub(crate) struct ResponseBody {
+    data: Bytes,
+    chunks: Option<Chunks>,
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if !self.data.is_empty() {
+            let data = std::mem::take(&mut self.data);
+            return Poll::Ready(Some(Ok(Frame::data(data))));
+        }
+        let Some(chunks) = self.chunks.as_mut() else {
+            return Poll::Ready(None);
+        };
+        match chunks.as_mut().poll_next(cx) {
+            Poll::Ready(Some(chunk)) => Poll::Ready(Some(Ok(Frame::data(Bytes::from(chunk))))),
+            Poll::Ready(None) => {
+                self.chunks = None;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_empty() && self.chunks.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.chunks {
+            Some(_) => SizeHint::new(),
+            None => SizeHint::with_exact(self.data.len() as u64),
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::client::HTTPClient;
+use crate::middleware::Pipeline;
+use crate::models::connection::ConnectionInfo;
+use crate::models::headers::append_header;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use crate::models::url::encode;
+use crate::router::Router;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// An API Gateway REST API (v1) or Application Load Balancer event
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyRequest {
+    pub http_method: String,
+    pub path: String,
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub multi_value_headers: Option<HashMap<String, Vec<String>>>,
+    #[serde(default)]
+    pub query_string_parameters: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub multi_value_query_string_parameters: Option<HashMap<String, Vec<String>>>,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub is_base64_encoded: bool,
+    #[serde(default)]
+    pub request_context: Value,
+}
+
+/// An API Gateway HTTP API (v2) event
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpApiRequest {
+    pub raw_path: String,
+    #[serde(default)]
+    pub raw_query_string: String,
+    #[serde(default)]
+    pub cookies: Option<Vec<String>>,
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub is_base64_encoded: bool,
+    #[serde(default)]
+    pub request_context: Value,
+}
+
+/// What invokes the function, telling apart the three shapes of HTTP
+/// event Lambda gets, each answered in its own shape.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LambdaEvent {
+    ApiGatewayV1(ProxyRequest),
+    ApiGatewayV2(HttpApiRequest),
+    Alb(ProxyRequest),
+}
+
+impl LambdaEvent {
+    pub fn from_json(event: Value) -> Result<Self, String> {
+        let invalid = |e: serde_json::Error| format!("Unsupported event: {}", e);
+        if event.get("version").and_then(Value::as_str) == Some("2.0") {
+            return serde_json::from_value(event).map(LambdaEvent::ApiGatewayV2).map_err(invalid);
+        }
+        let request: ProxyRequest = serde_json::from_value(event).map_err(invalid)?;
+        if request.request_context.get("elb").is_some() {
+            Ok(LambdaEvent::Alb(request))
+        } else {
+            Ok(LambdaEvent::ApiGatewayV1(request))
+        }
+    }
+
+    /// The request the event describes. `real_ip` gives the client's
+    /// address as API Gateway saw it; ALB events carry it in
+    /// `X-Forwarded-For`.
+    pub fn to_request(&self) -> Result<HTTPRequest, String> {
+        let mut headers = HashMap::new();
+        let (method, path, query, body, base64, source_ip) = match self {
+            LambdaEvent::ApiGatewayV1(event) | LambdaEvent::Alb(event) => {
+                match &event.multi_value_headers {
+                    Some(multi) if !multi.is_empty() => {
+                        for (name, values) in multi {
+                            for value in values {
+                                append_header(&mut headers, header_type(name), value.clone());
+                            }
+                        }
+                    }
+                    _ => {
+                        for (name, value) in event.headers.iter().flatten() {
+                            append_header(&mut headers, header_type(name), value.clone());
+                        }
+                    }
+                }
+                // API Gateway decodes parameters, a load balancer passes them as sent
+                let escape = |value: &str| match self {
+                    LambdaEvent::Alb(_) => value.to_string(),
+                    _ => encode(value, b""),
+                };
+                let mut pairs = Vec::new();
+                match &event.multi_value_query_string_parameters {
+                    Some(multi) if !multi.is_empty() => {
+                        for (name, values) in multi {
+                            pairs.extend(values.iter().map(|value| format!("{}={}", escape(name), escape(value))));
+                        }
+                    }
+                    _ => {
+                        for (name, value) in event.query_string_parameters.iter().flatten() {
+                            pairs.push(format!("{}={}", escape(name), escape(value)));
+                        }
+                    }
+                }
+                pairs.sort();
+                let source_ip = event.request_context.pointer("/identity/sourceIp");
+                (
+                    event.http_method.as_str(),
+                    event.path.as_str(),
+                    pairs.join("&"),
+                    &event.body,
+                    event.is_base64_encoded,
+                    source_ip,
+                )
+            }
+            LambdaEvent::ApiGatewayV2(event) => {
+                for (name, value) in event.headers.iter().flatten() {
+                    append_header(&mut headers, header_type(name), value.clone());
+                }
+                for cookie in event.cookies.iter().flatten() {
+                    append_header(&mut headers, HTTPHeaderType::Cookie, cookie.clone());
+                }
+                let method = event
+                    .request_context
+                    .pointer("/http/method")
+                    .and_then(Value::as_str)
+                    .ok_or("Event has no requestContext.http.method")?;
+                let source_ip = event.request_context.pointer("/http/sourceIp");
+                (
+                    method,
+                    event.raw_path.as_str(),
+                    event.raw_query_string.clone(),
+                    &event.body,
+                    event.is_base64_encoded,
+                    source_ip,
+                )
+            }
+        };
+        let body = match body {
+            Some(body) if base64 => STANDARD.decode(body).map_err(|e| format!("Invalid base64 body: {}", e))?,
+            body => body.clone().unwrap_or_default().into_bytes(),
+        };
+        let url = if query.is_empty() { path.to_string() } else { format!("{}?{}", path, query) };
+        let mut req = HTTPRequest {
+            method: HTTPMethod::from_str(method)?,
+            url,
+            headers,
+            connection: Some(ConnectionInfo {
+                client_ip: source_ip.and_then(Value::as_str).and_then(|ip| ip.parse().ok()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        req.set_body_bytes(body);
+        Ok(req)
+    }
+
+    /// `res` in the shape the event's source expects. Binary bodies
+    /// (`raw_body`) are sent base64-encoded.
+    pub fn to_response(&self, res: &HTTPResponse) -> Value {
+        let (body, base64) = match &res.raw_body {
+            Some(raw) => (STANDARD.encode(raw), true),
+            None => (res.body.clone().unwrap_or_default(), false),
+        };
+        let mut response = Map::new();
+        response.insert("statusCode".into(), json!(res.status.code()));
+        let headers = res.headers.iter().map(|(header, value)| (header.to_string(), value.clone()));
+        match self {
+            LambdaEvent::ApiGatewayV2(_) => {
+                let (cookies, headers): (Vec<_>, Vec<_>) =
+                    headers.partition(|(name, _)| name.eq_ignore_ascii_case("set-cookie"));
+                let headers: Map<String, Value> = headers.into_iter().map(|(name, value)| (name, json!(value))).collect();
+                response.insert("headers".into(), Value::Object(headers));
+                let cookies: Vec<String> = cookies.into_iter().map(|(_, cookie)| cookie).collect();
+                response.insert("cookies".into(), json!(cookies));
+            }
+            LambdaEvent::ApiGatewayV1(event) | LambdaEvent::Alb(event) => {
+                // a load balancer wants back whichever form it sent
+                if event.multi_value_headers.is_some() {
+                    let headers: Map<String, Value> = headers.map(|(name, value)| (name, json!([value]))).collect();
+                    response.insert("multiValueHeaders".into(), Value::Object(headers));
+                } else {
+                    let headers: Map<String, Value> = headers.map(|(name, value)| (name, json!(value))).collect();
+                    response.insert("headers".into(), Value::Object(headers));
+                }
+                if matches!(self, LambdaEvent::Alb(_)) {
+                    response.insert("statusDescription".into(), json!(format!("{} {}", res.status.code(), res.status)));
+                }
+            }
+        }
+        response.insert("body".into(), json!(body));
+        response.insert("isBase64Encoded".into(), json!(base64));
+        Value::Object(response)
+    }
+}
+
+fn header_type(name: &str) -> HTTPHeaderType {
+    HTTPHeaderType::from_str(name).unwrap_or(HTTPHeaderType::Other(name.to_string()))
+}
+
+/// Answer one invocation `event` through `pipeline`. Streamed bodies are
+/// collected first, as Lambda takes the response whole.
+pub async fn handle_event(pipeline: &Pipeline, event: Value) -> Result<Value, String> {
+    let event = LambdaEvent::from_json(event)?;
+    let mut res = match event.to_request() {
+        Ok(req) => pipeline.dispatch(req).await,
+        Err(e) => pipeline.router().render_error(
+            &HTTPRequest::default(),
+            &crate::models::error::HTTPError::client(HTTPStatus::BadRequest, &e),
+        ),
+    };
+    if let Some(mut chunks) = res.body_stream.take() {
+        let mut body = res.body_bytes().to_vec();
+        while let Some(chunk) = crate::models::body::next_chunk(&mut chunks).await {
+            body.extend_from_slice(&chunk);
+        }
+        match String::from_utf8(body) {
+            Ok(body) => res.body = Some(body),
+            Err(e) => res.set_raw_body(e.into_bytes()),
+        }
+    }
+    Ok(event.to_response(&res))
+}
+
+/// Serve `router` as a Lambda function behind API Gateway or a load
+/// balancer, taking invocations from the runtime API named by
+/// `AWS_LAMBDA_RUNTIME_API` until it fails. Events that aren't HTTP
+/// requests are reported as invocation errors.
+pub async fn run(router: Router) -> Result<(), String> {
+    let api = std::env::var("AWS_LAMBDA_RUNTIME_API").map_err(|_| String::from("AWS_LAMBDA_RUNTIME_API is not set"))?;
+    let base = format!("http://{}/2018-06-01/runtime/invocation", api);
+    let pipeline = Pipeline::new(Arc::new(router));
+    let client = HTTPClient::new();
+    loop {
+        let next = client.get(&format!("{}/next", base)).send().await.map_err(|e| e.to_string())?;
+        let id = next
+            .headers
+            .iter()
+            .find(|(header, _)| header.to_string().eq_ignore_ascii_case("lambda-runtime-aws-request-id"))
+            .map(|(_, id)| id.clone())
+            .ok_or("Invocation without a request id")?;
+        let outcome = match serde_json::from_slice(next.body_bytes()) {
+            Ok(event) => handle_event(&pipeline, event).await,
+            Err(e) => Err(format!("Invalid event: {}", e)),
+        };
+        let report = match outcome {
+            Ok(response) => client.post(&format!("{}/{}/response", base, id)).json(&response),
+            Err(e) => client
+                .post(&format!("{}/{}/error", base, id))
+                .json(&json!({ "errorMessage": e, "errorType": "UnsupportedEvent" })),
+        };
+        report
+            .map_err(|e| e.to_string())?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+}
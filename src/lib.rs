@@ -1,3 +1,72 @@
 pub mod models;
+pub mod logging;
 pub mod router;
-pub mod httpserver;
\ No newline at end of file
+pub mod middleware;
+pub mod retry;
+pub mod store;
+pub mod static_files;
+pub mod conditional;
+pub mod mime;
+pub mod openapi;
+pub mod testing;
+#[cfg(feature = "bench")]
+pub mod alloc;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "server")]
+pub mod httpserver;
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) mod http1;
+#[cfg(all(any(feature = "server", feature = "client"), not(target_family = "wasm")))]
+pub mod happy_eyeballs;
+#[cfg(any(feature = "server", feature = "client"))]
+pub mod socket;
+#[cfg(feature = "http2")]
+pub mod http2;
+#[cfg(feature = "hyper")]
+pub mod hyper_server;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod reload;
+#[cfg(feature = "server")]
+pub mod hooks;
+#[cfg(feature = "server")]
+pub mod tasks;
+#[cfg(feature = "server")]
+pub mod scheduler;
+#[cfg(feature = "server")]
+pub mod health;
+#[cfg(feature = "metrics")]
+pub mod stats;
+#[cfg(feature = "server")]
+pub mod listener;
+#[cfg(feature = "server")]
+pub mod transport;
+#[cfg(feature = "server")]
+pub mod tcp;
+#[cfg(all(feature = "server", not(target_family = "wasm")))]
+pub mod udp;
+#[cfg(feature = "sessions")]
+pub mod session;
+#[cfg(feature = "sessions")]
+pub mod auth;
+#[cfg(feature = "ws")]
+pub mod websocket;
+#[cfg(feature = "server")]
+pub mod sse;
+#[cfg(all(feature = "server", not(target_family = "wasm")))]
+pub mod proxy;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "client")]
+pub mod dns;
+#[cfg(feature = "tower")]
+pub mod tower;
+#[cfg(feature = "lambda")]
+pub mod lambda;
+
+#[cfg(feature = "macros")]
+pub use web_macros::{delete, get, head, options, patch, post, put, routes};
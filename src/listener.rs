@@ -0,0 +1,37 @@
+/// first fd passed by the service manager (SD_LISTEN_FDS_START)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Sockets handed over through systemd-style socket activation
+/// (`LISTEN_PID` / `LISTEN_FDS`). Returns an empty list when the process was
+/// not socket activated. The variables are cleared so children don't inherit them.
+#[cfg(unix)]
+pub fn activated_listeners() -> std::io::Result<Vec<std::net::TcpListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+    let (Some(pid), Some(fds)) = (pid, fds) else {
+        return Ok(Vec::new());
+    };
+    if pid.trim().parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(Vec::new());
+    }
+    let count: i32 = fds.trim().parse().map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid LISTEN_FDS: {}", fds))
+    })?;
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    Ok((LISTEN_FDS_START..LISTEN_FDS_START + count)
+        // SAFETY: the service manager guarantees these fds are open listening
+        // sockets owned by this process, and nothing else has claimed them
+        .map(|fd| unsafe { std::net::TcpListener::from_raw_fd(fd) })
+        .collect())
+}
+
+#[cfg(not(unix))]
+pub fn activated_listeners() -> std::io::Result<Vec<std::net::TcpListener>> {
+    Ok(Vec::new())
+}
@@ -0,0 +1,91 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much a log event matters, most to least
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+/// 0 when nothing is printed, otherwise one past the last level printed
+static PRINTED: AtomicU8 = AtomicU8::new(Level::Info as u8 + 1);
+
+/// Set the most detailed events printed when no `tracing` subscriber is
+/// installed, `Info` by default; `None` prints none. Events go to stdout,
+/// warnings and errors to stderr, as `message key=value ...`.
+///
+/// With the `tracing` feature, events go to the `tracing` subscriber once
+/// one is set, with their fields, and what it does with them is up to it.
+pub fn set_level(level: Option<Level>) {
+    PRINTED.store(level.map_or(0, |level| level as u8 + 1), Ordering::Relaxed);
+}
+
+/// whether a `tracing` subscriber takes events on this thread, global or
+/// scoped
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub fn subscribed() -> bool {
+    tracing::dispatcher::get_default(|dispatch| !dispatch.is::<tracing::subscriber::NoSubscriber>())
+}
+
+/// print an event when no subscriber took it
+#[doc(hidden)]
+pub fn fallback(level: Level, message: &str, fields: &[(&str, &dyn Display)]) {
+    if level as u8 >= PRINTED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut line = String::from(message);
+    for (key, value) in fields {
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    match level {
+        Level::Error | Level::Warn => eprintln!("{}", line),
+        Level::Info | Level::Debug => println!("{}", line),
+    }
+}
+
+/// an optional field, `-` when it's missing
+#[cfg(feature = "server")]
+pub(crate) struct Maybe<'a, T>(pub &'a Option<T>);
+
+#[cfg(feature = "server")]
+impl<T: Display> Display for Maybe<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(value) => value.fmt(f),
+            None => f.write_str("-"),
+        }
+    }
+}
+
+/// Log an event at `ERROR`, `WARN`, `INFO` or `DEBUG` with a fixed
+/// message and fields shown with `Display`, e.g.
+/// `log!(DEBUG, "route miss", method = request.method, path = request.path())`
+macro_rules! log {
+    ($level:ident, $message:literal $(, $key:ident = $value:expr)* $(,)?) => {{
+        #[cfg(feature = "tracing")]
+        if $crate::logging::subscribed() {
+            ::tracing::event!(::tracing::Level::$level, $($key = %$value,)* $message);
+        } else {
+            $crate::logging::log!(@fallback $level, $message $(, $key = $value)*);
+        }
+        #[cfg(not(feature = "tracing"))]
+        $crate::logging::log!(@fallback $level, $message $(, $key = $value)*);
+    }};
+    (@fallback $level:ident, $message:literal $(, $key:ident = $value:expr)*) => {
+        $crate::logging::fallback(
+            $crate::logging::log!(@level $level),
+            $message,
+            &[$((stringify!($key), &$value as &dyn ::std::fmt::Display)),*],
+        )
+    };
+    (@level ERROR) => { $crate::logging::Level::Error };
+    (@level WARN) => { $crate::logging::Level::Warn };
+    (@level INFO) => { $crate::logging::Level::Info };
+    (@level DEBUG) => { $crate::logging::Level::Debug };
+}
+
+pub(crate) use log;
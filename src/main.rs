@@ -1,4 +1,4 @@
-use web::models::http::{HTTPMethod, HTTPResponse};
+use web::models::http::{HTTPMethod, HTTPResponse, HTTPStatus};
 ///example
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
@@ -18,11 +18,10 @@ async fn main() -> std::io::Result<()> {
 
     router.bind(
         (HTTPMethod::GET, String::from("/posts/{id}")),
-        |req, res, pattern| {
-            let path_params = req.path_params(pattern).unwrap();
-            println!("Post ID: {:?}", path_params.get("id"));
+        |_req, res, params| {
+            println!("Post ID: {:?}", params.get("id"));
             *res = HTTPResponse::default();
-            res.body = Some(format!("Post {}", path_params["id"]));
+            res.body = Some(format!("Post {}", params.params["id"]));
         },
     );
 
@@ -40,6 +39,15 @@ async fn main() -> std::io::Result<()> {
         },
     );
 
+    router.enable_teapot();
+    router.on_error(|req, err| {
+        let mut res = HTTPResponse::error(err.status.clone(), &err.status.default_body());
+        if err.status == HTTPStatus::ImATeapot {
+            res.body = Some(format!("{} refuses to brew coffee", req.url));
+        }
+        res
+    });
+
     web::httpserver::HTTPServer::new(3000, router, std::collections::HashMap::new())
         .start()
         .await?;
@@ -0,0 +1,178 @@
+pub mod cache;
+pub mod canonical_host;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "server")]
+pub mod concurrency;
+pub mod https_redirect;
+pub mod locale;
+pub mod real_ip;
+pub mod request_log;
+#[cfg(feature = "server")]
+pub mod timeout;
+
+use crate::models::error::{panic_message, HTTPError};
+use crate::models::headers::VaryOn;
+use crate::models::http::{HTTPRequest, HTTPResponse};
+use crate::router::Router;
+#[cfg(feature = "metrics")]
+use crate::stats::ServerStats;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+use std::sync::Arc;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A layer around the router. It gets the request before routing and either
+/// passes it on with `next.run(req)` or answers it directly.
+pub trait Middleware: Send + Sync {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse>;
+
+    /// how the layer shows up in `Router::routes` and `Router::tree`
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// the rest of the middleware chain, ending in the router
+#[derive(Clone)]
+pub struct Next<'a> {
+    router: &'a Router,
+    chain: &'a [Arc<dyn Middleware>],
+}
+
+impl<'a> Next<'a> {
+    /// the router at the end of the chain
+    pub fn router(&self) -> &'a Router {
+        self.router
+    }
+
+    pub fn run(self, req: HTTPRequest) -> BoxFuture<'a, HTTPResponse> {
+        match self.chain.split_first() {
+            Some((layer, rest)) => layer.handle(
+                req,
+                Next {
+                    router: self.router,
+                    chain: rest,
+                },
+            ),
+            None => {
+                // a router for the request's host brings its own layers
+                if let Some((router, params)) = self.router.host_router(&req) {
+                    let mut req = req;
+                    if !params.0.is_empty() {
+                        req.extensions.insert(params);
+                    }
+                    return Next {
+                        router,
+                        chain: router.layers(),
+                    }
+                    .run(req);
+                }
+                let router = self.router;
+                Box::pin(async move {
+                    let res = router.route(&req);
+                    match res.deferred.take() {
+                        Some(deferred) => deferred.await,
+                        None => res,
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Router plus every layer wrapped around it, outermost first.
+/// This is what connections dispatch requests into.
+pub struct Pipeline {
+    router: Arc<Router>,
+    layers: Vec<Arc<dyn Middleware>>,
+    #[cfg(feature = "metrics")]
+    stats: Arc<ServerStats>,
+}
+
+impl Pipeline {
+    pub fn new(router: Arc<Router>) -> Self {
+        Self::with_layers(router, Vec::new())
+    }
+
+    /// `outer` layers run before the router's own layers
+    pub fn with_layers(router: Arc<Router>, outer: Vec<Arc<dyn Middleware>>) -> Self {
+        let mut layers = outer;
+        layers.extend(router.layers().iter().cloned());
+        Pipeline {
+            router,
+            layers,
+            #[cfg(feature = "metrics")]
+            stats: Arc::new(ServerStats::default()),
+        }
+    }
+
+    /// report into `stats` instead of a private instance
+    #[cfg(feature = "metrics")]
+    pub fn with_stats(mut self, stats: Arc<ServerStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    pub fn router(&self) -> &Router {
+        &self.router
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> &Arc<ServerStats> {
+        &self.stats
+    }
+
+    /// Run `req` through the layers and the router, after
+    /// `HTTPRequest::normalize`; targets it rejects never reach either.
+    /// A panic in a layer, handler or deferred response becomes a 500.
+    /// Headers noted with `HTTPRequest::vary_on` on the way are added to
+    /// the response's `Vary`.
+    pub async fn dispatch(&self, mut req: HTTPRequest) -> HTTPResponse {
+        #[cfg(feature = "metrics")]
+        self.stats.record_request();
+        if let Err(e) = req.normalize() {
+            return self.router.render_error(&req, &e);
+        }
+        let vary = VaryOn::default();
+        req.extensions.insert(vary.clone());
+        let (method, url) = (req.method.clone(), req.url.clone());
+        let next = Next {
+            router: &self.router,
+            chain: &self.layers,
+        };
+        // layers may panic before returning a future, so call them in one
+        let run = async move { next.run(req).await };
+        let mut res = match catch_unwind(run).await {
+            Ok(res) => res,
+            Err(message) => {
+                crate::logging::log!(ERROR, "panic while handling request", method = method, url = url, panic = message);
+                let req = HTTPRequest {
+                    method,
+                    url,
+                    ..Default::default()
+                };
+                return self.router.render_error(&req, &HTTPError::internal(""));
+            }
+        };
+        for header in vary.0.lock().unwrap().iter() {
+            res.add_vary(&header.to_string());
+        }
+        res
+    }
+}
+
+/// `future`'s output, or the message of a panic raised while polling it
+async fn catch_unwind<F: Future>(future: F) -> Result<F::Output, String> {
+    let mut future = std::pin::pin!(future);
+    std::future::poll_fn(|cx| {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic_message(&*panic))),
+        }
+    })
+    .await
+}
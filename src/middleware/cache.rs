@@ -0,0 +1,262 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::headers::VaryOn;
+use crate::models::http::{Buffering, HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keeps GET and HEAD responses in memory and answers repeats from there
+/// without running the handler, with an `Age` header. Responses are kept
+/// for their `Cache-Control` `s-maxage` or `max-age`, else the route's or
+/// the cache's TTL, one copy per combination of the request headers named
+/// in `Vary`. Once full, the least recently used response goes.
+///
+/// Nothing is kept that is marked `no-store`, `no-cache` or `private`, sets
+/// cookies, varies on `*` or streams its body, nor answers to requests with
+/// `Authorization` unless marked `public` or `s-maxage`. Requests with
+/// `Authorization`, `Cache-Control: no-cache` or `max-age=0` skip stored
+/// responses and `no-store` bypasses the cache.
+pub struct ResponseCache {
+    capacity: usize,
+    ttl: Option<Duration>,
+    /// with any, only paths matching one are cached
+    routes: Vec<(String, Option<Duration>)>,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// bumped on every use, for finding the least recently used entry
+    clock: u64,
+    len: usize,
+    /// variants by method, host and URL
+    map: HashMap<String, Vec<Entry>>,
+}
+
+struct Entry {
+    /// the `Vary` headers and the request's values for them
+    vary: Vec<(HTTPHeaderType, Option<String>)>,
+    response: HTTPResponse,
+    stored: Instant,
+    expires: Instant,
+    used: u64,
+}
+
+impl ResponseCache {
+    /// a cache of up to `capacity` responses, keeping those that say how
+    /// long they stay fresh
+    pub fn new(capacity: usize) -> Self {
+        ResponseCache {
+            capacity,
+            ttl: None,
+            routes: Vec::new(),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// keep responses without `max-age` for `ttl`
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Cache paths matching `pattern`, e.g. `/posts/{id}`, keeping
+    /// responses without `max-age` for `ttl` when given. Once a route is
+    /// added, other paths are not cached.
+    pub fn route(mut self, pattern: &str, ttl: Option<Duration>) -> Self {
+        self.routes.push((pattern.to_string(), ttl));
+        self
+    }
+
+    /// how many responses are stored, expired ones included
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+
+    /// `None` if `path` isn't cached, else the TTL its route brings
+    fn route_for(&self, path: &str) -> Option<Option<Duration>> {
+        if self.routes.is_empty() {
+            return Some(self.ttl);
+        }
+        self.routes
+            .iter()
+            .find(|(pattern, _)| crate::router::match_route(pattern, path).is_some())
+            .map(|(_, ttl)| ttl.or(self.ttl))
+    }
+
+    fn lookup(&self, key: &str, req: &HTTPRequest) -> Option<HTTPResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let variants = entries.map.get_mut(key)?;
+        let index = variants.iter().position(|entry| {
+            entry.vary.iter().all(|(header, value)| req.header(header) == value.as_deref())
+        })?;
+        if variants[index].expires <= now {
+            variants.remove(index);
+            if variants.is_empty() {
+                entries.map.remove(key);
+            }
+            entries.len -= 1;
+            return None;
+        }
+        let entry = &mut variants[index];
+        entry.used = clock;
+        let mut res = stored_copy(&entry.response);
+        res.set_header(HTTPHeaderType::Age, now.duration_since(entry.stored).as_secs().to_string());
+        Some(res)
+    }
+
+    /// how long `res` may be kept, if at all
+    fn freshness(&self, res: &HTTPResponse, route_ttl: Option<Duration>, authorized: bool) -> Option<Duration> {
+        let directives = res.cache_control();
+        let storable = cacheable_status(&res.status)
+            && res.buffering() == Buffering::Buffered
+            && !directives.no_store
+            && !directives.no_cache
+            && !directives.private
+            && (!authorized || directives.public || directives.s_maxage.is_some())
+            && res.header(&HTTPHeaderType::SetCookie).is_none()
+            && !res.header(&HTTPHeaderType::Vary).is_some_and(|vary| vary.split(',').any(|v| v.trim() == "*"));
+        if !storable {
+            return None;
+        }
+        directives
+            .s_maxage
+            .or(directives.max_age)
+            .map(Duration::from_secs)
+            .or(route_ttl)
+            .filter(|ttl| !ttl.is_zero())
+    }
+
+    fn store(&self, key: String, vary: Vec<(HTTPHeaderType, Option<String>)>, res: &HTTPResponse, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let now = Instant::now();
+        let entry = Entry {
+            vary,
+            response: stored_copy(res),
+            stored: now,
+            expires: now + ttl,
+            used: entries.clock,
+        };
+        let variants = entries.map.entry(key).or_default();
+        match variants.iter().position(|existing| existing.vary == entry.vary) {
+            Some(index) => variants[index] = entry,
+            None => {
+                variants.push(entry);
+                entries.len += 1;
+            }
+        }
+        while entries.len > self.capacity {
+            entries.evict_least_recently_used();
+        }
+    }
+}
+
+impl Entries {
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .map
+            .iter()
+            .flat_map(|(key, variants)| variants.iter().enumerate().map(move |(i, entry)| (entry.used, key, i)))
+            .min_by_key(|(used, _, _)| *used)
+            .map(|(_, key, i)| (key.clone(), i));
+        let Some((key, index)) = oldest else {
+            return;
+        };
+        let variants = self.map.get_mut(&key).unwrap();
+        variants.remove(index);
+        if variants.is_empty() {
+            self.map.remove(&key);
+        }
+        self.len -= 1;
+    }
+}
+
+impl Middleware for ResponseCache {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        if !matches!(req.method, HTTPMethod::GET | HTTPMethod::HEAD) {
+            return next.run(req);
+        }
+        let Some(route_ttl) = self.route_for(req.path()) else {
+            return next.run(req);
+        };
+        let directives = req.cache_control();
+        if directives.no_store {
+            return next.run(req);
+        }
+        Box::pin(async move {
+            let key = format!(
+                "{} {} {}",
+                req.method,
+                req.header(&HTTPHeaderType::Host).unwrap_or(""),
+                req.path_and_query()
+            );
+            let authorized = req.header(&HTTPHeaderType::Authorization).is_some();
+            if !authorized && !directives.no_cache && directives.max_age != Some(0) {
+                if let Some(res) = self.lookup(&key, &req) {
+                    return res;
+                }
+            }
+            let headers = req.headers.clone();
+            // headers noted with `vary_on` only reach `Vary` after the
+            // pipeline, too late for the key
+            let noted = req.extensions.get::<VaryOn>().cloned();
+            let mut res = next.run(req).await;
+            if let Some(noted) = noted {
+                for header in noted.0.lock().unwrap().iter() {
+                    res.add_vary(&header.to_string());
+                }
+            }
+            if let Some(ttl) = self.freshness(&res, route_ttl, authorized) {
+                let vary = res
+                    .header(&HTTPHeaderType::Vary)
+                    .map(|vary| {
+                        vary.split(',')
+                            .filter_map(|name| HTTPHeaderType::from_str(name.trim()).ok())
+                            .map(|header| {
+                                let value = headers.get(&header).cloned();
+                                (header, value)
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                self.store(key, vary, &res, ttl);
+            }
+            res
+        })
+    }
+}
+
+/// statuses that may be cached by default (RFC 9110 15.1)
+fn cacheable_status(status: &HTTPStatus) -> bool {
+    matches!(status.code(), 200 | 203 | 204 | 206 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501)
+}
+
+/// status, headers and body of `res`, leaving out what belongs to one
+/// exchange, e.g. extensions
+fn stored_copy(res: &HTTPResponse) -> HTTPResponse {
+    HTTPResponse {
+        status: res.status.clone(),
+        version: res.version.clone(),
+        headers: res.headers.clone(),
+        body: res.body.clone(),
+        raw_body: res.raw_body.clone(),
+        buffering: res.buffering,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,49 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::headers::strip_port;
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse, HTTPStatus};
+
+/// 301-redirects requests whose `Host` is not the canonical host, keeping
+/// path and query. Requests without a `Host` header are passed through.
+pub struct CanonicalHost {
+    host: String,
+    scheme: String,
+}
+
+impl CanonicalHost {
+    pub fn new(host: &str, scheme: &str) -> Self {
+        CanonicalHost {
+            host: host.to_ascii_lowercase(),
+            scheme: scheme.to_string(),
+        }
+    }
+
+    /// redirect target for `req`, or `None` if it is already canonical
+    pub fn redirect_for(&self, req: &HTTPRequest) -> Option<String> {
+        let host = req.headers.get(&HTTPHeaderType::Host)?.trim();
+        let matches = if self.host.contains(':') {
+            host.eq_ignore_ascii_case(&self.host)
+        } else {
+            strip_port(host).eq_ignore_ascii_case(&self.host)
+        };
+        if matches {
+            return None;
+        }
+        Some(format!("{}://{}{}", self.scheme, self.host, req.path_and_query()))
+    }
+}
+
+impl Middleware for CanonicalHost {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            match self.redirect_for(&req) {
+                Some(location) => {
+                    let mut res = HTTPResponse::error(HTTPStatus::MovedPermanently, "");
+                    res.body = None;
+                    res.set_location(&location);
+                    res
+                }
+                None => next.run(req).await,
+            }
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use crate::compression::Coding;
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::headers::{quality_list, EntityTag};
+use crate::models::http::{Buffering, HTTPHeaderType, HTTPRequest, HTTPResponse, HTTPStatus};
+
+/// Compresses response bodies with the best coding the client accepts.
+/// Bodies below `min_size`, streamed or partial responses, bodies that are
+/// already encoded and media types that don't compress are left alone.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    /// smallest body worth compressing, in bytes
+    pub min_size: usize,
+    /// 0 (fastest) to 9 (smallest)
+    pub level: u32,
+    /// codings offered, most preferred first
+    pub codings: Vec<Coding>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            min_size: 1024,
+            level: 6,
+            codings: Coding::ALL.to_vec(),
+        }
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The coding to use for a request's `Accept-Encoding`: highest q-value
+    /// wins, ties go to the order of `codings`. `None` for identity.
+    pub fn negotiate(&self, accept_encoding: &str) -> Option<Coding> {
+        let accepted = quality_list(accept_encoding);
+        let quality = |coding: &Coding| {
+            accepted
+                .iter()
+                .find(|(name, _)| name.parse::<Coding>().ok() == Some(*coding))
+                .or_else(|| accepted.iter().find(|(name, _)| name == "*"))
+                .map(|(_, q)| *q)
+        };
+        let mut best: Option<(Coding, f32)> = None;
+        for coding in &self.codings {
+            if let Some(q) = quality(coding).filter(|q| *q > 0.0) {
+                if best.is_none_or(|(_, best_q)| q > best_q) {
+                    best = Some((*coding, q));
+                }
+            }
+        }
+        best.map(|(coding, _)| coding)
+    }
+
+    fn eligible(&self, res: &HTTPResponse) -> bool {
+        let bodiless = res.status.is_informational()
+            || matches!(res.status, HTTPStatus::NoContent | HTTPStatus::NotModified | HTTPStatus::PartialContent);
+        !bodiless
+            && res.buffering() == Buffering::Buffered
+            && res.header(&HTTPHeaderType::ContentEncoding).is_none()
+            && res.body_bytes().len() >= self.min_size
+            && res
+                .header(&HTTPHeaderType::ContentType)
+                .is_some_and(crate::mime::is_compressible)
+    }
+
+    /// compress `res` in place for `coding`
+    pub fn compress(&self, res: &mut HTTPResponse, coding: Coding) -> std::io::Result<()> {
+        let compressed = coding.encode(res.body_bytes(), self.level)?;
+        res.set_raw_body(compressed);
+        res.set_header(HTTPHeaderType::ContentEncoding, coding.as_str());
+        res.headers.remove(&HTTPHeaderType::ContentLength);
+        // the compressed bytes differ, so a strong validator no longer holds
+        if let Some(etag) = res.etag().filter(|etag| !etag.weak) {
+            res.set_etag(&EntityTag::weak(&etag.tag));
+        }
+        Ok(())
+    }
+}
+
+impl Middleware for Compression {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            let coding = req
+                .header(&HTTPHeaderType::AcceptEncoding)
+                .and_then(|accept| self.negotiate(accept));
+            let mut res = next.run(req).await;
+            if !self.eligible(&res) {
+                return res;
+            }
+            res.add_vary("Accept-Encoding");
+            if let Some(coding) = coding {
+                if let Err(e) = self.compress(&mut res, coding) {
+                    crate::logging::log!(WARN, "compression failed", error = e);
+                }
+            }
+            res
+        })
+    }
+}
@@ -0,0 +1,116 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::error::{ErrorKind, HTTPError};
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse, HTTPStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Lets at most a given number of requests through to the rest of the
+/// chain at once. Requests over the limit wait in a queue of bounded length,
+/// none by default, and are answered 503 once it is full. Routes can get a
+/// limit and queue of their own with `route`, so a slow endpoint can't take
+/// every slot, e.g. `ConcurrencyLimit::new(256).route("/reports/{id}", 4, 16)`.
+pub struct ConcurrencyLimit {
+    global: Limit,
+    routes: Vec<(String, Limit)>,
+    retry_after: Option<u64>,
+}
+
+struct Limit {
+    max: usize,
+    permits: Semaphore,
+    queue: usize,
+    waiting: AtomicUsize,
+}
+
+impl Limit {
+    fn new(max: usize, queue: usize) -> Self {
+        Limit {
+            max,
+            permits: Semaphore::new(max),
+            queue,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// a slot, waiting for one if the queue has room; `None` when full
+    async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        if let Ok(permit) = self.permits.try_acquire() {
+            return Some(permit);
+        }
+        let waiting = self.waiting.fetch_add(1, Ordering::SeqCst);
+        // counted out again even if the request is dropped while waiting
+        let _queued = Queued(&self.waiting);
+        if waiting >= self.queue {
+            return None;
+        }
+        self.permits.acquire().await.ok()
+    }
+}
+
+struct Queued<'a>(&'a AtomicUsize);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ConcurrencyLimit {
+    /// at most `max` requests at once, not queueing any over it
+    pub fn new(max: usize) -> Self {
+        ConcurrencyLimit {
+            global: Limit::new(max, 0),
+            routes: Vec::new(),
+            retry_after: None,
+        }
+    }
+
+    /// let up to `queue` requests over the limit wait for a slot
+    pub fn with_queue(mut self, queue: usize) -> Self {
+        self.global.queue = queue;
+        self
+    }
+
+    /// give paths matching `pattern`, e.g. `/reports/{id}`, their own limit
+    /// of `max` at once and `queue` waiting, instead of the shared one; the
+    /// first matching route counts
+    pub fn route(mut self, pattern: &str, max: usize, queue: usize) -> Self {
+        self.routes.push((pattern.to_string(), Limit::new(max, queue)));
+        self
+    }
+
+    /// send `Retry-After: seconds` with the 503
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    fn limit_for(&self, path: &str) -> &Limit {
+        self.routes
+            .iter()
+            .find(|(pattern, _)| crate::router::match_route(pattern, path).is_some())
+            .map_or(&self.global, |(_, limit)| limit)
+    }
+
+    /// how many requests to `path` are running now
+    pub fn in_flight(&self, path: &str) -> usize {
+        let limit = self.limit_for(path);
+        limit.max - limit.permits.available_permits()
+    }
+}
+
+impl Middleware for ConcurrencyLimit {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            let Some(_permit) = self.limit_for(req.path()).acquire().await else {
+                let error = HTTPError::new(HTTPStatus::ServiceUnavailable, ErrorKind::Transient, "");
+                let mut res = next.router().render_error(&req, &error);
+                if let Some(seconds) = self.retry_after {
+                    res.set_header(HTTPHeaderType::RetryAfter, seconds.to_string());
+                }
+                return res;
+            };
+            next.run(req).await
+        })
+    }
+}
@@ -0,0 +1,48 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse, HTTPStatus};
+
+/// Answers every request with a 301 to its `https://` equivalent, keeping
+/// host, path and query. Used by `HTTPServer::add_https_redirect_listener`.
+#[derive(Debug, Clone)]
+pub struct HttpsRedirect {
+    /// port of the HTTPS listener, omitted from the URL when 443
+    https_port: u16,
+}
+
+impl HttpsRedirect {
+    pub fn new(https_port: u16) -> Self {
+        HttpsRedirect { https_port }
+    }
+
+    pub fn location(&self, req: &HTTPRequest) -> Option<String> {
+        let host = req.headers.get(&HTTPHeaderType::Host)?.trim();
+        if host.is_empty() {
+            return None;
+        }
+        let host = match host.rsplit_once(':') {
+            Some((name, port)) if !port.contains(']') => name,
+            _ => host,
+        };
+        if self.https_port == 443 {
+            Some(format!("https://{}{}", host, req.path_and_query()))
+        } else {
+            Some(format!("https://{}:{}{}", host, self.https_port, req.path_and_query()))
+        }
+    }
+}
+
+impl Middleware for HttpsRedirect {
+    fn handle<'a>(&'a self, req: HTTPRequest, _next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            match self.location(&req) {
+                Some(location) => {
+                    let mut res = HTTPResponse::error(HTTPStatus::MovedPermanently, "");
+                    res.body = None;
+                    res.set_location(&location);
+                    res
+                }
+                None => HTTPResponse::error(HTTPStatus::BadRequest, &HTTPStatus::BadRequest.default_body()),
+            }
+        })
+    }
+}
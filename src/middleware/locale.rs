@@ -0,0 +1,52 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse};
+
+/// The language tag a request was answered in, put in its extensions by
+/// `Localization`. Handlers read it with `req.locale()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale(pub String);
+
+/// Picks a language for each request from `Accept-Language` and the
+/// languages the app has, the first of them when nothing fits. Handlers
+/// find it with `req.locale()`; responses get `Content-Language`, unless
+/// the handler set one.
+pub struct Localization {
+    supported: Vec<String>,
+}
+
+impl Localization {
+    /// `supported` language tags, the default first, e.g. `&["en", "de", "fr-CA"]`
+    pub fn new(supported: &[&str]) -> Self {
+        assert!(!supported.is_empty(), "Localization needs at least one language");
+        Localization {
+            supported: supported.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    /// the language to answer `req` in
+    pub fn locale_for(&self, req: &HTTPRequest) -> String {
+        let supported: Vec<&str> = self.supported.iter().map(String::as_str).collect();
+        req.preferred_language(&supported).unwrap_or(supported[0]).to_string()
+    }
+}
+
+impl Middleware for Localization {
+    fn handle<'a>(&'a self, mut req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            let locale = self.locale_for(&req);
+            req.extensions.insert(Locale(locale.clone()));
+            let mut res = next.run(req).await;
+            if res.header(&HTTPHeaderType::ContentLanguage).is_none() {
+                res.set_header(HTTPHeaderType::ContentLanguage, locale);
+            }
+            res
+        })
+    }
+}
+
+impl HTTPRequest {
+    /// the language `Localization` picked for this request
+    pub fn locale(&self) -> Option<&str> {
+        self.extensions.get::<Locale>().map(|locale| locale.0.as_str())
+    }
+}
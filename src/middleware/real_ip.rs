@@ -0,0 +1,166 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// An address range such as `10.0.0.0/8` or `fd00::/8`. A bare address is a
+/// range of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid CIDR: {}", s);
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|_| invalid())?.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+/// Proxies allowed to report the client address through `Forwarded`,
+/// `X-Forwarded-For` or `X-Real-IP`. Those headers are ignored on requests
+/// from anywhere else, since any client can send them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+}
+
+impl TrustedProxies {
+    /// e.g. `&["10.0.0.0/8", "127.0.0.1"]`
+    pub fn new(ranges: &[&str]) -> Result<Self, String> {
+        Ok(TrustedProxies {
+            ranges: ranges.iter().map(|r| r.parse()).collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// The client address for `req`. Forwarding headers are read right to
+    /// left, skipping trusted hops, so a client can't spoof its address by
+    /// sending the headers itself. `None` when the peer address is unknown.
+    pub fn resolve(&self, req: &HTTPRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip().to_canonical();
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+        let hops = match forwarded_hops(req) {
+            Some(hops) => hops,
+            None => {
+                return Some(
+                    req.header(&HTTPHeaderType::XRealIP)
+                        .and_then(parse_node)
+                        .unwrap_or(peer),
+                )
+            }
+        };
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            match parse_node(hop) {
+                Some(ip) => {
+                    client = ip;
+                    if !self.is_trusted(ip) {
+                        break;
+                    }
+                }
+                // obfuscated or `unknown`: the last trusted hop is as far as we can see
+                None => break,
+            }
+        }
+        Some(client)
+    }
+}
+
+/// `for=` values of `Forwarded`, else the `X-Forwarded-For` list
+fn forwarded_hops(req: &HTTPRequest) -> Option<Vec<String>> {
+    if let Some(forwarded) = req.header(&HTTPHeaderType::Forwarded) {
+        let hops = forwarded
+            .split(',')
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .map(|(_, value)| value.trim().trim_matches('"').to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+        return Some(hops);
+    }
+    req.header(&HTTPHeaderType::XForwardedFor)
+        .map(|list| list.split(',').map(|hop| hop.trim().to_string()).collect())
+}
+
+/// `192.0.2.1`, `192.0.2.1:80`, `[2001:db8::1]` or `[2001:db8::1]:80`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    IpAddr::from_str(node)
+        .ok()
+        .or_else(|| SocketAddr::from_str(node).ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|n| n.strip_suffix(']'))
+                .and_then(|n| IpAddr::from_str(n).ok())
+        })
+        .map(|ip| ip.to_canonical())
+}
+
+/// Resolves the client address with `TrustedProxies` and records it on the
+/// request for `HTTPRequest::real_ip`
+pub struct RealIp {
+    proxies: TrustedProxies,
+}
+
+impl RealIp {
+    pub fn new(proxies: TrustedProxies) -> Self {
+        RealIp { proxies }
+    }
+}
+
+impl Middleware for RealIp {
+    fn handle<'a>(&'a self, mut req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            let client_ip = self.proxies.resolve(&req);
+            if let Some(connection) = req.connection.as_mut() {
+                connection.client_ip = client_ip;
+            }
+            next.run(req).await
+        })
+    }
+}
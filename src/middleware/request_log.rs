@@ -0,0 +1,29 @@
+use crate::logging::log;
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::http::{HTTPRequest, HTTPResponse};
+use std::time::Instant;
+
+/// Logs one `INFO` event per request with its method, url, status and how
+/// many microseconds the rest of the chain took, e.g.
+/// `request method=GET url=/users status=200 micros=1200`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestLog;
+
+impl Middleware for RequestLog {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            let (method, url) = (req.method.clone(), req.url.clone());
+            let started = Instant::now();
+            let res = next.run(req).await;
+            log!(
+                INFO,
+                "request",
+                method = method,
+                url = url,
+                status = res.status.code(),
+                micros = started.elapsed().as_micros()
+            );
+            res
+        })
+    }
+}
@@ -0,0 +1,69 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::error::{ErrorKind, HTTPError};
+use crate::models::http::{HTTPRequest, HTTPResponse, HTTPStatus};
+use std::time::Duration;
+
+/// Answers 504 when the rest of the chain takes longer than the deadline
+/// to produce a response, dropping the unfinished work. Routes can get a
+/// deadline of their own with `route`, e.g. longer for reports.
+///
+/// Only time spent waiting counts: a sync handler that blocks can't be
+/// interrupted, while async work it defers with `HTTPResponse::defer` can.
+pub struct Timeout {
+    deadline: Duration,
+    status: HTTPStatus,
+    routes: Vec<(String, Duration)>,
+}
+
+impl Timeout {
+    pub fn new(deadline: Duration) -> Self {
+        Timeout {
+            deadline,
+            status: HTTPStatus::GatewayTimeout,
+            routes: Vec::new(),
+        }
+    }
+
+    /// answer with `status` instead of 504, e.g. 408 or 503
+    pub fn with_status(mut self, status: HTTPStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// give paths matching `pattern`, e.g. `/reports/{id}`, `deadline`
+    /// instead; the first matching route counts
+    pub fn route(mut self, pattern: &str, deadline: Duration) -> Self {
+        self.routes.push((pattern.to_string(), deadline));
+        self
+    }
+
+    /// the deadline for requests to `path`
+    pub fn deadline_for(&self, path: &str) -> Duration {
+        self.routes
+            .iter()
+            .find(|(pattern, _)| crate::router::match_route(pattern, path).is_some())
+            .map_or(self.deadline, |(_, deadline)| *deadline)
+    }
+}
+
+impl Middleware for Timeout {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            let deadline = self.deadline_for(req.path());
+            let (method, url) = (req.method.clone(), req.url.clone());
+            let router = next.router();
+            match tokio::time::timeout(deadline, next.run(req)).await {
+                Ok(res) => res,
+                Err(_) => {
+                    crate::logging::log!(WARN, "request timed out", method = method, url = url, millis = deadline.as_millis());
+                    let req = HTTPRequest {
+                        method,
+                        url,
+                        ..Default::default()
+                    };
+                    router.render_error(&req, &HTTPError::new(self.status.clone(), ErrorKind::Timeout, ""))
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,184 @@
+use crate::models::headers::MediaType;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// Well-known media types, e.g. `res.set_content_type(Mime::APPLICATION_JSON)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mime(&'static str);
+
+impl Mime {
+    pub const TEXT_PLAIN: Mime = Mime("text/plain; charset=utf-8");
+    pub const TEXT_HTML: Mime = Mime("text/html; charset=utf-8");
+    pub const TEXT_CSS: Mime = Mime("text/css; charset=utf-8");
+    pub const TEXT_CSV: Mime = Mime("text/csv; charset=utf-8");
+    pub const TEXT_JAVASCRIPT: Mime = Mime("text/javascript; charset=utf-8");
+    pub const TEXT_EVENT_STREAM: Mime = Mime("text/event-stream");
+    pub const APPLICATION_JSON: Mime = Mime("application/json");
+    pub const APPLICATION_PROBLEM_JSON: Mime = Mime("application/problem+json");
+    pub const APPLICATION_XML: Mime = Mime("application/xml");
+    pub const APPLICATION_WASM: Mime = Mime("application/wasm");
+    pub const APPLICATION_PDF: Mime = Mime("application/pdf");
+    pub const APPLICATION_ZIP: Mime = Mime("application/zip");
+    pub const APPLICATION_GZIP: Mime = Mime("application/gzip");
+    pub const APPLICATION_OCTET_STREAM: Mime = Mime("application/octet-stream");
+    pub const APPLICATION_FORM_URLENCODED: Mime = Mime("application/x-www-form-urlencoded");
+    pub const MULTIPART_FORM_DATA: Mime = Mime("multipart/form-data");
+    pub const IMAGE_PNG: Mime = Mime("image/png");
+    pub const IMAGE_JPEG: Mime = Mime("image/jpeg");
+    pub const IMAGE_GIF: Mime = Mime("image/gif");
+    pub const IMAGE_WEBP: Mime = Mime("image/webp");
+    pub const IMAGE_AVIF: Mime = Mime("image/avif");
+    pub const IMAGE_SVG: Mime = Mime("image/svg+xml");
+    pub const IMAGE_ICON: Mime = Mime("image/x-icon");
+    pub const FONT_WOFF: Mime = Mime("font/woff");
+    pub const FONT_WOFF2: Mime = Mime("font/woff2");
+    pub const FONT_TTF: Mime = Mime("font/ttf");
+    pub const FONT_OTF: Mime = Mime("font/otf");
+    pub const AUDIO_MPEG: Mime = Mime("audio/mpeg");
+    pub const AUDIO_OGG: Mime = Mime("audio/ogg");
+    pub const AUDIO_WAV: Mime = Mime("audio/wav");
+    pub const VIDEO_MP4: Mime = Mime("video/mp4");
+    pub const VIDEO_WEBM: Mime = Mime("video/webm");
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+
+    pub fn media_type(&self) -> MediaType {
+        self.0.parse().expect("Mime constants are valid media types")
+    }
+
+    /// media type for a file extension (without the dot, any case)
+    pub fn from_extension(extension: &str) -> Option<Mime> {
+        let mime = match extension.to_ascii_lowercase().as_str() {
+            "txt" | "text" | "md" => Mime::TEXT_PLAIN,
+            "html" | "htm" => Mime::TEXT_HTML,
+            "css" => Mime::TEXT_CSS,
+            "csv" => Mime::TEXT_CSV,
+            "js" | "mjs" => Mime::TEXT_JAVASCRIPT,
+            "json" | "map" => Mime::APPLICATION_JSON,
+            "xml" => Mime::APPLICATION_XML,
+            "wasm" => Mime::APPLICATION_WASM,
+            "pdf" => Mime::APPLICATION_PDF,
+            "zip" => Mime::APPLICATION_ZIP,
+            "gz" => Mime::APPLICATION_GZIP,
+            "png" => Mime::IMAGE_PNG,
+            "jpg" | "jpeg" => Mime::IMAGE_JPEG,
+            "gif" => Mime::IMAGE_GIF,
+            "webp" => Mime::IMAGE_WEBP,
+            "avif" => Mime::IMAGE_AVIF,
+            "svg" => Mime::IMAGE_SVG,
+            "ico" => Mime::IMAGE_ICON,
+            "woff" => Mime::FONT_WOFF,
+            "woff2" => Mime::FONT_WOFF2,
+            "ttf" => Mime::FONT_TTF,
+            "otf" => Mime::FONT_OTF,
+            "mp3" => Mime::AUDIO_MPEG,
+            "ogg" | "oga" => Mime::AUDIO_OGG,
+            "wav" => Mime::AUDIO_WAV,
+            "mp4" | "m4v" => Mime::VIDEO_MP4,
+            "webm" => Mime::VIDEO_WEBM,
+            _ => return None,
+        };
+        Some(mime)
+    }
+
+    /// media type from a path's extension, `application/octet-stream` if unknown
+    pub fn from_path(path: impl AsRef<Path>) -> Mime {
+        path.as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(Mime::from_extension)
+            .unwrap_or(Mime::APPLICATION_OCTET_STREAM)
+    }
+}
+
+/// Text-like types worth compressing. Images, audio, video and archives
+/// are compressed already.
+pub fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/wasm"
+                | "image/svg+xml"
+                | "image/x-icon"
+                | "font/ttf"
+                | "font/otf"
+        )
+}
+
+impl Display for Mime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<Mime> for MediaType {
+    fn from(mime: Mime) -> Self {
+        mime.media_type()
+    }
+}
+
+/// Typed `Content-Type` header value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentType(pub MediaType);
+
+impl ContentType {
+    pub fn json() -> Self {
+        ContentType(Mime::APPLICATION_JSON.into())
+    }
+
+    pub fn html() -> Self {
+        ContentType(Mime::TEXT_HTML.into())
+    }
+
+    pub fn text() -> Self {
+        ContentType(Mime::TEXT_PLAIN.into())
+    }
+
+    pub fn media_type(&self) -> &MediaType {
+        &self.0
+    }
+
+    /// same type and subtype, ignoring parameters
+    pub fn is(&self, mime: Mime) -> bool {
+        self.0.essence() == mime.media_type().essence()
+    }
+}
+
+impl From<Mime> for ContentType {
+    fn from(mime: Mime) -> Self {
+        ContentType(mime.into())
+    }
+}
+
+impl From<MediaType> for ContentType {
+    fn from(media_type: MediaType) -> Self {
+        ContentType(media_type)
+    }
+}
+
+impl From<ContentType> for MediaType {
+    fn from(content_type: ContentType) -> Self {
+        content_type.0
+    }
+}
+
+impl std::str::FromStr for ContentType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        s.parse().map(ContentType)
+    }
+}
+
+impl Display for ContentType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -1 +1,20 @@
-pub mod http;
\ No newline at end of file
+pub mod http;
+pub mod url;
+pub mod headers;
+pub mod parse;
+pub mod error;
+pub mod problem;
+pub mod cookie;
+pub mod connection;
+pub mod extensions;
+pub mod deferred;
+#[cfg(feature = "server")]
+pub mod upgrade;
+#[cfg(feature = "server")]
+pub mod early_hints;
+#[cfg(feature = "server")]
+pub mod cancellation;
+#[cfg(any(feature = "server", feature = "client"))]
+pub mod body;
+#[cfg(feature = "http")]
+pub mod interop;
@@ -0,0 +1,115 @@
+use crate::models::http::{Buffering, HTTPResponse};
+use futures_core::Stream;
+use std::fmt::{Debug, Formatter};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// chunks of a body in the order they are written
+pub type Chunks = Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>;
+
+/// A body that is produced while the response is being sent, e.g. server-sent
+/// events. The server writes each chunk as it arrives and ends the response
+/// when the stream ends.
+#[derive(Clone, Default)]
+pub struct BodyStream {
+    chunks: Option<Arc<Mutex<Option<Chunks>>>>,
+}
+
+impl BodyStream {
+    pub fn new<S>(chunks: S) -> Self
+    where
+        S: Stream<Item = Vec<u8>> + Send + 'static,
+    {
+        BodyStream {
+            chunks: Some(Arc::new(Mutex::new(Some(Box::pin(chunks))))),
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.chunks.is_some()
+    }
+
+    /// the stream, for whoever sends the body; `None` after the first call on any clone
+    pub fn take(&self) -> Option<Chunks> {
+        self.chunks.as_ref().and_then(|chunks| chunks.lock().unwrap().take())
+    }
+}
+
+impl Debug for BodyStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BodyStream({})", if self.is_set() { "set" } else { "unset" })
+    }
+}
+
+/// like `Extensions`, not part of a response's identity
+impl PartialEq for BodyStream {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for BodyStream {}
+
+impl HTTPResponse {
+    /// Send `chunks` after the current body, as they are produced. Switches
+    /// the response to `Buffering::Streamed`.
+    pub fn set_body_stream<S>(&mut self, chunks: S)
+    where
+        S: Stream<Item = Vec<u8>> + Send + 'static,
+    {
+        self.body_stream = BodyStream::new(chunks);
+        self.buffering = Some(Buffering::Streamed);
+    }
+
+    /// Next piece of a streamed body, e.g. of a client response sent with
+    /// `send_streaming`; `None` once it has ended
+    pub async fn chunk(&mut self) -> Option<Vec<u8>> {
+        let mut chunks = self.body_stream.take()?;
+        let chunk = next_chunk(&mut chunks).await;
+        if chunk.is_some() {
+            self.body_stream = BodyStream {
+                chunks: Some(Arc::new(Mutex::new(Some(chunks)))),
+            };
+        }
+        chunk
+    }
+
+    /// read the rest of a streamed body into `raw_body`, making the
+    /// response buffered again
+    pub async fn collect_body(&mut self) {
+        let Some(mut chunks) = self.body_stream.take() else {
+            return;
+        };
+        let mut body = self.body_bytes().to_vec();
+        while let Some(chunk) = next_chunk(&mut chunks).await {
+            body.extend_from_slice(&chunk);
+        }
+        self.set_raw_body(body);
+        self.buffering = Some(Buffering::Buffered);
+    }
+}
+
+/// next item of `chunks`, or `None` at its end
+pub(crate) async fn next_chunk(chunks: &mut Chunks) -> Option<Vec<u8>> {
+    std::future::poll_fn(|cx| chunks.as_mut().poll_next(cx)).await
+}
+
+/// Items sent into a channel by other tasks, ending when every sender is
+/// dropped, e.g. `res.set_body_stream(ChannelStream::new(rx))`
+pub struct ChannelStream<T>(pub(crate) mpsc::Receiver<T>);
+
+impl<T> ChannelStream<T> {
+    pub fn new(receiver: mpsc::Receiver<T>) -> Self {
+        ChannelStream(receiver)
+    }
+}
+
+impl<T> Stream for ChannelStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.0.poll_recv(cx)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::models::http::HTTPRequest;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Fires when the client a request came from goes away before its response
+/// is sent: the connection closed or, on HTTP/2, the stream was reset. The
+/// server also drops the request's future then, so this is for work that
+/// outlives it, e.g. spawned tasks, or to stop early in a deferred response.
+///
+/// `let token = req.cancellation();`
+/// `res.defer(async move { token.run_until_cancelled(report()).await.unwrap_or_default() });`
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// wait until cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // registered before the check, so a cancel in between still wakes it
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// `future`'s output, or `None` if cancelled first
+    pub async fn run_until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        let mut future = std::pin::pin!(future);
+        let mut cancelled = std::pin::pin!(self.cancelled());
+        std::future::poll_fn(|cx| {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                return std::task::Poll::Ready(None);
+            }
+            future.as_mut().poll(cx).map(Some)
+        })
+        .await
+    }
+}
+
+impl HTTPRequest {
+    /// the token that fires when this request's client goes away; one that
+    /// never fires outside a server
+    pub fn cancellation(&self) -> CancellationToken {
+        self.extensions.get::<CancellationToken>().cloned().unwrap_or_default()
+    }
+}
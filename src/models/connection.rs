@@ -0,0 +1,54 @@
+use crate::models::http::{HTTPRequest, HTTPVersion};
+use std::net::{IpAddr, SocketAddr};
+
+/// Transport details of the connection a request arrived on, filled in by the
+/// server. Requests built by hand or parsed from text carry none.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConnectionInfo {
+    /// `None` on Unix sockets
+    pub peer_addr: Option<SocketAddr>,
+    pub local_addr: Option<SocketAddr>,
+    /// `None` for plaintext connections
+    pub tls: Option<TlsInfo>,
+    /// protocol actually spoken, e.g. `HTTP2` after ALPN or an h2c upgrade
+    pub version: HTTPVersion,
+    /// originating client behind trusted proxies, see `RealIp`
+    pub client_ip: Option<IpAddr>,
+}
+
+/// negotiated TLS session parameters
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TlsInfo {
+    /// e.g. `TLSv1_3`
+    pub protocol: Option<String>,
+    pub cipher_suite: Option<String>,
+    /// ALPN protocol, e.g. `h2`
+    pub alpn: Option<String>,
+    /// SNI host name sent by the client
+    pub server_name: Option<String>,
+}
+
+impl HTTPRequest {
+    pub fn connection_info(&self) -> Option<&ConnectionInfo> {
+        self.connection.as_ref()
+    }
+
+    /// address of the immediate peer; a proxy's address when behind one
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.connection.as_ref()?.peer_addr
+    }
+
+    /// The originating client's address: what trusted proxies reported when
+    /// `ServerConfig::trusted_proxies` (or a `RealIp` layer) is set up,
+    /// otherwise the peer address
+    pub fn real_ip(&self) -> Option<IpAddr> {
+        let connection = self.connection.as_ref()?;
+        connection
+            .client_ip
+            .or_else(|| connection.peer_addr.map(|addr| addr.ip()))
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.connection.as_ref().is_some_and(|c| c.tls.is_some())
+    }
+}
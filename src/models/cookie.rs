@@ -0,0 +1,102 @@
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// A cookie to send with `Set-Cookie`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    /// seconds; 0 deletes the cookie
+    pub max_age: Option<i64>,
+    pub http_only: bool,
+    pub secure: bool,
+    pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            http_only: false,
+            secure: false,
+            same_site: None,
+        }
+    }
+
+    /// a cookie that tells the browser to drop `name`
+    pub fn removal(name: &str, path: &str) -> Self {
+        let mut cookie = Cookie::new(name, "");
+        cookie.path = Some(path.to_string());
+        cookie.max_age = Some(0);
+        cookie
+    }
+}
+
+impl Display for Cookie {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => write!(f, "; SameSite=Strict")?,
+            Some(SameSite::Lax) => write!(f, "; SameSite=Lax")?,
+            Some(SameSite::None) => write!(f, "; SameSite=None")?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl HTTPRequest {
+    /// cookies from the `Cookie` header, by name
+    pub fn cookies(&self) -> std::collections::HashMap<String, String> {
+        self.headers
+            .get(&HTTPHeaderType::Cookie)
+            .map(|header| {
+                header
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .map(|(k, v)| (k.trim().to_string(), v.trim().trim_matches('"').to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+}
+
+impl HTTPResponse {
+    /// Set `cookie` on the client. Responses carry a single `Set-Cookie`
+    /// header for now, so this replaces any cookie set earlier.
+    pub fn set_cookie(&mut self, cookie: &Cookie) {
+        self.set_header(HTTPHeaderType::SetCookie, cookie.to_string());
+    }
+}
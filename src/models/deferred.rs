@@ -0,0 +1,60 @@
+use crate::middleware::BoxFuture;
+use crate::models::http::HTTPResponse;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// A response that is still being computed when the handler returns, e.g.
+/// while a request is forwarded upstream. The pipeline awaits it in place of
+/// the handler's response, before any middleware sees the result.
+#[derive(Clone, Default)]
+pub struct Deferred {
+    future: Option<Arc<Mutex<Option<BoxFuture<'static, HTTPResponse>>>>>,
+}
+
+impl Deferred {
+    pub fn new<F>(future: F) -> Self
+    where
+        F: Future<Output = HTTPResponse> + Send + 'static,
+    {
+        Deferred {
+            future: Some(Arc::new(Mutex::new(Some(Box::pin(future))))),
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.future.is_some()
+    }
+
+    /// the future, for whoever awaits it; `None` after the first call on any clone
+    pub fn take(&self) -> Option<BoxFuture<'static, HTTPResponse>> {
+        self.future.as_ref().and_then(|future| future.lock().unwrap().take())
+    }
+}
+
+impl Debug for Deferred {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Deferred({})", if self.is_set() { "set" } else { "unset" })
+    }
+}
+
+/// like `Extensions`, not part of a response's identity
+impl PartialEq for Deferred {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Deferred {}
+
+impl HTTPResponse {
+    /// Answer with the response `future` resolves to, letting a sync handler
+    /// do async work. Only honoured when dispatched through a `Pipeline`;
+    /// `Router::route` returns the response as the handler left it.
+    pub fn defer<F>(&mut self, future: F)
+    where
+        F: Future<Output = HTTPResponse> + Send + 'static,
+    {
+        self.deferred = Deferred::new(future);
+    }
+}
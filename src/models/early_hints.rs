@@ -0,0 +1,64 @@
+use crate::models::http::HTTPResponse;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// The way back to the connection a request arrived on, for sending
+/// `103 Early Hints` ahead of the response. The server puts one in the
+/// request's extensions and the router passes it on to the response.
+#[derive(Debug, Clone)]
+pub struct EarlyHints(UnboundedSender<Vec<String>>);
+
+impl EarlyHints {
+    pub(crate) fn channel() -> (Self, UnboundedReceiver<Vec<String>>) {
+        let (sender, receiver) = unbounded_channel();
+        (EarlyHints(sender), receiver)
+    }
+}
+
+impl HTTPResponse {
+    /// Send a `103 Early Hints` with a `Link` header for each of `links`,
+    /// e.g. `</app.css>; rel=preload; as=style`, so browsers can start
+    /// fetching them while this response is still being put together.
+    /// `false` if there is no connection to send it on, e.g. for HTTP/1.0
+    /// clients or outside a server.
+    pub fn early_hints<S: AsRef<str>>(&self, links: &[S]) -> bool {
+        let links: Vec<String> = links
+            .iter()
+            .map(|link| link.as_ref().to_string())
+            .filter(|link| !link.contains(['\r', '\n']))
+            .collect();
+        match self.extensions.get::<EarlyHints>() {
+            Some(hints) if !links.is_empty() => hints.0.send(links).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// What happened first while a request was being handled
+pub(crate) enum Event {
+    Response(HTTPResponse),
+    Hints(Vec<String>),
+    /// the client went away
+    Disconnected,
+}
+
+/// Poll `response` until it is ready, hints arrive or `disconnected` says
+/// the client is gone, whichever is first.
+pub(crate) async fn next<F, D>(mut response: Pin<&mut F>, hints: &mut UnboundedReceiver<Vec<String>>, mut disconnected: D) -> Event
+where
+    F: Future<Output = HTTPResponse>,
+    D: FnMut(&mut Context<'_>) -> Poll<()>,
+{
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(res) = response.as_mut().poll(cx) {
+            return Poll::Ready(Event::Response(res));
+        }
+        if let Poll::Ready(Some(links)) = hints.poll_recv(cx) {
+            return Poll::Ready(Event::Hints(links));
+        }
+        disconnected(cx).map(|()| Event::Disconnected)
+    })
+    .await
+}
@@ -0,0 +1,67 @@
+use crate::models::http::HTTPStatus;
+use std::fmt::{Display, Formatter};
+
+/// What went wrong inside a handler, independent of the status sent back.
+/// Lets layers such as retries decide what is worth trying again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// temporary failure (store unavailable, lock contention), may succeed on retry
+    Transient,
+    /// a dependency did not answer in time
+    Timeout,
+    /// bug or unexpected state, retrying will not help
+    Internal,
+    /// the request itself is at fault
+    Client,
+}
+
+/// Error returned by fallible handlers (`Router::try_bind`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HTTPError {
+    pub status: HTTPStatus,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl HTTPError {
+    pub fn new(status: HTTPStatus, kind: ErrorKind, message: &str) -> Self {
+        HTTPError {
+            status,
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn internal(message: &str) -> Self {
+        Self::new(HTTPStatus::InternalServerError, ErrorKind::Internal, message)
+    }
+
+    pub fn transient(message: &str) -> Self {
+        Self::new(HTTPStatus::InternalServerError, ErrorKind::Transient, message)
+    }
+
+    pub fn timeout(message: &str) -> Self {
+        Self::new(HTTPStatus::InternalServerError, ErrorKind::Timeout, message)
+    }
+
+    pub fn client(status: HTTPStatus, message: &str) -> Self {
+        Self::new(status, ErrorKind::Client, message)
+    }
+}
+
+impl Display for HTTPError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for HTTPError {}
+
+/// the message a panic was raised with, from its payload
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => String::from("unknown panic"),
+    }
+}
@@ -0,0 +1,691 @@
+use crate::mime::Mime;
+use crate::models::error::HTTPError;
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse, HTTPStatus};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Media type as found in `Content-Type`, e.g. `text/html; charset=utf-8`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType {
+    pub kind: String,
+    pub subtype: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl MediaType {
+    pub fn new(kind: &str, subtype: &str) -> Self {
+        MediaType {
+            kind: kind.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            params: Vec::new(),
+        }
+    }
+
+    /// `type/subtype` without parameters
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.kind, self.subtype)
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+}
+
+impl FromStr for MediaType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = s.split(';');
+        let essence = parts.next().unwrap_or("").trim();
+        let (kind, subtype) = essence
+            .split_once('/')
+            .ok_or_else(|| format!("Invalid media type: {}", s))?;
+        if !is_token(kind) || !is_token(subtype) {
+            return Err(format!("Invalid media type: {}", s));
+        }
+        let mut media_type = MediaType::new(kind, subtype);
+        for param in parts {
+            let param = param.trim();
+            if param.is_empty() {
+                continue;
+            }
+            let (name, value) = param
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid media type parameter: {}", param))?;
+            let value = value.trim().trim_matches('"');
+            media_type
+                .params
+                .push((name.trim().to_ascii_lowercase(), value.to_string()));
+        }
+        Ok(media_type)
+    }
+}
+
+impl From<&MediaType> for MediaType {
+    fn from(media_type: &MediaType) -> Self {
+        media_type.clone()
+    }
+}
+
+impl Display for MediaType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.kind, self.subtype)?;
+        for (name, value) in &self.params {
+            if is_token(value) {
+                write!(f, "; {}={}", name, value)?;
+            } else {
+                write!(f, "; {}=\"{}\"", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Entity tag as used by `ETag`, `If-Match` and `If-None-Match`.
+/// The `*` wildcard parses to a strong tag for which `is_any()` is true.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityTag {
+    pub weak: bool,
+    pub tag: String,
+}
+
+impl EntityTag {
+    pub fn strong(tag: &str) -> Self {
+        EntityTag {
+            weak: false,
+            tag: tag.to_string(),
+        }
+    }
+
+    pub fn weak(tag: &str) -> Self {
+        EntityTag {
+            weak: true,
+            tag: tag.to_string(),
+        }
+    }
+
+    pub fn any() -> Self {
+        EntityTag::strong("*")
+    }
+
+    pub fn is_any(&self) -> bool {
+        !self.weak && self.tag == "*"
+    }
+
+    /// strong comparison, RFC 9110 8.8.3.2
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// weak comparison, RFC 9110 8.8.3.2
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// parse a comma separated list, skipping malformed members
+    pub fn parse_list(value: &str) -> Vec<EntityTag> {
+        value
+            .split(',')
+            .filter_map(|tag| EntityTag::from_str(tag.trim()).ok())
+            .collect()
+    }
+}
+
+impl FromStr for EntityTag {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s == "*" {
+            return Ok(EntityTag::any());
+        }
+        let (weak, quoted) = match s.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let tag = quoted
+            .strip_prefix('"')
+            .and_then(|t| t.strip_suffix('"'))
+            .ok_or_else(|| format!("Invalid entity tag: {}", s))?;
+        if tag.contains('"') {
+            return Err(format!("Invalid entity tag: {}", s));
+        }
+        Ok(EntityTag {
+            weak,
+            tag: tag.to_string(),
+        })
+    }
+}
+
+impl Display for EntityTag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_any() {
+            write!(f, "*")
+        } else if self.weak {
+            write!(f, "W/\"{}\"", self.tag)
+        } else {
+            write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// The directives of a `Cache-Control` header that caches act on
+/// (RFC 9111 5.2). Unknown directives are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub private: bool,
+    pub public: bool,
+    /// seconds
+    pub max_age: Option<u64>,
+    /// seconds, for shared caches only
+    pub s_maxage: Option<u64>,
+}
+
+impl CacheControl {
+    pub fn parse(value: &str) -> Self {
+        let mut directives = CacheControl::default();
+        for directive in split_list(value, ',') {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            let seconds = argument.and_then(|argument| argument.parse().ok());
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => directives.no_store = true,
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "max-age" => directives.max_age = seconds,
+                "s-maxage" => directives.s_maxage = seconds,
+                _ => {}
+            }
+        }
+        directives
+    }
+}
+
+/// One range of a `Range: bytes=...` header (RFC 9110 14.1.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`, both inclusive
+    FromTo(u64, u64),
+    /// `first-`, to the end
+    From(u64),
+    /// `-n`, the last n bytes
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Parse a `Range` header value. `None` if it isn't a well-formed `bytes`
+    /// range set, in which case the header is to be ignored.
+    pub fn parse_set(value: &str) -> Option<Vec<ByteRange>> {
+        let (unit, set) = value.split_once('=')?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+        set.split(',')
+            .filter(|spec| !spec.trim().is_empty())
+            .map(|spec| {
+                let (first, last) = spec.trim().split_once('-')?;
+                match (first.trim(), last.trim()) {
+                    ("", suffix) => Some(ByteRange::Suffix(suffix.parse().ok()?)),
+                    (first, "") => Some(ByteRange::From(first.parse().ok()?)),
+                    (first, last) => {
+                        let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+                        (first <= last).then_some(ByteRange::FromTo(first, last))
+                    }
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|ranges| !ranges.is_empty())
+    }
+
+    /// inclusive `(first, last)` offsets within a representation of `length`
+    /// bytes, or `None` when the range is unsatisfiable
+    pub fn resolve(&self, length: u64) -> Option<(u64, u64)> {
+        match *self {
+            ByteRange::FromTo(first, last) if first < length => Some((first, last.min(length - 1))),
+            ByteRange::From(first) if first < length => Some((first, length - 1)),
+            ByteRange::Suffix(n) if n > 0 && length > 0 => Some((length.saturating_sub(n), length - 1)),
+            _ => None,
+        }
+    }
+}
+
+/// Members of a list header with `;q=` weights such as `Accept-Encoding`,
+/// highest quality first (ties keep their order). A missing or malformed
+/// weight counts as 1.
+pub fn quality_list(value: &str) -> Vec<(String, f32)> {
+    let mut items: Vec<(String, f32)> = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let name = parts.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let quality = parts
+                .filter_map(|param| param.trim().split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            Some((name.to_string(), quality))
+        })
+        .collect();
+    items.sort_by(|a, b| b.1.total_cmp(&a.1));
+    items
+}
+
+/// One member of an `Accept` header, e.g. `text/html;level=1;q=0.8`:
+/// a media type that may have `*` for its subtype or for both parts, and
+/// its weight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    pub media_type: MediaType,
+    pub quality: f32,
+}
+
+impl MediaRange {
+    /// Parse an `Accept` value, highest quality first and, among equal
+    /// weights, the more specific ranges first. Malformed members are
+    /// skipped and a malformed weight counts as 1.
+    pub fn parse_list(value: &str) -> Vec<MediaRange> {
+        let mut ranges: Vec<MediaRange> = split_list(value, ',')
+            .into_iter()
+            .filter_map(|item| item.parse().ok())
+            .collect();
+        ranges.sort_by(|a, b| b.quality.total_cmp(&a.quality).then(b.specificity().cmp(&a.specificity())));
+        ranges
+    }
+
+    /// whether `media_type` falls in this range, parameters of the range
+    /// included
+    pub fn matches(&self, media_type: &MediaType) -> bool {
+        let range = &self.media_type;
+        (range.kind == "*" || range.kind == media_type.kind)
+            && (range.subtype == "*" || range.subtype == media_type.subtype)
+            && range.params.iter().all(|(name, value)| {
+                media_type.param(name).is_some_and(|v| v.eq_ignore_ascii_case(value))
+            })
+    }
+
+    /// `*/*` 0, `text/*` 1, `text/html` 2, with parameters 3
+    fn specificity(&self) -> u8 {
+        match (self.media_type.kind.as_str(), self.media_type.subtype.as_str()) {
+            ("*", _) => 0,
+            (_, "*") => 1,
+            _ if self.media_type.params.is_empty() => 2,
+            _ => 3,
+        }
+    }
+}
+
+impl FromStr for MediaRange {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut media_type: MediaType = s.parse()?;
+        if media_type.kind == "*" && media_type.subtype != "*" {
+            return Err(format!("Invalid media range: {}", s));
+        }
+        // parameters after `q` are accept extensions, not part of the type
+        let mut quality = 1.0;
+        if let Some(q) = media_type.params.iter().position(|(name, _)| name == "q") {
+            quality = media_type.params[q].1.parse::<f32>().unwrap_or(1.0).clamp(0.0, 1.0);
+            media_type.params.truncate(q);
+        }
+        Ok(MediaRange { media_type, quality })
+    }
+}
+
+/// Request headers a response came to depend on, noted with
+/// `HTTPRequest::vary_on` as the request goes through the pipeline and
+/// added to the response's `Vary` at the end
+#[derive(Debug, Clone, Default)]
+pub(crate) struct VaryOn(pub(crate) Arc<Mutex<Vec<HTTPHeaderType>>>);
+
+/// Whether language `range` (e.g. `de` or `*`) takes `tag` (e.g. `de-CH`):
+/// basic filtering, RFC 4647 3.3.1
+pub fn language_matches(range: &str, tag: &str) -> bool {
+    range == "*"
+        || tag.eq_ignore_ascii_case(range)
+        || (tag.len() > range.len()
+            && tag.as_bytes()[range.len()] == b'-'
+            && tag[..range.len()].eq_ignore_ascii_case(range))
+}
+
+/// Add the value of a repeated header line to what `headers` holds, the
+/// way RFC 9110 5.3 combines them: as a comma-separated list, `Cookie`
+/// with `; ` (RFC 6265 5.4). `Host` and `Content-Length`, which can't be
+/// lists, keep the first value.
+pub(crate) fn append_header(
+    headers: &mut std::collections::HashMap<HTTPHeaderType, String>,
+    header: HTTPHeaderType,
+    value: String,
+) {
+    let separator = match header {
+        HTTPHeaderType::Cookie => "; ",
+        HTTPHeaderType::Host | HTTPHeaderType::ContentLength if headers.contains_key(&header) => return,
+        _ => ", ",
+    };
+    match headers.get_mut(&header) {
+        Some(existing) if !existing.is_empty() && !value.is_empty() => {
+            existing.push_str(separator);
+            existing.push_str(&value);
+        }
+        Some(existing) if existing.is_empty() => *existing = value,
+        Some(_) => {}
+        None => {
+            headers.insert(header, value);
+        }
+    }
+}
+
+/// the items of a `separator`-separated list, trimmed and non-empty,
+/// leaving separators inside quoted strings alone
+fn split_list(value: &str, separator: char) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                items.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(value[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// whether `name` can be a header name: a token (RFC 9110 5.1)
+pub fn valid_header_name(name: &str) -> bool {
+    is_token(name)
+}
+
+/// whether `value` can be a header value: no CR, LF, NUL or other control
+/// characters except tab (RFC 9110 5.5), so it can't end the header early
+pub fn valid_header_value(value: &str) -> bool {
+    value.bytes().all(|b| b == b'\t' || (b >= 0x20 && b != 0x7f))
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+impl HTTPRequest {
+    pub fn header(&self, header: &HTTPHeaderType) -> Option<&str> {
+        self.headers.get(header).map(|v| v.as_str())
+    }
+
+    /// Every value of `header`, from repeated lines and comma-separated
+    /// lists alike (commas in quoted strings kept), e.g. each hop of `Via`;
+    /// `Cookie` splits on `;`. Not meant for headers whose single value
+    /// holds commas, like dates.
+    pub fn header_values(&self, header: &HTTPHeaderType) -> Vec<&str> {
+        let separator = if *header == HTTPHeaderType::Cookie { ';' } else { ',' };
+        self.header(header).map(|value| split_list(value, separator)).unwrap_or_default()
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.header(&HTTPHeaderType::ContentLength)?.trim().parse().ok()
+    }
+
+    pub fn content_type(&self) -> Option<MediaType> {
+        self.header(&HTTPHeaderType::ContentType)?.parse().ok()
+    }
+
+    pub fn host(&self) -> Option<&str> {
+        self.header(&HTTPHeaderType::Host)
+    }
+
+    /// The host the request is for, without port: from `Host`, or from
+    /// TLS SNI when there is no `Host`
+    pub fn hostname(&self) -> Option<&str> {
+        match self.host() {
+            Some(host) => Some(strip_port(host.trim())),
+            None => self.connection.as_ref()?.tls.as_ref()?.server_name.as_deref(),
+        }
+    }
+
+    pub fn if_match(&self) -> Vec<EntityTag> {
+        self.header(&HTTPHeaderType::IfMatch)
+            .map(EntityTag::parse_list)
+            .unwrap_or_default()
+    }
+
+    pub fn if_none_match(&self) -> Vec<EntityTag> {
+        self.header(&HTTPHeaderType::IfNoneMatch)
+            .map(EntityTag::parse_list)
+            .unwrap_or_default()
+    }
+
+    pub fn if_modified_since(&self) -> Option<SystemTime> {
+        httpdate::parse_http_date(self.header(&HTTPHeaderType::IfModifiedSince)?).ok()
+    }
+
+    pub fn if_unmodified_since(&self) -> Option<SystemTime> {
+        httpdate::parse_http_date(self.header(&HTTPHeaderType::IfUnmodifiedSince)?).ok()
+    }
+
+    /// Whether `Accept-Encoding` allows `coding` (e.g. `gzip`), explicitly or
+    /// through `*`. Without the header only `identity` is assumed.
+    pub fn accepts_encoding(&self, coding: &str) -> bool {
+        self.vary_on(&HTTPHeaderType::AcceptEncoding);
+        let accept = match self.header(&HTTPHeaderType::AcceptEncoding) {
+            Some(accept) => quality_list(accept),
+            None => return coding.eq_ignore_ascii_case("identity"),
+        };
+        let quality = |name: &str| {
+            accept
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, q)| *q)
+        };
+        match quality(coding).or_else(|| quality("*")) {
+            Some(q) => q > 0.0,
+            None => coding.eq_ignore_ascii_case("identity"),
+        }
+    }
+
+    /// Note that the response depends on `header`, so it ends up in the
+    /// response's `Vary` once the request has been through the pipeline.
+    /// `accept`, `negotiate`, `preferred_language` and `accepts_encoding`
+    /// do this themselves; handlers and layers that branch on other request
+    /// headers, e.g. `Origin`, call it.
+    pub fn vary_on(&self, header: &HTTPHeaderType) {
+        if let Some(vary) = self.extensions.get::<VaryOn>() {
+            let mut headers = vary.0.lock().unwrap();
+            if !headers.contains(header) {
+                headers.push(header.clone());
+            }
+        }
+    }
+
+    /// the media ranges of `Accept`, best first; `*/*` without the header
+    pub fn accept(&self) -> Vec<MediaRange> {
+        self.vary_on(&HTTPHeaderType::Accept);
+        match self.header(&HTTPHeaderType::Accept) {
+            Some(accept) => MediaRange::parse_list(accept),
+            None => vec![MediaRange {
+                media_type: MediaType::new("*", "*"),
+                quality: 1.0,
+            }],
+        }
+    }
+
+    /// Pick which of `offered` to send, going by `Accept` (RFC 9110 12.5.1):
+    /// each type gets the weight of the most specific range it falls in and
+    /// the heaviest wins, earlier offers winning ties. 406 if the client
+    /// takes none of them.
+    ///
+    /// `let mime = req.negotiate(&[Mime::APPLICATION_JSON, Mime::TEXT_HTML])?;`
+    pub fn negotiate(&self, offered: &[Mime]) -> Result<Mime, HTTPError> {
+        let ranges = self.accept();
+        let mut best: Option<(Mime, f32)> = None;
+        for mime in offered {
+            let media_type = mime.media_type();
+            let quality = ranges
+                .iter()
+                .filter(|range| range.matches(&media_type))
+                .max_by_key(|range| range.specificity())
+                .map_or(0.0, |range| range.quality);
+            if quality > 0.0 && !best.is_some_and(|(_, q)| q >= quality) {
+                best = Some((*mime, quality));
+            }
+        }
+        best.map(|(mime, _)| mime).ok_or_else(|| {
+            HTTPError::client(HTTPStatus::NotAcceptable, "none of the available content types is acceptable")
+        })
+    }
+
+    /// The best of `supported` language tags (e.g. `["en", "fr-CA"]`) for
+    /// `Accept-Language`, going through its ranges by weight. A range picks
+    /// the first supported tag it is a prefix of (`fr` takes `fr-CA`), or
+    /// failing that, the longest supported prefix of itself (`en-GB` takes
+    /// `en`, RFC 4647 3.4). `None` without the header or a match.
+    pub fn preferred_language<'a>(&self, supported: &[&'a str]) -> Option<&'a str> {
+        self.vary_on(&HTTPHeaderType::AcceptLanguage);
+        let accept = quality_list(self.header(&HTTPHeaderType::AcceptLanguage)?);
+        let excluded = |tag: &str| {
+            accept
+                .iter()
+                .any(|(range, q)| *q == 0.0 && range != "*" && language_matches(range, tag))
+        };
+        let supported: Vec<&'a str> = supported.iter().copied().filter(|tag| !excluded(tag)).collect();
+        for (range, _) in accept.iter().filter(|(_, q)| *q > 0.0) {
+            if range == "*" {
+                return supported.first().copied();
+            }
+            if let Some(tag) = supported.iter().find(|tag| language_matches(range, tag)) {
+                return Some(tag);
+            }
+            let mut prefix = range.as_str();
+            while let Some((shorter, _)) = prefix.rsplit_once('-') {
+                prefix = shorter;
+                if let Some(tag) = supported.iter().find(|tag| tag.eq_ignore_ascii_case(prefix)) {
+                    return Some(tag);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn cache_control(&self) -> CacheControl {
+        self.header(&HTTPHeaderType::CacheControl).map(CacheControl::parse).unwrap_or_default()
+    }
+
+    /// the requested byte ranges; `None` without a (valid) `Range` header
+    pub fn range(&self) -> Option<Vec<ByteRange>> {
+        ByteRange::parse_set(self.header(&HTTPHeaderType::Range)?)
+    }
+}
+
+impl HTTPResponse {
+    pub fn header(&self, header: &HTTPHeaderType) -> Option<&str> {
+        self.headers.get(header).map(|v| v.as_str())
+    }
+
+    pub fn set_header(&mut self, header: HTTPHeaderType, value: impl Into<String>) {
+        self.headers.insert(header, value.into());
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.header(&HTTPHeaderType::ContentLength)?.trim().parse().ok()
+    }
+
+    pub fn set_content_length(&mut self, length: u64) {
+        self.set_header(HTTPHeaderType::ContentLength, length.to_string());
+    }
+
+    pub fn content_type(&self) -> Option<MediaType> {
+        self.header(&HTTPHeaderType::ContentType)?.parse().ok()
+    }
+
+    /// e.g. `res.set_content_type(Mime::APPLICATION_JSON)`
+    pub fn set_content_type(&mut self, media_type: impl Into<MediaType>) {
+        self.set_header(HTTPHeaderType::ContentType, media_type.into().to_string());
+    }
+
+    /// JSON-encode `value` as the body
+    pub fn json<T: serde::Serialize>(&mut self, value: &T) -> Result<(), crate::models::error::HTTPError> {
+        let body = serde_json::to_string(value)
+            .map_err(|e| crate::models::error::HTTPError::internal(&e.to_string()))?;
+        self.body = Some(body);
+        self.raw_body = None;
+        self.set_content_type(crate::mime::Mime::APPLICATION_JSON);
+        Ok(())
+    }
+
+    pub fn html(&mut self, body: impl Into<String>) {
+        self.body = Some(body.into());
+        self.raw_body = None;
+        self.set_content_type(crate::mime::Mime::TEXT_HTML);
+    }
+
+    pub fn text(&mut self, body: impl Into<String>) {
+        self.body = Some(body.into());
+        self.raw_body = None;
+        self.set_content_type(crate::mime::Mime::TEXT_PLAIN);
+    }
+
+    pub fn set_location(&mut self, url: &str) {
+        self.set_header(HTTPHeaderType::Location, url);
+    }
+
+    /// Add `header` to `Vary`, unless it is already there or `Vary` is `*`,
+    /// for responses that depend on that request header.
+    pub fn add_vary(&mut self, header: &str) {
+        let vary = match self.header(&HTTPHeaderType::Vary) {
+            Some(vary) if vary.split(',').any(|v| v.trim().eq_ignore_ascii_case(header) || v.trim() == "*") => return,
+            Some(vary) => format!("{}, {}", vary, header),
+            None => header.to_string(),
+        };
+        self.set_header(HTTPHeaderType::Vary, vary);
+    }
+
+    pub fn cache_control(&self) -> CacheControl {
+        self.header(&HTTPHeaderType::CacheControl).map(CacheControl::parse).unwrap_or_default()
+    }
+
+    pub fn etag(&self) -> Option<EntityTag> {
+        self.header(&HTTPHeaderType::ETag)?.parse().ok()
+    }
+
+    pub fn set_etag(&mut self, etag: &EntityTag) {
+        self.set_header(HTTPHeaderType::ETag, etag.to_string());
+    }
+
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        httpdate::parse_http_date(self.header(&HTTPHeaderType::LastModified)?).ok()
+    }
+
+    pub fn set_last_modified(&mut self, time: SystemTime) {
+        self.set_header(HTTPHeaderType::LastModified, httpdate::fmt_http_date(time));
+    }
+}
+
+/// `example.com:8080` -> `example.com`, leaving IPv6 literals intact
+pub(crate) fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return host.split_once(']').map(|(h, _)| &host[..h.len() + 1]).unwrap_or(host);
+    }
+    host.split_once(':').map(|(h, _)| h).unwrap_or(host)
+}
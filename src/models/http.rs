@@ -1,20 +1,77 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
-use std::io::{BufRead, BufReader, Cursor, Read};
+use std::io::Write;
+use crate::models::parse::{parse_head, text, ParseError, RequestHead};
 use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
 pub struct HTTPRequest {
     pub method: HTTPMethod,
     pub url: String,
     pub version: HTTPVersion,
     pub headers: std::collections::HashMap<HTTPHeaderType, String>,
     pub body: Option<String>,
+    /// the body's bytes when they aren't valid UTF-8, which `body` holds
+    /// with the invalid parts replaced; see `HTTPRequest::set_body_bytes`
+    #[serde(skip)]
+    pub raw_body: Option<Vec<u8>>,
+    /// header lines with their original casing, in arrival order.
+    /// only filled when parsed with `new_preserving_headers`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub raw_headers: Vec<(String, String)>,
+    /// peer/local address, TLS and protocol details, set by the server
+    #[serde(skip)]
+    pub connection: Option<crate::models::connection::ConnectionInfo>,
+    /// typed values attached by middleware for later layers and the handler
+    #[serde(skip)]
+    pub extensions: crate::models::extensions::Extensions,
 }
 
 impl HTTPRequest {
+    /// parse `data`, panicking if it's malformed; `models::parse::parse_request`
+    /// reports that instead
     pub fn new(data: String) -> HTTPRequest {
-        parse_http_request(data)
+        parse_http_request(data, false)
+    }
+
+    /// like `new`, but also keeps the original header names and order in `raw_headers`
+    pub fn new_preserving_headers(data: String) -> HTTPRequest {
+        parse_http_request(data, true)
+    }
+
+    /// the body as it arrived: `raw_body` if set, else `body`
+    pub fn body_bytes(&self) -> &[u8] {
+        match (&self.raw_body, &self.body) {
+            (Some(raw), _) => raw,
+            (None, Some(body)) => body.as_bytes(),
+            (None, None) => &[],
+        }
+    }
+
+    /// Set the body from bytes off the wire: `body` as text, and `raw_body`
+    /// with the bytes themselves when they aren't valid UTF-8, so they can
+    /// be passed on intact. No bytes leave no body.
+    pub fn set_body_bytes(&mut self, bytes: Vec<u8>) {
+        (self.body, self.raw_body) = match String::from_utf8(bytes) {
+            Ok(body) => ((!body.is_empty()).then_some(body), None),
+            Err(e) => (Some(text(e.as_bytes()).into_owned()), Some(e.into_bytes())),
+        };
+    }
+
+    /// request line and headers as they would go on the wire. Uses the
+    /// original casing and order when `raw_headers` was preserved.
+    pub fn head_to_string(&self) -> String {
+        let mut head = format!("{} {} {}\r\n", self.method, self.url, self.version);
+        let headers: Vec<(String, &str)> = if self.raw_headers.is_empty() {
+            self.headers.iter().map(|(key, value)| (key.to_string(), value.as_str())).collect()
+        } else {
+            self.raw_headers.iter().map(|(key, value)| (key.clone(), value.as_str())).collect()
+        };
+        for (key, value) in headers {
+            push_header(&mut head, &key, value);
+        }
+        head.push_str("\r\n");
+        head
     }
 
     pub fn method(&self) -> HTTPMethod {
@@ -24,36 +81,71 @@ impl HTTPRequest {
 
 impl Display for HTTPRequest {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "HTTPRequest {{ method: {:?}, url: {:?}, version: {:?}, headers: {:?}, body: {:?} }}",
-            self.method, self.url, self.version, self.headers, self.body
-        )
+        if self.raw_headers.is_empty() {
+            write!(
+                f,
+                "HTTPRequest {{ method: {:?}, url: {:?}, version: {:?}, headers: {:?}, body: {:?} }}",
+                self.method, self.url, self.version, self.headers, self.body
+            )
+        } else {
+            write!(
+                f,
+                "HTTPRequest {{ method: {:?}, url: {:?}, version: {:?}, headers: {:?}, body: {:?} }}",
+                self.method, self.url, self.version, self.raw_headers, self.body
+            )
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub enum HTTPMethod {
+    #[default]
     GET,
+    HEAD,
     POST,
+    PUT,
     PATCH,
     DELETE,
+    OPTIONS,
+    /// opens a tunnel to the host:port in the URL, see `proxy::ForwardProxy`
+    CONNECT,
 }
 impl FromStr for HTTPMethod {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, String> {
         match s.to_uppercase().as_str() {
             "GET" => Ok(HTTPMethod::GET),
+            "HEAD" => Ok(HTTPMethod::HEAD),
             "POST" => Ok(HTTPMethod::POST),
+            "PUT" => Ok(HTTPMethod::PUT),
             "PATCH" => Ok(HTTPMethod::PATCH),
             "DELETE" => Ok(HTTPMethod::DELETE),
+            "OPTIONS" => Ok(HTTPMethod::OPTIONS),
+            "CONNECT" => Ok(HTTPMethod::CONNECT),
             _ => Err(format!("Invalid HTTP method: {}", s)),
         }
     }
 }
+impl HTTPMethod {
+    /// RFC 9110 9.2.2: repeating the request has the same effect as sending it once
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            HTTPMethod::GET | HTTPMethod::HEAD | HTTPMethod::PUT | HTTPMethod::DELETE | HTTPMethod::OPTIONS
+        )
+    }
+}
+impl Display for HTTPMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
 pub enum HTTPVersion {
+    /// answered without keep-alive, chunked bodies or interim responses
+    HTTP1_0,
+    #[default]
     HTTP1_1,
     HTTP2,
     HTTP3,
@@ -62,6 +154,7 @@ impl FromStr for HTTPVersion {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, String> {
         match s.to_uppercase().trim() {
+            "HTTP/1.0" => Ok(HTTPVersion::HTTP1_0),
             "HTTP/1.1" => Ok(HTTPVersion::HTTP1_1),
             "HTTP/2" => Ok(HTTPVersion::HTTP2),
             "HTTP/3" => Ok(HTTPVersion::HTTP3),
@@ -69,6 +162,16 @@ impl FromStr for HTTPVersion {
         }
     }
 }
+impl Display for HTTPVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HTTPVersion::HTTP1_0 => write!(f, "HTTP/1.0"),
+            HTTPVersion::HTTP1_1 => write!(f, "HTTP/1.1"),
+            HTTPVersion::HTTP2 => write!(f, "HTTP/2"),
+            HTTPVersion::HTTP3 => write!(f, "HTTP/3"),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum HTTPHeaderType {
@@ -177,6 +280,10 @@ pub enum HTTPHeaderType {
     SecWebSocketAccept,
     SecWebSocketVersion,
     SecWebSocketProtocol,
+    SecWebSocketExtensions,
+
+    // --- Server-sent events ---
+    LastEventId,
 
     // --- Forwarding / proxies ---
     Forwarded,
@@ -193,145 +300,157 @@ pub enum HTTPHeaderType {
     Other(String),
 }
 
-impl Display for HTTPHeaderType {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl HTTPHeaderType {
+    /// The name as it goes on the wire: a static string for every known
+    /// header, so writing one takes no formatting
+    pub fn as_str(&self) -> &str {
         match self {
             // --- Content negotiation ---
-            HTTPHeaderType::Accept => write!(f, "Accept"),
-            HTTPHeaderType::AcceptCharset => write!(f, "Accept-Charset"),
-            HTTPHeaderType::AcceptEncoding => write!(f, "Accept-Encoding"),
-            HTTPHeaderType::AcceptLanguage => write!(f, "Accept-Language"),
+            HTTPHeaderType::Accept => "Accept",
+            HTTPHeaderType::AcceptCharset => "Accept-Charset",
+            HTTPHeaderType::AcceptEncoding => "Accept-Encoding",
+            HTTPHeaderType::AcceptLanguage => "Accept-Language",
 
             // --- Request metadata ---
-            HTTPHeaderType::Authorization => write!(f, "Authorization"),
-            HTTPHeaderType::ProxyAuthorization => write!(f, "Proxy-Authorization"),
-            HTTPHeaderType::UserAgent => write!(f, "User-Agent"),
-            HTTPHeaderType::Referer => write!(f, "Referer"),
-            HTTPHeaderType::Origin => write!(f, "Origin"),
-            HTTPHeaderType::Host => write!(f, "Host"),
+            HTTPHeaderType::Authorization => "Authorization",
+            HTTPHeaderType::ProxyAuthorization => "Proxy-Authorization",
+            HTTPHeaderType::UserAgent => "User-Agent",
+            HTTPHeaderType::Referer => "Referer",
+            HTTPHeaderType::Origin => "Origin",
+            HTTPHeaderType::Host => "Host",
 
             // --- Content description ---
-            HTTPHeaderType::ContentType => write!(f, "Content-Type"),
-            HTTPHeaderType::ContentLength => write!(f, "Content-Length"),
-            HTTPHeaderType::ContentEncoding => write!(f, "Content-Encoding"),
-            HTTPHeaderType::ContentLanguage => write!(f, "Content-Language"),
-            HTTPHeaderType::ContentLocation => write!(f, "Content-Location"),
-            HTTPHeaderType::ContentDisposition => write!(f, "Content-Disposition"),
-            HTTPHeaderType::ContentRange => write!(f, "Content-Range"),
+            HTTPHeaderType::ContentType => "Content-Type",
+            HTTPHeaderType::ContentLength => "Content-Length",
+            HTTPHeaderType::ContentEncoding => "Content-Encoding",
+            HTTPHeaderType::ContentLanguage => "Content-Language",
+            HTTPHeaderType::ContentLocation => "Content-Location",
+            HTTPHeaderType::ContentDisposition => "Content-Disposition",
+            HTTPHeaderType::ContentRange => "Content-Range",
 
             // --- Caching ---
-            HTTPHeaderType::CacheControl => write!(f, "Cache-Control"),
-            HTTPHeaderType::Pragma => write!(f, "Pragma"),
-            HTTPHeaderType::Expires => write!(f, "Expires"),
-            HTTPHeaderType::ETag => write!(f, "ETag"),
-            HTTPHeaderType::IfMatch => write!(f, "If-Match"),
-            HTTPHeaderType::IfNoneMatch => write!(f, "If-None-Match"),
-            HTTPHeaderType::IfModifiedSince => write!(f, "If-Modified-Since"),
-            HTTPHeaderType::IfUnmodifiedSince => write!(f, "If-Unmodified-Since"),
-            HTTPHeaderType::IfRange => write!(f, "If-Range"),
-            HTTPHeaderType::LastModified => write!(f, "Last-Modified"),
-            HTTPHeaderType::Age => write!(f, "Age"),
-            HTTPHeaderType::Vary => write!(f, "Vary"),
+            HTTPHeaderType::CacheControl => "Cache-Control",
+            HTTPHeaderType::Pragma => "Pragma",
+            HTTPHeaderType::Expires => "Expires",
+            HTTPHeaderType::ETag => "ETag",
+            HTTPHeaderType::IfMatch => "If-Match",
+            HTTPHeaderType::IfNoneMatch => "If-None-Match",
+            HTTPHeaderType::IfModifiedSince => "If-Modified-Since",
+            HTTPHeaderType::IfUnmodifiedSince => "If-Unmodified-Since",
+            HTTPHeaderType::IfRange => "If-Range",
+            HTTPHeaderType::LastModified => "Last-Modified",
+            HTTPHeaderType::Age => "Age",
+            HTTPHeaderType::Vary => "Vary",
 
             // --- Connection / transport ---
-            HTTPHeaderType::Connection => write!(f, "Connection"),
-            HTTPHeaderType::KeepAlive => write!(f, "Keep-Alive"),
-            HTTPHeaderType::TransferEncoding => write!(f, "Transfer-Encoding"),
-            HTTPHeaderType::Upgrade => write!(f, "Upgrade"),
-            HTTPHeaderType::Via => write!(f, "Via"),
+            HTTPHeaderType::Connection => "Connection",
+            HTTPHeaderType::KeepAlive => "Keep-Alive",
+            HTTPHeaderType::TransferEncoding => "Transfer-Encoding",
+            HTTPHeaderType::Upgrade => "Upgrade",
+            HTTPHeaderType::Via => "Via",
 
             // --- Cookies ---
-            HTTPHeaderType::Cookie => write!(f, "Cookie"),
-            HTTPHeaderType::SetCookie => write!(f, "Set-Cookie"),
+            HTTPHeaderType::Cookie => "Cookie",
+            HTTPHeaderType::SetCookie => "Set-Cookie",
 
             // --- Redirection ---
-            HTTPHeaderType::Location => write!(f, "Location"),
+            HTTPHeaderType::Location => "Location",
 
             // --- Range requests ---
-            HTTPHeaderType::Range => write!(f, "Range"),
-            HTTPHeaderType::AcceptRanges => write!(f, "Accept-Ranges"),
+            HTTPHeaderType::Range => "Range",
+            HTTPHeaderType::AcceptRanges => "Accept-Ranges",
 
             // --- Security ---
-            HTTPHeaderType::WWWAuthenticate => write!(f, "WWW-Authenticate"),
-            HTTPHeaderType::ProxyAuthenticate => write!(f, "Proxy-Authenticate"),
-            HTTPHeaderType::StrictTransportSecurity => write!(f, "Strict-Transport-Security"),
-            HTTPHeaderType::ContentSecurityPolicy => write!(f, "Content-Security-Policy"),
-            HTTPHeaderType::ContentSecurityPolicyReportOnly => {
-                write!(f, "Content-Security-Policy-Report-Only")
-            }
-            HTTPHeaderType::XContentTypeOptions => write!(f, "X-Content-Type-Options"),
-            HTTPHeaderType::XFrameOptions => write!(f, "X-Frame-Options"),
-            HTTPHeaderType::XXSSProtection => write!(f, "X-XSS-Protection"),
-            HTTPHeaderType::ReferrerPolicy => write!(f, "Referrer-Policy"),
-            HTTPHeaderType::PermissionsPolicy => write!(f, "Permissions-Policy"),
-            HTTPHeaderType::ExpectCT => write!(f, "Expect-CT"),
+            HTTPHeaderType::WWWAuthenticate => "WWW-Authenticate",
+            HTTPHeaderType::ProxyAuthenticate => "Proxy-Authenticate",
+            HTTPHeaderType::StrictTransportSecurity => "Strict-Transport-Security",
+            HTTPHeaderType::ContentSecurityPolicy => "Content-Security-Policy",
+            HTTPHeaderType::ContentSecurityPolicyReportOnly => "Content-Security-Policy-Report-Only",
+            HTTPHeaderType::XContentTypeOptions => "X-Content-Type-Options",
+            HTTPHeaderType::XFrameOptions => "X-Frame-Options",
+            HTTPHeaderType::XXSSProtection => "X-XSS-Protection",
+            HTTPHeaderType::ReferrerPolicy => "Referrer-Policy",
+            HTTPHeaderType::PermissionsPolicy => "Permissions-Policy",
+            HTTPHeaderType::ExpectCT => "Expect-CT",
 
             // --- CORS ---
-            HTTPHeaderType::AccessControlAllowOrigin => write!(f, "Access-Control-Allow-Origin"),
-            HTTPHeaderType::AccessControlAllowMethods => write!(f, "Access-Control-Allow-Methods"),
-            HTTPHeaderType::AccessControlAllowHeaders => write!(f, "Access-Control-Allow-Headers"),
-            HTTPHeaderType::AccessControlAllowCredentials => {
-                write!(f, "Access-Control-Allow-Credentials")
-            }
-            HTTPHeaderType::AccessControlExposeHeaders => {
-                write!(f, "Access-Control-Expose-Headers")
-            }
-            HTTPHeaderType::AccessControlMaxAge => write!(f, "Access-Control-Max-Age"),
-            HTTPHeaderType::AccessControlRequestMethod => {
-                write!(f, "Access-Control-Request-Method")
-            }
-            HTTPHeaderType::AccessControlRequestHeaders => {
-                write!(f, "Access-Control-Request-Headers")
-            }
+            HTTPHeaderType::AccessControlAllowOrigin => "Access-Control-Allow-Origin",
+            HTTPHeaderType::AccessControlAllowMethods => "Access-Control-Allow-Methods",
+            HTTPHeaderType::AccessControlAllowHeaders => "Access-Control-Allow-Headers",
+            HTTPHeaderType::AccessControlAllowCredentials => "Access-Control-Allow-Credentials",
+            HTTPHeaderType::AccessControlExposeHeaders => "Access-Control-Expose-Headers",
+            HTTPHeaderType::AccessControlMaxAge => "Access-Control-Max-Age",
+            HTTPHeaderType::AccessControlRequestMethod => "Access-Control-Request-Method",
+            HTTPHeaderType::AccessControlRequestHeaders => "Access-Control-Request-Headers",
 
             // --- Fetch metadata ---
-            HTTPHeaderType::SecFetchSite => write!(f, "Sec-Fetch-Site"),
-            HTTPHeaderType::SecFetchMode => write!(f, "Sec-Fetch-Mode"),
-            HTTPHeaderType::SecFetchDest => write!(f, "Sec-Fetch-Dest"),
-            HTTPHeaderType::SecFetchUser => write!(f, "Sec-Fetch-User"),
+            HTTPHeaderType::SecFetchSite => "Sec-Fetch-Site",
+            HTTPHeaderType::SecFetchMode => "Sec-Fetch-Mode",
+            HTTPHeaderType::SecFetchDest => "Sec-Fetch-Dest",
+            HTTPHeaderType::SecFetchUser => "Sec-Fetch-User",
 
             // --- Client hints ---
-            HTTPHeaderType::SecCHUA => write!(f, "Sec-CH-UA"),
-            HTTPHeaderType::SecCHUAMobile => write!(f, "Sec-CH-UA-Mobile"),
-            HTTPHeaderType::SecCHUAPlatform => write!(f, "Sec-CH-UA-Platform"),
-            HTTPHeaderType::AcceptCH => write!(f, "Accept-CH"),
+            HTTPHeaderType::SecCHUA => "Sec-CH-UA",
+            HTTPHeaderType::SecCHUAMobile => "Sec-CH-UA-Mobile",
+            HTTPHeaderType::SecCHUAPlatform => "Sec-CH-UA-Platform",
+            HTTPHeaderType::AcceptCH => "Accept-CH",
 
             // --- Server / diagnostics ---
-            HTTPHeaderType::Server => write!(f, "Server"),
-            HTTPHeaderType::Date => write!(f, "Date"),
-            HTTPHeaderType::Allow => write!(f, "Allow"),
-            HTTPHeaderType::RetryAfter => write!(f, "Retry-After"),
-            HTTPHeaderType::Warning => write!(f, "Warning"),
+            HTTPHeaderType::Server => "Server",
+            HTTPHeaderType::Date => "Date",
+            HTTPHeaderType::Allow => "Allow",
+            HTTPHeaderType::RetryAfter => "Retry-After",
+            HTTPHeaderType::Warning => "Warning",
 
             // --- Compression helpers ---
-            HTTPHeaderType::TE => write!(f, "TE"),
+            HTTPHeaderType::TE => "TE",
 
             // --- WebSockets ---
-            HTTPHeaderType::SecWebSocketKey => write!(f, "Sec-WebSocket-Key"),
-            HTTPHeaderType::SecWebSocketAccept => write!(f, "Sec-WebSocket-Accept"),
-            HTTPHeaderType::SecWebSocketVersion => write!(f, "Sec-WebSocket-Version"),
-            HTTPHeaderType::SecWebSocketProtocol => write!(f, "Sec-WebSocket-Protocol"),
+            HTTPHeaderType::SecWebSocketKey => "Sec-WebSocket-Key",
+            HTTPHeaderType::SecWebSocketAccept => "Sec-WebSocket-Accept",
+            HTTPHeaderType::SecWebSocketVersion => "Sec-WebSocket-Version",
+            HTTPHeaderType::SecWebSocketProtocol => "Sec-WebSocket-Protocol",
+            HTTPHeaderType::SecWebSocketExtensions => "Sec-WebSocket-Extensions",
+
+            // --- Server-sent events ---
+            HTTPHeaderType::LastEventId => "Last-Event-ID",
 
             // --- Forwarding / proxies ---
-            HTTPHeaderType::Forwarded => write!(f, "Forwarded"),
-            HTTPHeaderType::XForwardedFor => write!(f, "X-Forwarded-For"),
-            HTTPHeaderType::XForwardedHost => write!(f, "X-Forwarded-Host"),
-            HTTPHeaderType::XForwardedProto => write!(f, "X-Forwarded-Proto"),
-            HTTPHeaderType::XRealIP => write!(f, "X-Real-IP"),
+            HTTPHeaderType::Forwarded => "Forwarded",
+            HTTPHeaderType::XForwardedFor => "X-Forwarded-For",
+            HTTPHeaderType::XForwardedHost => "X-Forwarded-Host",
+            HTTPHeaderType::XForwardedProto => "X-Forwarded-Proto",
+            HTTPHeaderType::XRealIP => "X-Real-IP",
 
             // --- Misc ---
-            HTTPHeaderType::Expect => write!(f, "Expect"),
-            HTTPHeaderType::DNT => write!(f, "DNT"),
+            HTTPHeaderType::Expect => "Expect",
+            HTTPHeaderType::DNT => "DNT",
 
             // --- Escape hatch ---
-            HTTPHeaderType::Other(s) => write!(f, "{}", s),
+            HTTPHeaderType::Other(s) => s,
         }
     }
 }
+
+impl Display for HTTPHeaderType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 impl FromStr for HTTPHeaderType {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, ()> {
-        match s.to_ascii_lowercase().as_str() {
+        // lowercased on the stack, long enough for every name below
+        let mut lower = [0; 48];
+        let lower = match lower.get_mut(..s.len()) {
+            Some(lower) => {
+                lower.copy_from_slice(s.as_bytes());
+                lower.make_ascii_lowercase();
+                std::str::from_utf8(lower).unwrap_or_default()
+            }
+            None => "",
+        };
+        match lower {
             // --- Content negotiation ---
             "accept" => Ok(Self::Accept),
             "accept-charset" => Ok(Self::AcceptCharset),
@@ -437,6 +556,10 @@ impl FromStr for HTTPHeaderType {
             "sec-websocket-accept" => Ok(Self::SecWebSocketAccept),
             "sec-websocket-version" => Ok(Self::SecWebSocketVersion),
             "sec-websocket-protocol" => Ok(Self::SecWebSocketProtocol),
+            "sec-websocket-extensions" => Ok(Self::SecWebSocketExtensions),
+
+            // --- Server-sent events ---
+            "last-event-id" => Ok(Self::LastEventId),
 
             // --- Forwarding / proxies ---
             "forwarded" => Ok(Self::Forwarded),
@@ -456,78 +579,293 @@ impl FromStr for HTTPHeaderType {
 }
 
 /// turn http request (string) to HTTPRequest object
-fn parse_http_request(data: String) -> HTTPRequest {
-    let mut buff = BufReader::new(Cursor::new(data)); //reader of data
-    let mut line = String::new();
-    // First header line
-    buff.read_line(&mut line).unwrap();
-    let head: Vec<&str> = line.trim_end().split(" ").collect();
-    let method = HTTPMethod::from_str(head[0]).unwrap();
-    let url = head[1].to_string();
-    let version = HTTPVersion::from_str(head[2]).unwrap();
-    // Actual headers
-    let mut headers = std::collections::HashMap::new();
-    loop {
-        line.clear();
-        let bytes_read = buff.read_line(&mut line).unwrap();
-        if bytes_read == 0 || line.trim().is_empty() {
-            break;
+fn parse_http_request(data: String, preserve_raw_headers: bool) -> HTTPRequest {
+    let head = match parse_head(data.as_bytes()) {
+        Ok(head) => head,
+        Err(e) => panic!("invalid HTTP request: {}", e),
+    };
+    let mut req = match HTTPRequest::from_head(&head, preserve_raw_headers) {
+        Ok(req) => req,
+        Err(e) => panic!("invalid HTTP request: {}", e),
+    };
+    let body = &data[head.len..];
+    req.body = (!body.is_empty()).then(|| body.to_string());
+    req
+}
+
+impl HTTPRequest {
+    /// A request with `head`'s method, target, version and headers and no
+    /// body, copying each piece out of the buffer once. Keeps the header
+    /// lines as they came in `raw_headers` when `preserve_raw_headers`.
+    pub(crate) fn from_head(head: &RequestHead<'_>, preserve_raw_headers: bool) -> Result<HTTPRequest, ParseError> {
+        let method = method_from_bytes(head.method).ok_or(ParseError::Method)?;
+        let version = version_from_bytes(head.version).ok_or(ParseError::Version)?;
+        let mut headers: std::collections::HashMap<HTTPHeaderType, String> =
+            std::collections::HashMap::with_capacity(head.headers.len());
+        let mut raw_headers: Vec<(String, String)> = Vec::new();
+        let mut last = None;
+        for (name, value) in &head.headers {
+            let value = text(value);
+            // obsolete line folding continues the previous value after a space
+            if name.is_empty() {
+                if let Some(header) = &last {
+                    if let Some(existing) = headers.get_mut(header) {
+                        existing.push(' ');
+                        existing.push_str(&value);
+                    }
+                    if let Some((_, existing)) = raw_headers.last_mut() {
+                        existing.push(' ');
+                        existing.push_str(&value);
+                    }
+                }
+                continue;
+            }
+            let name = text(name);
+            if preserve_raw_headers {
+                raw_headers.push((name.to_string(), value.to_string()));
+            }
+            let header = HTTPHeaderType::from_str(&name).unwrap();
+            crate::models::headers::append_header(&mut headers, header.clone(), value.into_owned());
+            last = Some(header);
         }
-        if let Some((key, value)) = line.trim_end().split_once(":") {
-            headers.insert(
-                HTTPHeaderType::from_str(key).unwrap(),
-                value.trim().to_string(),
-            );
+        Ok(HTTPRequest {
+            method,
+            url: text(head.target).into_owned(),
+            version,
+            headers,
+            body: None,
+            raw_body: None,
+            raw_headers,
+            connection: None,
+            extensions: Default::default(),
+        })
+    }
+}
+
+/// like `HTTPMethod::from_str` without allocating
+fn method_from_bytes(bytes: &[u8]) -> Option<HTTPMethod> {
+    let methods = [
+        (&b"GET"[..], HTTPMethod::GET),
+        (b"HEAD", HTTPMethod::HEAD),
+        (b"POST", HTTPMethod::POST),
+        (b"PUT", HTTPMethod::PUT),
+        (b"PATCH", HTTPMethod::PATCH),
+        (b"DELETE", HTTPMethod::DELETE),
+        (b"OPTIONS", HTTPMethod::OPTIONS),
+        (b"CONNECT", HTTPMethod::CONNECT),
+    ];
+    methods.into_iter().find(|(name, _)| bytes.eq_ignore_ascii_case(name)).map(|(_, method)| method)
+}
+
+/// like `HTTPVersion::from_str` without allocating
+fn version_from_bytes(bytes: &[u8]) -> Option<HTTPVersion> {
+    let versions = [
+        (&b"HTTP/1.0"[..], HTTPVersion::HTTP1_0),
+        (b"HTTP/1.1", HTTPVersion::HTTP1_1),
+        (b"HTTP/2", HTTPVersion::HTTP2),
+        (b"HTTP/3", HTTPVersion::HTTP3),
+    ];
+    let bytes = bytes.trim_ascii();
+    versions.into_iter().find(|(name, _)| bytes.eq_ignore_ascii_case(name)).map(|(_, version)| version)
+}
+
+/// Append `name: value` to a message head, unless either holds characters
+/// that would let it end the line early and inject headers of its own,
+/// e.g. a handler copying user input with a CR LF into a header
+fn push_header(head: &mut String, name: &str, value: &str) {
+    if valid_header(name, value) {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+}
+
+/// like `push_header`, for a head being built as bytes
+fn push_header_bytes(head: &mut Vec<u8>, name: &str, value: &str) {
+    if valid_header(name, value) {
+        for piece in [name, ": ", value, "\r\n"] {
+            head.extend_from_slice(piece.as_bytes());
         }
     }
-    // Body
-    let mut body = String::new();
-    buff.read_to_string(&mut body).unwrap();
-    HTTPRequest {
-        method,
-        url,
-        version,
-        headers,
-        body: if body.is_empty() { None } else { Some(body) },
+}
+
+fn valid_header(name: &str, value: &str) -> bool {
+    let valid = crate::models::headers::valid_header_name(name) && crate::models::headers::valid_header_value(value);
+    if !valid {
+        crate::logging::log!(WARN, "dropped invalid header", name = format_args!("{:?}", name));
     }
+    valid
+}
+
+/// The shapes a request target takes (RFC 9112, section 3.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetForm {
+    /// `/path?query`, what origin servers usually get
+    Origin,
+    /// `http://example.com/path?query`, what forward proxies get
+    Absolute,
+    /// `example.com:443`, for CONNECT
+    Authority,
+    /// `*`, for OPTIONS about the server as a whole
+    Asterisk,
+}
+
+/// How a response goes out on the wire
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Buffering {
+    /// assembled in full first, so `Content-Length` (and anything else that
+    /// needs the whole body, e.g. ETags or compression) can be added
+    #[default]
+    Buffered,
+    /// the head is sent as soon as it is ready and the body follows as it is
+    /// produced; the connection is closed to mark its end
+    Streamed,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct HTTPResponse {
     pub status: HTTPStatus,
+    /// for the status line: `HTTP1_0` when answering HTTP/1.0 clients,
+    /// otherwise `HTTP1_1`
+    #[serde(default)]
+    pub version: HTTPVersion,
     pub headers: std::collections::HashMap<HTTPHeaderType, String>,
     pub body: Option<String>,
+    /// `None` leaves it to the route (`Router::buffering`), then `Buffered`
+    #[serde(skip)]
+    pub buffering: Option<Buffering>,
+    /// binary body, e.g. file contents; sent instead of `body` when set
+    #[serde(skip)]
+    pub raw_body: Option<Vec<u8>>,
+    /// typed values attached along the way, e.g. the redirects a client followed
+    #[serde(skip)]
+    pub extensions: crate::models::extensions::Extensions,
+    /// the real response, still being computed; see `HTTPResponse::defer`
+    #[serde(skip)]
+    pub deferred: crate::models::deferred::Deferred,
+    /// takes over the connection after a 101, see `HTTPResponse::on_upgrade`
+    #[cfg(feature = "server")]
+    #[serde(skip)]
+    pub upgrade: crate::models::upgrade::OnUpgrade,
+    /// body produced while it is sent (or received, for client responses),
+    /// see `HTTPResponse::set_body_stream`
+    #[cfg(any(feature = "server", feature = "client"))]
+    #[serde(skip)]
+    pub body_stream: crate::models::body::BodyStream,
 }
 
-impl HTTPResponse {
-    pub fn default() -> Self {
+impl Default for HTTPResponse {
+    fn default() -> Self {
         HTTPResponse {
             status: HTTPStatus::Ok,
+            version: HTTPVersion::HTTP1_1,
             headers: std::collections::HashMap::new(),
             body: Some(String::from("hello world")),
+            buffering: None,
+            raw_body: None,
+            extensions: Default::default(),
+            deferred: Default::default(),
+            #[cfg(feature = "server")]
+            upgrade: Default::default(),
+            #[cfg(any(feature = "server", feature = "client"))]
+            body_stream: Default::default(),
         }
     }
+}
 
+impl HTTPResponse {
     pub fn error(status: HTTPStatus, message: &str) -> Self {
         HTTPResponse {
             status,
+            version: HTTPVersion::HTTP1_1,
             headers: std::collections::HashMap::new(),
             body: Some(message.to_string()),
+            buffering: None,
+            raw_body: None,
+            extensions: Default::default(),
+            deferred: Default::default(),
+            #[cfg(feature = "server")]
+            upgrade: Default::default(),
+            #[cfg(any(feature = "server", feature = "client"))]
+            body_stream: Default::default(),
+        }
+    }
+
+    pub fn buffering(&self) -> Buffering {
+        self.buffering.unwrap_or_default()
+    }
+
+    /// the body as it goes on the wire: `raw_body` if set, else `body`
+    pub fn body_bytes(&self) -> &[u8] {
+        match (&self.raw_body, &self.body) {
+            (Some(raw), _) => raw,
+            (None, Some(body)) => body.as_bytes(),
+            (None, None) => &[],
         }
     }
+
+    /// replace the body with binary content
+    pub fn set_raw_body(&mut self, bytes: Vec<u8>) {
+        self.body = None;
+        self.raw_body = Some(bytes);
+    }
+
+    /// status line and headers, up to and including the blank line
+    pub fn head_to_string(&self) -> String {
+        let mut head = Vec::new();
+        self.write_head(&mut head);
+        // built from `str`s only
+        String::from_utf8(head).unwrap()
+    }
+
+    /// Append the status line and headers, up to and including the blank
+    /// line, to `out`, with no intermediate strings. Lets the head and the
+    /// body go out as separate buffers.
+    pub fn write_head(&self, out: &mut Vec<u8>) {
+        out.reserve(64 + self.headers.len() * 32);
+        match (self.status.status_line(), &self.version) {
+            (Some(line), HTTPVersion::HTTP1_0) => {
+                out.extend_from_slice(b"HTTP/1.0");
+                out.extend_from_slice(&line[8..]);
+            }
+            (Some(line), _) => out.extend_from_slice(line),
+            (None, version) => {
+                let version = if *version == HTTPVersion::HTTP1_0 { "HTTP/1.0" } else { "HTTP/1.1" };
+                // writing to a Vec can't fail
+                let _ = write!(out, "{} {} {}\r\n", version, self.status.code(), self.status);
+            }
+        }
+        for (key, value) in &self.headers {
+            // known names are valid as they are; only values need checking
+            match key {
+                HTTPHeaderType::Other(name) => push_header_bytes(out, name, value),
+                key if crate::models::headers::valid_header_value(value) => {
+                    for piece in [key.as_str().as_bytes(), b": ", value.as_bytes(), b"\r\n"] {
+                        out.extend_from_slice(piece);
+                    }
+                }
+                key => crate::logging::log!(WARN, "dropped invalid header", name = format_args!("{:?}", key.as_str())),
+            }
+        }
+        out.extend_from_slice(b"\r\n");
+    }
+
+    /// the complete HTTP/1.x message, binary bodies included
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body = self.body_bytes();
+        let mut bytes = Vec::with_capacity(256 + body.len());
+        self.write_head(&mut bytes);
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    /// override the buffering policy for this response only
+    pub fn set_buffering(&mut self, buffering: Buffering) {
+        self.buffering = Some(buffering);
+    }
 }
 
 impl Display for HTTPResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut res = format!("HTTP/1.1 {} {}\r\n", self.status.code(), self.status);
-        for (key, value) in &self.headers {
-            res.push_str(&format!("{}: {}\r\n", key, value));
-        }
-        res.push_str("\r\n");
-        if let Some(body) = &self.body {
-            res.push_str(body);
-        }
-        write!(f, "{}", res)
+        write!(f, "{}{}", self.head_to_string(), String::from_utf8_lossy(self.body_bytes()))
     }
 }
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -603,6 +941,10 @@ pub enum HTTPStatus {
     LoopDetected,
     NotExtended,
     NetworkAuthenticationRequired,
+
+    /// A code without a variant of its own, e.g. `499` from an upstream,
+    /// and its reason phrase. Make one with `HTTPStatus::custom`.
+    Custom(u16, String),
 }
 
 impl Display for HTTPStatus {
@@ -679,54 +1021,268 @@ impl Display for HTTPStatus {
             Self::LoopDetected => write!(f, "Loop Detected"),
             Self::NotExtended => write!(f, "Not Extended"),
             Self::NetworkAuthenticationRequired => write!(f, "Network Authentication Required"),
+
+            // built by hand it may not have been checked, and it ends up in a status line
+            Self::Custom(_, reason) => {
+                let reason: String = reason.chars().filter(|c| !c.is_control()).collect();
+                write!(f, "{}", reason)
+            }
         }
     }
 }
 
 impl HTTPRequest {
+    /// the decoded query parameters, the last value winning for repeated names
     pub fn query_params(&self) -> std::collections::HashMap<String, String> {
-        if let Some((_, query)) = self.url.split_once('?') {
-            query
-                .split('&')
-                .filter_map(|pair| {
-                    pair.split_once('=')
-                        .map(|(k, v)| (k.to_string(), v.to_string()))
-                })
-                .collect()
+        self.query().map(crate::models::url::parse_query).unwrap_or_default().into_iter().collect()
+    }
+
+    /// the path matched against `pattern` again; handlers already have
+    /// the parameters of the route that matched in their `PathParams`
+    pub fn path_params(&self, pattern: &str) -> Option<std::collections::HashMap<String, String>> {
+        crate::router::match_route(pattern, self.path())
+    }
+
+    /// which of the four forms the request target (`url`) takes
+    pub fn target_form(&self) -> TargetForm {
+        if self.url.starts_with('/') {
+            TargetForm::Origin
+        } else if self.url == "*" {
+            TargetForm::Asterisk
+        } else if self.absolute_parts().is_some() {
+            TargetForm::Absolute
         } else {
-            std::collections::HashMap::new()
+            TargetForm::Authority
         }
     }
 
-    pub fn path_params(&self, pattern: &str) -> Option<std::collections::HashMap<String, String>> {
-        let path = self.url.split('?').next().unwrap_or(&self.url);
-        match_route(pattern, path)
+    /// scheme, authority and the rest of an absolute-form target
+    fn absolute_parts(&self) -> Option<(&str, &str, &str)> {
+        let (scheme, rest) = self.url.split_once("://")?;
+        let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        if !valid {
+            return None;
+        }
+        let (authority, rest) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+        Some((scheme, authority, rest))
     }
-}
 
-fn match_route(pattern: &str, path: &str) -> Option<std::collections::HashMap<String, String>> {
-    let pattern_parts: Vec<&str> = pattern.trim_matches('/').split('/').collect();
-    let path_parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    /// the scheme of an absolute-form target, e.g. `http`
+    pub fn scheme(&self) -> Option<&str> {
+        self.absolute_parts().map(|(scheme, _, _)| scheme)
+    }
 
-    if pattern_parts.len() != path_parts.len() {
-        return None;
+    /// the `host[:port]` of an absolute-form target, or an authority-form
+    /// target as a whole
+    pub fn authority(&self) -> Option<&str> {
+        match self.target_form() {
+            TargetForm::Absolute => self.absolute_parts().map(|(_, authority, _)| authority),
+            TargetForm::Authority => Some(&self.url),
+            _ => None,
+        }
     }
 
-    let mut params = std::collections::HashMap::new();
+    /// Path and query of the target, as an origin server sees it: the
+    /// target itself in origin-form, what follows the authority in
+    /// absolute-form, and empty otherwise.
+    pub fn path_and_query(&self) -> &str {
+        match self.target_form() {
+            TargetForm::Origin => &self.url,
+            TargetForm::Absolute => match self.absolute_parts() {
+                Some((_, _, "")) => "/",
+                Some((_, _, rest)) => rest,
+                None => "",
+            },
+            _ => "",
+        }
+    }
 
-    for (pat, p) in pattern_parts.iter().zip(path_parts) {
-        if pat.starts_with('{') && pat.ends_with('}') {
-            let param_name = &pat[1..pat.len() - 1];
-            params.insert(param_name.to_string(), p.to_string());
-        } else if *pat != p {
-            return None;
+    /// the path of `path_and_query`, at least `/` in absolute-form
+    pub fn path(&self) -> &str {
+        match self.path_and_query().split('?').next() {
+            Some("") if self.target_form() == TargetForm::Absolute => "/",
+            Some(path) => path,
+            None => "",
         }
     }
 
-    Some(params)
+    pub fn query(&self) -> Option<&str> {
+        self.path_and_query().split_once('?').map(|(_, query)| query)
+    }
+
+    /// a label captured by the `Router::host` pattern the request was
+    /// routed by, e.g. `tenant` for `{tenant}.example.com`
+    pub fn host_param(&self, name: &str) -> Option<&str> {
+        let params = self.extensions.get::<crate::router::HostParams>()?;
+        params.0.get(name).map(String::as_str)
+    }
+
+    /// Check that the target's form suits the method (authority-form for
+    /// CONNECT and only for it, `*` only for OPTIONS) and bring its path
+    /// into normal form with `router::normalize_path`. An absolute-form
+    /// target's authority replaces `Host`, and a default port is dropped
+    /// from `Host` (`:80`, or `:443` over TLS). Fails with 400 for
+    /// misplaced forms and paths that climb above the root.
+    pub fn normalize(&mut self) -> Result<(), crate::models::error::HTTPError> {
+        let bad_request = |message: &str| crate::models::error::HTTPError::client(HTTPStatus::BadRequest, message);
+        let form = self.target_form();
+        match (&self.method, form) {
+            (HTTPMethod::CONNECT, TargetForm::Authority) => {}
+            (HTTPMethod::CONNECT, _) => return Err(bad_request("CONNECT needs an authority-form target")),
+            (_, TargetForm::Authority) => return Err(bad_request("Malformed request target")),
+            (HTTPMethod::OPTIONS, TargetForm::Asterisk) => {}
+            (_, TargetForm::Asterisk) => return Err(bad_request("Only OPTIONS may target *")),
+            _ => {}
+        }
+        if matches!(form, TargetForm::Origin | TargetForm::Absolute) {
+            let path = self.path();
+            let prefix_len = self.url.len() - self.path_and_query().len();
+            let mut url = self.url[..prefix_len].to_string();
+            url.push_str(&crate::router::normalize_path(path).ok_or_else(|| bad_request("Request target escapes the root"))?);
+            if let Some(query) = self.query() {
+                url.push('?');
+                url.push_str(query);
+            }
+            self.url = url;
+        }
+        if let Some(authority) = self.authority().filter(|_| form == TargetForm::Absolute) {
+            let authority = authority.to_string();
+            self.headers.insert(HTTPHeaderType::Host, authority);
+        }
+        let default_port = if self.is_tls() { ":443" } else { ":80" };
+        if let Some(host) = self.headers.get_mut(&HTTPHeaderType::Host) {
+            if let Some(stripped) = host.strip_suffix(default_port) {
+                *host = stripped.to_string();
+            }
+        }
+        Ok(())
+    }
 }
 
 impl HTTPStatus {
+    /// minimal body for responses that carry no message of their own, e.g. `404 Not Found`
+    pub fn default_body(&self) -> String {
+        format!("{} {}", self.code(), self)
+    }
+
+    /// the variant for a numeric code, e.g. from an upstream status line;
+    /// `None` for codes without one
+    pub fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            // 1xx
+            100 => Self::Continue,
+            101 => Self::SwitchingProtocols,
+            102 => Self::Processing,
+            103 => Self::EarlyHints,
+
+            // 2xx
+            200 => Self::Ok,
+            201 => Self::Created,
+            202 => Self::Accepted,
+            203 => Self::NonAuthoritativeInformation,
+            204 => Self::NoContent,
+            205 => Self::ResetContent,
+            206 => Self::PartialContent,
+            207 => Self::MultiStatus,
+            208 => Self::AlreadyReported,
+            226 => Self::ImUsed,
+
+            // 3xx
+            300 => Self::MultipleChoices,
+            301 => Self::MovedPermanently,
+            302 => Self::Found,
+            303 => Self::SeeOther,
+            304 => Self::NotModified,
+            305 => Self::UseProxy,
+            307 => Self::TemporaryRedirect,
+            308 => Self::PermanentRedirect,
+
+            // 4xx
+            400 => Self::BadRequest,
+            401 => Self::Unauthorized,
+            402 => Self::PaymentRequired,
+            403 => Self::Forbidden,
+            404 => Self::NotFound,
+            405 => Self::MethodNotAllowed,
+            406 => Self::NotAcceptable,
+            407 => Self::ProxyAuthenticationRequired,
+            408 => Self::RequestTimeout,
+            409 => Self::Conflict,
+            410 => Self::Gone,
+            411 => Self::LengthRequired,
+            412 => Self::PreconditionFailed,
+            413 => Self::PayloadTooLarge,
+            414 => Self::UriTooLong,
+            415 => Self::UnsupportedMediaType,
+            416 => Self::RangeNotSatisfiable,
+            417 => Self::ExpectationFailed,
+            418 => Self::ImATeapot,
+            421 => Self::MisdirectedRequest,
+            422 => Self::UnprocessableEntity,
+            423 => Self::Locked,
+            424 => Self::FailedDependency,
+            425 => Self::TooEarly,
+            426 => Self::UpgradeRequired,
+            428 => Self::PreconditionRequired,
+            429 => Self::TooManyRequests,
+            431 => Self::RequestHeaderFieldsTooLarge,
+            451 => Self::UnavailableForLegalReasons,
+
+            // 5xx
+            500 => Self::InternalServerError,
+            501 => Self::NotImplemented,
+            502 => Self::BadGateway,
+            503 => Self::ServiceUnavailable,
+            504 => Self::GatewayTimeout,
+            505 => Self::HttpVersionNotSupported,
+            506 => Self::VariantAlsoNegotiates,
+            507 => Self::InsufficientStorage,
+            508 => Self::LoopDetected,
+            510 => Self::NotExtended,
+            511 => Self::NetworkAuthenticationRequired,
+            _ => return None,
+        })
+    }
+
+    /// The variant for `code`, or a `Custom` status with `reason` for codes
+    /// without one, e.g. `HTTPStatus::custom(499, "Client Closed Request")`.
+    /// `code` must have three digits and `reason` no control characters.
+    pub fn custom(code: u16, reason: &str) -> Result<Self, String> {
+        if !(100..=999).contains(&code) {
+            return Err(format!("status code {} does not have three digits", code));
+        }
+        if reason.chars().any(|c| c.is_control() && c != '\t') {
+            return Err(format!("reason phrase {:?} has control characters", reason));
+        }
+        Ok(Self::from_code(code).unwrap_or_else(|| Self::Custom(code, reason.to_string())))
+    }
+
+    /// 1xx
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.code())
+    }
+
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    /// 3xx
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.code())
+    }
+
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.code())
+    }
+
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.code())
+    }
+
     pub fn code(&self) -> u16 {
         match self {
             // 1xx
@@ -800,6 +1356,89 @@ impl HTTPStatus {
             Self::LoopDetected => 508,
             Self::NotExtended => 510,
             Self::NetworkAuthenticationRequired => 511,
+
+            Self::Custom(code, _) => *code,
         }
     }
+
+    /// The HTTP/1.1 status line, CR LF included, e.g.
+    /// `HTTP/1.1 404 Not Found\r\n`, ready to write as is. `None` for
+    /// `Custom`, whose line has to be formatted.
+    pub fn status_line(&self) -> Option<&'static [u8]> {
+        Some(match self {
+            // 1xx
+            Self::Continue => b"HTTP/1.1 100 Continue\r\n",
+            Self::SwitchingProtocols => b"HTTP/1.1 101 Switching Protocols\r\n",
+            Self::Processing => b"HTTP/1.1 102 Processing\r\n",
+            Self::EarlyHints => b"HTTP/1.1 103 Early Hints\r\n",
+
+            // 2xx
+            Self::Ok => b"HTTP/1.1 200 OK\r\n",
+            Self::Created => b"HTTP/1.1 201 Created\r\n",
+            Self::Accepted => b"HTTP/1.1 202 Accepted\r\n",
+            Self::NonAuthoritativeInformation => b"HTTP/1.1 203 Non Authoritative Information\r\n",
+            Self::NoContent => b"HTTP/1.1 204 No Content\r\n",
+            Self::ResetContent => b"HTTP/1.1 205 Reset Content\r\n",
+            Self::PartialContent => b"HTTP/1.1 206 Partial Content\r\n",
+            Self::MultiStatus => b"HTTP/1.1 207 Multi-Status\r\n",
+            Self::AlreadyReported => b"HTTP/1.1 208 Already Reported\r\n",
+            Self::ImUsed => b"HTTP/1.1 226 IM Used\r\n",
+
+            // 3xx
+            Self::MultipleChoices => b"HTTP/1.1 300 Multiple Choices\r\n",
+            Self::MovedPermanently => b"HTTP/1.1 301 Moved Permanently\r\n",
+            Self::Found => b"HTTP/1.1 302 Found\r\n",
+            Self::SeeOther => b"HTTP/1.1 303 See Other\r\n",
+            Self::NotModified => b"HTTP/1.1 304 Not Modified\r\n",
+            Self::UseProxy => b"HTTP/1.1 305 Use Proxy\r\n",
+            Self::TemporaryRedirect => b"HTTP/1.1 307 Temporary Redirect\r\n",
+            Self::PermanentRedirect => b"HTTP/1.1 308 Permanent Redirect\r\n",
+
+            // 4xx
+            Self::BadRequest => b"HTTP/1.1 400 Bad Request\r\n",
+            Self::Unauthorized => b"HTTP/1.1 401 Unauthorized\r\n",
+            Self::PaymentRequired => b"HTTP/1.1 402 Payment Required\r\n",
+            Self::Forbidden => b"HTTP/1.1 403 Forbidden\r\n",
+            Self::NotFound => b"HTTP/1.1 404 Not Found\r\n",
+            Self::MethodNotAllowed => b"HTTP/1.1 405 Method Not Allowed\r\n",
+            Self::NotAcceptable => b"HTTP/1.1 406 Not Acceptable\r\n",
+            Self::ProxyAuthenticationRequired => b"HTTP/1.1 407 Proxy Authentication Required\r\n",
+            Self::RequestTimeout => b"HTTP/1.1 408 Request Timeout\r\n",
+            Self::Conflict => b"HTTP/1.1 409 Conflict\r\n",
+            Self::Gone => b"HTTP/1.1 410 Gone\r\n",
+            Self::LengthRequired => b"HTTP/1.1 411 Length Required\r\n",
+            Self::PreconditionFailed => b"HTTP/1.1 412 Precondition Failed\r\n",
+            Self::PayloadTooLarge => b"HTTP/1.1 413 Payload Too Large\r\n",
+            Self::UriTooLong => b"HTTP/1.1 414 URI Too Long\r\n",
+            Self::UnsupportedMediaType => b"HTTP/1.1 415 Unsupported Media Type\r\n",
+            Self::RangeNotSatisfiable => b"HTTP/1.1 416 Range Not Satisfiable\r\n",
+            Self::ExpectationFailed => b"HTTP/1.1 417 Expectation Failed\r\n",
+            Self::ImATeapot => b"HTTP/1.1 418 I'm a teapot\r\n",
+            Self::MisdirectedRequest => b"HTTP/1.1 421 Misdirected Request\r\n",
+            Self::UnprocessableEntity => b"HTTP/1.1 422 Unprocessable Entity\r\n",
+            Self::Locked => b"HTTP/1.1 423 Locked\r\n",
+            Self::FailedDependency => b"HTTP/1.1 424 Failed Dependency\r\n",
+            Self::TooEarly => b"HTTP/1.1 425 Too Early\r\n",
+            Self::UpgradeRequired => b"HTTP/1.1 426 Upgrade Required\r\n",
+            Self::PreconditionRequired => b"HTTP/1.1 428 Precondition Required\r\n",
+            Self::TooManyRequests => b"HTTP/1.1 429 Too Many Requests\r\n",
+            Self::RequestHeaderFieldsTooLarge => b"HTTP/1.1 431 Request Header Fields Too Large\r\n",
+            Self::UnavailableForLegalReasons => b"HTTP/1.1 451 Unavailable For Legal Reasons\r\n",
+
+            // 5xx
+            Self::InternalServerError => b"HTTP/1.1 500 Internal Server Error\r\n",
+            Self::NotImplemented => b"HTTP/1.1 501 Not Implemented\r\n",
+            Self::BadGateway => b"HTTP/1.1 502 Bad Gateway\r\n",
+            Self::ServiceUnavailable => b"HTTP/1.1 503 Service Unavailable\r\n",
+            Self::GatewayTimeout => b"HTTP/1.1 504 Gateway Timeout\r\n",
+            Self::HttpVersionNotSupported => b"HTTP/1.1 505 HTTP Version Not Supported\r\n",
+            Self::VariantAlsoNegotiates => b"HTTP/1.1 506 Variant Also Negotiates\r\n",
+            Self::InsufficientStorage => b"HTTP/1.1 507 Insufficient Storage\r\n",
+            Self::LoopDetected => b"HTTP/1.1 508 Loop Detected\r\n",
+            Self::NotExtended => b"HTTP/1.1 510 Not Extended\r\n",
+            Self::NetworkAuthenticationRequired => b"HTTP/1.1 511 Network Authentication Required\r\n",
+
+            Self::Custom(..) => return None,
+        })
+    }
 }
@@ -0,0 +1,183 @@
+use crate::models::headers::append_header;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus, HTTPVersion};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+impl From<HTTPMethod> for http::Method {
+    fn from(method: HTTPMethod) -> Self {
+        match method {
+            HTTPMethod::GET => http::Method::GET,
+            HTTPMethod::HEAD => http::Method::HEAD,
+            HTTPMethod::POST => http::Method::POST,
+            HTTPMethod::PUT => http::Method::PUT,
+            HTTPMethod::PATCH => http::Method::PATCH,
+            HTTPMethod::DELETE => http::Method::DELETE,
+            HTTPMethod::OPTIONS => http::Method::OPTIONS,
+            HTTPMethod::CONNECT => http::Method::CONNECT,
+        }
+    }
+}
+
+/// fails for methods without a variant, e.g. `TRACE`
+impl TryFrom<http::Method> for HTTPMethod {
+    type Error = String;
+    fn try_from(method: http::Method) -> Result<Self, String> {
+        HTTPMethod::from_str(method.as_str())
+    }
+}
+
+impl From<HTTPStatus> for http::StatusCode {
+    fn from(status: HTTPStatus) -> Self {
+        http::StatusCode::from_u16(status.code()).unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// fails for codes without a variant
+impl TryFrom<http::StatusCode> for HTTPStatus {
+    type Error = String;
+    fn try_from(status: http::StatusCode) -> Result<Self, String> {
+        HTTPStatus::from_code(status.as_u16()).ok_or_else(|| format!("Unsupported status code: {}", status.as_u16()))
+    }
+}
+
+impl From<HTTPVersion> for http::Version {
+    fn from(version: HTTPVersion) -> Self {
+        match version {
+            HTTPVersion::HTTP1_0 => http::Version::HTTP_10,
+            HTTPVersion::HTTP1_1 => http::Version::HTTP_11,
+            HTTPVersion::HTTP2 => http::Version::HTTP_2,
+            HTTPVersion::HTTP3 => http::Version::HTTP_3,
+        }
+    }
+}
+
+/// HTTP/0.9 counts as 1.0
+impl From<http::Version> for HTTPVersion {
+    fn from(version: http::Version) -> Self {
+        match version {
+            http::Version::HTTP_09 | http::Version::HTTP_10 => HTTPVersion::HTTP1_0,
+            http::Version::HTTP_2 => HTTPVersion::HTTP2,
+            http::Version::HTTP_3 => HTTPVersion::HTTP3,
+            _ => HTTPVersion::HTTP1_1,
+        }
+    }
+}
+
+/// Repeated headers are joined into one value, as when parsing. Fails for
+/// bodies that aren't UTF-8, which `HTTPRequest` can't carry.
+impl TryFrom<http::Request<Bytes>> for HTTPRequest {
+    type Error = String;
+    fn try_from(req: http::Request<Bytes>) -> Result<Self, String> {
+        let (parts, body) = req.into_parts();
+        let body = String::from_utf8(body.into()).map_err(|_| String::from("Request body is not UTF-8"))?;
+        let mut req = request_from_parts(parts)?;
+        req.body = (!body.is_empty()).then_some(body);
+        Ok(req)
+    }
+}
+
+/// `HTTPRequest` from a request head; the body is left empty
+pub(crate) fn request_from_parts(parts: http::request::Parts) -> Result<HTTPRequest, String> {
+    let url = parts
+        .uri
+        .path_and_query()
+        .map(|pq| pq.as_str().to_string())
+        .unwrap_or_else(|| String::from("/"));
+    let mut headers = from_header_map(&parts.headers);
+    // absolute-form and HTTP/2's :authority stand in for Host
+    if let Some(authority) = parts.uri.authority() {
+        headers
+            .entry(HTTPHeaderType::Host)
+            .or_insert_with(|| authority.to_string());
+    }
+    Ok(HTTPRequest {
+        method: parts.method.try_into()?,
+        url,
+        version: parts.version.into(),
+        headers,
+        ..Default::default()
+    })
+}
+
+impl TryFrom<HTTPRequest> for http::Request<Bytes> {
+    type Error = String;
+    fn try_from(req: HTTPRequest) -> Result<Self, String> {
+        let mut builder = http::Request::builder()
+            .method(http::Method::from(req.method))
+            .uri(&req.url)
+            .version(req.version.into());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = to_header_map(&req.headers)?;
+        }
+        builder
+            .body(req.raw_body.map(Bytes::from).or(req.body.map(Bytes::from)).unwrap_or_default())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// bodies that aren't UTF-8 become the `raw_body`
+impl TryFrom<http::Response<Bytes>> for HTTPResponse {
+    type Error = String;
+    fn try_from(res: http::Response<Bytes>) -> Result<Self, String> {
+        let (parts, body) = res.into_parts();
+        let mut res = HTTPResponse {
+            status: parts.status.try_into()?,
+            version: parts.version.into(),
+            headers: from_header_map(&parts.headers),
+            ..Default::default()
+        };
+        match String::from_utf8(body.into()) {
+            Ok(body) => res.body = (!body.is_empty()).then_some(body),
+            Err(e) => res.set_raw_body(e.into_bytes()),
+        }
+        Ok(res)
+    }
+}
+
+/// fails for responses still streaming their body or waiting on a
+/// deferred one
+impl TryFrom<HTTPResponse> for http::Response<Bytes> {
+    type Error = String;
+    fn try_from(res: HTTPResponse) -> Result<Self, String> {
+        #[cfg(any(feature = "server", feature = "client"))]
+        if res.body_stream.is_set() {
+            return Err(String::from("Response body is still streaming"));
+        }
+        if res.deferred.is_set() {
+            return Err(String::from("Response is deferred"));
+        }
+        let mut builder = http::Response::builder()
+            .status(http::StatusCode::from(res.status.clone()))
+            .version(res.version.clone().into());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = to_header_map(&res.headers)?;
+        }
+        builder
+            .body(Bytes::copy_from_slice(res.body_bytes()))
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn from_header_map(map: &http::HeaderMap) -> HashMap<HTTPHeaderType, String> {
+    let mut headers = HashMap::new();
+    for (name, value) in map {
+        append_header(
+            &mut headers,
+            HTTPHeaderType::from_str(name.as_str()).unwrap(),
+            String::from_utf8_lossy(value.as_bytes()).to_string(),
+        );
+    }
+    headers
+}
+
+fn to_header_map(headers: &HashMap<HTTPHeaderType, String>) -> Result<http::HeaderMap, String> {
+    let mut map = http::HeaderMap::with_capacity(headers.len());
+    for (header, value) in headers {
+        let name = http::HeaderName::from_bytes(header.to_string().as_bytes())
+            .map_err(|_| format!("Invalid header name: {}", header))?;
+        let value = http::HeaderValue::from_str(value).map_err(|_| format!("Invalid value for {}", header))?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
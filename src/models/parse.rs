@@ -0,0 +1,361 @@
+use std::fmt::{Display, Formatter};
+
+/// An HTTP/1 request head split into its pieces in place: everything
+/// borrows the buffer it was read into, so parsing allocates nothing but
+/// the list of headers. Pieces are raw bytes; `text` reads one as a `str`
+/// without copying when it is valid UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHead<'a> {
+    pub method: &'a [u8],
+    pub target: &'a [u8],
+    pub version: &'a [u8],
+    /// name and value of each header line in arrival order, the value
+    /// trimmed. A line continuing the previous value (obsolete line
+    /// folding) comes as a header with an empty name
+    pub headers: Vec<(&'a [u8], &'a [u8])>,
+    /// bytes the head took up, its blank line included
+    pub len: usize,
+}
+
+/// Why a request head couldn't be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// no method, target and version separated by single spaces
+    RequestLine,
+    /// a header line without a colon
+    HeaderLine,
+    /// a method `HTTPMethod` doesn't have
+    Method,
+    /// a version other than HTTP/1.0 to HTTP/3
+    Version,
+    /// a header name or value holding characters it can't
+    Header,
+    /// a body length proxies and servers could read differently
+    Framing,
+    /// a chunked body with a malformed chunk size, line ending or trailer
+    Chunk,
+    /// the buffer ends before the head's blank line or the body's last byte
+    Incomplete,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::RequestLine => write!(f, "malformed request line"),
+            ParseError::HeaderLine => write!(f, "malformed header line"),
+            ParseError::Method => write!(f, "unknown method"),
+            ParseError::Version => write!(f, "unsupported HTTP version"),
+            ParseError::Header => write!(f, "invalid header name or value"),
+            ParseError::Framing => write!(f, "ambiguous message framing"),
+            ParseError::Chunk => write!(f, "malformed chunked body"),
+            ParseError::Incomplete => write!(f, "incomplete request"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// headers most requests fit in without the list growing
+const TYPICAL_HEADERS: usize = 16;
+
+/// Split the request head at the start of `buf`, up to its first blank
+/// line or the end of `buf`. Lines may end in CR LF or a bare LF.
+pub fn parse_head(buf: &[u8]) -> Result<RequestHead<'_>, ParseError> {
+    let mut lines = Lines { buf, at: 0 };
+    let request_line = lines.next().ok_or(ParseError::RequestLine)?;
+    let mut parts = request_line.split(|&b| b == b' ');
+    let (Some(method), Some(target), Some(version), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::RequestLine);
+    };
+    if method.is_empty() || target.is_empty() || version.is_empty() {
+        return Err(ParseError::RequestLine);
+    }
+    let mut headers = Vec::with_capacity(TYPICAL_HEADERS);
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        if line[0] == b' ' || line[0] == b'\t' {
+            headers.push((&line[..0], line.trim_ascii()));
+            continue;
+        }
+        let colon = line.iter().position(|&b| b == b':').ok_or(ParseError::HeaderLine)?;
+        headers.push((&line[..colon], line[colon + 1..].trim_ascii()));
+    }
+    Ok(RequestHead {
+        method,
+        target,
+        version,
+        headers,
+        len: lines.at,
+    })
+}
+
+/// Parse the request at the start of `buf`: its head, then its body, as
+/// much as its `Content-Length` announces or decoded from its chunks.
+/// Returns the request and how many bytes of `buf` it took up, or
+/// `Incomplete` if `buf` ends first; bytes after it are left alone. Never
+/// panics and does no I/O, so any bytes a client could send get a
+/// deterministic answer.
+pub fn parse_request(buf: &[u8]) -> Result<(crate::models::http::HTTPRequest, usize), ParseError> {
+    let head = parse_head(&buf[..head_length(buf).ok_or(ParseError::Incomplete)?])?;
+    let (body, end) = match body_length(&head)? {
+        BodyLength::Fixed(length) => {
+            let end = head.len.checked_add(length).filter(|&end| end <= buf.len()).ok_or(ParseError::Incomplete)?;
+            (std::borrow::Cow::Borrowed(&buf[head.len..end]), end)
+        }
+        BodyLength::Chunked => {
+            let mut decoder = ChunkedDecoder::default();
+            let used = decoder.decode(&buf[head.len..])?;
+            if !decoder.is_done() {
+                return Err(ParseError::Incomplete);
+            }
+            (std::borrow::Cow::Owned(decoder.into_body()), head.len + used)
+        }
+    };
+    let mut request = crate::models::http::HTTPRequest::from_head(&head, false)?;
+    request.set_body_bytes(body.into_owned());
+    Ok((request, end))
+}
+
+/// How a request head says its body ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyLength {
+    /// after this many bytes, 0 without a `Content-Length`
+    Fixed(usize),
+    /// after the last chunk and its trailers; see `ChunkedDecoder`
+    Chunked,
+}
+
+/// How the body of a request head is framed. Errors for heads with header
+/// names or values holding characters they can't, and for heads that
+/// proxies and servers could frame differently, the basis of request
+/// smuggling (RFC 9112 5, 6.3): `Content-Length` along with
+/// `Transfer-Encoding`, differing or malformed `Content-Length`s, a
+/// `Transfer-Encoding` on HTTP/1.0 or not ending in `chunked`, more than
+/// one `Host`, obsolete line folding and whitespace before a header's colon.
+pub fn body_length(head: &RequestHead<'_>) -> Result<BodyLength, ParseError> {
+    let http1_0 = head.version == b"HTTP/1.0";
+    let mut length = None;
+    let mut last_coding = None;
+    let mut host = false;
+    for (name, value) in &head.headers {
+        // a folded line has an empty name, never a valid one
+        let name = std::str::from_utf8(name).map_err(|_| ParseError::Header)?;
+        let value = text(value);
+        let valid = crate::models::headers::valid_header_name(name) && crate::models::headers::valid_header_value(&value);
+        if !valid {
+            return Err(ParseError::Header);
+        }
+        if name.eq_ignore_ascii_case("host") {
+            if host {
+                return Err(ParseError::Framing);
+            }
+            host = true;
+        } else if name.eq_ignore_ascii_case("content-length") {
+            for value in value.split(',').map(str::trim) {
+                if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(ParseError::Framing);
+                }
+                let value: usize = value.parse().map_err(|_| ParseError::Framing)?;
+                if length.is_some_and(|length| length != value) {
+                    return Err(ParseError::Framing);
+                }
+                length = Some(value);
+            }
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            last_coding = value.rsplit(',').next().map(|coding| coding.trim().to_ascii_lowercase());
+        }
+    }
+    match last_coding {
+        Some(coding) if length.is_some() || http1_0 || coding != "chunked" => Err(ParseError::Framing),
+        Some(_) => Ok(BodyLength::Chunked),
+        None => Ok(BodyLength::Fixed(length.unwrap_or(0))),
+    }
+}
+
+/// the longest chunk size line, extensions included, and the most trailer
+/// bytes a chunked body may have
+const MAX_CHUNK_LINE: usize = 4096;
+
+/// Decodes a chunked body (RFC 9112 7.1) as its bytes arrive: each call to
+/// `decode` takes what it can from the front of the buffer and keeps the
+/// chunks' data, so however the body is split it is scanned once. Chunk
+/// extensions and trailer fields are read and dropped. Lines must end in
+/// CR LF; a bare LF is an error, as proxies disagree on it.
+#[derive(Debug, Default)]
+pub struct ChunkedDecoder {
+    state: ChunkState,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ChunkState {
+    /// a chunk size line
+    #[default]
+    Size,
+    /// bytes of the current chunk still to come
+    Data(usize),
+    /// the CR LF after a chunk's data
+    DataEnd,
+    /// trailer lines after the last chunk, and how many bytes of them so far
+    Trailers(usize),
+    Done,
+}
+
+impl ChunkedDecoder {
+    /// Decode what it can from the front of `buf`, returning how many bytes
+    /// it took; the rest waits for more to arrive. Nothing is taken once
+    /// the body is done.
+    pub fn decode(&mut self, buf: &[u8]) -> Result<usize, ParseError> {
+        let mut at = 0;
+        loop {
+            let rest = &buf[at..];
+            match self.state {
+                ChunkState::Size => {
+                    let Some(line) = chunk_line(rest, MAX_CHUNK_LINE)? else {
+                        return Ok(at);
+                    };
+                    at += line.len() + 2;
+                    let size = line.split(|&b| b == b';').next().unwrap_or_default().trim_ascii_end();
+                    if size.is_empty() || !size.iter().all(u8::is_ascii_hexdigit) {
+                        return Err(ParseError::Chunk);
+                    }
+                    let size = std::str::from_utf8(size).ok().and_then(|size| usize::from_str_radix(size, 16).ok());
+                    self.state = match size.ok_or(ParseError::Chunk)? {
+                        0 => ChunkState::Trailers(0),
+                        size => ChunkState::Data(size),
+                    };
+                }
+                ChunkState::Data(left) => {
+                    if rest.is_empty() {
+                        return Ok(at);
+                    }
+                    let taken = left.min(rest.len());
+                    self.body.extend_from_slice(&rest[..taken]);
+                    at += taken;
+                    self.state = match left - taken {
+                        0 => ChunkState::DataEnd,
+                        left => ChunkState::Data(left),
+                    };
+                }
+                ChunkState::DataEnd => match rest {
+                    [] | [b'\r'] => return Ok(at),
+                    [b'\r', b'\n', ..] => {
+                        at += 2;
+                        self.state = ChunkState::Size;
+                    }
+                    _ => return Err(ParseError::Chunk),
+                },
+                ChunkState::Trailers(seen) => {
+                    let Some(line) = chunk_line(rest, MAX_CHUNK_LINE - seen)? else {
+                        return Ok(at);
+                    };
+                    at += line.len() + 2;
+                    self.state = match line.is_empty() {
+                        true => ChunkState::Done,
+                        false => ChunkState::Trailers(seen + line.len() + 2),
+                    };
+                }
+                ChunkState::Done => return Ok(at),
+            }
+        }
+    }
+
+    /// whether the last chunk and the trailers have been decoded
+    pub fn is_done(&self) -> bool {
+        self.state == ChunkState::Done
+    }
+
+    /// the data decoded so far
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+/// the line at the start of `buf` without its CR LF, `None` until all of
+/// it is there; errors for a bare LF, or for a line that with its CR LF
+/// takes more than `max` bytes
+fn chunk_line(buf: &[u8], max: usize) -> Result<Option<&[u8]>, ParseError> {
+    match buf.iter().position(|&b| b == b'\n') {
+        Some(end) if end + 1 > max => Err(ParseError::Chunk),
+        Some(end) => buf[..end].strip_suffix(b"\r").map(Some).ok_or(ParseError::Chunk),
+        None if buf.len() >= max => Err(ParseError::Chunk),
+        None => Ok(None),
+    }
+}
+
+/// the length of the head at the start of `buf`, its blank line
+/// included, once all of it is there
+fn head_length(buf: &[u8]) -> Option<usize> {
+    let mut lines = Lines { buf, at: 0 };
+    // the request line, even an empty one
+    lines.next()?;
+    loop {
+        let line = lines.next()?;
+        // not a lone CR at the very end, which may yet become CR LF
+        if line.is_empty() && buf[..lines.at].ends_with(b"\n") {
+            return Some(lines.at);
+        }
+    }
+}
+
+/// `bytes` as text, borrowed unless it needs invalid UTF-8 replaced
+pub fn text(bytes: &[u8]) -> std::borrow::Cow<'_, str> {
+    String::from_utf8_lossy(bytes)
+}
+
+/// Finds where a request head ends as its bytes arrive, picking up each
+/// time where the last search stopped instead of rescanning the buffer.
+/// Lines may end in CR LF or a bare LF, as for `parse_head`.
+#[derive(Debug, Default)]
+pub struct HeadScanner {
+    searched: usize,
+}
+
+impl HeadScanner {
+    /// the length of the head, its blank line included, once `buf` holds
+    /// all of it
+    pub fn scan(&mut self, buf: &[u8]) -> Option<usize> {
+        // the blank line may straddle the old end
+        let mut at = self.searched.saturating_sub(2).min(buf.len());
+        self.searched = buf.len();
+        while let Some(i) = buf[at..].iter().position(|&b| b == b'\n') {
+            let lf = at + i;
+            match &buf[lf + 1..] {
+                [b'\n', ..] => return Some(lf + 2),
+                [b'\r', b'\n', ..] => return Some(lf + 3),
+                _ => at = lf + 1,
+            }
+        }
+        None
+    }
+}
+
+/// lines of a head without their CR LF or LF; `at` is just past the last
+struct Lines<'a> {
+    buf: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let rest = &self.buf[self.at..];
+        if rest.is_empty() {
+            return None;
+        }
+        let (line, used) = match rest.iter().position(|&b| b == b'\n') {
+            Some(end) => (&rest[..end], end + 1),
+            None => (rest, rest.len()),
+        };
+        self.at += used;
+        Some(line.strip_suffix(b"\r").unwrap_or(line))
+    }
+}
@@ -0,0 +1,84 @@
+use crate::mime::Mime;
+use crate::models::error::HTTPError;
+use crate::models::http::{HTTPRequest, HTTPResponse, HTTPStatus};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// An error as `application/problem+json` (RFC 9457, formerly RFC 7807):
+///
+/// `{"type": "about:blank", "title": "Not Found", "status": 404, "detail": "no post 7", "instance": "/posts/7"}`
+///
+/// `Router::enable_problem_details` renders every error this way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    /// URI naming the kind of problem; `about:blank` when the status says it all
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// URI of this occurrence, e.g. the request path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// further members, e.g. `"balance": 30`
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl ProblemDetails {
+    /// `about:blank` with the status's reason phrase as title
+    pub fn new(status: &HTTPStatus) -> Self {
+        ProblemDetails {
+            problem_type: String::from("about:blank"),
+            title: status.to_string(),
+            status: status.code(),
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// `error` while handling `req`: its message as detail, the request
+    /// path as instance
+    pub fn from_error(error: &HTTPError, req: &HTTPRequest) -> Self {
+        let mut problem = ProblemDetails::new(&error.status);
+        if !error.message.is_empty() {
+            problem.detail = Some(error.message.clone());
+        }
+        if !req.path().is_empty() {
+            problem.instance = Some(req.path().to_string());
+        }
+        problem
+    }
+
+    pub fn with_type(mut self, uri: &str, title: &str) -> Self {
+        self.problem_type = uri.to_string();
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn with_detail(mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    pub fn with_instance(mut self, instance: &str) -> Self {
+        self.instance = Some(instance.to_string());
+        self
+    }
+
+    pub fn with_extension(mut self, name: &str, value: Value) -> Self {
+        self.extensions.insert(name.to_string(), value);
+        self
+    }
+
+    /// a response with this as its body and the matching status
+    pub fn to_response(&self) -> HTTPResponse {
+        let status = HTTPStatus::custom(self.status, "").unwrap_or(HTTPStatus::InternalServerError);
+        let mut res = HTTPResponse::error(status, "");
+        res.body = Some(serde_json::to_string(self).unwrap_or_default());
+        res.set_content_type(Mime::APPLICATION_PROBLEM_JSON);
+        res
+    }
+}
@@ -0,0 +1,126 @@
+use crate::middleware::BoxFuture;
+use crate::models::http::HTTPResponse;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The raw connection, handed over once an upgrade response has been written
+pub trait Upgraded: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Upgraded for T {}
+
+type UpgradeHandler = Box<dyn FnOnce(Box<dyn Upgraded>) -> BoxFuture<'static, ()> + Send>;
+
+/// What to do with the connection after a `101 Switching Protocols`, or a
+/// 2xx answering `CONNECT`. The server writes the response head, then runs
+/// the handler on the connection instead of closing it. Ignored over HTTP/2.
+#[derive(Clone, Default)]
+pub struct OnUpgrade {
+    handler: Option<Arc<Mutex<Option<UpgradeHandler>>>>,
+}
+
+impl OnUpgrade {
+    pub fn new<F, Fut>(handler: F) -> Self
+    where
+        F: FnOnce(Box<dyn Upgraded>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler: UpgradeHandler = Box::new(move |io| Box::pin(handler(io)));
+        OnUpgrade {
+            handler: Some(Arc::new(Mutex::new(Some(handler)))),
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    /// run the handler on `io`; only the first call on any clone does anything
+    pub async fn run(&self, io: Box<dyn Upgraded>) {
+        let handler = self.handler.as_ref().and_then(|h| h.lock().unwrap().take());
+        if let Some(handler) = handler {
+            handler(io).await;
+        }
+    }
+}
+
+impl Debug for OnUpgrade {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OnUpgrade({})", if self.is_set() { "set" } else { "unset" })
+    }
+}
+
+/// like `Extensions`, not part of a response's identity
+impl PartialEq for OnUpgrade {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for OnUpgrade {}
+
+impl HTTPResponse {
+    /// take over the connection with `handler` once this (101, or 2xx to
+    /// `CONNECT`) response is sent
+    pub fn on_upgrade<F, Fut>(&mut self, handler: F)
+    where
+        F: FnOnce(Box<dyn Upgraded>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.upgrade = OnUpgrade::new(handler);
+    }
+}
+
+/// IO wrapper that replays bytes already consumed from the stream before
+/// reading from it again
+pub(crate) struct Rewind<S> {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> Rewind<S> {
+    pub(crate) fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Rewind {
+            prefix,
+            position: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Rewind<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.position < self.prefix.len() {
+            let n = (self.prefix.len() - self.position).min(buf.remaining());
+            buf.put_slice(&self.prefix[self.position..self.position + n]);
+            self.position += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Rewind<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
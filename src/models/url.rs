@@ -0,0 +1,260 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A URL like `https://example.com:8443/a/b?x=1#top`, or a reference like
+/// `/a/b?x=1` without scheme and host, split into its parts. Path, query
+/// and fragment stay percent-encoded as written, so a parsed URL writes
+/// back out unchanged; `segments` and `query_pairs` decode them.
+///
+/// Build one with `Url::new` and the `with_` methods, which encode what
+/// they are given:
+///
+/// `Url::new("https", "example.com").with_segment("a b").with_query("q", "x&y")`
+/// is `https://example.com/a%20b?q=x%26y`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Url {
+    /// lower-cased, e.g. `https`
+    pub scheme: Option<String>,
+    /// lower-cased, brackets included for IPv6, e.g. `[::1]`
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub path: String,
+    /// without the `?`
+    pub query: Option<String>,
+    /// without the `#`
+    pub fragment: Option<String>,
+}
+
+impl Url {
+    /// `scheme://host/`
+    pub fn new(scheme: &str, host: &str) -> Self {
+        Url {
+            scheme: Some(scheme.to_ascii_lowercase()),
+            host: Some(host.to_ascii_lowercase()),
+            path: String::from("/"),
+            ..Default::default()
+        }
+    }
+
+    /// Parse an absolute URL or a reference starting with `/`. URLs with
+    /// credentials (`user:pass@host`) and anything with whitespace or
+    /// control characters are refused.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let invalid = |why: &str| format!("invalid URL {:?}: {}", input, why);
+        if input.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(invalid("whitespace or control characters"));
+        }
+        let mut url = Url::default();
+        let (rest, fragment) = match input.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment.to_string())),
+            None => (input, None),
+        };
+        url.fragment = fragment;
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query.to_string())),
+            None => (rest, None),
+        };
+        url.query = query;
+
+        let Some((scheme, rest)) = rest.split_once("://").filter(|(scheme, _)| valid_scheme(scheme)) else {
+            if !rest.is_empty() && !rest.starts_with('/') {
+                return Err(invalid("expected a scheme or a path starting with /"));
+            }
+            url.path = rest.to_string();
+            return Ok(url);
+        };
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if authority.contains('@') {
+            return Err(invalid("credentials are not supported"));
+        }
+        let (host, port) = if authority.starts_with('[') {
+            let end = authority.find(']').ok_or_else(|| invalid("unclosed IPv6 address"))?;
+            match &authority[end + 1..] {
+                "" => (&authority[..=end], None),
+                port => (&authority[..=end], Some(port.strip_prefix(':').ok_or_else(|| invalid("bad port"))?)),
+            }
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+        if host.is_empty() {
+            return Err(invalid("no host"));
+        }
+        url.scheme = Some(scheme.to_ascii_lowercase());
+        url.host = Some(host.to_ascii_lowercase());
+        url.port = match port {
+            Some(port) => Some(port.parse().map_err(|_| invalid("bad port"))?),
+            None => None,
+        };
+        url.path = path.to_string();
+        Ok(url)
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// replace the path with `path`, encoding everything but `/` that
+    /// needs it
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = encode(path, b"/:@!$&'()*+,;=");
+        if self.host.is_some() && !self.path.starts_with('/') {
+            self.path.insert(0, '/');
+        }
+        self
+    }
+
+    /// append `segment` to the path, `/` and all encoded
+    pub fn with_segment(mut self, segment: &str) -> Self {
+        if !self.path.ends_with('/') {
+            self.path.push('/');
+        }
+        self.path.push_str(&encode(segment, b":@!$&'()*+,;="));
+        self
+    }
+
+    /// add `name=value` to the query
+    pub fn with_query(mut self, name: &str, value: &str) -> Self {
+        let pair = format!("{}={}", encode(name, b""), encode(value, b""));
+        match &mut self.query {
+            Some(query) if !query.is_empty() => {
+                query.push('&');
+                query.push_str(&pair);
+            }
+            _ => self.query = Some(pair),
+        }
+        self
+    }
+
+    pub fn with_fragment(mut self, fragment: &str) -> Self {
+        self.fragment = Some(encode(fragment, b"/?:@!$&'()*+,;="));
+        self
+    }
+
+    /// the port, or the scheme's usual one for http(s) and ws(s)
+    pub fn port_or_default(&self) -> Option<u16> {
+        self.port.or(match self.scheme.as_deref()? {
+            "http" | "ws" => Some(80),
+            "https" | "wss" => Some(443),
+            _ => None,
+        })
+    }
+
+    /// `host[:port]`
+    pub fn authority(&self) -> Option<String> {
+        let host = self.host.as_ref()?;
+        Some(match self.port {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.clone(),
+        })
+    }
+
+    /// e.g. `/a/b?x=1`, as sent in a request line
+    pub fn path_and_query(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone(),
+        }
+    }
+
+    /// the decoded path segments, empty ones left out
+    pub fn segments(&self) -> Vec<String> {
+        self.path.split('/').filter(|segment| !segment.is_empty()).map(decode).collect()
+    }
+
+    /// the decoded query names and values, in order
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        self.query.as_deref().map(parse_query).unwrap_or_default()
+    }
+
+    /// the first decoded value of `name` in the query
+    pub fn query_value(&self, name: &str) -> Option<String> {
+        self.query_pairs().into_iter().find(|(key, _)| key == name).map(|(_, value)| value)
+    }
+}
+
+impl FromStr for Url {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        Url::parse(s)
+    }
+}
+
+impl Display for Url {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{}://", scheme)?;
+        }
+        if let Some(authority) = self.authority() {
+            write!(f, "{}", authority)?;
+        }
+        write!(f, "{}", self.path)?;
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+fn valid_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+/// `a=1&b=x%20y&c` as decoded pairs, `+` read as a space
+pub(crate) fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(&name.replace('+', " ")), decode(&value.replace('+', " ")))
+        })
+        .collect()
+}
+
+/// percent-encode everything but unreserved characters (RFC 3986 2.3)
+/// and `keep`
+pub(crate) fn encode(value: &str, keep: &[u8]) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) || keep.contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// decode `%XX` escapes, leaving malformed ones as they are and replacing
+/// invalid UTF-8
+pub(crate) fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
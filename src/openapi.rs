@@ -0,0 +1,304 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::mime::Mime;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse};
+use crate::router::{segment_parts, Part, Router};
+use serde_json::{json, Map, Value};
+use std::sync::OnceLock;
+
+/// content type and schema of a body
+pub type Content = (String, Value);
+
+/// What an OpenAPI document says about one route, attached with
+/// `Router::document`. Schemas are JSON Schema values, e.g.
+/// `json!({"type": "object", "properties": {"title": {"type": "string"}}})`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Operation {
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub operation_id: Option<String>,
+    pub tags: Vec<String>,
+    pub deprecated: bool,
+    pub request_body: Option<Content>,
+    /// status, description and body if there is one
+    pub responses: Vec<(u16, String, Option<Content>)>,
+}
+
+impl Operation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn summary(mut self, summary: &str) -> Self {
+        self.summary = Some(summary.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn operation_id(mut self, id: &str) -> Self {
+        self.operation_id = Some(id.to_string());
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn deprecated(mut self) -> Self {
+        self.deprecated = true;
+        self
+    }
+
+    /// a JSON request body of `schema`
+    pub fn json_body(self, schema: Value) -> Self {
+        self.request_body(Mime::APPLICATION_JSON.as_str(), schema)
+    }
+
+    pub fn request_body(mut self, content_type: &str, schema: Value) -> Self {
+        self.request_body = Some((content_type.to_string(), schema));
+        self
+    }
+
+    /// a response without a body
+    pub fn response(mut self, status: u16, description: &str) -> Self {
+        self.responses.push((status, description.to_string(), None));
+        self
+    }
+
+    /// a JSON response of `schema`
+    pub fn json_response(mut self, status: u16, description: &str, schema: Value) -> Self {
+        let content = (Mime::APPLICATION_JSON.as_str().to_string(), schema);
+        self.responses.push((status, description.to_string(), Some(content)));
+        self
+    }
+}
+
+/// Pages that render the document in a browser. Their scripts and styles
+/// load from a CDN, so viewers need internet access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocsUi {
+    SwaggerUi,
+    Redoc,
+}
+
+/// An OpenAPI 3.1 document of a router's routes. As a layer, it serves the
+/// document at `/openapi.json` (see `with_spec_path`) and, with `with_ui`,
+/// a documentation page. Routes appear with their path parameters, the
+/// body types allowed with `Router::accept`, and whatever
+/// `Router::document` added.
+#[derive(Debug)]
+pub struct OpenApi {
+    title: String,
+    version: String,
+    description: Option<String>,
+    servers: Vec<String>,
+    spec_path: String,
+    ui: Option<(String, DocsUi)>,
+    cached: OnceLock<String>,
+}
+
+impl OpenApi {
+    /// `title` and `version` of the API, not of the OpenAPI format
+    pub fn new(title: &str, version: &str) -> Self {
+        OpenApi {
+            title: title.to_string(),
+            version: version.to_string(),
+            description: None,
+            servers: Vec::new(),
+            spec_path: String::from("/openapi.json"),
+            ui: None,
+            cached: OnceLock::new(),
+        }
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// base URL the API is reachable at, e.g. `https://api.example.com/v1`
+    pub fn with_server(mut self, url: &str) -> Self {
+        self.servers.push(url.to_string());
+        self
+    }
+
+    pub fn with_spec_path(mut self, path: &str) -> Self {
+        self.spec_path = path.to_string();
+        self
+    }
+
+    /// also serve `ui` at `path`, e.g. `/docs`
+    pub fn with_ui(mut self, path: &str, ui: DocsUi) -> Self {
+        self.ui = Some((path.to_string(), ui));
+        self
+    }
+
+    /// the document for `router`'s routes
+    pub fn generate(&self, router: &Router) -> Value {
+        let mut paths = Map::new();
+        for info in router.routes() {
+            let Some(method) = operation_key(info.method) else {
+                continue;
+            };
+            let route = &(info.method.clone(), info.pattern.to_string());
+            let (path, parameters) = path_template(info.pattern);
+            let operation = router.operation(route).cloned().unwrap_or_default();
+            let mut object = Map::new();
+            if let Some(summary) = operation.summary {
+                object.insert("summary".into(), json!(summary));
+            }
+            if let Some(description) = operation.description {
+                object.insert("description".into(), json!(description));
+            }
+            if let Some(id) = operation.operation_id {
+                object.insert("operationId".into(), json!(id));
+            }
+            if !operation.tags.is_empty() {
+                object.insert("tags".into(), json!(operation.tags));
+            }
+            if operation.deprecated {
+                object.insert("deprecated".into(), json!(true));
+            }
+            if !parameters.is_empty() {
+                let parameters: Vec<Value> = parameters
+                    .iter()
+                    .map(|(name, constraint)| {
+                        let mut schema = json!({"type": "string"});
+                        if let Some(constraint) = constraint {
+                            schema["pattern"] = json!(format!("^(?:{})$", constraint));
+                        }
+                        json!({"name": name, "in": "path", "required": true, "schema": schema})
+                    })
+                    .collect();
+                object.insert("parameters".into(), json!(parameters));
+            }
+            let body_types: Vec<Content> = match (operation.request_body, info.accepts) {
+                (Some(body), _) => vec![body],
+                (None, Some(types)) => types.iter().map(|t| (t.essence(), json!({}))).collect(),
+                (None, None) => Vec::new(),
+            };
+            if !body_types.is_empty() {
+                let content: Map<String, Value> = body_types
+                    .into_iter()
+                    .map(|(content_type, schema)| (content_type, json!({"schema": schema})))
+                    .collect();
+                object.insert("requestBody".into(), json!({"content": content}));
+            }
+            let mut responses = Map::new();
+            for (status, description, content) in operation.responses {
+                let mut response = json!({"description": description});
+                if let Some((content_type, schema)) = content {
+                    response["content"] = json!({content_type: {"schema": schema}});
+                }
+                responses.insert(status.to_string(), response);
+            }
+            if responses.is_empty() {
+                responses.insert("200".into(), json!({"description": "OK"}));
+            }
+            object.insert("responses".into(), Value::Object(responses));
+
+            let item = paths.entry(path).or_insert_with(|| json!({}));
+            item[method] = Value::Object(object);
+        }
+
+        let mut info = json!({"title": self.title, "version": self.version});
+        if let Some(description) = &self.description {
+            info["description"] = json!(description);
+        }
+        let mut document = json!({"openapi": "3.1.0", "info": info, "paths": paths});
+        if !self.servers.is_empty() {
+            let servers: Vec<Value> = self.servers.iter().map(|url| json!({"url": url})).collect();
+            document["servers"] = json!(servers);
+        }
+        document
+    }
+
+    fn ui_page(&self, ui: DocsUi) -> String {
+        let title = self.title.replace('&', "&amp;").replace('<', "&lt;").replace('"', "&quot;");
+        let spec = self.spec_path.replace('"', "%22");
+        let body = match ui {
+            DocsUi::SwaggerUi => format!(
+                "<link rel=\"stylesheet\" href=\"https://unpkg.com/swagger-ui-dist@5/swagger-ui.css\">\n\
+                 </head><body><div id=\"swagger-ui\"></div>\n\
+                 <script src=\"https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js\"></script>\n\
+                 <script>SwaggerUIBundle({{url: \"{}\", dom_id: \"#swagger-ui\"}});</script>",
+                spec
+            ),
+            DocsUi::Redoc => format!(
+                "</head><body><redoc spec-url=\"{}\"></redoc>\n\
+                 <script src=\"https://cdn.redoc.ly/redoc/latest/bundles/redoc.standalone.js\"></script>",
+                spec
+            ),
+        };
+        format!(
+            "<!doctype html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>\n{}\n</body></html>\n",
+            title, body
+        )
+    }
+}
+
+impl Middleware for OpenApi {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        if !matches!(req.method, HTTPMethod::GET | HTTPMethod::HEAD) {
+            return next.run(req);
+        }
+        let path = req.path();
+        let mut res = HTTPResponse::default();
+        if path == self.spec_path {
+            // the router can't change once it serves requests
+            let document = self.cached.get_or_init(|| self.generate(next.router()).to_string());
+            res.set_header(HTTPHeaderType::ContentType, Mime::APPLICATION_JSON.as_str());
+            res.body = Some(document.clone());
+        } else if let Some((_, ui)) = self.ui.as_ref().filter(|(ui_path, _)| *ui_path == path) {
+            res.set_header(HTTPHeaderType::ContentType, Mime::TEXT_HTML.as_str());
+            res.body = Some(self.ui_page(*ui));
+        } else {
+            return next.run(req);
+        }
+        Box::pin(async move { res })
+    }
+}
+
+/// the OpenAPI field for `method`, if it has one
+fn operation_key(method: &HTTPMethod) -> Option<&'static str> {
+    Some(match method {
+        HTTPMethod::GET => "get",
+        HTTPMethod::HEAD => "head",
+        HTTPMethod::POST => "post",
+        HTTPMethod::PUT => "put",
+        HTTPMethod::PATCH => "patch",
+        HTTPMethod::DELETE => "delete",
+        HTTPMethod::OPTIONS => "options",
+        HTTPMethod::CONNECT => return None,
+    })
+}
+
+/// `/files/{*path}` as `/files/{path}` and `/posts/{id:\d+}` as
+/// `/posts/{id}`, along with the parameter names and constraints
+fn path_template(pattern: &str) -> (String, Vec<(String, Option<String>)>) {
+    let mut parameters = Vec::new();
+    let segments: Vec<String> = pattern
+        .split('/')
+        .map(|segment| {
+            let Ok(parts) = segment_parts(segment) else {
+                return segment.to_string();
+            };
+            parts
+                .iter()
+                .map(|part| match part {
+                    Part::Literal(text) => text.to_string(),
+                    Part::Param(name, constraint) => {
+                        let name = name.trim_start_matches('*');
+                        parameters.push((name.to_string(), constraint.map(str::to_string)));
+                        format!("{{{}}}", name)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    (segments.join("/"), parameters)
+}
@@ -0,0 +1,409 @@
+pub mod balance;
+pub mod forward;
+pub mod health;
+
+pub use balance::{Balance, HashKey};
+pub use forward::ForwardProxy;
+pub use health::{HealthCheck, PassiveHealth};
+
+use crate::http1::{self, Framing};
+use crate::models::upgrade::Rewind;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use balance::Ring;
+use health::{InFlight, UpstreamState};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+/// how long to wait for an upstream to accept the connection by default
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// headers that only describe one connection and are never forwarded
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A server requests are forwarded to, parsed from a base URL such as
+/// `http://127.0.0.1:8080` or `http://backend/api`. The path, if any, is
+/// prepended to every forwarded request's URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    /// as written in the URL, brackets included for IPv6
+    pub host: String,
+    pub port: u16,
+    /// without a trailing slash; empty for the root
+    pub base_path: String,
+}
+
+impl Upstream {
+    /// `host:port`, leaving out the default port
+    pub fn authority(&self) -> String {
+        if self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl FromStr for Upstream {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let rest = s.trim();
+        let rest = match rest.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => rest,
+            Some((scheme, _)) => return Err(format!("unsupported upstream scheme: {}", scheme)),
+            None => rest,
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        // the port follows the last colon, unless it is inside an IPv6 literal
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => {
+                let port = authority[i + 1..]
+                    .parse()
+                    .map_err(|_| format!("invalid upstream port: {}", s))?;
+                (&authority[..i], port)
+            }
+            _ => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("upstream without a host: {}", s));
+        }
+        Ok(Upstream {
+            host: host.to_string(),
+            port,
+            base_path: path.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+impl Display for Upstream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "http://{}{}", self.authority(), self.base_path)
+    }
+}
+
+/// Forwards requests to a pool of upstream servers, taking turns between
+/// them unless another `Balance` is set. Used as a route handler, e.g.
+/// `router.proxy("/api", proxy)`: `Host` is rewritten to the upstream,
+/// `X-Forwarded-For`/`-Host`/`-Proto` describe the original request, and
+/// the upstream's response is streamed back as it arrives. Upgrade requests,
+/// e.g. WebSocket handshakes, are forwarded too, and after a 101 the two
+/// connections are spliced together. Upstreams that can't be reached give
+/// 502, or 504 when connecting times out; 503 means every upstream is out
+/// of the pool.
+///
+/// With `ServerConfig::preserve_header_case`, request headers keep the
+/// client's casing and order on the way upstream.
+///
+/// Request bodies are forwarded as the bytes that arrived, binary ones
+/// included (`HTTPRequest::body_bytes`), with `Content-Length` recomputed.
+/// Responses repeating a header keep a comma-joined value, except
+/// `Set-Cookie`, which keeps the last one.
+#[derive(Debug, Clone)]
+pub struct ReverseProxy {
+    upstreams: Arc<Vec<Upstream>>,
+    states: Arc<Vec<UpstreamState>>,
+    ring: Arc<Ring>,
+    next: Arc<AtomicUsize>,
+    balance: Balance,
+    passive: Option<PassiveHealth>,
+    health_check: Option<HealthCheck>,
+    connect_timeout: Duration,
+}
+
+impl ReverseProxy {
+    /// a proxy over the base URLs in `upstreams`; fails on an empty pool or
+    /// an invalid URL
+    pub fn new<I>(upstreams: I) -> Result<Self, String>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let upstreams = upstreams
+            .into_iter()
+            .map(|upstream| upstream.as_ref().parse())
+            .collect::<Result<Vec<Upstream>, String>>()?;
+        if upstreams.is_empty() {
+            return Err(String::from("a reverse proxy needs at least one upstream"));
+        }
+        Ok(ReverseProxy {
+            states: Arc::new(upstreams.iter().map(|_| UpstreamState::default()).collect()),
+            ring: Arc::new(Ring::new(&upstreams)),
+            upstreams: Arc::new(upstreams),
+            next: Arc::new(AtomicUsize::new(0)),
+            balance: Balance::default(),
+            passive: None,
+            health_check: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        })
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn with_balance(mut self, balance: Balance) -> Self {
+        self.balance = balance;
+        self
+    }
+
+    /// eject upstreams that keep failing, see `PassiveHealth`
+    pub fn with_passive_health(mut self, passive: PassiveHealth) -> Self {
+        self.passive = Some(passive);
+        self
+    }
+
+    /// probe upstreams once `spawn_health_checks` is called
+    pub fn with_health_check(mut self, check: HealthCheck) -> Self {
+        self.health_check = Some(check);
+        self
+    }
+
+    pub fn upstreams(&self) -> &[Upstream] {
+        &self.upstreams
+    }
+
+    /// upstreams currently taking requests
+    pub fn healthy(&self) -> Vec<&Upstream> {
+        self.upstreams
+            .iter()
+            .zip(self.states.iter())
+            .filter(|(_, state)| state.is_available())
+            .map(|(upstream, _)| upstream)
+            .collect()
+    }
+
+    /// Start probing upstreams as set up with `with_health_check`, right
+    /// away and then every interval. The task ends once every clone of the
+    /// proxy is dropped; `None` without a health check.
+    pub fn spawn_health_checks(&self) -> Option<JoinHandle<()>> {
+        let check = self.health_check.clone()?;
+        let upstreams = Arc::clone(&self.upstreams);
+        let states = Arc::downgrade(&self.states);
+        Some(tokio::spawn(async move {
+            loop {
+                let Some(states) = states.upgrade() else {
+                    return;
+                };
+                for (upstream, state) in upstreams.iter().zip(states.iter()) {
+                    state.record_probe(probe(upstream, &check).await);
+                }
+                drop(states);
+                tokio::time::sleep(check.interval).await;
+            }
+        }))
+    }
+
+    /// Route handler forwarding `req`. The response is deferred, so it is
+    /// only filled in when dispatched through a `Pipeline`.
+    pub fn handle(&self, req: &HTTPRequest, res: &mut HTTPResponse, _pattern: &str) {
+        let proxy = self.clone();
+        let req = req.clone();
+        res.defer(async move { proxy.forward(req).await });
+    }
+
+    /// send `req` to an upstream and return its response, with the body
+    /// still arriving
+    pub async fn forward(&self, req: HTTPRequest) -> HTTPResponse {
+        let Some(index) = self.pick(&req) else {
+            return HTTPResponse::error(HTTPStatus::ServiceUnavailable, "no healthy upstream");
+        };
+        let upstream = &self.upstreams[index];
+        let state = &self.states[index];
+        let in_flight = InFlight::new(&self.states, index);
+        let connect = crate::happy_eyeballs::connect_host(&upstream.host, upstream.port);
+        let mut stream = match tokio::time::timeout(self.connect_timeout, connect).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(_)) => {
+                state.record(false, self.passive.as_ref());
+                return HTTPResponse::error(HTTPStatus::BadGateway, "upstream unreachable");
+            }
+            Err(_) => {
+                state.record(false, self.passive.as_ref());
+                return HTTPResponse::error(HTTPStatus::GatewayTimeout, "upstream connect timed out");
+            }
+        };
+        match exchange(&mut stream, &req, upstream).await {
+            Ok((res, buffered, framing)) => {
+                state.record(true, self.passive.as_ref());
+                if res.status == HTTPStatus::SwitchingProtocols {
+                    return switch(res, &req, stream, buffered, in_flight);
+                }
+                let mut res = res;
+                http1::stream_body(&mut res, stream, buffered, framing, in_flight);
+                res
+            }
+            Err(_) => {
+                state.record(false, self.passive.as_ref());
+                HTTPResponse::error(HTTPStatus::BadGateway, "invalid upstream response")
+            }
+        }
+    }
+
+    /// index of the upstream for `req`, `None` when none is available
+    fn pick(&self, req: &HTTPRequest) -> Option<usize> {
+        let available = |i: &usize| self.states[*i].is_available();
+        let len = self.upstreams.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut in_turn = (0..len).map(|k| (start + k) % len);
+        match &self.balance {
+            Balance::RoundRobin => in_turn.find(available),
+            Balance::LeastConnections => in_turn
+                .filter(available)
+                .min_by_key(|i| self.states[*i].active.load(Ordering::Relaxed)),
+            Balance::ConsistentHash(key) => match key.of(req) {
+                Some(key) => self.ring.walk(&key).find(available),
+                None => in_turn.find(available),
+            },
+        }
+    }
+}
+
+/// whether `upstream` answers `check` in time with a 2xx or 3xx
+async fn probe(upstream: &Upstream, check: &HealthCheck) -> bool {
+    let req = HTTPRequest {
+        url: check.path.clone(),
+        ..Default::default()
+    };
+    let attempt = async {
+        let mut stream = crate::happy_eyeballs::connect_host(&upstream.host, upstream.port).await?;
+        let (res, _, _) = exchange(&mut stream, &req, upstream).await?;
+        Ok::<u16, io::Error>(res.status.code())
+    };
+    matches!(tokio::time::timeout(check.timeout, attempt).await, Ok(Ok(200..=399)))
+}
+
+/// Write `req` and read the response head. Returns the response with only
+/// end-to-end headers, the body bytes read along with the head and the
+/// body's framing.
+async fn exchange(
+    stream: &mut TcpStream,
+    req: &HTTPRequest,
+    upstream: &Upstream,
+) -> io::Result<(HTTPResponse, Vec<u8>, Framing)> {
+    let body = req.body_bytes();
+    stream.write_all(request_head(req, upstream, body.len()).as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+    let (mut res, buffered, framing) = http1::read_response(stream, &req.method).await?;
+    strip_hop_by_hop(&mut res);
+    Ok((res, buffered, framing))
+}
+
+/// request headers the proxy writes itself, lowercased
+const REPLACED: [&str; 5] = ["host", "content-length", "x-forwarded-for", "x-forwarded-host", "x-forwarded-proto"];
+
+/// request line and headers as forwarded to `upstream`
+fn request_head(req: &HTTPRequest, upstream: &Upstream, body_len: usize) -> String {
+    let listed = connection_tokens(req.header(&HTTPHeaderType::Connection));
+    let mut head = format!("{} {}{} HTTP/1.1\r\n", req.method, upstream.base_path, req.path_and_query());
+    // the client's own casing and order when the server kept them
+    let headers: Vec<(String, &str)> = if req.raw_headers.is_empty() {
+        req.headers.iter().map(|(name, value)| (name.to_string(), value.as_str())).collect()
+    } else {
+        req.raw_headers.iter().map(|(name, value)| (name.clone(), value.as_str())).collect()
+    };
+    for (name, value) in headers {
+        let lower = name.to_ascii_lowercase();
+        if REPLACED.contains(&lower.as_str()) || HOP_BY_HOP.contains(&lower.as_str()) || listed.contains(&lower) {
+            continue;
+        }
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!("Host: {}\r\n", upstream.authority()));
+    let forwarded_for = req.header(&HTTPHeaderType::XForwardedFor);
+    match (forwarded_for, req.peer_addr()) {
+        (Some(chain), Some(peer)) => head.push_str(&format!("X-Forwarded-For: {}, {}\r\n", chain, peer.ip())),
+        (None, Some(peer)) => head.push_str(&format!("X-Forwarded-For: {}\r\n", peer.ip())),
+        (Some(chain), None) => head.push_str(&format!("X-Forwarded-For: {}\r\n", chain)),
+        (None, None) => {}
+    }
+    if let Some(host) = req.host() {
+        head.push_str(&format!("X-Forwarded-Host: {}\r\n", host));
+    }
+    let proto = if req.is_tls() { "https" } else { "http" };
+    head.push_str(&format!("X-Forwarded-Proto: {}\r\n", proto));
+    if body_len > 0 || matches!(req.method, HTTPMethod::POST | HTTPMethod::PUT | HTTPMethod::PATCH) {
+        head.push_str(&format!("Content-Length: {}\r\n", body_len));
+    }
+    match requested_upgrade(req) {
+        Some(protocol) => head.push_str(&format!("Upgrade: {}\r\nConnection: Upgrade\r\n\r\n", protocol)),
+        None => head.push_str("Connection: close\r\n\r\n"),
+    }
+    head
+}
+
+/// the protocol `req` asks to switch to, e.g. `websocket`
+fn requested_upgrade(req: &HTTPRequest) -> Option<&str> {
+    let listed = connection_tokens(req.header(&HTTPHeaderType::Connection));
+    if !listed.iter().any(|token| token == "upgrade") {
+        return None;
+    }
+    req.header(&HTTPHeaderType::Upgrade)
+}
+
+/// lowercased header names listed in a `Connection` header
+fn connection_tokens(connection: Option<&str>) -> Vec<String> {
+    connection
+        .unwrap_or("")
+        .split(',')
+        .map(|token| token.trim().to_ascii_lowercase())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+/// drop the upstream's connection-specific headers; the coding of a
+/// chunked body goes once it is decoded, `Upgrade` stays on a 101
+fn strip_hop_by_hop(res: &mut HTTPResponse) {
+    let listed = connection_tokens(res.header(&HTTPHeaderType::Connection));
+    let switching = res.status == HTTPStatus::SwitchingProtocols;
+    res.headers.retain(|name, _| {
+        let lower = name.to_string().to_ascii_lowercase();
+        match lower.as_str() {
+            "transfer-encoding" => true,
+            "upgrade" if switching => true,
+            _ => !HOP_BY_HOP.contains(&lower.as_str()) && !listed.contains(&lower),
+        }
+    });
+}
+
+/// Pass the upstream's 101 on and, once the client's connection is handed
+/// over, copy bytes both ways until either side closes
+fn switch(
+    mut res: HTTPResponse,
+    req: &HTTPRequest,
+    stream: TcpStream,
+    buffered: Vec<u8>,
+    in_flight: InFlight,
+) -> HTTPResponse {
+    if requested_upgrade(req).is_none() || res.header(&HTTPHeaderType::Upgrade).is_none() {
+        return HTTPResponse::error(HTTPStatus::BadGateway, "unexpected upstream upgrade");
+    }
+    res.set_header(HTTPHeaderType::Connection, "Upgrade");
+    res.on_upgrade(move |mut client| async move {
+        let mut upstream = Rewind::new(buffered, stream);
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+        drop(in_flight);
+    });
+    res
+}
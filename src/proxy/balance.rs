@@ -0,0 +1,78 @@
+use super::Upstream;
+use crate::models::http::{HTTPHeaderType, HTTPRequest};
+
+/// points each upstream gets on the hash ring; more spread keys more evenly
+const VIRTUAL_NODES: usize = 64;
+
+/// How the proxy picks an upstream for a request. Every strategy skips
+/// upstreams that are ejected or failing their health checks.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Balance {
+    /// each upstream in turn
+    #[default]
+    RoundRobin,
+    /// the upstream with the fewest requests in flight, ties taking turns
+    LeastConnections,
+    /// Requests with the same key go to the same upstream, e.g. to keep a
+    /// client on the server holding its cache. When an upstream leaves or
+    /// rejoins only its own keys move. Requests without the key are
+    /// balanced round-robin.
+    ConsistentHash(HashKey),
+}
+
+/// what `Balance::ConsistentHash` hashes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashKey {
+    /// `HTTPRequest::real_ip`
+    ClientIp,
+    Header(HTTPHeaderType),
+    /// the path without the query
+    Path,
+}
+
+impl HashKey {
+    pub(crate) fn of(&self, req: &HTTPRequest) -> Option<String> {
+        match self {
+            HashKey::ClientIp => req.real_ip().map(|ip| ip.to_string()),
+            HashKey::Header(header) => req.header(header).map(str::to_string),
+            HashKey::Path => Some(req.path().to_string()),
+        }
+    }
+}
+
+/// upstream indices placed on a circle by hash
+#[derive(Debug)]
+pub(crate) struct Ring(Vec<(u64, usize)>);
+
+impl Ring {
+    pub(crate) fn new(upstreams: &[Upstream]) -> Self {
+        let mut points: Vec<(u64, usize)> = upstreams
+            .iter()
+            .enumerate()
+            .flat_map(|(i, upstream)| {
+                (0..VIRTUAL_NODES).map(move |node| (fnv1a(format!("{}#{}", upstream, node).as_bytes()), i))
+            })
+            .collect();
+        points.sort_unstable();
+        Ring(points)
+    }
+
+    /// upstreams in ring order from where `key` lands; each may appear
+    /// several times
+    pub(crate) fn walk(&self, key: &str) -> impl Iterator<Item = usize> + '_ {
+        let hash = fnv1a(key.as_bytes());
+        let start = self.0.partition_point(|(point, _)| *point < hash);
+        self.0[start..]
+            .iter()
+            .chain(&self.0[..start])
+            .map(|(_, i)| *i)
+    }
+}
+
+/// FNV-1a, stable across builds and platforms so every instance of a
+/// proxy maps keys the same way
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
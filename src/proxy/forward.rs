@@ -0,0 +1,110 @@
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use std::time::Duration;
+
+type AuthCheck = Box<dyn Fn(&HTTPRequest) -> bool + Send + Sync>;
+
+/// Forward proxy layer answering `CONNECT host:port`: it connects to the
+/// target, replies 200 and then tunnels bytes both ways until either side
+/// closes. Other requests pass on to the router.
+///
+/// Only ports in the allow list can be reached, 443 by default, so the
+/// proxy can't be used to relay mail or reach internal services. Targets
+/// refused this way get 403, unreachable ones 502 (504 on timeout).
+pub struct ForwardProxy {
+    allowed_ports: Vec<u16>,
+    auth: Option<(String, AuthCheck)>,
+    connect_timeout: Duration,
+}
+
+impl ForwardProxy {
+    pub fn new() -> Self {
+        ForwardProxy {
+            allowed_ports: vec![443],
+            auth: None,
+            connect_timeout: super::DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+
+    /// ports tunnels may be opened to, replacing the default
+    pub fn with_allowed_ports(mut self, ports: impl IntoIterator<Item = u16>) -> Self {
+        self.allowed_ports = ports.into_iter().collect();
+        self
+    }
+
+    /// Only open tunnels for requests `check` accepts, typically by looking
+    /// at `Proxy-Authorization`. Others get 407 with a Basic challenge for
+    /// `realm`.
+    pub fn with_auth<F>(mut self, realm: impl Into<String>, check: F) -> Self
+    where
+        F: Fn(&HTTPRequest) -> bool + Send + Sync + 'static,
+    {
+        self.auth = Some((realm.into(), Box::new(check)));
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    async fn tunnel(&self, req: &HTTPRequest) -> HTTPResponse {
+        if let Some((realm, check)) = &self.auth {
+            if !check(req) {
+                let mut res = HTTPResponse::error(
+                    HTTPStatus::ProxyAuthenticationRequired,
+                    &HTTPStatus::ProxyAuthenticationRequired.default_body(),
+                );
+                res.set_header(
+                    HTTPHeaderType::ProxyAuthenticate,
+                    format!("Basic realm=\"{}\"", realm.replace('"', "")),
+                );
+                return res;
+            }
+        }
+        let Some((host, port)) = target(&req.url) else {
+            return HTTPResponse::error(HTTPStatus::BadRequest, "CONNECT needs a host:port target");
+        };
+        if !self.allowed_ports.contains(&port) {
+            return HTTPResponse::error(HTTPStatus::Forbidden, "port not allowed");
+        }
+        let connect = crate::happy_eyeballs::connect_host(host, port);
+        let mut upstream = match tokio::time::timeout(self.connect_timeout, connect).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(_)) => return HTTPResponse::error(HTTPStatus::BadGateway, "target unreachable"),
+            Err(_) => return HTTPResponse::error(HTTPStatus::GatewayTimeout, "target connect timed out"),
+        };
+        let mut res = HTTPResponse {
+            body: None,
+            ..Default::default()
+        };
+        res.on_upgrade(move |mut client| async move {
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+        });
+        res
+    }
+}
+
+impl Default for ForwardProxy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for ForwardProxy {
+    fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        if req.method != HTTPMethod::CONNECT {
+            return next.run(req);
+        }
+        Box::pin(async move { self.tunnel(&req).await })
+    }
+}
+
+/// host and port of an authority-form target, e.g. `example.com:443` or `[::1]:8443`
+fn target(authority: &str) -> Option<(&str, u16)> {
+    let (host, port) = authority.trim().rsplit_once(':')?;
+    if host.is_empty() || port.contains(']') {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
@@ -0,0 +1,121 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Passive health checking: an upstream failing `max_failures` forwards in
+/// a row is ejected from the pool and rejoins after `eject_for`. Connect
+/// errors and invalid responses count as failures, error statuses don't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassiveHealth {
+    pub max_failures: u32,
+    pub eject_for: Duration,
+}
+
+impl Default for PassiveHealth {
+    fn default() -> Self {
+        PassiveHealth {
+            max_failures: 5,
+            eject_for: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Active health checking: every `interval`, each upstream gets a
+/// `GET {path}`. A 2xx or 3xx answer within `timeout` marks it healthy,
+/// anything else takes it out of the pool until a later probe passes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub path: String,
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl HealthCheck {
+    pub fn new(path: impl Into<String>) -> Self {
+        HealthCheck {
+            path: path.into(),
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// what the proxy knows about one upstream
+#[derive(Debug, Default)]
+pub(crate) struct UpstreamState {
+    /// requests forwarded and not yet finished
+    pub(crate) active: AtomicUsize,
+    failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+    /// set by active health checks
+    probe_failed: AtomicBool,
+}
+
+impl UpstreamState {
+    pub(crate) fn is_available(&self) -> bool {
+        if self.probe_failed.load(Ordering::Relaxed) {
+            return false;
+        }
+        let mut ejected_until = self.ejected_until.lock().unwrap();
+        match *ejected_until {
+            Some(until) if until > Instant::now() => false,
+            Some(_) => {
+                *ejected_until = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// count a forward's outcome, ejecting the upstream when it failed too often
+    pub(crate) fn record(&self, ok: bool, passive: Option<&PassiveHealth>) {
+        if ok {
+            self.failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        let Some(passive) = passive else {
+            return;
+        };
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= passive.max_failures {
+            self.failures.store(0, Ordering::Relaxed);
+            *self.ejected_until.lock().unwrap() = Some(Instant::now() + passive.eject_for);
+        }
+    }
+
+    pub(crate) fn record_probe(&self, ok: bool) {
+        self.probe_failed.store(!ok, Ordering::Relaxed);
+    }
+}
+
+/// counts a request as in flight on an upstream until dropped
+pub(crate) struct InFlight {
+    states: Arc<Vec<UpstreamState>>,
+    index: usize,
+}
+
+impl InFlight {
+    pub(crate) fn new(states: &Arc<Vec<UpstreamState>>, index: usize) -> Self {
+        states[index].active.fetch_add(1, Ordering::Relaxed);
+        InFlight {
+            states: Arc::clone(states),
+            index,
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.states[self.index].active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::config::ServerConfig;
+use std::sync::{Arc, Mutex, RwLock};
+
+type ReloadHook = Arc<dyn Fn(&ServerConfig) -> Result<(), String> + Send + Sync>;
+type Listener = Box<dyn Fn(&Arc<ServerConfig>) + Send + Sync>;
+
+/// Swaps the `ServerConfig` of a running server, from
+/// `HTTPServer::reloader`. Connections accepted afterwards get the new
+/// timeouts, limits, layers and TLS certificate, while open ones keep what
+/// they started with. Listener addresses and `max_connections` stay as
+/// they were at start.
+///
+/// `HTTPServer::reload_on_sighup` reloads on SIGHUP; an admin endpoint
+/// can call `reload` too, e.g. from a route bound with `DynamicRoutes`.
+#[derive(Clone)]
+pub struct ConfigReloader(Arc<Inner>);
+
+struct Inner {
+    config: RwLock<Arc<ServerConfig>>,
+    #[cfg(feature = "toml")]
+    file: Mutex<Option<std::path::PathBuf>>,
+    hooks: Mutex<Vec<ReloadHook>>,
+    /// rebuild what each listener serves new connections with
+    listeners: Mutex<Vec<Listener>>,
+}
+
+impl ConfigReloader {
+    pub(crate) fn new(config: ServerConfig) -> Self {
+        ConfigReloader(Arc::new(Inner {
+            config: RwLock::new(Arc::new(config)),
+            #[cfg(feature = "toml")]
+            file: Mutex::new(None),
+            hooks: Mutex::new(Vec::new()),
+            listeners: Mutex::new(Vec::new()),
+        }))
+    }
+
+    /// the config new connections get
+    pub fn current(&self) -> Arc<ServerConfig> {
+        Arc::clone(&self.0.config.read().unwrap())
+    }
+
+    /// Read the config again, from the file given to
+    /// `HTTPServer::with_config_file` if any, with the environment on top
+    /// (`ServerConfig::merge_env`), and `apply` it.
+    pub fn reload(&self) -> Result<(), String> {
+        #[cfg(feature = "toml")]
+        if let Some(file) = self.0.file.lock().unwrap().clone() {
+            return self.apply(ServerConfig::from_file(file)?.merge_env()?);
+        }
+        self.apply((*self.current()).clone().merge_env()?)
+    }
+
+    /// Switch to `config` and run the `on_reload` hooks. A TLS
+    /// certificate that doesn't load leaves the old config in place.
+    pub fn apply(&self, config: ServerConfig) -> Result<(), String> {
+        #[cfg(feature = "tls")]
+        crate::httpserver::tls_from_config(&config).map_err(|e| format!("TLS: {}", e))?;
+        let config = Arc::new(config);
+        *self.0.config.write().unwrap() = Arc::clone(&config);
+        for listener in self.0.listeners.lock().unwrap().iter() {
+            listener(&config);
+        }
+        self.run_hooks(&config)
+    }
+
+    /// run every `on_reload` hook with `config`, reporting all failures
+    pub(crate) fn run_hooks(&self, config: &ServerConfig) -> Result<(), String> {
+        let hooks = self.0.hooks.lock().unwrap().clone();
+        let errors: Vec<String> = hooks.iter().filter_map(|hook| hook(config).err()).collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// use `config` from now on without rebuilding anything, before start
+    pub(crate) fn replace(&self, config: ServerConfig) {
+        *self.0.config.write().unwrap() = Arc::new(config);
+    }
+
+    #[cfg(feature = "toml")]
+    pub(crate) fn set_file(&self, file: std::path::PathBuf) {
+        *self.0.file.lock().unwrap() = Some(file);
+    }
+
+    pub(crate) fn on_reload(&self, hook: ReloadHook) {
+        self.0.hooks.lock().unwrap().push(hook);
+    }
+
+    pub(crate) fn on_apply(&self, listener: Listener) {
+        self.0.listeners.lock().unwrap().push(listener);
+    }
+}
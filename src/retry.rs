@@ -0,0 +1,110 @@
+use crate::models::error::{ErrorKind, HTTPError};
+use crate::models::http::{HTTPRequest, HTTPResponse};
+use crate::router::PathParams;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Opt-in, per-route retries for handlers that fail with a retryable
+/// `ErrorKind`. Only idempotent requests are retried, and attempts are
+/// immediate: handlers are synchronous, so there is no backoff.
+/// Use with `router.try_bind(route, policy.wrap(handler))`.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    kinds: Vec<ErrorKind>,
+    stats: Arc<RetryStats>,
+}
+
+impl RetryPolicy {
+    /// retry `Transient` errors up to `max_retries` times
+    pub fn new(max_retries: u32) -> Self {
+        RetryPolicy {
+            max_retries,
+            kinds: vec![ErrorKind::Transient],
+            stats: Arc::new(RetryStats::default()),
+        }
+    }
+
+    /// also retry errors of `kind`
+    pub fn on(mut self, kind: ErrorKind) -> Self {
+        if !self.kinds.contains(&kind) {
+            self.kinds.push(kind);
+        }
+        self
+    }
+
+    pub fn stats(&self) -> Arc<RetryStats> {
+        Arc::clone(&self.stats)
+    }
+
+    pub fn should_retry(&self, req: &HTTPRequest, error: &HTTPError, attempt: u32) -> bool {
+        attempt < self.max_retries && req.method.is_idempotent() && self.kinds.contains(&error.kind)
+    }
+
+    pub fn wrap<F>(
+        &self,
+        handler: F,
+    ) -> impl Fn(&HTTPRequest, &mut HTTPResponse, &PathParams) -> Result<(), HTTPError> + Send + Sync + 'static
+    where
+        F: Fn(&HTTPRequest, &mut HTTPResponse, &PathParams) -> Result<(), HTTPError> + Send + Sync + 'static,
+    {
+        let policy = self.clone();
+        move |req, res, params| {
+            policy.stats.calls.fetch_add(1, Ordering::Relaxed);
+            let mut attempt = 0;
+            loop {
+                match handler(req, res, params) {
+                    Ok(()) => {
+                        if attempt > 0 {
+                            policy.stats.recovered.fetch_add(1, Ordering::Relaxed);
+                        }
+                        return Ok(());
+                    }
+                    Err(e) if policy.should_retry(req, &e, attempt) => {
+                        attempt += 1;
+                        policy.stats.retries.fetch_add(1, Ordering::Relaxed);
+                        // a failed attempt may have left a half-built response
+                        *res = HTTPResponse::default();
+                    }
+                    Err(e) => {
+                        if attempt > 0 {
+                            policy.stats.exhausted.fetch_add(1, Ordering::Relaxed);
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// counters for judging whether retries pay off
+#[derive(Debug, Default)]
+pub struct RetryStats {
+    calls: AtomicU64,
+    retries: AtomicU64,
+    recovered: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+impl RetryStats {
+    /// handler invocations from the router, not counting retries
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// extra attempts made
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// calls that failed at first but succeeded on a retry
+    pub fn recovered(&self) -> u64 {
+        self.recovered.load(Ordering::Relaxed)
+    }
+
+    /// calls that were retried and still failed
+    pub fn exhausted(&self) -> u64 {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+}
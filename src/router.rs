@@ -1,69 +1,1036 @@
-/// Match path against pattern, extract params. Pattern like "/posts/{id}"
+/// Split a request target into its path and decoded query parameters,
+/// e.g. "/users?name=bob" (see `models::url::Url` for the other parts)
 pub fn parse_url(url: &str) -> (String, std::collections::HashMap<String, String>) {
-    let mut query_params = std::collections::HashMap::new();
-    if let Some((path, query)) = url.split_once('?') {
-        for pair in query.split('&') {
-            if let Some((key, value)) = pair.split_once('=') {
-                query_params.insert(key.to_string(), value.to_string());
+    match crate::models::url::Url::parse(url) {
+        Ok(url) => {
+            let query = url.query_pairs().into_iter().collect();
+            (url.path, query)
+        }
+        Err(_) => (url.to_string(), std::collections::HashMap::new()),
+    }
+}
+
+/// `path` in normal form: percent-encoded unreserved characters decoded
+/// and other escapes upper-cased, repeated slashes collapsed and `.` and
+/// `..` segments resolved, e.g. `/a//b/./%63/../d` -> `/a/b/d`. `None`
+/// if `..` would climb above the root.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(i) = rest.find('%') {
+        decoded.push_str(&rest[..i]);
+        let escape = rest.get(i + 1..i + 3).filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+        match escape.map(|hex| (hex, u8::from_str_radix(hex, 16).unwrap())) {
+            Some((_, byte)) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => decoded.push(byte as char),
+            Some((hex, _)) => decoded.push_str(&format!("%{}", hex.to_ascii_uppercase())),
+            None => {
+                decoded.push('%');
+                rest = &rest[i + 1..];
+                continue;
+            }
+        }
+        rest = &rest[i + 3..];
+    }
+    decoded.push_str(rest);
+
+    let mut segments: Vec<&str> = Vec::new();
+    // `/a/.` and `/a/b/..` name the directory `/a/`
+    let mut directory = false;
+    for segment in decoded.split('/').filter(|segment| !segment.is_empty()) {
+        directory = matches!(segment, "." | "..");
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop()?;
             }
+            _ => segments.push(segment),
         }
-        (path.to_string(), query_params)
-    } else {
-        (url.to_string(), query_params)
     }
+    let mut normalized = format!("/{}", segments.join("/"));
+    if (directory || decoded.ends_with('/')) && !segments.is_empty() {
+        normalized.push('/');
+    }
+    Some(normalized)
 }
 
+/// A parameter may carry a regex its value must match, e.g.
+/// "/posts/{id:\\d+}", and share its segment with literal text or other
+/// parameters, e.g. "/files/{name:[a-z-]+}.{ext}".
+///
+/// A last segment of `{*name}` captures the rest of the path, slashes
+/// included (possibly empty), e.g. "/static/{*file}"
 pub fn match_route(pattern: &str, path: &str) -> Option<std::collections::HashMap<String, String>> {
     let pattern_parts: Vec<&str> = pattern.trim_matches('/').split('/').collect();
     let path_parts: Vec<&str> = path.trim_matches('/').split('/').collect();
 
-    if pattern_parts.len() != path_parts.len() {
-        return None;
-    }
-
     let mut params = std::collections::HashMap::new();
 
-    for (pat, p) in pattern_parts.iter().zip(path_parts) {
-        if pat.starts_with('{') && pat.ends_with('}') {
-            let param_name = &pat[1..pat.len() - 1];
+    for (i, pat) in pattern_parts.iter().enumerate() {
+        if let Some(name) = pat.strip_prefix("{*").and_then(|p| p.strip_suffix('}')) {
+            if i + 1 != pattern_parts.len() {
+                return None;
+            }
+            let rest = path_parts.get(i..).map(|rest| rest.join("/")).unwrap_or_default();
+            params.insert(name.to_string(), rest);
+            return Some(params);
+        }
+        let p = *path_parts.get(i)?;
+        if !pat.contains('{') {
+            if *pat != p {
+                return None;
+            }
+        } else if let Some(param_name) = lone_param(pat) {
             params.insert(param_name.to_string(), p.to_string());
-        } else if *pat != p {
-            return None;
+        } else {
+            let regex = segment_regex(pat).ok()??;
+            let captures = regex.captures(p)?;
+            for name in regex.capture_names().flatten() {
+                params.insert(name.to_string(), captures[name].to_string());
+            }
         }
     }
 
+    if pattern_parts.len() != path_parts.len() {
+        return None;
+    }
+
     Some(params)
 }
 
 pub type HTTPRoute = (crate::models::http::HTTPMethod, String);
-pub type HTTPHandler = Box<dyn Fn(&crate::models::http::HTTPRequest, &mut crate::models::http::HTTPResponse, &str) + Send + Sync>;
+pub type ErrorHandler = Box<dyn Fn(&crate::models::http::HTTPRequest, &crate::models::error::HTTPError) -> crate::models::http::HTTPResponse + Send + Sync>;
+pub type HTTPHandler = Box<dyn Fn(&crate::models::http::HTTPRequest, &mut crate::models::http::HTTPResponse, &PathParams) -> Result<(), crate::models::error::HTTPError> + Send + Sync>;
+
+/// Whether `/users` and `/users/` are the same path, set with
+/// `Router::trailing_slash`. Wildcard routes like `/static/{*file}` and
+/// the root path are unaffected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// either form matches a route bound with either
+    #[default]
+    Merge,
+    /// a path only matches a route bound with the same form
+    Strict,
+    /// like `Strict`, but a path that only matches in the other form is
+    /// redirected there: 301 for GET and HEAD, 308 otherwise
+    Redirect,
+}
+
+/// Labels captured by a `Router::host` pattern, kept in the request's
+/// extensions; see `HTTPRequest::host_param`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostParams(pub std::collections::HashMap<String, String>);
+
+/// The route a request matched, as its handler gets it: the pattern and
+/// the parameters captured from the path while routing, so handlers don't
+/// match the path again. It derefs to the pattern, so handlers taking the
+/// pattern as `&str` work unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathParams<'a> {
+    pub pattern: &'a str,
+    pub params: &'a std::collections::HashMap<String, String>,
+}
+
+impl<'a> PathParams<'a> {
+    /// the value captured for `{name}`
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.params.get(name).map(String::as_str)
+    }
+}
+
+impl std::ops::Deref for PathParams<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.pattern
+    }
+}
+
+impl std::fmt::Display for PathParams<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.pattern)
+    }
+}
+
+/// A handler with its method and path, e.g. from `routes![]` for
+/// functions declared with `#[get("/path")]` and friends
+pub struct Route {
+    pub method: crate::models::http::HTTPMethod,
+    pub path: String,
+    pub handler: HTTPHandler,
+}
+
+/// What `Router::routes` reports about a route
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteInfo<'a> {
+    pub method: &'a crate::models::http::HTTPMethod,
+    pub pattern: &'a str,
+    /// set with `Router::bind_named`
+    pub name: Option<&'a str>,
+    /// request body types allowed with `Router::accept`
+    pub accepts: Option<&'a [crate::models::headers::MediaType]>,
+    /// default set with `Router::buffering`
+    pub buffering: Option<crate::models::http::Buffering>,
+    /// the router's layers it runs behind, outermost first
+    pub middleware: Vec<&'static str>,
+}
 
 pub struct Router {
     routes: std::collections::HashMap<HTTPRoute, HTTPHandler>,
+    layers: Vec<std::sync::Arc<dyn crate::middleware::Middleware>>,
+    error_handler: Option<ErrorHandler>,
+    accepted_types: std::collections::HashMap<HTTPRoute, Vec<crate::models::headers::MediaType>>,
+    buffering: std::collections::HashMap<HTTPRoute, crate::models::http::Buffering>,
+    operations: std::collections::HashMap<HTTPRoute, crate::openapi::Operation>,
+    names: std::collections::HashMap<String, HTTPRoute>,
+    /// routes bound again, replacing the earlier handler
+    rebound: Vec<HTTPRoute>,
+    hosts: Vec<(String, Router)>,
+    trailing_slash: TrailingSlash,
+    problem_details: bool,
+    dynamic: Option<DynamicRoutes>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Router {
     pub fn new() -> Self {
         Router {
             routes: std::collections::HashMap::new(),
+            layers: Vec::new(),
+            error_handler: None,
+            accepted_types: std::collections::HashMap::new(),
+            buffering: std::collections::HashMap::new(),
+            operations: std::collections::HashMap::new(),
+            names: std::collections::HashMap::new(),
+            rebound: Vec::new(),
+            hosts: Vec::new(),
+            trailing_slash: TrailingSlash::default(),
+            problem_details: false,
+            dynamic: None,
+        }
+    }
+
+    /// render routing and handler errors yourself instead of the default
+    /// status line + message body
+    pub fn on_error<F>(&mut self, handler: F)
+    where
+        F: Fn(&crate::models::http::HTTPRequest, &crate::models::error::HTTPError) -> crate::models::http::HTTPResponse + 'static + Send + Sync,
+    {
+        self.error_handler = Some(Box::new(handler));
+    }
+
+    /// opt-in `GET /teapot` answering 418 through the error pipeline,
+    /// handy for checking custom error rendering end to end
+    pub fn enable_teapot(&mut self) {
+        self.try_bind((crate::models::http::HTTPMethod::GET, String::from("/teapot")), |_req, _res, _pattern| {
+            Err(crate::models::error::HTTPError::client(crate::models::http::HTTPStatus::ImATeapot, ""))
+        });
+    }
+
+    /// render errors as `application/problem+json` (`ProblemDetails`)
+    /// instead of text, unless `on_error` renders them
+    pub fn enable_problem_details(&mut self) {
+        self.problem_details = true;
+    }
+
+    /// Answer `route` with a tower `Service`, called with a copy of the
+    /// request. Its failures become 500s.
+    #[cfg(feature = "tower")]
+    pub fn bind_service<S>(&mut self, route: HTTPRoute, service: S)
+    where
+        S: tower_service::Service<crate::models::http::HTTPRequest, Response = crate::models::http::HTTPResponse> + Clone + Send + Sync + 'static,
+        S::Error: std::fmt::Display,
+        S::Future: Send,
+    {
+        self.bind(route, move |req, res, _pattern| {
+            let call = crate::tower::call_service(service.clone(), req.clone());
+            res.defer(async move {
+                call.await.unwrap_or_else(|e| {
+                    crate::models::http::HTTPResponse::error(crate::models::http::HTTPStatus::InternalServerError, &e)
+                })
+            });
+        });
+    }
+
+    /// this router and its layers as a tower `Service`
+    #[cfg(feature = "tower")]
+    pub fn into_service(self) -> crate::tower::RouterService {
+        crate::tower::RouterService::new(self)
+    }
+
+    /// serve the files below `root` for `GET {prefix}/...`, with range support
+    pub fn serve_dir(&mut self, prefix: &str, root: impl Into<std::path::PathBuf>) {
+        let files = crate::static_files::StaticFiles::new(root);
+        let pattern = format!("{}/{{*path}}", prefix.trim_end_matches('/'));
+        self.try_bind((crate::models::http::HTTPMethod::GET, pattern), move |req, res, params| {
+            files.serve_path(req, res, params.get("path").unwrap_or_default())
+        });
+    }
+
+    /// forward every request below `prefix` to the upstreams of `proxy`,
+    /// path and query unchanged
+    #[cfg(all(feature = "server", not(target_family = "wasm")))]
+    pub fn proxy(&mut self, prefix: &str, proxy: crate::proxy::ReverseProxy) {
+        for route in proxy_routes(prefix) {
+            let proxy = proxy.clone();
+            self.bind(route, move |req, res, pattern| proxy.handle(req, res, pattern));
         }
     }
 
+    /// Serve health probes: `GET {path}` for liveness, answering 200 as
+    /// long as the server runs, and `GET {path}/ready` for readiness,
+    /// running `checks` and answering 200 or 503 with a `HealthReport`.
+    #[cfg(feature = "server")]
+    pub fn health(&mut self, path: &str, checks: crate::health::Health) {
+        let path = path.trim_end_matches('/');
+        self.bind((crate::models::http::HTTPMethod::GET, path.to_string()), |_req, res, _pattern| {
+            res.set_header(crate::models::http::HTTPHeaderType::CacheControl, "no-store");
+            res.text("ok");
+        });
+        let checks = std::sync::Arc::new(checks);
+        self.bind((crate::models::http::HTTPMethod::GET, format!("{}/ready", path)), move |_req, res, _pattern| {
+            let checks = std::sync::Arc::clone(&checks);
+            res.defer(async move { checks.respond().await });
+        });
+    }
+
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
+    /// Serve requests for `host` (e.g. `api.example.com`, from the `Host`
+    /// header or else TLS SNI) from `router`, with its own routes, layers
+    /// and error handler. Requests for other hosts stay with this router.
+    /// Layers added to this router run first, for every host.
+    ///
+    /// A `{name}` label matches any one label, e.g. `{tenant}.example.com`,
+    /// and handlers read it with `HTTPRequest::host_param`. Exact host
+    /// names are tried before patterns, patterns in the order added.
+    pub fn host(&mut self, host: &str, router: Router) {
+        self.hosts.push((host.to_ascii_lowercase(), router));
+    }
+
+    /// the router added with `host` for `request`'s host, if any, and the
+    /// labels its pattern captured
+    pub fn host_router(&self, request: &crate::models::http::HTTPRequest) -> Option<(&Router, HostParams)> {
+        let hostname = request.hostname()?.trim_end_matches('.').to_ascii_lowercase();
+        if let Some((_, router)) = self.hosts.iter().find(|(host, _)| *host == hostname) {
+            return Some((router, HostParams::default()));
+        }
+        self.hosts
+            .iter()
+            .find_map(|(host, router)| Some((router, match_host(host, &hostname)?)))
+    }
+
+    /// wrap every route in `middleware`. Layers run in the order they are added.
+    pub fn layer<M>(&mut self, middleware: M)
+    where
+        M: crate::middleware::Middleware + 'static,
+    {
+        self.layers.push(std::sync::Arc::new(middleware));
+    }
+
+    pub fn layers(&self) -> &[std::sync::Arc<dyn crate::middleware::Middleware>] {
+        &self.layers
+    }
+
     pub fn bind<F>(&mut self, route: HTTPRoute, handler: F)
     where
-        F: Fn(&crate::models::http::HTTPRequest, &mut crate::models::http::HTTPResponse, &str) + 'static + Send + Sync,
+        F: Fn(&crate::models::http::HTTPRequest, &mut crate::models::http::HTTPResponse, &PathParams) + 'static + Send + Sync,
+    {
+        self.insert(
+            route,
+            Box::new(move |req, res, params| {
+                handler(req, res, params);
+                Ok(())
+            }),
+        );
+    }
+
+    fn insert(&mut self, route: HTTPRoute, handler: HTTPHandler) {
+        if self.routes.insert(route.clone(), handler).is_some() {
+            self.rebound.push(route);
+        }
+    }
+
+    /// Check for routes that shadow each other: a method and pattern bound
+    /// more than once, where only the last handler is kept, and patterns
+    /// that only differ in parameter names, like `/posts/{id}` and
+    /// `/posts/{slug}`, where only one can ever match. Parameters with
+    /// different constraints, like `{id:\d+}` and `{slug}`, don't
+    /// conflict. Patterns with an invalid constraint are reported too.
+    /// The error lists every conflict. `HTTPServer::start` refuses to
+    /// serve a router that fails this.
+    pub fn finalize(&self) -> Result<(), String> {
+        let mut conflicts: Vec<String> = self
+            .rebound
+            .iter()
+            .map(|(method, pattern)| format!("{} {} is bound more than once", method, pattern))
+            .collect();
+        let mut shapes: std::collections::HashMap<(&crate::models::http::HTTPMethod, String), Vec<&str>> =
+            std::collections::HashMap::new();
+        for (method, pattern) in self.routes.keys() {
+            let mut segments = Vec::new();
+            for segment in pattern.split('/') {
+                if let Err(e) = segment_regex(segment) {
+                    conflicts.push(format!("{} {} has {}", method, pattern, e));
+                }
+                let parts = segment_parts(segment).unwrap_or_else(|_| vec![Part::Literal(segment)]);
+                let shape: String = parts
+                    .iter()
+                    .map(|part| match part {
+                        Part::Literal(text) => text.to_string(),
+                        Part::Param(name, None) if name.starts_with('*') => String::from("{*}"),
+                        Part::Param(_, None) => String::from("{}"),
+                        Part::Param(_, Some(constraint)) => format!("{{:{}}}", constraint),
+                    })
+                    .collect();
+                segments.push(shape);
+            }
+            let shape = segments.join("/");
+            shapes.entry((method, shape)).or_default().push(pattern);
+        }
+        for ((method, _), mut patterns) in shapes {
+            if patterns.len() > 1 {
+                patterns.sort();
+                conflicts.push(format!("{} {} match the same paths", method, patterns.join(" and ")));
+            }
+        }
+        for (host, router) in &self.hosts {
+            if let Err(e) = router.finalize() {
+                conflicts.extend(e.lines().skip(1).map(|conflict| format!("{} on {}", conflict, host)));
+            }
+        }
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        conflicts.sort();
+        Err(format!("conflicting routes:\n{}", conflicts.join("\n")))
+    }
+
+    /// like `bind`, also naming the route for `url_for`
+    pub fn bind_named<F>(&mut self, name: &str, route: HTTPRoute, handler: F)
+    where
+        F: Fn(&crate::models::http::HTTPRequest, &mut crate::models::http::HTTPResponse, &PathParams) + 'static + Send + Sync,
+    {
+        self.names.insert(name.to_string(), route.clone());
+        self.bind(route, handler);
+    }
+
+    /// The path of the route named `name`, its parameters filled from
+    /// `params` and the other entries of `params` appended as the query,
+    /// e.g. `url_for("post_detail", &[("id", "7"), ("page", "2")])` gives
+    /// `/posts/7?page=2`. Values are percent-encoded.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, String> {
+        let (_, pattern) = self.names.get(name).ok_or_else(|| format!("no route named {}", name))?;
+        let mut used = Vec::new();
+        let mut segments = Vec::new();
+        for segment in pattern.split('/') {
+            let mut encoded = String::new();
+            for part in segment_parts(segment)? {
+                let param = match part {
+                    Part::Literal(text) => {
+                        encoded.push_str(text);
+                        continue;
+                    }
+                    Part::Param(param, _) => param,
+                };
+                let (param, rest) = match param.strip_prefix('*') {
+                    Some(param) => (param, true),
+                    None => (param, false),
+                };
+                let (_, value) = params
+                    .iter()
+                    .find(|(key, _)| *key == param)
+                    .ok_or_else(|| format!("route {} needs a value for {}", name, param))?;
+                used.push(param);
+                encoded.push_str(&crate::models::url::encode(value, if rest { b"/" } else { b"" }));
+            }
+            if let Some(regex) = segment_regex(segment)? {
+                if !regex.is_match(&encoded) {
+                    return Err(format!("route {} doesn't match {}", name, encoded));
+                }
+            }
+            segments.push(encoded);
+        }
+        let mut url = segments.join("/");
+        let query: Vec<String> = params
+            .iter()
+            .filter(|(key, _)| !used.contains(key))
+            .map(|(key, value)| format!("{}={}", crate::models::url::encode(key, b""), crate::models::url::encode(value, b"")))
+            .collect();
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        Ok(url)
+    }
+
+    /// like `bind`, for handlers that can fail. The error's status and message
+    /// become the response.
+    pub fn try_bind<F>(&mut self, route: HTTPRoute, handler: F)
+    where
+        F: Fn(&crate::models::http::HTTPRequest, &mut crate::models::http::HTTPResponse, &PathParams) -> Result<(), crate::models::error::HTTPError> + 'static + Send + Sync,
+    {
+        self.insert(route, Box::new(handler));
+    }
+
+    /// The router's table of routes that can be bound, replaced and
+    /// removed while the server is running. Where one of them and a route
+    /// bound here match equally, it wins, so it can replace the route.
+    /// Layers, `on_error` and the trailing slash policy apply to them as
+    /// to any route.
+    pub fn dynamic_routes(&mut self) -> DynamicRoutes {
+        self.dynamic.get_or_insert_with(DynamicRoutes::default).clone()
+    }
+
+    /// bind every route in `routes`, e.g. `router.mount(routes![index, show])`
+    pub fn mount(&mut self, routes: impl IntoIterator<Item = Route>) {
+        for route in routes {
+            self.insert((route.method, route.path), route.handler);
+        }
+    }
+
+    /// Accept WebSocket connections on GET `path`. Valid handshakes are
+    /// answered with 101 and `handler` then owns the connection, along with
+    /// the upgrade request; anything else gets 400 or 426.
+    #[cfg(feature = "ws")]
+    pub fn bind_ws<F, Fut>(&mut self, path: &str, handler: F)
+    where
+        F: Fn(crate::websocket::WebSocket, crate::models::http::HTTPRequest) -> Fut + 'static + Send + Sync,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
     {
-        self.routes.insert(route, Box::new(handler));
-    }
-    pub fn handle(&self, method: crate::models::http::HTTPMethod, request: &crate::models::http::HTTPRequest, response: &mut crate::models::http::HTTPResponse) -> Result<(), std::string::String> {
-        for ((route_method, route_path), handler) in &self.routes {
-            if *route_method == method {
-                if request.path_params(route_path).is_some() {
-                    handler(request, response, route_path);
-                    return Ok(());
+        let handler = std::sync::Arc::new(handler);
+        self.bind((crate::models::http::HTTPMethod::GET, path.to_string()), move |req, res, _pattern| {
+            if crate::websocket::handshake(req, res) {
+                let handler = std::sync::Arc::clone(&handler);
+                let req = req.clone();
+                let extensions = res
+                    .header(&crate::models::http::HTTPHeaderType::SecWebSocketExtensions)
+                    .map(str::to_string);
+                res.on_upgrade(move |io| {
+                    handler(crate::websocket::WebSocket::with_extensions(io, extensions.as_deref()), req)
+                });
+            }
+        });
+    }
+
+    /// Only accept request bodies of the given media types on `route`, e.g.
+    /// `&["application/json"]`. `type/*` matches any subtype. Other bodies are
+    /// rejected with 415 before the handler runs; requests without a body pass.
+    pub fn accept(&mut self, route: HTTPRoute, media_types: &[&str]) -> Result<(), String> {
+        let media_types = media_types
+            .iter()
+            .map(|m| m.parse::<crate::models::headers::MediaType>())
+            .collect::<Result<Vec<_>, _>>()?;
+        self.accepted_types.insert(route, media_types);
+        Ok(())
+    }
+
+    /// the allowlist declared with `accept`, if any
+    pub fn accepted_types(&self, route: &HTTPRoute) -> Option<&[crate::models::headers::MediaType]> {
+        self.accepted_types.get(route).map(|v| v.as_slice())
+    }
+
+    /// default buffering policy for responses on `route`. Handlers can still
+    /// override it per response with `HTTPResponse::set_buffering`.
+    pub fn buffering(&mut self, route: HTTPRoute, buffering: crate::models::http::Buffering) {
+        self.buffering.insert(route, buffering);
+    }
+
+    /// describe `route` in the `OpenApi` document
+    pub fn document(&mut self, route: HTTPRoute, operation: crate::openapi::Operation) {
+        self.operations.insert(route, operation);
+    }
+
+    /// the description added with `document`, if any
+    pub fn operation(&self, route: &HTTPRoute) -> Option<&crate::openapi::Operation> {
+        self.operations.get(route)
+    }
+
+    /// every bound route, sorted by pattern, then method
+    pub fn routes(&self) -> Vec<RouteInfo<'_>> {
+        let middleware: Vec<&'static str> = self.layers.iter().map(|layer| layer.name()).collect();
+        let mut routes: Vec<RouteInfo<'_>> = self
+            .routes
+            .keys()
+            .map(|route| RouteInfo {
+                method: &route.0,
+                pattern: &route.1,
+                name: self.names.iter().find(|(_, named)| *named == route).map(|(name, _)| name.as_str()),
+                accepts: self.accepted_types(route),
+                buffering: self.buffering.get(route).copied(),
+                middleware: middleware.clone(),
+            })
+            .collect();
+        routes.sort_by_key(|route| (route.pattern, route.method.to_string()));
+        routes
+    }
+
+    /// The routes as a tree of path segments with the methods bound at
+    /// each, e.g. to see why a request doesn't match:
+    ///
+    /// ```text
+    /// /
+    /// ├── posts  [GET, POST]
+    /// │   └── {id}  [GET]
+    /// └── static
+    ///     └── {*file}  [GET]
+    /// ```
+    pub fn tree(&self) -> String {
+        #[derive(Default)]
+        struct Node<'a> {
+            methods: Vec<String>,
+            children: std::collections::BTreeMap<&'a str, Node<'a>>,
+        }
+        fn render(node: &Node, prefix: &str, out: &mut String) {
+            let count = node.children.len();
+            for (i, (segment, child)) in node.children.iter().enumerate() {
+                let last = i + 1 == count;
+                out.push_str(&format!("{}{}{}", prefix, if last { "└── " } else { "├── " }, segment));
+                if !child.methods.is_empty() {
+                    out.push_str(&format!("  [{}]", child.methods.join(", ")));
+                }
+                out.push('\n');
+                render(child, &format!("{}{}", prefix, if last { "    " } else { "│   " }), out);
+            }
+        }
+
+        let mut root = Node::default();
+        for route in self.routes() {
+            let mut node = &mut root;
+            for segment in route.pattern.split('/').filter(|s| !s.is_empty()) {
+                node = node.children.entry(segment).or_default();
+            }
+            node.methods.push(route.method.to_string());
+        }
+        let mut out = String::from("/");
+        if !root.methods.is_empty() {
+            out.push_str(&format!("  [{}]", root.methods.join(", ")));
+        }
+        out.push('\n');
+        render(&root, "", &mut out);
+        if !self.layers.is_empty() {
+            let names: Vec<&str> = self.layers.iter().map(|layer| layer.name()).collect();
+            out.push_str(&format!("layers: {}\n", names.join(", ")));
+        }
+        for (host, router) in &self.hosts {
+            out.push_str(&format!("\nhost {}:\n{}", host, router.tree()));
+        }
+        out
+    }
+
+    /// print `tree` to stdout
+    pub fn print_tree(&self) {
+        print!("{}", self.tree());
+    }
+
+    fn check_content_type(&self, route: &HTTPRoute, request: &crate::models::http::HTTPRequest) -> Result<(), crate::models::error::HTTPError> {
+        let allowed = match self.accepted_types.get(route) {
+            Some(allowed) => allowed,
+            None => return Ok(()),
+        };
+        let has_body = request.body.as_ref().is_some_and(|b| !b.is_empty())
+            || request.content_length().is_some_and(|n| n > 0);
+        if !has_body {
+            return Ok(());
+        }
+        let matches = request.content_type().is_some_and(|ct| {
+            allowed
+                .iter()
+                .any(|a| a.kind == ct.kind && (a.subtype == "*" || a.subtype == ct.subtype))
+        });
+        if matches {
+            Ok(())
+        } else {
+            let allowed = allowed.iter().map(|a| a.essence()).collect::<Vec<_>>().join(", ");
+            Err(crate::models::error::HTTPError::client(
+                crate::models::http::HTTPStatus::UnsupportedMediaType,
+                &format!("Expected one of: {}", allowed),
+            ))
+        }
+    }
+
+    /// run the handler of the most specific route matching `request`:
+    /// literal segments beat constrained parameters beat parameters beat
+    /// wildcards, left to right
+    pub fn handle(&self, method: &crate::models::http::HTTPMethod, request: &crate::models::http::HTTPRequest, response: &mut crate::models::http::HTTPResponse) -> Result<(), crate::models::error::HTTPError> {
+        let path = request.path();
+        let strict = self.trailing_slash != TrailingSlash::Merge;
+        // the most specific match wins, whatever the binding order; each
+        // candidate is matched once and the winner keeps its parameters
+        let best = |strict: bool| {
+            let matches = |(route_method, route_path): &HTTPRoute| {
+                if route_method != method || (strict && !same_slash(route_path, path)) {
+                    return None;
+                }
+                match_route(route_path, path)
+            };
+            let bound = self
+                .routes
+                .iter()
+                .filter_map(|(route, handler)| Some((route, handler, matches(route)?)))
+                .max_by(|(a, ..), (b, ..)| route_order(&a.1, &b.1));
+            let dynamic = self.dynamic.as_ref().and_then(|dynamic| dynamic.best(matches));
+            (bound, dynamic)
+        };
+        let (bound, dynamic) = best(strict);
+        let found = match (&bound, &dynamic) {
+            (Some((route, handler, params)), Some((other, ..))) if route_order(&route.1, &other.1) == std::cmp::Ordering::Greater => {
+                Some((*route, *handler, params))
+            }
+            (_, Some((route, handler, params))) => Some((route, &**handler, params)),
+            (Some((route, handler, params)), None) => Some((*route, *handler, params)),
+            (None, None) => None,
+        };
+        let Some((route, handler, params)) = found else {
+            let elsewhere = || {
+                let (bound, dynamic) = best(false);
+                bound.is_some() || dynamic.is_some()
+            };
+            if self.trailing_slash == TrailingSlash::Redirect && elsewhere() {
+                let mut location = match path.strip_suffix('/') {
+                    Some(path) => path.to_string(),
+                    None => format!("{}/", path),
+                };
+                if let Some(query) = request.query() {
+                    location.push('?');
+                    location.push_str(query);
+                }
+                response.status = match method {
+                    crate::models::http::HTTPMethod::GET | crate::models::http::HTTPMethod::HEAD => crate::models::http::HTTPStatus::MovedPermanently,
+                    _ => crate::models::http::HTTPStatus::PermanentRedirect,
+                };
+                response.set_location(&location);
+                return Ok(());
+            }
+            if !self.allowed_methods(request).is_empty() {
+                crate::logging::log!(DEBUG, "route miss", method = method, path = path, status = 405);
+                return Err(crate::models::error::HTTPError::client(crate::models::http::HTTPStatus::MethodNotAllowed, ""));
+            }
+            crate::logging::log!(DEBUG, "route miss", method = method, path = path, status = 404);
+            return Err(crate::models::error::HTTPError::client(crate::models::http::HTTPStatus::NotFound, "Route not found"));
+        };
+        self.check_content_type(route, request)?;
+        if let Some(buffering) = self.buffering.get(route) {
+            response.buffering = Some(*buffering);
+        }
+        // a panicking handler fails its request, not the connection
+        let params = PathParams { pattern: &route.1, params };
+        let started = std::time::Instant::now();
+        let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler(request, response, &params)));
+        crate::logging::log!(
+            DEBUG,
+            "handler finished",
+            method = method,
+            route = route.1,
+            micros = started.elapsed().as_micros(),
+        );
+        outcome.unwrap_or_else(|panic| {
+            crate::logging::log!(
+                ERROR,
+                "handler panicked",
+                method = method,
+                path = path,
+                panic = crate::models::error::panic_message(&*panic),
+            );
+            Err(crate::models::error::HTTPError::internal(""))
+        })
+    }
+
+    /// run the matching handler for `request`, turning routing and handler errors into responses
+    pub fn route(&self, request: &crate::models::http::HTTPRequest) -> crate::models::http::HTTPResponse {
+        if let Some((router, params)) = self.host_router(request) {
+            if params.0.is_empty() {
+                return router.route(request);
+            }
+            let mut request = request.clone();
+            request.extensions.insert(params);
+            return router.route(&request);
+        }
+        let mut res = crate::models::http::HTTPResponse::default();
+        #[cfg(feature = "server")]
+        if let Some(hints) = request.extensions.get::<crate::models::early_hints::EarlyHints>() {
+            res.extensions.insert(hints.clone());
+        }
+        if let Err(e) = self.handle(&request.method, request, &mut res) {
+            if e.status.is_server_error() {
+                crate::logging::log!(WARN, "request failed", path = request.path(), status = e.status.code(), error = e);
+            } else {
+                crate::logging::log!(DEBUG, "request failed", path = request.path(), status = e.status.code(), error = e);
+            }
+            let mut res = self.render_error(request, &e);
+            if e.status == crate::models::http::HTTPStatus::MethodNotAllowed {
+                let allowed: Vec<String> = self.allowed_methods(request).iter().map(|method| method.to_string()).collect();
+                res.set_header(crate::models::http::HTTPHeaderType::Allow, allowed.join(", "));
+            }
+            return res;
+        }
+        res
+    }
+
+    /// methods with a route for `request`'s path, for `Allow`
+    pub fn allowed_methods(&self, request: &crate::models::http::HTTPRequest) -> Vec<crate::models::http::HTTPMethod> {
+        let mut methods: Vec<crate::models::http::HTTPMethod> = Vec::new();
+        for (method, path) in self.routes.keys() {
+            if !methods.contains(method) && request.path_params(path).is_some() {
+                methods.push(method.clone());
+            }
+        }
+        if let Some(dynamic) = &self.dynamic {
+            for (method, path) in dynamic.0.read().unwrap().keys() {
+                if !methods.contains(method) && request.path_params(path).is_some() {
+                    methods.push(method.clone());
+                }
+            }
+        }
+        methods.sort_by_key(|method| method.to_string());
+        methods
+    }
+
+    pub fn render_error(&self, request: &crate::models::http::HTTPRequest, error: &crate::models::error::HTTPError) -> crate::models::http::HTTPResponse {
+        if let Some(handler) = &self.error_handler {
+            return handler(request, error);
+        }
+        if self.problem_details {
+            return crate::models::problem::ProblemDetails::from_error(error, request).to_response();
+        }
+        if error.message.is_empty() {
+            crate::models::http::HTTPResponse::error(error.status.clone(), &error.status.default_body())
+        } else {
+            crate::models::http::HTTPResponse::error(error.status.clone(), &error.message)
+        }
+    }
+}
+
+/// Routes of a `Router` that can change while it serves, e.g. for plugins
+/// or endpoints managed through an admin API. Get them with
+/// `Router::dynamic_routes`; clones share the same table.
+#[derive(Clone, Default)]
+pub struct DynamicRoutes(std::sync::Arc<std::sync::RwLock<std::collections::HashMap<HTTPRoute, std::sync::Arc<HTTPHandler>>>>);
+
+/// the routes a proxy at `prefix` serves
+#[cfg(all(feature = "server", not(target_family = "wasm")))]
+fn proxy_routes(prefix: &str) -> Vec<HTTPRoute> {
+    use crate::models::http::HTTPMethod;
+    let pattern = format!("{}/{{*path}}", prefix.trim_end_matches('/'));
+    [
+        HTTPMethod::GET,
+        HTTPMethod::HEAD,
+        HTTPMethod::POST,
+        HTTPMethod::PUT,
+        HTTPMethod::PATCH,
+        HTTPMethod::DELETE,
+        HTTPMethod::OPTIONS,
+    ]
+    .into_iter()
+    .map(|method| (method, pattern.clone()))
+    .collect()
+}
+
+impl DynamicRoutes {
+    /// bind `handler`, replacing any handler for the same route
+    pub fn bind<F>(&self, route: HTTPRoute, handler: F)
+    where
+        F: Fn(&crate::models::http::HTTPRequest, &mut crate::models::http::HTTPResponse, &PathParams) + 'static + Send + Sync,
+    {
+        self.try_bind(route, move |req, res, params| {
+            handler(req, res, params);
+            Ok(())
+        });
+    }
+
+    /// like `bind`, for handlers that can fail
+    pub fn try_bind<F>(&self, route: HTTPRoute, handler: F)
+    where
+        F: Fn(&crate::models::http::HTTPRequest, &mut crate::models::http::HTTPResponse, &PathParams) -> Result<(), crate::models::error::HTTPError> + 'static + Send + Sync,
+    {
+        self.0.write().unwrap().insert(route, std::sync::Arc::new(Box::new(handler)));
+    }
+
+    /// Unbind `route`, returning whether it was bound. Requests already
+    /// running its handler finish.
+    pub fn remove(&self, route: &HTTPRoute) -> bool {
+        self.0.write().unwrap().remove(route).is_some()
+    }
+
+    pub fn contains(&self, route: &HTTPRoute) -> bool {
+        self.0.read().unwrap().contains_key(route)
+    }
+
+    /// the bound routes, sorted by pattern, then method
+    pub fn routes(&self) -> Vec<HTTPRoute> {
+        let mut routes: Vec<HTTPRoute> = self.0.read().unwrap().keys().cloned().collect();
+        routes.sort_by_key(|(method, pattern)| (pattern.clone(), method.to_string()));
+        routes
+    }
+
+    pub fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+
+    /// like `Router::proxy`, replacing the proxy already bound at `prefix`,
+    /// e.g. with one for the upstreams of a reloaded config
+    #[cfg(all(feature = "server", not(target_family = "wasm")))]
+    pub fn proxy(&self, prefix: &str, proxy: crate::proxy::ReverseProxy) {
+        for route in proxy_routes(prefix) {
+            let proxy = proxy.clone();
+            self.bind(route, move |req, res, params| proxy.handle(req, res, params));
+        }
+    }
+
+    /// the most specific route `matches`, with its handler taken out so it
+    /// runs without holding the lock, and the parameters it captured
+    #[allow(clippy::type_complexity)]
+    fn best(
+        &self,
+        matches: impl Fn(&HTTPRoute) -> Option<std::collections::HashMap<String, String>>,
+    ) -> Option<(HTTPRoute, std::sync::Arc<HTTPHandler>, std::collections::HashMap<String, String>)> {
+        let routes = self.0.read().unwrap();
+        let (route, handler, params) = routes
+            .iter()
+            .filter_map(|(route, handler)| Some((route, handler, matches(route)?)))
+            .max_by(|(a, ..), (b, ..)| route_order(&a.1, &b.1))?;
+        Some((route.clone(), std::sync::Arc::clone(handler), params))
+    }
+}
+
+/// orders patterns matching the same path, the one to pick greatest
+fn route_order(a: &str, b: &str) -> std::cmp::Ordering {
+    specificity(a).cmp(&specificity(b)).then_with(|| b.cmp(a))
+}
+
+/// whether `path` ends in a slash exactly when `pattern` does, or it
+/// doesn't matter: for the root and for wildcard patterns
+fn same_slash(pattern: &str, path: &str) -> bool {
+    let wildcard = pattern.rsplit('/').next().is_some_and(|last| last.starts_with("{*"));
+    path == "/" || wildcard || pattern.ends_with('/') == path.ends_with('/')
+}
+
+/// Match a host name against a pattern of labels like `{tenant}.example.com`
+fn match_host(pattern: &str, hostname: &str) -> Option<HostParams> {
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    let labels: Vec<&str> = hostname.split('.').collect();
+    if pattern.len() != labels.len() {
+        return None;
+    }
+    let mut params = std::collections::HashMap::new();
+    for (pattern, label) in pattern.iter().zip(labels) {
+        match pattern.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+            Some(name) if !label.is_empty() => {
+                params.insert(name.to_string(), label.to_string());
+            }
+            _ if *pattern == label => {}
+            _ => return None,
+        }
+    }
+    Some(HostParams(params))
+}
+
+/// How specific a pattern is, to order patterns matching the same path:
+/// segment by segment, literals beat segments with constraints or text
+/// around their parameters, which beat parameters, which beat wildcards, and
+/// among patterns alike up to where one ends, the longer one wins.
+fn specificity(pattern: &str) -> Vec<u8> {
+    pattern
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| match segment_parts(segment).as_deref() {
+            Ok([Part::Param(name, None)]) if name.starts_with('*') => 0,
+            Ok([Part::Param(_, None)]) => 1,
+            Ok(parts) if parts.iter().any(|part| matches!(part, Part::Param(..))) => 2,
+            _ => 3,
+        })
+        .collect()
+}
+
+/// A piece of a pattern segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Part<'a> {
+    Literal(&'a str),
+    /// name and constraint, if any
+    Param(&'a str, Option<&'a str>),
+}
+
+/// `{name:[a-z-]+}.{ext}` as its text and parameters. Braces nest inside a
+/// constraint, e.g. `{year:\d{4}}`.
+pub(crate) fn segment_parts(segment: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = segment;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Literal(&rest[..start]));
+        }
+        let mut depth = 0;
+        let mut escaped = false;
+        let mut end = None;
+        for (i, c) in rest[start..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(start + i);
+                        break;
+                    }
                 }
+                _ => {}
             }
         }
-        Err("Route not found".to_string())
+        let end = end.ok_or_else(|| format!("an unclosed {{ in {}", segment))?;
+        let param = &rest[start + 1..end];
+        parts.push(match param.split_once(':') {
+            Some((name, constraint)) => Part::Param(name, Some(constraint)),
+            None => Part::Param(param, None),
+        });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() || parts.is_empty() {
+        parts.push(Part::Literal(rest));
+    }
+    Ok(parts)
+}
+
+/// the name of a segment that is one unconstrained parameter, like `{id}`
+fn lone_param(segment: &str) -> Option<&str> {
+    let name = segment.strip_prefix('{')?.strip_suffix('}')?;
+    (!name.contains(['{', '}', ':'])).then_some(name)
+}
+
+/// The regex matching `segment`, capturing its parameters by name, unless
+/// it's plain text or a lone parameter. Compiled once per segment.
+fn segment_regex(segment: &str) -> Result<Option<regex::Regex>, String> {
+    type Cache = std::sync::RwLock<std::collections::HashMap<String, Result<Option<regex::Regex>, String>>>;
+    static CACHE: std::sync::OnceLock<Cache> = std::sync::OnceLock::new();
+    if !segment.contains('{') || lone_param(segment).is_some() || segment.starts_with("{*") {
+        return Ok(None);
+    }
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(compiled) = cache.read().unwrap().get(segment) {
+        return compiled.clone();
     }
+    let compiled = segment_parts(segment).and_then(|parts| {
+        let mut regex = String::from("^");
+        for part in parts {
+            match part {
+                Part::Literal(text) => regex.push_str(&regex::escape(text)),
+                Part::Param(name, constraint) => {
+                    regex.push_str(&format!("(?P<{}>{})", name, constraint.unwrap_or(".+")));
+                }
+            }
+        }
+        regex.push('$');
+        regex::Regex::new(&regex)
+            .map(Some)
+            .map_err(|e| format!("an invalid constraint in {}: {}", segment, e))
+    });
+    cache.write().unwrap().insert(segment.to_string(), compiled.clone());
+    compiled
 }
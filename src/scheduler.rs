@@ -0,0 +1,296 @@
+use crate::middleware::BoxFuture;
+use crate::tasks::TaskSet;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// When a `Job` runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// every period, the first time one period after start
+    Every(Duration),
+    Cron(CronSchedule),
+}
+
+/// A cron expression of five fields, minute, hour, day of month, month and
+/// day of week, e.g. `*/15 * * * *` or `0 3 * * 1-5`, evaluated in UTC.
+/// Fields take `*`, numbers, ranges, lists and steps; days of week run
+/// from 0 (Sunday) to 6, with 7 for Sunday too. As in cron, when both day
+/// fields are restricted a day matching either counts. `@hourly`, `@daily`,
+/// `@weekly`, `@monthly` and `@yearly` stand for the usual expressions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// the first whole minute after `time` that matches, if any in the
+    /// next five years
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let start = secs / 60 + 1;
+        let (first_day, first_minute) = (start / 1440, start % 1440);
+        for day in first_day..first_day + 5 * 366 {
+            if !self.matches_day(day) {
+                continue;
+            }
+            let from = if day == first_day { first_minute } else { 0 };
+            let minute = (from..1440).find(|minute| {
+                self.hours & (1 << (minute / 60)) != 0 && self.minutes & (1 << (minute % 60)) != 0
+            });
+            if let Some(minute) = minute {
+                return Some(UNIX_EPOCH + Duration::from_secs((day * 1440 + minute) * 60));
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, day: u64) -> bool {
+        let (_, month, date) = civil_from_days(day);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        // 1970-01-01 was a Thursday
+        let weekday = (day + 4) % 7;
+        let by_date = self.days & (1 << date) != 0;
+        let by_weekday = self.weekdays & (1 << weekday) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => by_date || by_weekday,
+            _ => by_date && by_weekday,
+        }
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let expr = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("Invalid cron expression: {}", s));
+        };
+        let invalid = |field: &str| format!("Invalid cron field '{}' in: {}", field, s);
+        let mut weekdays = parse_field(weekday, 0, 7).ok_or_else(|| invalid(weekday))?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(minute, 0, 59).ok_or_else(|| invalid(minute))?,
+            hours: parse_field(hour, 0, 23).ok_or_else(|| invalid(hour))? as u32,
+            days: parse_field(day, 1, 31).ok_or_else(|| invalid(day))? as u32,
+            months: parse_field(month, 1, 12).ok_or_else(|| invalid(month))? as u16,
+            weekdays: (weekdays & 0x7f) as u8,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+/// the values in `min..=max` a field allows, as bits
+fn parse_field(field: &str, min: u64, max: u64) -> Option<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((from, to)) => (from.parse().ok()?, to.parse().ok()?),
+                // `5/10` runs from 5 to the end
+                None if step > 1 => (range.parse().ok()?, max),
+                None => {
+                    let value = range.parse().ok()?;
+                    (value, value)
+                }
+            },
+        };
+        if from < min || to > max || from > to {
+            return None;
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Some(bits)
+}
+
+/// year, month and day of the `days`th day after 1970-01-01
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's algorithm, for dates after the epoch
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A named piece of work run on a `Schedule`. A run that is still going
+/// when the next is due makes that one skip, unless `allow_overlap`.
+#[derive(Clone)]
+pub struct Job {
+    name: String,
+    schedule: Schedule,
+    jitter: Duration,
+    overlap: bool,
+    run: Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>,
+}
+
+impl Job {
+    pub fn new<F, Fut>(name: &str, schedule: Schedule, run: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Job {
+            name: name.to_string(),
+            schedule,
+            jitter: Duration::ZERO,
+            overlap: false,
+            run: Arc::new(move || Box::pin(run())),
+        }
+    }
+
+    /// run every `period`
+    pub fn every<F, Fut>(name: &str, period: Duration, run: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self::new(name, Schedule::Every(period), run)
+    }
+
+    /// run at the times `expr` gives, see `CronSchedule`
+    pub fn cron<F, Fut>(name: &str, expr: &str, run: F) -> Result<Self, String>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Ok(Self::new(name, Schedule::Cron(expr.parse()?), run))
+    }
+
+    /// delay each run by a random amount below `jitter`, so instances
+    /// started together don't all run at once
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// start runs even while the previous one is still going
+    pub fn allow_overlap(mut self) -> Self {
+        self.overlap = true;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+        Duration::from_nanos(random % self.jitter.as_nanos().min(u64::MAX as u128) as u64)
+    }
+}
+
+/// Periodic and cron jobs, e.g. pruning sessions or flushing metrics. Add
+/// them to a server with `HTTPServer::schedule`, which runs them as
+/// background tasks so they stop at shutdown, or start them on a `TaskSet`.
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn job(mut self, job: Job) -> Self {
+        self.jobs.push(job);
+        self
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// run every job on `tasks` until its cancellation fires
+    pub fn start(&self, tasks: &TaskSet) {
+        for job in &self.jobs {
+            tasks.spawn(run_job(job.clone(), tasks.clone()));
+        }
+    }
+}
+
+async fn run_job(job: Job, tasks: TaskSet) {
+    let stop = tasks.cancellation();
+    let running = Arc::new(AtomicBool::new(false));
+    let mut next = tokio::time::Instant::now();
+    loop {
+        let wait = match &job.schedule {
+            Schedule::Every(period) => {
+                next += *period;
+                next.saturating_duration_since(tokio::time::Instant::now())
+            }
+            Schedule::Cron(cron) => {
+                let now = SystemTime::now();
+                let Some(at) = cron.next_after(now) else {
+                    crate::logging::log!(WARN, "schedule never fires", job = job.name);
+                    return;
+                };
+                at.duration_since(now).unwrap_or_default()
+            }
+        };
+        let wait = wait + job.jitter();
+        if stop.run_until_cancelled(tokio::time::sleep(wait)).await.is_none() {
+            return;
+        }
+        if !job.overlap && running.swap(true, Ordering::SeqCst) {
+            crate::logging::log!(WARN, "previous run still going, skipping", job = job.name);
+            continue;
+        }
+        let run = (job.run)();
+        let done = Done((!job.overlap).then(|| Arc::clone(&running)));
+        tasks.spawn(async move {
+            // cleared even if the run panics
+            let _done = done;
+            run.await;
+        });
+    }
+}
+
+/// marks a run finished when dropped
+struct Done(Option<Arc<AtomicBool>>);
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        if let Some(running) = &self.0 {
+            running.store(false, Ordering::SeqCst);
+        }
+    }
+}
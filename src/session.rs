@@ -0,0 +1,112 @@
+use crate::models::cookie::{Cookie, SameSite};
+use crate::models::http::{HTTPRequest, HTTPResponse};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Cookie-backed sessions. The session data travels in the cookie itself,
+/// signed with HMAC-SHA256 so clients can read but not forge it. Don't put
+/// secrets in it.
+#[derive(Clone)]
+pub struct Sessions {
+    key: Vec<u8>,
+    pub cookie_name: String,
+    pub path: String,
+    pub secure: bool,
+    /// seconds; `None` makes it a browser-session cookie
+    pub max_age: Option<i64>,
+}
+
+/// data of one session; save changes with `Sessions::save`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    data: BTreeMap<String, String>,
+}
+
+impl Session {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.data.get(key).map(|v| v.as_str())
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.data.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.data.remove(key)
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl Sessions {
+    /// `key` should be at least 32 random bytes and stable across restarts
+    pub fn new(key: &[u8]) -> Self {
+        Sessions {
+            key: key.to_vec(),
+            cookie_name: String::from("session"),
+            path: String::from("/"),
+            secure: true,
+            max_age: None,
+        }
+    }
+
+    /// the request's session; empty when the cookie is missing or tampered with
+    pub fn load(&self, req: &HTTPRequest) -> Session {
+        req.cookie(&self.cookie_name)
+            .and_then(|value| self.verify(&value))
+            .unwrap_or_default()
+    }
+
+    /// write `session` back to the client. An empty session removes the cookie.
+    pub fn save(&self, session: &Session, res: &mut HTTPResponse) {
+        if session.is_empty() {
+            res.set_cookie(&Cookie::removal(&self.cookie_name, &self.path));
+            return;
+        }
+        let mut cookie = Cookie::new(&self.cookie_name, &self.sign(session));
+        cookie.path = Some(self.path.clone());
+        cookie.max_age = self.max_age;
+        cookie.http_only = true;
+        cookie.secure = self.secure;
+        cookie.same_site = Some(SameSite::Lax);
+        res.set_cookie(&cookie);
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length")
+    }
+
+    fn sign(&self, session: &Session) -> String {
+        let payload = serde_json::to_vec(&session.data).unwrap();
+        let mut mac = self.mac();
+        mac.update(&payload);
+        let signature = mac.finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    fn verify(&self, value: &str) -> Option<Session> {
+        let (payload, signature) = value.split_once('.')?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let mut mac = self.mac();
+        mac.update(&payload);
+        mac.verify_slice(&signature).ok()?;
+        let data = serde_json::from_slice(&payload).ok()?;
+        Some(Session { data })
+    }
+}
@@ -0,0 +1,156 @@
+#[cfg(all(feature = "server", not(target_family = "wasm")))]
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(not(target_family = "wasm"))]
+use socket2::{SockRef, TcpKeepalive};
+#[cfg(not(target_family = "wasm"))]
+use std::io;
+#[cfg(not(target_family = "wasm"))]
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// TCP options for listeners and connections. `None` leaves a setting at
+/// the system's default; options a platform lacks are skipped, except
+/// `reuse_port`, which is an error where it isn't supported.
+///
+/// `SocketOptions { nodelay: Some(true), keepalive: Some(Keepalive::new(Duration::from_secs(60))), ..Default::default() }`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// `TCP_NODELAY`: send small writes right away instead of coalescing them
+    pub nodelay: Option<bool>,
+    /// `SO_KEEPALIVE`, probing connections that have gone quiet
+    pub keepalive: Option<Keepalive>,
+    /// `SO_REUSEADDR`, on by default for listeners outside Windows so a
+    /// restarted server can bind while old connections linger
+    pub reuse_address: Option<bool>,
+    /// `SO_REUSEPORT`, letting several sockets bind the same address
+    pub reuse_port: Option<bool>,
+    /// how many connections may wait to be accepted, 1024 by default
+    pub backlog: Option<u32>,
+    /// `SO_SNDBUF`, in bytes
+    pub send_buffer_size: Option<usize>,
+    /// `SO_RCVBUF`, in bytes
+    pub recv_buffer_size: Option<usize>,
+}
+
+/// When and how often a quiet connection is probed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// how long the connection is quiet before the first probe
+    pub time: Duration,
+    /// between unanswered probes
+    pub interval: Option<Duration>,
+    /// unanswered probes before the connection is dropped
+    pub retries: Option<u32>,
+}
+
+impl Keepalive {
+    pub fn new(time: Duration) -> Self {
+        Keepalive {
+            time,
+            interval: None,
+            retries: None,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+}
+
+/// two hours, what most systems wait before the first probe
+impl Default for Keepalive {
+    fn default() -> Self {
+        Keepalive::new(Duration::from_secs(7200))
+    }
+}
+
+/// what tokio and std listen with
+#[cfg(all(feature = "server", not(target_family = "wasm")))]
+const DEFAULT_BACKLOG: u32 = 1024;
+
+/// a listener on `addr` with `options`, ready for tokio
+#[cfg(all(feature = "server", not(target_family = "wasm")))]
+pub(crate) fn listen(addr: SocketAddr, options: &SocketOptions) -> io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(options.reuse_address.unwrap_or(cfg!(not(windows))))?;
+    before_connecting(SockRef::from(&socket), options)?;
+    socket.bind(&addr.into())?;
+    let backlog = options.backlog.unwrap_or(DEFAULT_BACKLOG).min(i32::MAX as u32);
+    socket.listen(backlog as i32)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// a connection to `addr` with `options`
+#[cfg(not(target_family = "wasm"))]
+pub(crate) async fn connect(addr: SocketAddr, options: SocketOptions) -> io::Result<tokio::net::TcpStream> {
+    let socket = match addr {
+        SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+    };
+    if let Some(reuse) = options.reuse_address {
+        socket.set_reuseaddr(reuse)?;
+    }
+    before_connecting(SockRef::from(&socket), &options)?;
+    let stream = socket.connect(addr).await?;
+    configure(&stream, &options)?;
+    Ok(stream)
+}
+
+/// the per-connection options, e.g. on a connection just accepted
+#[cfg(not(target_family = "wasm"))]
+pub(crate) fn configure(stream: &tokio::net::TcpStream, options: &SocketOptions) -> io::Result<()> {
+    let socket = SockRef::from(stream);
+    if let Some(nodelay) = options.nodelay {
+        socket.set_tcp_nodelay(nodelay)?;
+    }
+    if let Some(keepalive) = options.keepalive {
+        #[allow(unused_mut)]
+        let mut params = TcpKeepalive::new().with_time(keepalive.time);
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "windows"
+        ))]
+        {
+            if let Some(interval) = keepalive.interval {
+                params = params.with_interval(interval);
+            }
+            if let Some(retries) = keepalive.retries {
+                params = params.with_retries(retries);
+            }
+        }
+        socket.set_tcp_keepalive(&params)?;
+    }
+    Ok(())
+}
+
+/// options that must be set before binding or connecting
+#[cfg(not(target_family = "wasm"))]
+fn before_connecting(socket: SockRef<'_>, options: &SocketOptions) -> io::Result<()> {
+    if let Some(reuse) = options.reuse_port {
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))))]
+        socket.set_reuse_port(reuse)?;
+        #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin")))))]
+        if reuse {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "SO_REUSEPORT isn't supported here"));
+        }
+    }
+    if let Some(size) = options.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = options.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    Ok(())
+}
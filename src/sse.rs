@@ -0,0 +1,248 @@
+use crate::mime::Mime;
+use crate::models::body::ChannelStream;
+use crate::models::http::{HTTPHeaderType, HTTPRequest, HTTPResponse, HTTPStatus};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Sleep};
+
+/// how often an idle stream sends a comment by default, so proxies don't
+/// time the connection out
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// One server-sent event. `data` may span lines; each becomes a `data:` field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    pub id: Option<String>,
+    pub event: Option<String>,
+    pub data: String,
+    /// reconnection delay the client should use from now on
+    pub retry: Option<Duration>,
+    /// sent as a `:` line, ignored by clients
+    pub comment: Option<String>,
+}
+
+impl Event {
+    pub fn new(data: impl Into<String>) -> Self {
+        Event {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    /// an event carrying only a comment
+    pub fn comment(text: impl Into<String>) -> Self {
+        Event {
+            comment: Some(text.into()),
+            ..Default::default()
+        }
+    }
+
+    /// the event type; listeners register for it with `addEventListener`
+    pub fn with_event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+/// the event-stream framing, ending in the blank line that dispatches it
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // line breaks would end a field early, so they are dropped from
+        // single-line fields
+        let single_line = |s: &str| s.replace(['\r', '\n', '\0'], "");
+        if let Some(comment) = &self.comment {
+            for line in comment.lines() {
+                writeln!(f, ": {}", line)?;
+            }
+        }
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {}", single_line(event))?;
+        }
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {}", single_line(id))?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        if !self.data.is_empty() || (self.comment.is_none() && self.retry.is_none()) {
+            for line in self.data.split('\n') {
+                writeln!(f, "data: {}", line.strip_suffix('\r').unwrap_or(line))?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+type Events = Pin<Box<dyn Stream<Item = Event> + Send>>;
+
+/// A `text/event-stream` response driven by a stream of events, e.g.
+/// `Sse::from_channel(rx).respond(res)`. Idle streams get a keep-alive
+/// comment every `DEFAULT_KEEP_ALIVE`; the response ends with the stream.
+pub struct Sse {
+    events: Events,
+    replay: Vec<Event>,
+    keep_alive: Option<Duration>,
+    retry: Option<Duration>,
+}
+
+impl Sse {
+    pub fn new<S>(events: S) -> Self
+    where
+        S: Stream<Item = Event> + Send + 'static,
+    {
+        Sse {
+            events: Box::pin(events),
+            replay: Vec::new(),
+            keep_alive: Some(DEFAULT_KEEP_ALIVE),
+            retry: None,
+        }
+    }
+
+    /// events sent into `events` by other tasks; the stream ends when every
+    /// sender is dropped
+    pub fn from_channel(events: mpsc::Receiver<Event>) -> Self {
+        Self::new(ChannelStream(events))
+    }
+
+    /// `None` disables keep-alive comments
+    pub fn with_keep_alive(mut self, interval: Option<Duration>) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+
+    /// ask clients to wait `retry` before reconnecting
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// events to send before the stream, e.g. `History::since` for a
+    /// reconnecting client
+    pub fn with_replay(mut self, events: Vec<Event>) -> Self {
+        self.replay = events;
+        self
+    }
+
+    /// turn `res` into the event stream
+    pub fn respond(self, res: &mut HTTPResponse) {
+        let mut head = String::new();
+        if let Some(retry) = self.retry {
+            head.push_str(&Event::default().with_retry(retry).to_string());
+        }
+        for event in &self.replay {
+            head.push_str(&event.to_string());
+        }
+        res.status = HTTPStatus::Ok;
+        res.set_content_type(Mime::TEXT_EVENT_STREAM);
+        res.set_header(HTTPHeaderType::CacheControl, "no-cache");
+        res.body = None;
+        res.set_raw_body(head.into_bytes());
+        res.set_body_stream(Framed {
+            events: self.events,
+            keep_alive: self
+                .keep_alive
+                .map(|interval| (interval, Box::pin(tokio::time::sleep(interval)))),
+        });
+    }
+}
+
+/// events as wire bytes, with keep-alive comments while idle
+struct Framed {
+    events: Events,
+    keep_alive: Option<(Duration, Pin<Box<Sleep>>)>,
+}
+
+impl Stream for Framed {
+    type Item = Vec<u8>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        if let Poll::Ready(event) = self.events.as_mut().poll_next(cx) {
+            if let Some((interval, timer)) = self.keep_alive.as_mut() {
+                timer.as_mut().reset(Instant::now() + *interval);
+            }
+            return Poll::Ready(event.map(|event| event.to_string().into_bytes()));
+        }
+        if let Some((interval, timer)) = self.keep_alive.as_mut() {
+            if timer.as_mut().poll(cx).is_ready() {
+                timer.as_mut().reset(Instant::now() + *interval);
+                return Poll::Ready(Some(b":\n\n".to_vec()));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Recent events, kept so clients reconnecting with `Last-Event-ID` can
+/// catch up. Recorded events get ascending numeric ids.
+pub struct History {
+    state: Mutex<HistoryState>,
+    capacity: usize,
+}
+
+struct HistoryState {
+    events: VecDeque<Event>,
+    next_id: u64,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            state: Mutex::new(HistoryState {
+                events: VecDeque::with_capacity(capacity),
+                next_id: 1,
+            }),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// assign `event` the next id and keep it, evicting the oldest event when full
+    pub fn record(&self, event: Event) -> Event {
+        let mut state = self.state.lock().unwrap();
+        let event = event.with_id(state.next_id.to_string());
+        state.next_id += 1;
+        if state.events.len() == self.capacity {
+            state.events.pop_front();
+        }
+        state.events.push_back(event.clone());
+        event
+    }
+
+    /// Events after `last_id`. Nothing for new clients (`None`); everything
+    /// kept when the id is unknown or was already evicted.
+    pub fn since(&self, last_id: Option<&str>) -> Vec<Event> {
+        let Some(last_id) = last_id else {
+            return Vec::new();
+        };
+        let state = self.state.lock().unwrap();
+        let start = state
+            .events
+            .iter()
+            .position(|event| event.id.as_deref() == Some(last_id.trim()))
+            .map_or(0, |i| i + 1);
+        state.events.iter().skip(start).cloned().collect()
+    }
+}
+
+impl HTTPRequest {
+    /// id of the last event a reconnecting `EventSource` received
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.header(&HTTPHeaderType::LastEventId)
+    }
+}
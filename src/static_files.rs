@@ -0,0 +1,376 @@
+use crate::conditional::{self, Precondition};
+use crate::mime::Mime;
+use crate::models::error::HTTPError;
+use crate::models::headers::EntityTag;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+#[cfg(feature = "compression")]
+use std::sync::Mutex;
+#[cfg(feature = "compression")]
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A file on disk as a response. Sets `ETag` and `Last-Modified` and answers
+/// conditional requests with 304/412, honors single `Range` requests with
+/// 206, answers unsatisfiable ones with 416 and otherwise sends the whole file.
+/// Files and ranges longer than 64 KiB are streamed as they are read rather
+/// than held in memory.
+#[derive(Debug, Clone)]
+pub struct FileResponse {
+    path: PathBuf,
+    length: u64,
+    modified: Option<SystemTime>,
+    content_type: String,
+    /// `Content-Encoding` of the bytes served, e.g. a precompressed `.gz`
+    encoding: Option<String>,
+    /// served instead of reading `path`, e.g. compressed on the fly
+    contents: Option<Arc<Vec<u8>>>,
+}
+
+impl FileResponse {
+    /// fails if `path` doesn't exist or is a directory
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let metadata = std::fs::metadata(&path)?;
+        if !metadata.is_file() {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not a file"));
+        }
+        Ok(FileResponse {
+            content_type: Mime::from_path(&path).to_string(),
+            length: metadata.len(),
+            modified: metadata.modified().ok(),
+            path,
+            encoding: None,
+            contents: None,
+        })
+    }
+
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = content_type.to_string();
+        self
+    }
+
+    /// the file holds the representation in `coding`, e.g. `gzip` for a
+    /// precompressed `app.js.gz` served for `app.js`
+    pub fn with_encoding(mut self, coding: &str) -> Self {
+        self.encoding = Some(coding.to_string());
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Fill `res` with the file, or the part of it `req` asked for. Only a
+    /// single range is served; requests for several get the whole file,
+    /// which RFC 9110 allows.
+    pub fn respond(&self, req: &HTTPRequest, res: &mut HTTPResponse) -> Result<(), HTTPError> {
+        res.set_header(HTTPHeaderType::ContentType, self.content_type.as_str());
+        res.set_header(HTTPHeaderType::AcceptRanges, "bytes");
+        if let Some(encoding) = &self.encoding {
+            res.set_header(HTTPHeaderType::ContentEncoding, encoding.as_str());
+        }
+        let etag = self.etag();
+        res.set_etag(&etag);
+        if let Some(modified) = self.modified {
+            res.set_last_modified(modified);
+        }
+        match conditional::evaluate(req, Some(&etag), self.modified) {
+            Precondition::Proceed => {}
+            _ => {
+                res.status = HTTPStatus::Ok;
+                conditional::apply(req, res);
+                return Ok(());
+            }
+        }
+
+        let ranges = match req.method {
+            HTTPMethod::GET if self.if_range_matches(req, &etag) => req.range().unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        if let [range] = ranges.as_slice() {
+            let (first, last) = match range.resolve(self.length) {
+                Some(bounds) => bounds,
+                None => {
+                    res.status = HTTPStatus::RangeNotSatisfiable;
+                    res.body = None;
+                    res.set_header(HTTPHeaderType::ContentRange, format!("bytes */{}", self.length));
+                    return Ok(());
+                }
+            };
+            self.send(res, first, last - first + 1).map_err(io_error)?;
+            res.status = HTTPStatus::PartialContent;
+            res.set_header(
+                HTTPHeaderType::ContentRange,
+                format!("bytes {}-{}/{}", first, last, self.length),
+            );
+            return Ok(());
+        }
+
+        res.status = HTTPStatus::Ok;
+        self.send(res, 0, self.length).map_err(io_error)
+    }
+
+    /// weak validator from size and modification time, like most file servers
+    pub fn etag(&self) -> EntityTag {
+        let modified = self
+            .modified
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |m| m.as_secs());
+        match &self.encoding {
+            Some(encoding) => EntityTag::weak(&format!("{:x}-{:x}-{}", self.length, modified, encoding)),
+            None => EntityTag::weak(&format!("{:x}-{:x}", self.length, modified)),
+        }
+    }
+
+    /// `If-Range` (RFC 9110 13.1.5): only honor `Range` while the client's
+    /// copy is still current. Our tags are weak, so only dates can match.
+    fn if_range_matches(&self, req: &HTTPRequest, etag: &EntityTag) -> bool {
+        let if_range = match req.header(&HTTPHeaderType::IfRange) {
+            Some(if_range) => if_range.trim(),
+            None => return true,
+        };
+        if let Ok(tag) = if_range.parse::<EntityTag>() {
+            return tag.strong_eq(etag);
+        }
+        match (httpdate::parse_http_date(if_range), self.modified) {
+            (Ok(date), Some(modified)) => httpdate::fmt_http_date(modified) == httpdate::fmt_http_date(date),
+            _ => false,
+        }
+    }
+
+    /// make `length` bytes from `offset` the body of `res`, streaming them
+    /// from disk when they're more than a chunk
+    fn send(&self, res: &mut HTTPResponse, offset: u64, length: u64) -> std::io::Result<()> {
+        #[cfg(any(feature = "server", feature = "client"))]
+        if self.contents.is_none() && length > FILE_CHUNK {
+            let mut file = std::fs::File::open(&self.path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            res.body = None;
+            res.raw_body = None;
+            res.set_content_length(length);
+            res.set_body_stream(FileChunks(file.take(length)));
+            return Ok(());
+        }
+        res.set_raw_body(self.read(offset, length)?);
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, length: u64) -> std::io::Result<Vec<u8>> {
+        if let Some(contents) = &self.contents {
+            let start = (offset as usize).min(contents.len());
+            let end = (start + length as usize).min(contents.len());
+            return Ok(contents[start..end].to_vec());
+        }
+        let mut file = std::fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = Vec::with_capacity(length as usize);
+        file.take(length).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// how much of a file is read at a time when it is streamed
+#[cfg(any(feature = "server", feature = "client"))]
+const FILE_CHUNK: u64 = 64 * 1024;
+
+/// the rest of a file, read a chunk at a time as the body is sent
+#[cfg(any(feature = "server", feature = "client"))]
+struct FileChunks(std::io::Take<std::fs::File>);
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl futures_core::Stream for FileChunks {
+    type Item = Vec<u8>;
+
+    // local reads block about as briefly as the `metadata` call that opened
+    // the file; a failed one ends the body short of its `Content-Length`
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Vec<u8>>> {
+        let file = &mut self.get_mut().0;
+        let mut chunk = Vec::with_capacity(FILE_CHUNK.min(file.limit()) as usize);
+        std::task::Poll::Ready(match file.by_ref().take(FILE_CHUNK).read_to_end(&mut chunk) {
+            Ok(0) => None,
+            Ok(_) => Some(chunk),
+            Err(e) => {
+                crate::logging::log!(WARN, "file read failed", error = e);
+                None
+            }
+        })
+    }
+}
+
+/// compressed contents and the validator of the file they were made from
+#[cfg(feature = "compression")]
+type CachedGzip = (EntityTag, Arc<Vec<u8>>);
+
+/// largest file `StaticFiles::compress` gzips; bigger ones are streamed as
+/// they are
+#[cfg(feature = "compression")]
+const MAX_COMPRESSED_FILE: u64 = 1024 * 1024;
+
+/// precompressed siblings looked for, most preferred first
+const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// Serves the files below a directory, see `Router::serve_dir`
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    /// file served for directory paths
+    pub index: Option<String>,
+    /// serve `file.br` / `file.gz` next to `file` when the client accepts
+    /// that encoding
+    pub precompressed: bool,
+    /// gzip compressible files up to 1 MiB without a precompressed sibling
+    /// on first request and keep the result in memory
+    #[cfg(feature = "compression")]
+    pub compress: bool,
+    #[cfg(feature = "compression")]
+    cache: Arc<Mutex<HashMap<PathBuf, CachedGzip>>>,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        StaticFiles {
+            root: root.into(),
+            index: Some(String::from("index.html")),
+            precompressed: true,
+            #[cfg(feature = "compression")]
+            compress: false,
+            #[cfg(feature = "compression")]
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The file for a (percent-encoded) path relative to the root. `None` for
+    /// paths that would escape the root, e.g. through `..`.
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let path = percent_decode(path)?;
+        let mut file = self.root.clone();
+        for component in Path::new(path.trim_start_matches('/')).components() {
+            match component {
+                Component::Normal(part) => file.push(part),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        if file.is_dir() {
+            file.push(self.index.as_ref()?);
+        }
+        Some(file)
+    }
+
+    /// serve the file named by the `param` path parameter of `pattern`
+    pub fn serve(&self, req: &HTTPRequest, res: &mut HTTPResponse, pattern: &str, param: &str) -> Result<(), HTTPError> {
+        let path = req
+            .path_params(pattern)
+            .and_then(|mut params| params.remove(param))
+            .ok_or_else(|| HTTPError::client(HTTPStatus::NotFound, ""))?;
+        self.serve_path(req, res, &path)
+    }
+
+    /// serve the file at `path` below the root, e.g. a parameter the
+    /// router has already captured
+    pub fn serve_path(&self, req: &HTTPRequest, res: &mut HTTPResponse, path: &str) -> Result<(), HTTPError> {
+        let not_found = || HTTPError::client(HTTPStatus::NotFound, "");
+        let file = self.resolve(path).ok_or_else(not_found)?;
+        let file = FileResponse::open(file).map_err(|_| not_found())?;
+        let file = self.encoded(file, req).map_err(io_error)?;
+        #[cfg(feature = "compression")]
+        let negotiated = self.precompressed || self.compress;
+        #[cfg(not(feature = "compression"))]
+        let negotiated = self.precompressed;
+        if negotiated {
+            res.set_header(HTTPHeaderType::Vary, "Accept-Encoding");
+        }
+        file.respond(req, res)
+    }
+
+    /// the best representation of `file` the client accepts
+    fn encoded(&self, file: FileResponse, req: &HTTPRequest) -> std::io::Result<FileResponse> {
+        if self.precompressed {
+            for (coding, extension) in PRECOMPRESSED {
+                if !req.accepts_encoding(coding) {
+                    continue;
+                }
+                let mut sibling = file.path.clone().into_os_string();
+                sibling.push(".");
+                sibling.push(extension);
+                if let Ok(sibling) = FileResponse::open(sibling) {
+                    return Ok(sibling
+                        .with_content_type(&file.content_type)
+                        .with_encoding(coding));
+                }
+            }
+        }
+        #[cfg(feature = "compression")]
+        if self.compress
+            && file.length <= MAX_COMPRESSED_FILE
+            && req.accepts_encoding("gzip")
+            && crate::mime::is_compressible(&file.content_type)
+        {
+            let contents = self.gzipped(&file)?;
+            return Ok(FileResponse {
+                length: contents.len() as u64,
+                contents: Some(contents),
+                encoding: Some(String::from("gzip")),
+                ..file
+            });
+        }
+        Ok(file)
+    }
+
+    /// gzip of `file`, from the cache while the file is unchanged
+    #[cfg(feature = "compression")]
+    fn gzipped(&self, file: &FileResponse) -> std::io::Result<Arc<Vec<u8>>> {
+        let etag = file.etag();
+        if let Some((cached_etag, contents)) = self.cache.lock().unwrap().get(&file.path) {
+            if *cached_etag == etag {
+                return Ok(Arc::clone(contents));
+            }
+        }
+        let contents = Arc::new(crate::compression::Coding::Gzip.encode(&std::fs::read(&file.path)?, 6)?);
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(file.path.clone(), (etag, Arc::clone(&contents)));
+        Ok(contents)
+    }
+}
+
+fn io_error(e: std::io::Error) -> HTTPError {
+    HTTPError::internal(&e.to_string())
+}
+
+/// decode `%XX` escapes; `None` for malformed escapes or non-UTF-8 results
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
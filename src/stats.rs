@@ -0,0 +1,303 @@
+#[cfg(feature = "server")]
+use crate::models::http::HTTPHeaderType;
+use std::collections::HashMap;
+use std::future::Future;
+#[cfg(feature = "server")]
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "server")]
+use std::task::Context;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+#[cfg(feature = "server")]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::Notify;
+
+/// Kinds of connections that outlive a single request/response exchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LongLivedKind {
+    WebSocket,
+    EventStream,
+    Streaming,
+}
+
+impl LongLivedKind {
+    /// what a response with `headers` turns its connection into: a websocket
+    /// after a 101 to `Upgrade: websocket`, an event stream for
+    /// `text/event-stream`
+    #[cfg(feature = "server")]
+    pub(crate) fn of(headers: &HashMap<HTTPHeaderType, String>) -> Self {
+        let header = |header| headers.get(&header).map(|value| value.to_ascii_lowercase());
+        if header(HTTPHeaderType::Upgrade).is_some_and(|protocol| protocol == "websocket") {
+            LongLivedKind::WebSocket
+        } else if header(HTTPHeaderType::ContentType).is_some_and(|kind| kind.starts_with("text/event-stream")) {
+            LongLivedKind::EventStream
+        } else {
+            LongLivedKind::Streaming
+        }
+    }
+}
+
+/// Live counters for a server. Long-lived connections (websockets, SSE,
+/// streaming bodies) are tracked individually so they can be drained
+/// during deploys without touching regular traffic. `HTTPServer` registers
+/// its own upgrades and streamed responses in `HTTPServer::stats`.
+#[derive(Debug)]
+pub struct ServerStats {
+    started: Instant,
+    active_connections: AtomicU64,
+    total_connections: AtomicU64,
+    total_requests: AtomicU64,
+    next_id: AtomicU64,
+    long_lived: Mutex<HashMap<u64, Arc<LongLivedState>>>,
+}
+
+#[derive(Debug)]
+struct LongLivedState {
+    kind: LongLivedKind,
+    opened: Instant,
+    /// millis since `ServerStats::started`
+    last_activity: AtomicU64,
+    closing: AtomicBool,
+    notify: Notify,
+}
+
+impl Default for ServerStats {
+    fn default() -> Self {
+        ServerStats {
+            started: Instant::now(),
+            active_connections: AtomicU64::new(0),
+            total_connections: AtomicU64::new(0),
+            total_requests: AtomicU64::new(0),
+            next_id: AtomicU64::new(0),
+            long_lived: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ServerStats {
+    /// count an accepted connection until the returned guard is dropped
+    pub fn connection(self: &Arc<Self>) -> ConnectionGuard {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            stats: Arc::clone(self),
+        }
+    }
+
+    pub fn record_request(&self) {
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// track a long-lived connection until the returned handle is dropped
+    pub fn register(self: &Arc<Self>, kind: LongLivedKind) -> LongLivedHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let state = Arc::new(LongLivedState {
+            kind,
+            opened: Instant::now(),
+            last_activity: AtomicU64::new(self.elapsed_millis()),
+            closing: AtomicBool::new(false),
+            notify: Notify::new(),
+        });
+        self.long_lived
+            .lock()
+            .unwrap()
+            .insert(id, Arc::clone(&state));
+        LongLivedHandle {
+            id,
+            stats: Arc::clone(self),
+            state,
+        }
+    }
+
+    /// Ask matching long-lived connections to close. Returns how many were
+    /// signalled; their owners see it through `LongLivedHandle::closed`.
+    pub fn drain(&self, policy: &DrainPolicy) -> usize {
+        let now = self.elapsed_millis();
+        let entries = self.long_lived.lock().unwrap();
+        let mut drained = 0;
+        for state in entries.values() {
+            if policy.matches(state, now) && !state.closing.swap(true, Ordering::SeqCst) {
+                state.notify.notify_waiters();
+                drained += 1;
+            }
+        }
+        drained
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let mut long_lived = HashMap::new();
+        for state in self.long_lived.lock().unwrap().values() {
+            *long_lived.entry(state.kind).or_insert(0) += 1;
+        }
+        StatsSnapshot {
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            long_lived,
+        }
+    }
+
+    fn elapsed_millis(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+/// point-in-time view of `ServerStats`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    pub active_connections: u64,
+    pub total_connections: u64,
+    pub total_requests: u64,
+    /// open long-lived connections per kind
+    pub long_lived: HashMap<LongLivedKind, u64>,
+}
+
+/// Which long-lived connections `ServerStats::drain` closes. Criteria combine
+/// with OR; `kind` restricts the selection. The default drains everything.
+#[derive(Debug, Clone, Default)]
+pub struct DrainPolicy {
+    pub kind: Option<LongLivedKind>,
+    /// close connections without activity for at least this long
+    pub idle_for: Option<Duration>,
+    /// close connections open for at least this long
+    pub max_lifetime: Option<Duration>,
+}
+
+impl DrainPolicy {
+    fn matches(&self, state: &LongLivedState, now_millis: u64) -> bool {
+        if self.kind.is_some_and(|kind| kind != state.kind) {
+            return false;
+        }
+        if self.idle_for.is_none() && self.max_lifetime.is_none() {
+            return true;
+        }
+        let idle = Duration::from_millis(now_millis.saturating_sub(state.last_activity.load(Ordering::Relaxed)));
+        self.idle_for.is_some_and(|limit| idle >= limit)
+            || self.max_lifetime.is_some_and(|limit| state.opened.elapsed() >= limit)
+    }
+}
+
+pub struct ConnectionGuard {
+    stats: Arc<ServerStats>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.stats.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Registration of one long-lived connection. Owners call `touch` on traffic
+/// and stop when `closed` resolves.
+pub struct LongLivedHandle {
+    id: u64,
+    stats: Arc<ServerStats>,
+    state: Arc<LongLivedState>,
+}
+
+impl LongLivedHandle {
+    pub fn kind(&self) -> LongLivedKind {
+        self.state.kind
+    }
+
+    /// mark activity, resetting the idle timer
+    pub fn touch(&self) {
+        self.state
+            .last_activity
+            .store(self.stats.elapsed_millis(), Ordering::Relaxed);
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.state.closing.load(Ordering::SeqCst)
+    }
+
+    /// resolves once the connection has been drained
+    pub async fn closed(&self) {
+        loop {
+            let notified = self.state.notify.notified();
+            if self.is_closing() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// run `work` until it's done, or `None` if the connection is drained first
+    pub async fn until_closed<F: Future>(&self, work: F) -> Option<F::Output> {
+        let mut work = std::pin::pin!(work);
+        let mut closed = std::pin::pin!(self.closed());
+        std::future::poll_fn(|cx| match work.as_mut().poll(cx) {
+            Poll::Ready(output) => Poll::Ready(Some(output)),
+            Poll::Pending => closed.as_mut().poll(cx).map(|()| None),
+        })
+        .await
+    }
+}
+
+impl Drop for LongLivedHandle {
+    fn drop(&mut self) {
+        self.stats.long_lived.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Run `upgrade` on `io`, registered as a `kind` connection until the
+/// handler returns or the connection is drained. Traffic either way counts
+/// as activity.
+#[cfg(feature = "server")]
+pub(crate) async fn run_upgrade(
+    stats: &Arc<ServerStats>,
+    kind: LongLivedKind,
+    upgrade: &crate::models::upgrade::OnUpgrade,
+    io: Box<dyn crate::models::upgrade::Upgraded>,
+) {
+    let handle = Arc::new(stats.register(kind));
+    let io = Box::new(Tracked::new(io, Arc::clone(&handle)));
+    handle.until_closed(upgrade.run(io)).await;
+}
+
+/// IO that touches `handle` whenever bytes go either way
+#[cfg(feature = "server")]
+struct Tracked<S> {
+    inner: S,
+    handle: Arc<LongLivedHandle>,
+}
+
+#[cfg(feature = "server")]
+impl<S> Tracked<S> {
+    fn new(inner: S, handle: Arc<LongLivedHandle>) -> Self {
+        Tracked { inner, handle }
+    }
+}
+
+#[cfg(feature = "server")]
+impl<S: AsyncRead + Unpin> AsyncRead for Tracked<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let read = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            self.handle.touch();
+        }
+        read
+    }
+}
+
+#[cfg(feature = "server")]
+impl<S: AsyncWrite + Unpin> AsyncWrite for Tracked<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let written = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if matches!(written, Poll::Ready(Ok(n)) if n > 0) {
+            self.handle.touch();
+        }
+        written
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+/// In-memory records keyed by an auto-incremented id. Enough state for
+/// examples, prototypes and tests; share it between handlers in an `Arc`.
+#[derive(Debug)]
+pub struct Store<T> {
+    inner: RwLock<Records<T>>,
+}
+
+#[derive(Debug)]
+struct Records<T> {
+    next_id: u64,
+    items: BTreeMap<u64, T>,
+}
+
+impl<T> Default for Records<T> {
+    fn default() -> Self {
+        Records {
+            next_id: 1,
+            items: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> Default for Store<T> {
+    fn default() -> Self {
+        Store {
+            inner: RwLock::new(Records::default()),
+        }
+    }
+}
+
+impl<T: Clone> Store<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a store holding `items` with ids 1, 2, ...
+    pub fn seeded(items: impl IntoIterator<Item = T>) -> Self {
+        let store = Store::new();
+        for item in items {
+            store.insert(item);
+        }
+        store
+    }
+
+    /// add `item`, returning its id
+    pub fn insert(&self, item: T) -> u64 {
+        let mut records = self.inner.write().unwrap();
+        let id = records.next_id;
+        records.next_id += 1;
+        records.items.insert(id, item);
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<T> {
+        self.inner.read().unwrap().items.get(&id).cloned()
+    }
+
+    /// replace an existing record; false if there is none with `id`
+    pub fn update(&self, id: u64, item: T) -> bool {
+        match self.inner.write().unwrap().items.get_mut(&id) {
+            Some(existing) => {
+                *existing = item;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&self, id: u64) -> Option<T> {
+        self.inner.write().unwrap().items.remove(&id)
+    }
+
+    /// every record, ordered by id
+    pub fn list(&self) -> Vec<(u64, T)> {
+        self.inner
+            .read()
+            .unwrap()
+            .items
+            .iter()
+            .map(|(id, item)| (*id, item.clone()))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,76 @@
+use crate::models::cancellation::CancellationToken;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// Jobs that run alongside the server, e.g. cache refreshers or queue
+/// consumers, so they stop with it instead of being orphaned. At shutdown
+/// their `cancellation` token fires, they get
+/// `ServerConfig::background_shutdown_timeout` to wrap up, and whatever is
+/// still running after that is aborted.
+///
+/// `let stop = server.background_tasks().cancellation();`
+/// `server.spawn_background(async move { while stop.run_until_cancelled(refresh()).await.is_some() {} });`
+#[derive(Clone, Default)]
+pub struct TaskSet {
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    cancellation: CancellationToken,
+}
+
+impl TaskSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `future` on the current tokio runtime, returning a handle to
+    /// abort it early.
+    pub fn spawn<F>(&self, future: F) -> AbortHandle
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(future);
+        let abort = handle.abort_handle();
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(handle);
+        abort
+    }
+
+    /// the token that fires when the tasks are asked to stop
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// how many tasks are still running
+    pub fn len(&self) -> usize {
+        self.tasks.lock().unwrap().iter().filter(|task| !task.is_finished()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cancel the tasks and wait for them, up to `timeout` when given,
+    /// then abort those still running.
+    pub async fn shutdown(&self, timeout: Option<Duration>) {
+        self.cancellation.cancel();
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        for mut task in tasks {
+            let result = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, &mut task).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        task.abort();
+                        continue;
+                    }
+                },
+                None => task.await,
+            };
+            if let Err(e) = result {
+                crate::logging::log!(ERROR, "background task failed", error = e);
+            }
+        }
+    }
+}
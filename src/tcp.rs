@@ -0,0 +1,255 @@
+pub mod codec;
+
+pub use codec::{LengthDelimitedCodec, LinesCodec};
+
+use crate::middleware::BoxFuture;
+use crate::models::cancellation::CancellationToken;
+use crate::models::connection::ConnectionInfo;
+use crate::socket::SocketOptions;
+use crate::transport::{Stream, Transport};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Semaphore;
+
+/// How a protocol's frames are cut from and written to a byte stream.
+/// Each connection gets its own clone, so a codec can keep state between
+/// calls, e.g. the length of a frame whose body hasn't arrived yet.
+pub trait Codec: Clone + Send + 'static {
+    type Frame: Send + 'static;
+
+    /// Take one frame off the front of `buf`, removing the bytes it used.
+    /// `Ok(None)` means more bytes are needed; an error closes the
+    /// connection.
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Option<Self::Frame>, String>;
+
+    /// append `frame` to `buf`
+    fn encode(&mut self, frame: Self::Frame, buf: &mut Vec<u8>) -> Result<(), String>;
+}
+
+type Handler<F> = Arc<dyn Fn(F, ConnectionInfo) -> BoxFuture<'static, Option<F>> + Send + Sync>;
+
+/// where one listener accepts connections
+enum Bind {
+    Addr(String),
+    Std(std::net::TcpListener),
+    Transport(Arc<dyn Transport>),
+}
+
+/// A server for protocols other than HTTP, with the same accept loop as
+/// `HTTPServer`: every connection runs in its own task, reading frames
+/// with `C` and answering each with what the handler returns, if anything.
+///
+/// `let server = Server::new("0.0.0.0:7000", codec, |frame, _peer| async move { Some(frame) });`
+pub struct Server<C: Codec> {
+    listeners: Vec<Bind>,
+    codec: C,
+    handler: Handler<C::Frame>,
+    max_connections: Option<usize>,
+    idle_timeout: Option<Duration>,
+    socket: SocketOptions,
+    acceptors: usize,
+    shutdown: CancellationToken,
+}
+
+impl<C: Codec> Server<C> {
+    /// serve `addr`, e.g. `0.0.0.0:7000`
+    pub fn new<H, Fut>(addr: &str, codec: C, handler: H) -> Self
+    where
+        H: Fn(C::Frame, ConnectionInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<C::Frame>> + Send + 'static,
+    {
+        Self::with_listener(Bind::Addr(addr.to_string()), codec, handler)
+    }
+
+    /// serve on an already bound socket
+    pub fn from_listener<H, Fut>(listener: std::net::TcpListener, codec: C, handler: H) -> Self
+    where
+        H: Fn(C::Frame, ConnectionInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<C::Frame>> + Send + 'static,
+    {
+        Self::with_listener(Bind::Std(listener), codec, handler)
+    }
+
+    /// serve the connections `transport` accepts
+    pub fn from_transport<H, Fut>(transport: impl Transport, codec: C, handler: H) -> Self
+    where
+        H: Fn(C::Frame, ConnectionInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<C::Frame>> + Send + 'static,
+    {
+        Self::with_listener(Bind::Transport(Arc::new(transport)), codec, handler)
+    }
+
+    fn with_listener<H, Fut>(bind: Bind, codec: C, handler: H) -> Self
+    where
+        H: Fn(C::Frame, ConnectionInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<C::Frame>> + Send + 'static,
+    {
+        Server {
+            listeners: vec![bind],
+            codec,
+            handler: Arc::new(move |frame, peer| Box::pin(handler(frame, peer))),
+            max_connections: None,
+            idle_timeout: None,
+            socket: SocketOptions::default(),
+            acceptors: 1,
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// also accept on `addr`, with the same codec and handler
+    pub fn add_listener(mut self, addr: &str) -> Self {
+        self.listeners.push(Bind::Addr(addr.to_string()));
+        self
+    }
+
+    /// also accept the connections `transport` hands over
+    pub fn add_transport(mut self, transport: impl Transport) -> Self {
+        self.listeners.push(Bind::Transport(Arc::new(transport)));
+        self
+    }
+
+    /// stop accepting while `max` connections are open
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// close connections that send nothing for `timeout`
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// TCP options for the listeners and the connections they accept
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket = options;
+        self
+    }
+
+    /// Accept on `count` sockets per address, bound with `SO_REUSEPORT`
+    /// so the kernel balances connections between their accept loops; see
+    /// `ServerConfig::acceptors`.
+    pub fn acceptors(mut self, count: usize) -> Self {
+        self.acceptors = count.max(1);
+        self
+    }
+
+    /// Stop accepting connections and return from `start`. Connections
+    /// already accepted are left to finish.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// a token whose `cancel` does what `shutdown` does
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Bind every listener and serve until one of them fails or `shutdown`
+    /// is called.
+    pub async fn start(&self) -> std::io::Result<()> {
+        let mut listeners = Vec::new();
+        for bind in &self.listeners {
+            match bind {
+                Bind::Addr(addr) => listeners.extend(crate::transport::bind(addr, self.socket, self.acceptors).await?),
+                Bind::Std(listener) => listeners.push(crate::transport::from_std(listener, self.socket)?),
+                Bind::Transport(transport) => listeners.push(Arc::clone(transport)),
+            }
+        }
+        let mut accept_loops = tokio::task::JoinSet::new();
+        let limit = self.max_connections.map(|max| Arc::new(Semaphore::new(max)));
+        for listener in listeners {
+            crate::logging::log!(INFO, "tcp server running", address = listener.address()?);
+            accept_loops.spawn(accept_loop(
+                listener,
+                self.codec.clone(),
+                Arc::clone(&self.handler),
+                self.idle_timeout,
+                limit.clone(),
+            ));
+        }
+        let serve = async {
+            while let Some(result) = accept_loops.join_next().await {
+                result.map_err(std::io::Error::other)??;
+            }
+            Ok(())
+        };
+        match self.shutdown.run_until_cancelled(serve).await {
+            Some(result) => result,
+            None => {
+                accept_loops.shutdown().await;
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn accept_loop<C: Codec>(
+    listener: Arc<dyn Transport>,
+    codec: C,
+    handler: Handler<C::Frame>,
+    idle_timeout: Option<Duration>,
+    limit: Option<Arc<Semaphore>>,
+) -> std::io::Result<()> {
+    loop {
+        let permit = match &limit {
+            Some(limit) => Some(Arc::clone(limit).acquire_owned().await.map_err(std::io::Error::other)?),
+            None => None,
+        };
+        let (stream, info) = listener.accept().await?;
+        let addr = match info.peer_addr {
+            Some(peer) => peer.to_string(),
+            None => listener.address()?,
+        };
+        let (codec, handler) = (codec.clone(), Arc::clone(&handler));
+        tokio::spawn(async move {
+            let _permit = permit;
+            if let Err(e) = serve_connection(stream, info, codec, handler, idle_timeout).await {
+                crate::logging::log!(DEBUG, "connection failed", peer = addr, error = e);
+            }
+        });
+    }
+}
+
+/// read frames off `stream` until it closes, goes idle or sends one that
+/// doesn't decode, writing back the handler's answers in order
+async fn serve_connection<C: Codec>(
+    mut stream: Box<dyn Stream>,
+    info: ConnectionInfo,
+    mut codec: C,
+    handler: Handler<C::Frame>,
+    idle_timeout: Option<Duration>,
+) -> std::io::Result<()> {
+    let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+    let mut buffer = Vec::new();
+    let mut out = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        while let Some(frame) = codec.decode(&mut buffer).map_err(invalid)? {
+            if let Some(reply) = handler(frame, info.clone()).await {
+                codec.encode(reply, &mut out).map_err(invalid)?;
+                stream.write_all(&out).await?;
+                out.clear();
+            }
+        }
+        let read = match idle_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, stream.read(&mut chunk)).await {
+                Ok(read) => read?,
+                Err(_) => return Ok(()),
+            },
+            None => stream.read(&mut chunk).await?,
+        };
+        if read == 0 {
+            if buffer.is_empty() {
+                return Ok(());
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed in the middle of a frame",
+            ));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+}
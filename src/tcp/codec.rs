@@ -0,0 +1,134 @@
+use super::Codec;
+
+/// longest line `LinesCodec::new` accepts
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 64 * 1024;
+/// largest frame `LengthDelimitedCodec::new` accepts
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 8 * 1024 * 1024;
+
+/// Text lines ending in `\n`, with any `\r` before it dropped. Lines that
+/// aren't UTF-8 or grow past the maximum length close the connection.
+#[derive(Debug, Clone)]
+pub struct LinesCodec {
+    max_length: usize,
+    /// how far the current line has been searched for `\n`
+    searched: usize,
+}
+
+impl LinesCodec {
+    pub fn new() -> Self {
+        Self::with_max_length(DEFAULT_MAX_LINE_LENGTH)
+    }
+
+    /// accept lines of up to `max_length` bytes, not counting the line ending
+    pub fn with_max_length(max_length: usize) -> Self {
+        LinesCodec { max_length, searched: 0 }
+    }
+}
+
+impl Default for LinesCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Codec for LinesCodec {
+    type Frame = String;
+
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Option<String>, String> {
+        let Some(end) = buf[self.searched..].iter().position(|&b| b == b'\n').map(|i| self.searched + i) else {
+            self.searched = buf.len();
+            // leave room for a `\r` before the `\n` still to come
+            if buf.len() > self.max_length + 1 {
+                return Err(format!("Line longer than {} bytes", self.max_length));
+            }
+            return Ok(None);
+        };
+        self.searched = 0;
+        let mut line: Vec<u8> = buf.drain(..=end).collect();
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if line.len() > self.max_length {
+            return Err(format!("Line longer than {} bytes", self.max_length));
+        }
+        String::from_utf8(line).map(Some).map_err(|_| String::from("Line is not UTF-8"))
+    }
+
+    fn encode(&mut self, line: String, buf: &mut Vec<u8>) -> Result<(), String> {
+        if line.contains('\n') {
+            return Err(String::from("Line contains a line break"));
+        }
+        buf.extend_from_slice(line.as_bytes());
+        buf.push(b'\n');
+        Ok(())
+    }
+}
+
+/// Binary frames, each after its length as a big-endian integer of
+/// `length_field_bytes` bytes, 4 by default. Frames longer than the
+/// maximum close the connection when read and fail when written.
+#[derive(Debug, Clone)]
+pub struct LengthDelimitedCodec {
+    max_frame_length: usize,
+    length_field_bytes: usize,
+}
+
+impl LengthDelimitedCodec {
+    pub fn new() -> Self {
+        Self::with_max_frame_length(DEFAULT_MAX_FRAME_LENGTH)
+    }
+
+    pub fn with_max_frame_length(max_frame_length: usize) -> Self {
+        LengthDelimitedCodec {
+            max_frame_length,
+            length_field_bytes: 4,
+        }
+    }
+
+    /// the width of the length prefix, from 1 to 8 bytes
+    pub fn length_field_bytes(mut self, bytes: usize) -> Self {
+        assert!((1..=8).contains(&bytes), "length field must be 1 to 8 bytes");
+        self.length_field_bytes = bytes;
+        self
+    }
+}
+
+impl Default for LengthDelimitedCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Codec for LengthDelimitedCodec {
+    type Frame = Vec<u8>;
+
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+        let width = self.length_field_bytes;
+        if buf.len() < width {
+            return Ok(None);
+        }
+        let length = buf[..width].iter().fold(0u64, |length, &b| length << 8 | u64::from(b));
+        if length > self.max_frame_length as u64 {
+            return Err(format!("Frame longer than {} bytes", self.max_frame_length));
+        }
+        let end = width + length as usize;
+        if buf.len() < end {
+            return Ok(None);
+        }
+        let frame = buf[width..end].to_vec();
+        buf.drain(..end);
+        Ok(Some(frame))
+    }
+
+    fn encode(&mut self, frame: Vec<u8>, buf: &mut Vec<u8>) -> Result<(), String> {
+        let width = self.length_field_bytes;
+        let length = frame.len() as u64;
+        if frame.len() > self.max_frame_length || (width < 8 && length >> (width * 8) != 0) {
+            return Err(format!("Frame of {} bytes is too long", frame.len()));
+        }
+        buf.extend_from_slice(&length.to_be_bytes()[8 - width..]);
+        buf.extend_from_slice(&frame);
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+#[cfg(feature = "server")]
+pub mod mock;
+
+#[cfg(feature = "server")]
+pub use mock::{Mock, MockServer};
+
+use crate::middleware::Pipeline;
+use crate::models::connection::ConnectionInfo;
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use crate::router::Router;
+use std::net::SocketAddr;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// Sends requests through a router and its layers in process, without a
+/// server or sockets, e.g. in tests:
+///
+/// `client.get("/users/1").send().await.assert_status(HTTPStatus::Ok)`
+pub struct TestClient {
+    pipeline: Pipeline,
+}
+
+impl TestClient {
+    pub fn new(router: Router) -> Self {
+        TestClient {
+            pipeline: Pipeline::new(Arc::new(router)),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> TestRequest<'_> {
+        self.request(HTTPMethod::GET, url)
+    }
+
+    pub fn head(&self, url: &str) -> TestRequest<'_> {
+        self.request(HTTPMethod::HEAD, url)
+    }
+
+    pub fn post(&self, url: &str) -> TestRequest<'_> {
+        self.request(HTTPMethod::POST, url)
+    }
+
+    pub fn put(&self, url: &str) -> TestRequest<'_> {
+        self.request(HTTPMethod::PUT, url)
+    }
+
+    pub fn patch(&self, url: &str) -> TestRequest<'_> {
+        self.request(HTTPMethod::PATCH, url)
+    }
+
+    pub fn delete(&self, url: &str) -> TestRequest<'_> {
+        self.request(HTTPMethod::DELETE, url)
+    }
+
+    /// `url` is a path with an optional query, e.g. `/search?q=x`
+    pub fn request(&self, method: HTTPMethod, url: &str) -> TestRequest<'_> {
+        let mut req = HTTPRequest {
+            method,
+            url: url.to_string(),
+            connection: Some(ConnectionInfo {
+                peer_addr: Some(SocketAddr::from(([127, 0, 0, 1], 0))),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        req.headers.insert(HTTPHeaderType::Host, String::from("localhost"));
+        TestRequest { client: self, req }
+    }
+}
+
+/// A request being put together for a `TestClient`
+pub struct TestRequest<'a> {
+    client: &'a TestClient,
+    req: HTTPRequest,
+}
+
+impl TestRequest<'_> {
+    pub fn header(mut self, header: HTTPHeaderType, value: impl Into<String>) -> Self {
+        self.req.headers.insert(header, value.into());
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        let body = body.into();
+        self.req.headers.insert(HTTPHeaderType::ContentLength, body.len().to_string());
+        self.req.body = Some(body);
+        self
+    }
+
+    /// `value` as a JSON body; panics if it doesn't serialize
+    pub fn json<T: serde::Serialize>(self, value: &T) -> Self {
+        let body = serde_json::to_string(value).expect("request body serializes to JSON");
+        self.header(HTTPHeaderType::ContentType, crate::mime::Mime::APPLICATION_JSON.to_string())
+            .body(body)
+    }
+
+    /// connection details the request appears to arrive on; a loopback
+    /// peer without TLS by default
+    pub fn connection(mut self, info: ConnectionInfo) -> Self {
+        self.req.connection = Some(info);
+        self
+    }
+
+    /// Dispatch the request and read the whole response, streamed bodies
+    /// included.
+    pub async fn send(self) -> TestResponse {
+        #[allow(unused_mut)]
+        let mut res = self.send_streaming().await;
+        #[cfg(any(feature = "server", feature = "client"))]
+        res.0.collect_body().await;
+        res
+    }
+
+    /// Dispatch the request, leaving a streamed body to `chunk`, e.g. for
+    /// server-sent events that never end.
+    pub async fn send_streaming(self) -> TestResponse {
+        TestResponse(self.client.pipeline.dispatch(self.req).await)
+    }
+}
+
+/// The response to a `TestRequest`, with assertions that panic with the
+/// response in the message
+#[derive(Debug)]
+pub struct TestResponse(HTTPResponse);
+
+impl TestResponse {
+    pub fn assert_status(&self, status: HTTPStatus) -> &Self {
+        assert_eq!(self.0.status, status, "unexpected status, body: {}", self.text());
+        self
+    }
+
+    pub fn assert_header(&self, header: HTTPHeaderType, value: &str) -> &Self {
+        assert_eq!(self.0.header(&header), Some(value), "unexpected {} header", header);
+        self
+    }
+
+    pub fn assert_no_header(&self, header: HTTPHeaderType) -> &Self {
+        assert_eq!(self.0.header(&header), None, "unexpected {} header", header);
+        self
+    }
+
+    pub fn assert_body(&self, body: &str) -> &Self {
+        assert_eq!(self.text(), body, "unexpected body");
+        self
+    }
+
+    /// the body, invalid UTF-8 replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(self.0.body_bytes()).into_owned()
+    }
+
+    /// the body parsed as JSON; panics if it isn't a `T`
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(self.0.body_bytes())
+            .unwrap_or_else(|e| panic!("body is not the expected JSON ({}): {}", e, self.text()))
+    }
+
+    pub fn into_inner(self) -> HTTPResponse {
+        self.0
+    }
+}
+
+impl Deref for TestResponse {
+    type Target = HTTPResponse;
+
+    fn deref(&self) -> &HTTPResponse {
+        &self.0
+    }
+}
+
+impl DerefMut for TestResponse {
+    fn deref_mut(&mut self) -> &mut HTTPResponse {
+        &mut self.0
+    }
+}
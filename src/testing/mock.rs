@@ -0,0 +1,177 @@
+use crate::httpserver::HTTPServer;
+use crate::middleware::{BoxFuture, Middleware, Next};
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use crate::router::Router;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+type Predicate = Box<dyn Fn(&HTTPRequest) -> bool + Send + Sync>;
+
+/// An expected request and the response it gets, registered with
+/// `MockServer::mock`
+pub struct Mock {
+    method: HTTPMethod,
+    path: String,
+    predicates: Vec<Predicate>,
+    response: HTTPResponse,
+    times: Option<usize>,
+}
+
+impl Mock {
+    /// requests for `path`, query left out; answered with an empty 200
+    pub fn new(method: HTTPMethod, path: &str) -> Self {
+        Mock {
+            method,
+            path: path.to_string(),
+            predicates: Vec::new(),
+            response: HTTPResponse::default(),
+            times: None,
+        }
+    }
+
+    /// only requests `predicate` accepts
+    pub fn matching<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&HTTPRequest) -> bool + Send + Sync + 'static,
+    {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    pub fn with_header(self, header: HTTPHeaderType, value: &str) -> Self {
+        let value = value.to_string();
+        self.matching(move |req| req.header(&header) == Some(value.as_str()))
+    }
+
+    pub fn with_body(self, body: &str) -> Self {
+        let body = body.to_string();
+        self.matching(move |req| req.body.as_deref().unwrap_or("") == body)
+    }
+
+    pub fn respond(mut self, status: HTTPStatus, body: impl Into<String>) -> Self {
+        self.response.status = status;
+        self.response.body = Some(body.into());
+        self
+    }
+
+    /// answer with a copy of `response`
+    pub fn respond_with(mut self, response: HTTPResponse) -> Self {
+        self.response = response;
+        self
+    }
+
+    /// expect exactly `n` matching requests instead of at least one
+    pub fn times(mut self, n: usize) -> Self {
+        self.times = Some(n);
+        self
+    }
+
+    fn matches(&self, req: &HTTPRequest) -> bool {
+        req.method == self.method && req.path() == self.path && self.predicates.iter().all(|p| p(req))
+    }
+
+    /// what's wrong with having been matched `hits` times, if anything
+    fn unmet(&self, hits: usize) -> Option<String> {
+        match self.times {
+            Some(n) if hits != n => Some(format!("{} {}: expected {} requests, got {}", self.method, self.path, n, hits)),
+            None if hits == 0 => Some(format!("{} {}: expected requests, got none", self.method, self.path)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    mocks: Vec<(Mock, usize)>,
+    received: Vec<HTTPRequest>,
+    unexpected: Vec<String>,
+}
+
+/// A server on an ephemeral local port that answers scripted requests,
+/// e.g. as the upstream of a client or proxy under test. Requests no mock
+/// matches get a 404. Dropping it checks that every mock was requested as
+/// often as expected and nothing unexpected arrived, and panics otherwise.
+///
+/// Needs a running tokio runtime.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: tokio::task::JoinHandle<std::io::Result<()>>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind a local port");
+        let addr = listener.local_addr().expect("bound address");
+        let state = Arc::new(Mutex::new(State::default()));
+        let mut router = Router::new();
+        router.layer(Responder(Arc::clone(&state)));
+        let server = HTTPServer::from_listener(listener, router, Default::default()).expect("serve the mock");
+        let task = tokio::spawn(async move { server.start().await });
+        MockServer { addr, state, task }
+    }
+
+    pub fn mock(&self, mock: Mock) -> &Self {
+        self.state.lock().unwrap().mocks.push((mock, 0));
+        self
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// `http://` URL of `path` on this server
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// every request so far, in arrival order
+    pub fn received(&self) -> Vec<HTTPRequest> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Panic unless every mock got its expected requests and no others
+    /// arrived; also done on drop.
+    pub fn verify(&self) {
+        let state = self.state.lock().unwrap();
+        let problems: Vec<String> = state
+            .mocks
+            .iter()
+            .filter_map(|(mock, hits)| mock.unmet(*hits))
+            .chain(state.unexpected.iter().map(|request| format!("unexpected request {}", request)))
+            .collect();
+        if !problems.is_empty() {
+            panic!("mock server expectations not met:\n{}", problems.join("\n"));
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        if !std::thread::panicking() {
+            self.verify();
+        }
+    }
+}
+
+/// answers every request from the mocks, before routing
+struct Responder(Arc<Mutex<State>>);
+
+impl Middleware for Responder {
+    fn handle<'a>(&'a self, req: HTTPRequest, _next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        let mut state = self.0.lock().unwrap();
+        let res = match state.mocks.iter_mut().find(|(mock, _)| mock.matches(&req)) {
+            Some((mock, hits)) => {
+                *hits += 1;
+                mock.response.clone()
+            }
+            None => {
+                state.unexpected.push(format!("{} {}", req.method, req.url));
+                HTTPResponse::error(HTTPStatus::NotFound, "no mock matches this request")
+            }
+        };
+        state.received.push(req);
+        Box::pin(async move { res })
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::TlsAcceptor;
+
+/// ALPN protocol ids offered to clients, most preferred first
+pub const ALPN_H2: &[u8] = b"h2";
+pub const ALPN_HTTP1_1: &[u8] = b"http/1.1";
+
+/// TLS settings for `HTTPServer`. Offers `h2` (with the `http2` feature) and
+/// `http/1.1` over ALPN.
+#[derive(Clone)]
+pub struct TlsConfig {
+    acceptor: TlsAcceptor,
+}
+
+impl TlsConfig {
+    /// load a PEM certificate chain and private key from disk
+    pub fn from_pem_files(cert_path: &str, key_path: &str) -> std::io::Result<Self> {
+        let cert = std::fs::read(cert_path)?;
+        let key = std::fs::read(key_path)?;
+        Self::from_pem(&cert, &key)
+    }
+
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> std::io::Result<Self> {
+        let certs = CertificateDer::pem_slice_iter(cert)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid_data)?;
+        let key = PrivateKeyDer::from_pem_slice(key).map_err(invalid_data)?;
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .map_err(invalid_data)?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(invalid_data)?;
+        Ok(Self::from_server_config(config))
+    }
+
+    /// wrap an existing rustls config. ALPN protocols are overwritten so that
+    /// negotiation always matches what the server can speak.
+    pub fn from_server_config(mut config: rustls::ServerConfig) -> Self {
+        config.alpn_protocols = vec![ALPN_HTTP1_1.to_vec()];
+        #[cfg(feature = "http2")]
+        config.alpn_protocols.insert(0, ALPN_H2.to_vec());
+        TlsConfig {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        }
+    }
+
+    pub fn acceptor(&self) -> &TlsAcceptor {
+        &self.acceptor
+    }
+}
+
+/// what a finished handshake negotiated, for `ConnectionInfo`
+pub fn session_info(connection: &rustls::ServerConnection) -> crate::models::connection::TlsInfo {
+    crate::models::connection::TlsInfo {
+        protocol: connection
+            .protocol_version()
+            .map(|v| v.as_str().map_or_else(|| format!("{:?}", v), str::to_string)),
+        cipher_suite: connection
+            .negotiated_cipher_suite()
+            .map(|s| s.suite().as_str().map_or_else(|| format!("{:?}", s.suite()), str::to_string)),
+        alpn: connection
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).to_string()),
+        server_name: connection.server_name().map(str::to_string),
+    }
+}
+
+fn invalid_data<E: std::fmt::Display>(e: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
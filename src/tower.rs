@@ -0,0 +1,153 @@
+use crate::middleware::{BoxFuture, Middleware, Next, Pipeline};
+use crate::models::error::HTTPError;
+use crate::models::http::{HTTPRequest, HTTPResponse};
+use crate::router::Router;
+use std::convert::Infallible;
+use std::future::{poll_fn, Future};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::{mpsc, oneshot};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// the error type tower middleware usually works with
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A `Router` with its layers as a tower `Service`, from
+/// `Router::into_service`, to wrap in tower middleware or mount in another
+/// stack. Cheap to clone; requests go through `Pipeline::dispatch`, so
+/// handler panics become 500s and the service never fails.
+#[derive(Clone)]
+pub struct RouterService(Arc<Pipeline>);
+
+impl RouterService {
+    pub fn new(router: Router) -> Self {
+        RouterService(Arc::new(Pipeline::new(Arc::new(router))))
+    }
+
+    pub fn router(&self) -> &Router {
+        self.0.router()
+    }
+}
+
+impl From<Pipeline> for RouterService {
+    fn from(pipeline: Pipeline) -> Self {
+        RouterService(Arc::new(pipeline))
+    }
+}
+
+impl Service<HTTPRequest> for RouterService {
+    type Response = HTTPResponse;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<HTTPResponse, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: HTTPRequest) -> Self::Future {
+        let pipeline = Arc::clone(&self.0);
+        Box::pin(async move { Ok(pipeline.dispatch(req).await) })
+    }
+}
+
+/// run `req` through `service` once it is ready
+pub(crate) async fn call_service<S>(mut service: S, req: HTTPRequest) -> Result<HTTPResponse, String>
+where
+    S: Service<HTTPRequest, Response = HTTPResponse>,
+    S::Error: std::fmt::Display,
+{
+    poll_fn(|cx| service.poll_ready(cx)).await.map_err(|e| e.to_string())?;
+    service.call(req).await.map_err(|e| e.to_string())
+}
+
+/// the way back into the chain for the request `Downstream` gets
+#[derive(Clone)]
+struct Continuation(mpsc::UnboundedSender<(HTTPRequest, oneshot::Sender<HTTPResponse>)>);
+
+/// The service a tower `Layer` wraps inside a `TowerLayer`: the rest of
+/// the middleware chain and the router, for whichever request it is
+/// called with. It may be called more than once per request, e.g. by a
+/// retry layer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Downstream;
+
+impl Service<HTTPRequest> for Downstream {
+    type Response = HTTPResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<HTTPResponse, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: HTTPRequest) -> Self::Future {
+        let continuation = req.extensions.get::<Continuation>().map(|c| c.0.clone());
+        Box::pin(async move {
+            let continuation = continuation.ok_or("request did not come through a TowerLayer")?;
+            let (reply, response) = oneshot::channel();
+            continuation.send((req, reply)).map_err(|_| "the request has finished")?;
+            Ok(response.await?)
+        })
+    }
+}
+
+/// A tower `Layer` run as `Middleware`, e.g.
+/// `router.layer(TowerLayer::new(ConcurrencyLimitLayer::new(64)))`. The
+/// layer wraps `Downstream` once and the service is cloned per request,
+/// as tower expects, so limits and other state are shared. Errors from
+/// the service are rendered as 500s through the router's error handler.
+pub struct TowerLayer<S> {
+    service: S,
+}
+
+impl<S> TowerLayer<S> {
+    pub fn new<L>(layer: L) -> Self
+    where
+        L: Layer<Downstream, Service = S>,
+    {
+        TowerLayer {
+            service: layer.layer(Downstream),
+        }
+    }
+}
+
+impl<S> Middleware for TowerLayer<S>
+where
+    S: Service<HTTPRequest, Response = HTTPResponse> + Clone + Send + Sync + 'static,
+    S::Error: std::fmt::Display,
+    S::Future: Send,
+{
+    fn handle<'a>(&'a self, mut req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+        Box::pin(async move {
+            let (continuation, mut requests) = mpsc::unbounded_channel();
+            req.extensions.insert(Continuation(continuation));
+            let router = next.router();
+            let head = HTTPRequest {
+                method: req.method.clone(),
+                url: req.url.clone(),
+                ..Default::default()
+            };
+            let mut outer = std::pin::pin!(call_service(self.service.clone(), req));
+            // answers the service's calls into `Downstream` until it is done
+            let mut inner = std::pin::pin!(async move {
+                while let Some((req, reply)) = requests.recv().await {
+                    let _ = reply.send(next.clone().run(req).await);
+                }
+            });
+            let mut inner_done = false;
+            poll_fn(|cx| {
+                if !inner_done {
+                    inner_done = inner.as_mut().poll(cx).is_ready();
+                }
+                outer.as_mut().poll(cx)
+            })
+            .await
+            .unwrap_or_else(|e| router.render_error(&head, &HTTPError::internal(&e)))
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        std::any::type_name::<S>()
+    }
+}
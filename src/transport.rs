@@ -0,0 +1,162 @@
+use crate::middleware::BoxFuture;
+use crate::models::connection::ConnectionInfo;
+use crate::socket::SocketOptions;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A byte stream one connection is served over
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Stream for T {}
+
+/// Where a listener's connections come from, see
+/// `HTTPServer::from_transport`. TCP and Unix sockets are built in; on
+/// targets without them, e.g. `wasm32-wasi`, implement it over whatever
+/// hands the program its streams, or serve each stream with
+/// `httpserver::serve_connection`.
+pub trait Transport: Send + Sync + 'static {
+    /// the next connection and what is known about it so far
+    fn accept(&self) -> BoxFuture<'_, std::io::Result<(Box<dyn Stream>, ConnectionInfo)>>;
+
+    /// what the listener is logged as, e.g. `127.0.0.1:8080`
+    fn address(&self) -> std::io::Result<String>;
+}
+
+/// TCP listeners on `addr`, e.g. `0.0.0.0:8080`, the first of its
+/// addresses that binds. With more than one acceptor they all bind it
+/// with `SO_REUSEPORT`, so the kernel spreads new connections across
+/// their accept loops.
+pub(crate) async fn bind(
+    addr: &str,
+    mut options: SocketOptions,
+    acceptors: usize,
+) -> std::io::Result<Vec<Arc<dyn Transport>>> {
+    #[cfg(not(target_family = "wasm"))]
+    {
+        if acceptors > 1 {
+            options.reuse_port = Some(true);
+        }
+        let mut last = None;
+        for addr in tokio::net::lookup_host(addr).await? {
+            let first = match crate::socket::listen(addr, &options) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    last = Some(e);
+                    continue;
+                }
+            };
+            // the port the first one got, if `addr` asked for any
+            let addr = first.local_addr()?;
+            let mut listeners = vec![first];
+            while listeners.len() < acceptors {
+                listeners.push(crate::socket::listen(addr, &options)?);
+            }
+            return listeners
+                .into_iter()
+                .map(|listener| {
+                    let listener = tokio::net::TcpListener::from_std(listener)?;
+                    Ok(Arc::new(TcpTransport { listener, options }) as Arc<dyn Transport>)
+                })
+                .collect();
+        }
+        Err(last.unwrap_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("no addresses for {}", addr))
+        }))
+    }
+    #[cfg(target_family = "wasm")]
+    {
+        let _ = (&mut options, acceptors);
+        Err(unsupported(addr))
+    }
+}
+
+/// `listener`, already bound, e.g. inherited from a process manager; only
+/// the per-connection `options` still apply
+pub(crate) fn from_std(listener: &std::net::TcpListener, options: SocketOptions) -> std::io::Result<Arc<dyn Transport>> {
+    let listener = listener.try_clone()?;
+    listener.set_nonblocking(true)?;
+    #[cfg(not(target_family = "wasm"))]
+    return Ok(Arc::new(TcpTransport {
+        listener: tokio::net::TcpListener::from_std(listener)?,
+        options,
+    }));
+    #[cfg(target_family = "wasm")]
+    {
+        let _ = options;
+        Err(unsupported(&listener.local_addr()?.to_string()))
+    }
+}
+
+#[cfg(target_family = "wasm")]
+fn unsupported(addr: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("can't listen on {} without sockets, serve through a Transport", addr),
+    )
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Transport for tokio::net::TcpListener {
+    fn accept(&self) -> BoxFuture<'_, std::io::Result<(Box<dyn Stream>, ConnectionInfo)>> {
+        Box::pin(accept_tcp(self, None))
+    }
+
+    fn address(&self) -> std::io::Result<String> {
+        Ok(self.local_addr()?.to_string())
+    }
+}
+
+/// a TCP listener and the options its connections get
+#[cfg(not(target_family = "wasm"))]
+pub(crate) struct TcpTransport {
+    listener: tokio::net::TcpListener,
+    options: SocketOptions,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Transport for TcpTransport {
+    fn accept(&self) -> BoxFuture<'_, std::io::Result<(Box<dyn Stream>, ConnectionInfo)>> {
+        Box::pin(accept_tcp(&self.listener, Some(&self.options)))
+    }
+
+    fn address(&self) -> std::io::Result<String> {
+        self.listener.address()
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+async fn accept_tcp(
+    listener: &tokio::net::TcpListener,
+    options: Option<&SocketOptions>,
+) -> std::io::Result<(Box<dyn Stream>, ConnectionInfo)> {
+    let (socket, addr) = listener.accept().await?;
+    if let Some(options) = options {
+        // the connection is still worth serving without them
+        if let Err(e) = crate::socket::configure(&socket, options) {
+            crate::logging::log!(DEBUG, "socket options not applied", peer = addr, error = e);
+        }
+    }
+    let info = ConnectionInfo {
+        peer_addr: Some(addr),
+        local_addr: socket.local_addr().ok(),
+        ..Default::default()
+    };
+    Ok((Box::new(socket) as Box<dyn Stream>, info))
+}
+
+/// a Unix domain socket and the path it is bound to
+#[cfg(unix)]
+pub(crate) struct UnixTransport(pub(crate) tokio::net::UnixListener, pub(crate) std::path::PathBuf);
+
+#[cfg(unix)]
+impl Transport for UnixTransport {
+    fn accept(&self) -> BoxFuture<'_, std::io::Result<(Box<dyn Stream>, ConnectionInfo)>> {
+        Box::pin(async move {
+            let (socket, _) = self.0.accept().await?;
+            Ok((Box::new(socket) as Box<dyn Stream>, ConnectionInfo::default()))
+        })
+    }
+
+    fn address(&self) -> std::io::Result<String> {
+        Ok(format!("unix:{}", self.1.display()))
+    }
+}
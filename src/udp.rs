@@ -0,0 +1,298 @@
+use crate::middleware::BoxFuture;
+use crate::models::cancellation::CancellationToken;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+
+/// the largest payload a UDP datagram can carry
+pub const MAX_DATAGRAM: usize = 65_507;
+
+/// The sender of a datagram, with the state kept for it between datagrams
+/// when the server was built with `UdpServer::with_state`
+pub struct Peer<S = ()> {
+    pub addr: SocketAddr,
+    pub state: Arc<tokio::sync::Mutex<S>>,
+}
+
+type Handler<S> = Arc<dyn Fn(Vec<u8>, Peer<S>) -> BoxFuture<'static, Option<Vec<u8>>> + Send + Sync>;
+
+/// where the server receives datagrams
+enum Bind {
+    Addr(String),
+    Std(std::net::UdpSocket),
+}
+
+/// Answers UDP datagrams: each one runs the handler in its own task and
+/// whatever it returns is sent back to the peer. Per-peer state starts as
+/// `S::default()` and is dropped once the peer has been quiet for
+/// `peer_timeout`.
+///
+/// `UdpServer::new("0.0.0.0:5353", |datagram, _peer| async move { Some(datagram) })`
+pub struct UdpServer<S = ()> {
+    bind: Bind,
+    handler: Handler<S>,
+    peer_timeout: Duration,
+    shutdown: CancellationToken,
+}
+
+impl UdpServer<()> {
+    pub fn new<H, Fut>(addr: &str, handler: H) -> Self
+    where
+        H: Fn(Vec<u8>, Peer) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<Vec<u8>>> + Send + 'static,
+    {
+        Self::with_state(addr, handler)
+    }
+}
+
+impl<S: Default + Send + 'static> UdpServer<S> {
+    /// like `new`, keeping an `S` per peer, e.g. a session or rate limiter
+    pub fn with_state<H, Fut>(addr: &str, handler: H) -> Self
+    where
+        H: Fn(Vec<u8>, Peer<S>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<Vec<u8>>> + Send + 'static,
+    {
+        Self::with_bind(Bind::Addr(addr.to_string()), handler)
+    }
+
+    /// serve on an already bound socket
+    pub fn from_socket<H, Fut>(socket: std::net::UdpSocket, handler: H) -> Self
+    where
+        H: Fn(Vec<u8>, Peer<S>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<Vec<u8>>> + Send + 'static,
+    {
+        Self::with_bind(Bind::Std(socket), handler)
+    }
+
+    fn with_bind<H, Fut>(bind: Bind, handler: H) -> Self
+    where
+        H: Fn(Vec<u8>, Peer<S>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<Vec<u8>>> + Send + 'static,
+    {
+        UdpServer {
+            bind,
+            handler: Arc::new(move |datagram, peer| Box::pin(handler(datagram, peer))),
+            peer_timeout: Duration::from_secs(60),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// forget a peer's state after it has sent nothing for `timeout`
+    pub fn peer_timeout(mut self, timeout: Duration) -> Self {
+        self.peer_timeout = timeout;
+        self
+    }
+
+    /// stop receiving and return from `start`
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// a token whose `cancel` does what `shutdown` does
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Bind the socket and answer datagrams until receiving fails or
+    /// `shutdown` is called.
+    pub async fn start(&self) -> std::io::Result<()> {
+        let socket = Arc::new(match &self.bind {
+            Bind::Addr(addr) => UdpSocket::bind(addr).await?,
+            Bind::Std(socket) => {
+                let socket = socket.try_clone()?;
+                socket.set_nonblocking(true)?;
+                UdpSocket::from_std(socket)?
+            }
+        });
+        crate::logging::log!(INFO, "udp server running", address = socket.local_addr()?);
+        let mut peers: HashMap<SocketAddr, (Arc<tokio::sync::Mutex<S>>, Instant)> = HashMap::new();
+        let mut swept = Instant::now();
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        loop {
+            let Some(received) = self.shutdown.run_until_cancelled(socket.recv_from(&mut buffer)).await else {
+                return Ok(());
+            };
+            let (read, addr) = match received {
+                Ok(received) => received,
+                // an ICMP error for an earlier reply, not a problem with the socket
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => continue,
+                Err(e) => return Err(e),
+            };
+            let now = Instant::now();
+            if now.duration_since(swept) >= self.peer_timeout {
+                peers.retain(|_, (_, seen)| now.duration_since(*seen) < self.peer_timeout);
+                swept = now;
+            }
+            let entry = peers.entry(addr).or_insert_with(|| (Arc::default(), now));
+            entry.1 = now;
+            let peer = Peer {
+                addr,
+                state: Arc::clone(&entry.0),
+            };
+            let reply = (self.handler)(buffer[..read].to_vec(), peer);
+            let socket = Arc::clone(&socket);
+            tokio::spawn(async move {
+                if let Some(reply) = reply.await {
+                    if let Err(e) = socket.send_to(&reply, addr).await {
+                        crate::logging::log!(DEBUG, "udp reply failed", peer = addr, error = e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+type Correlate = Arc<dyn Fn(&[u8]) -> Option<u64> + Send + Sync>;
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Vec<u8>>>>>;
+
+/// Sends requests to one UDP peer and waits for the answers, resending
+/// each up to `retries` times after `timeout`.
+///
+/// Without `correlate`, requests go one at a time and the next datagram
+/// from the peer is the answer, so a late reply to an attempt that timed
+/// out can be taken for the answer to the next one. With it, many requests
+/// can be in flight and replies are matched on the id it extracts from
+/// both, e.g. the transaction id of a DNS message:
+///
+/// `let client = UdpClient::connect("10.0.0.2:53").await?.correlate(|msg| msg.get(..2).map(|id| u16::from_be_bytes([id[0], id[1]]) as u64));`
+pub struct UdpClient {
+    socket: Arc<UdpSocket>,
+    correlate: Option<(Correlate, Pending, tokio::task::AbortHandle)>,
+    /// uncorrelated requests wait their turn
+    turn: tokio::sync::Mutex<()>,
+    timeout: Duration,
+    retries: usize,
+}
+
+impl UdpClient {
+    /// a client for `addr`, from an ephemeral local port
+    pub async fn connect(addr: &str) -> std::io::Result<Self> {
+        let peer = tokio::net::lookup_host(addr)
+            .await?
+            .next()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("no address for {}", addr)))?;
+        let local: SocketAddr = if peer.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(peer).await?;
+        Ok(UdpClient {
+            socket: Arc::new(socket),
+            correlate: None,
+            turn: tokio::sync::Mutex::new(()),
+            timeout: Duration::from_secs(5),
+            retries: 0,
+        })
+    }
+
+    /// how long to wait for each attempt's reply, 5 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// how many times to resend a request that got no reply
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Match replies to requests by the id `id_of` finds in both.
+    /// Datagrams without one, or with an id nothing waits for, are dropped.
+    pub fn correlate<F>(mut self, id_of: F) -> Self
+    where
+        F: Fn(&[u8]) -> Option<u64> + Send + Sync + 'static,
+    {
+        let id_of: Correlate = Arc::new(id_of);
+        let pending: Pending = Arc::default();
+        let (socket, ids, waiting) = (Arc::clone(&self.socket), Arc::clone(&id_of), Arc::clone(&pending));
+        let receiver = tokio::spawn(async move {
+            let mut buffer = vec![0; MAX_DATAGRAM];
+            loop {
+                let Ok(read) = socket.recv(&mut buffer).await else {
+                    continue;
+                };
+                let reply = &buffer[..read];
+                let waiter = ids(reply).and_then(|id| waiting.lock().unwrap().remove(&id));
+                if let Some(waiter) = waiter {
+                    let _ = waiter.send(reply.to_vec());
+                }
+            }
+        });
+        if let Some((_, _, previous)) = self.correlate.replace((id_of, pending, receiver.abort_handle())) {
+            previous.abort();
+        }
+        self
+    }
+
+    /// the address the client sends from
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// send `datagram` without waiting for anything back
+    pub async fn send(&self, datagram: &[u8]) -> std::io::Result<()> {
+        self.socket.send(datagram).await.map(|_| ())
+    }
+
+    /// Send `datagram` and wait for its reply, failing with `TimedOut`
+    /// once every attempt has gone unanswered.
+    pub async fn request(&self, datagram: &[u8]) -> std::io::Result<Vec<u8>> {
+        let Some((id_of, pending, _)) = &self.correlate else {
+            let _turn = self.turn.lock().await;
+            let mut buffer = vec![0; MAX_DATAGRAM];
+            for _ in 0..=self.retries {
+                self.socket.send(datagram).await?;
+                if let Ok(read) = tokio::time::timeout(self.timeout, self.socket.recv(&mut buffer)).await {
+                    buffer.truncate(read?);
+                    return Ok(buffer);
+                }
+            }
+            return Err(timed_out());
+        };
+        let id = id_of(datagram)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "request has no correlation id"))?;
+        let (waiter, mut reply) = oneshot::channel();
+        {
+            let mut pending = pending.lock().unwrap();
+            if pending.contains_key(&id) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("a request with id {} is already in flight", id),
+                ));
+            }
+            pending.insert(id, waiter);
+        }
+        let _waiting = Waiting(pending, id);
+        for _ in 0..=self.retries {
+            self.socket.send(datagram).await?;
+            if let Ok(reply) = tokio::time::timeout(self.timeout, &mut reply).await {
+                return reply.map_err(|_| std::io::Error::other("the receiver stopped"));
+            }
+        }
+        Err(timed_out())
+    }
+}
+
+impl Drop for UdpClient {
+    fn drop(&mut self) {
+        if let Some((_, _, receiver)) = &self.correlate {
+            receiver.abort();
+        }
+    }
+}
+
+/// stops waiting for a reply when the request finishes or is dropped
+struct Waiting<'a>(&'a Pending, u64);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().remove(&self.1);
+    }
+}
+
+fn timed_out() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::TimedOut, "no reply")
+}
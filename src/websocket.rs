@@ -0,0 +1,643 @@
+#[cfg(feature = "compression")]
+pub mod deflate;
+pub mod hub;
+
+pub use hub::Hub;
+
+use crate::models::http::{HTTPHeaderType, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus};
+use crate::models::upgrade::Upgraded;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::Mutex;
+
+/// appended to the client's key before hashing (RFC 6455 1.3)
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// Answer a WebSocket opening handshake (RFC 6455 4.2) in `res`: 101 with
+/// `Sec-WebSocket-Accept` when `req` is a valid upgrade, otherwise 400, or
+/// 426 with the `Upgrade` / `Sec-WebSocket-Version` the server expects.
+/// With the `compression` feature a `permessage-deflate` offer is accepted
+/// in `Sec-WebSocket-Extensions`. Returns whether the connection can be
+/// upgraded.
+pub fn handshake(req: &HTTPRequest, res: &mut HTTPResponse) -> bool {
+    let has_token = |header: HTTPHeaderType, token: &str| {
+        req.header(&header)
+            .is_some_and(|value| value.split(',').any(|v| v.trim().eq_ignore_ascii_case(token)))
+    };
+    if !has_token(HTTPHeaderType::Upgrade, "websocket") || !has_token(HTTPHeaderType::Connection, "upgrade") {
+        reject(res, HTTPStatus::UpgradeRequired, "Expected a WebSocket upgrade");
+        res.set_header(HTTPHeaderType::Upgrade, "websocket");
+        res.set_header(HTTPHeaderType::Connection, "Upgrade");
+        return false;
+    }
+    if req.method != HTTPMethod::GET {
+        reject(res, HTTPStatus::BadRequest, "WebSocket upgrades must use GET");
+        return false;
+    }
+    if req.header(&HTTPHeaderType::SecWebSocketVersion).map(str::trim) != Some("13") {
+        reject(res, HTTPStatus::UpgradeRequired, "Unsupported WebSocket version");
+        res.set_header(HTTPHeaderType::SecWebSocketVersion, "13");
+        return false;
+    }
+    let key = req.header(&HTTPHeaderType::SecWebSocketKey).unwrap_or("");
+    let decoded = base64::engine::general_purpose::STANDARD.decode(key.trim());
+    if decoded.map_or(true, |nonce| nonce.len() != 16) {
+        reject(res, HTTPStatus::BadRequest, "Invalid Sec-WebSocket-Key");
+        return false;
+    }
+
+    res.status = HTTPStatus::SwitchingProtocols;
+    res.body = None;
+    res.raw_body = None;
+    res.set_header(HTTPHeaderType::Upgrade, "websocket");
+    res.set_header(HTTPHeaderType::Connection, "Upgrade");
+    res.set_header(HTTPHeaderType::SecWebSocketAccept, accept_key(key));
+    #[cfg(feature = "compression")]
+    if let Some(config) = req
+        .header(&HTTPHeaderType::SecWebSocketExtensions)
+        .and_then(deflate::PerMessageDeflate::negotiate)
+    {
+        res.set_header(HTTPHeaderType::SecWebSocketExtensions, config.response_header());
+    }
+    true
+}
+
+fn reject(res: &mut HTTPResponse, status: HTTPStatus, message: &str) {
+    res.status = status;
+    res.text(message);
+}
+
+/// Frame type (RFC 6455 5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0x0 => Some(OpCode::Continuation),
+            0x1 => Some(OpCode::Text),
+            0x2 => Some(OpCode::Binary),
+            0x8 => Some(OpCode::Close),
+            0x9 => Some(OpCode::Ping),
+            0xA => Some(OpCode::Pong),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            OpCode::Continuation => 0x0,
+            OpCode::Text => 0x1,
+            OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
+            OpCode::Ping => 0x9,
+            OpCode::Pong => 0xA,
+        }
+    }
+
+    /// close, ping and pong; these can't be fragmented and carry at most 125 bytes
+    pub fn is_control(&self) -> bool {
+        matches!(self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+    }
+}
+
+/// Status code of a close frame (RFC 6455 7.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CloseCode(pub u16);
+
+impl CloseCode {
+    pub const NORMAL: CloseCode = CloseCode(1000);
+    pub const GOING_AWAY: CloseCode = CloseCode(1001);
+    pub const PROTOCOL_ERROR: CloseCode = CloseCode(1002);
+    pub const UNSUPPORTED_DATA: CloseCode = CloseCode(1003);
+    pub const INVALID_DATA: CloseCode = CloseCode(1007);
+    pub const POLICY_VIOLATION: CloseCode = CloseCode(1008);
+    pub const MESSAGE_TOO_BIG: CloseCode = CloseCode(1009);
+    pub const INTERNAL_ERROR: CloseCode = CloseCode(1011);
+
+    /// whether the code may appear on the wire; 1005, 1006 and 1015 are
+    /// reserved for reporting and unassigned codes below 3000 are invalid
+    pub fn is_valid(&self) -> bool {
+        matches!(self.0, 1000..=1003 | 1007..=1011 | 3000..=4999)
+    }
+}
+
+impl Display for CloseCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A single frame. `payload` is always unmasked; `mask` is the key it
+/// arrived with, or the key to apply when encoding (client to server).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    /// set by extensions such as permessage-deflate
+    pub rsv1: bool,
+    pub opcode: OpCode,
+    pub mask: Option<[u8; 4]>,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// a final, unmasked frame
+    pub fn new(opcode: OpCode, payload: Vec<u8>) -> Self {
+        Frame {
+            fin: true,
+            rsv1: false,
+            opcode,
+            mask: None,
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.payload.len();
+        let mut out = Vec::with_capacity(len + 14);
+        out.push((self.fin as u8) << 7 | (self.rsv1 as u8) << 6 | self.opcode.as_u8());
+        let mask_bit = (self.mask.is_some() as u8) << 7;
+        if len < 126 {
+            out.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+        match self.mask {
+            Some(key) => {
+                out.extend_from_slice(&key);
+                let start = out.len();
+                out.extend_from_slice(&self.payload);
+                apply_mask(&mut out[start..], key);
+            }
+            None => out.extend_from_slice(&self.payload),
+        }
+        out
+    }
+
+    /// Parse the frame at the front of `buf`, returning it and the bytes it
+    /// took, or `None` until the whole frame has arrived. Errors carry the
+    /// code to close the connection with.
+    pub fn parse(buf: &[u8], max_payload: usize) -> Result<Option<(Frame, usize)>, CloseCode> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let fin = buf[0] & 0x80 != 0;
+        let rsv1 = buf[0] & 0x40 != 0;
+        if buf[0] & 0x30 != 0 {
+            return Err(CloseCode::PROTOCOL_ERROR);
+        }
+        let opcode = OpCode::from_u8(buf[0] & 0x0F).ok_or(CloseCode::PROTOCOL_ERROR)?;
+        let masked = buf[1] & 0x80 != 0;
+        let (len, mut offset) = match buf[1] & 0x7F {
+            126 => match buf.get(2..4) {
+                Some(bytes) => (u16::from_be_bytes([bytes[0], bytes[1]]) as u64, 4),
+                None => return Ok(None),
+            },
+            127 => match buf.get(2..10) {
+                Some(bytes) => (u64::from_be_bytes(bytes.try_into().unwrap()), 10),
+                None => return Ok(None),
+            },
+            len => (len as u64, 2),
+        };
+        if opcode.is_control() && (!fin || len > 125) {
+            return Err(CloseCode::PROTOCOL_ERROR);
+        }
+        if len > max_payload as u64 {
+            return Err(CloseCode::MESSAGE_TOO_BIG);
+        }
+        let mask = if masked {
+            match buf.get(offset..offset + 4) {
+                Some(key) => {
+                    offset += 4;
+                    Some([key[0], key[1], key[2], key[3]])
+                }
+                None => return Ok(None),
+            }
+        } else {
+            None
+        };
+        let end = offset + len as usize;
+        let Some(payload) = buf.get(offset..end) else {
+            return Ok(None);
+        };
+        let mut payload = payload.to_vec();
+        if let Some(key) = mask {
+            apply_mask(&mut payload, key);
+        }
+        Ok(Some((
+            Frame {
+                fin,
+                rsv1,
+                opcode,
+                mask,
+                payload,
+            },
+            end,
+        )))
+    }
+}
+
+/// XOR `data` with the masking key; applying it twice restores the input
+pub fn apply_mask(data: &mut [u8], key: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[i % 4];
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: CloseCode,
+    pub reason: String,
+}
+
+/// A complete message; fragments are reassembled before they are returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// `None` when the peer closed without a status code
+    Close(Option<CloseFrame>),
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Message::Text(text.to_string())
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Message::Text(text)
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(data: Vec<u8>) -> Self {
+        Message::Binary(data)
+    }
+}
+
+/// largest message `recv` accepts by default
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+/// data frames `send` produces are at most this long by default
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// Server side of an upgraded WebSocket connection. Pings are answered
+/// automatically and still returned from `recv`; the close handshake is
+/// completed when the peer starts it. Use `split` to send from other tasks
+/// while one task receives.
+pub struct WebSocket {
+    sender: WsSender,
+    receiver: WsReceiver,
+}
+
+impl WebSocket {
+    /// a socket without extensions
+    pub fn from_upgraded(io: Box<dyn Upgraded>) -> Self {
+        Self::with_extensions(io, None)
+    }
+
+    /// a socket using the extensions `handshake` agreed to, given as the
+    /// `Sec-WebSocket-Extensions` value it answered with
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    pub fn with_extensions(io: Box<dyn Upgraded>, extensions: Option<&str>) -> Self {
+        #[cfg(feature = "compression")]
+        let deflate = extensions.and_then(deflate::PerMessageDeflate::from_response);
+        let (read, write) = tokio::io::split(io);
+        let sender = WsSender {
+            writer: Arc::new(Mutex::new(Writer {
+                io: write,
+                closed: false,
+                #[cfg(feature = "compression")]
+                deflater: deflate.as_ref().map(deflate::Deflater::new),
+            })),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        };
+        WebSocket {
+            receiver: WsReceiver {
+                io: read,
+                buffer: Vec::new(),
+                partial: None,
+                closed: false,
+                max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+                #[cfg(feature = "compression")]
+                inflater: deflate.as_ref().map(deflate::Inflater::new),
+                sender: sender.clone(),
+            },
+            sender,
+        }
+    }
+
+    /// bigger incoming messages close the connection with 1009
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.receiver.max_message_size = size;
+    }
+
+    /// outgoing text and binary messages are fragmented above this size
+    pub fn set_max_frame_size(&mut self, size: usize) {
+        self.sender.max_frame_size = size.max(1);
+        self.receiver.sender.max_frame_size = size.max(1);
+    }
+
+    pub async fn send(&mut self, message: impl Into<Message>) -> std::io::Result<()> {
+        self.sender.send(message).await
+    }
+
+    /// the next message, or `None` once the connection is closed
+    pub async fn recv(&mut self) -> std::io::Result<Option<Message>> {
+        self.receiver.recv().await
+    }
+
+    /// Start the close handshake and wait for the peer's close frame,
+    /// discarding any messages still in flight.
+    pub async fn close(&mut self, code: CloseCode, reason: &str) -> std::io::Result<()> {
+        self.sender.close(code, reason).await?;
+        while self.receiver.recv().await?.is_some() {}
+        Ok(())
+    }
+
+    pub fn split(self) -> (WsSender, WsReceiver) {
+        (self.sender, self.receiver)
+    }
+}
+
+struct Writer {
+    io: WriteHalf<Box<dyn Upgraded>>,
+    /// a close frame went out; nothing may follow it
+    closed: bool,
+    #[cfg(feature = "compression")]
+    deflater: Option<deflate::Deflater>,
+}
+
+/// Sending half of a `WebSocket`. Clones share the connection.
+#[derive(Clone)]
+pub struct WsSender {
+    writer: Arc<Mutex<Writer>>,
+    max_frame_size: usize,
+}
+
+impl WsSender {
+    pub async fn send(&self, message: impl Into<Message>) -> std::io::Result<()> {
+        let (opcode, data) = match message.into() {
+            Message::Text(text) => (OpCode::Text, text.into_bytes()),
+            Message::Binary(data) => (OpCode::Binary, data),
+            Message::Ping(data) => return self.write_frames(vec![control_frame(OpCode::Ping, data)?]).await,
+            Message::Pong(data) => return self.write_frames(vec![control_frame(OpCode::Pong, data)?]).await,
+            Message::Close(frame) => return self.write_frames(vec![close_frame(frame.as_ref())?]).await,
+        };
+        let mut writer = self.writer.lock().await;
+        // compressed under the lock: the peer inflates in the order we send
+        #[cfg(feature = "compression")]
+        if let Some(deflater) = writer.deflater.as_mut() {
+            let mut frames = self.fragment(opcode, deflater.compress(&data)?);
+            frames[0].rsv1 = true;
+            return Self::write_locked(&mut writer, &frames).await;
+        }
+        Self::write_locked(&mut writer, &self.fragment(opcode, data)).await
+    }
+
+    /// send a close frame; the peer is expected to answer with its own
+    pub async fn close(&self, code: CloseCode, reason: &str) -> std::io::Result<()> {
+        self.send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.to_string(),
+        })))
+        .await
+    }
+
+    fn fragment(&self, opcode: OpCode, data: Vec<u8>) -> Vec<Frame> {
+        if data.len() <= self.max_frame_size {
+            return vec![Frame::new(opcode, data)];
+        }
+        let chunks: Vec<&[u8]> = data.chunks(self.max_frame_size).collect();
+        let last = chunks.len() - 1;
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| Frame {
+                fin: i == last,
+                opcode: if i == 0 { opcode } else { OpCode::Continuation },
+                ..Frame::new(opcode, chunk.to_vec())
+            })
+            .collect()
+    }
+
+    async fn write_frames(&self, frames: Vec<Frame>) -> std::io::Result<()> {
+        Self::write_locked(&mut *self.writer.lock().await, &frames).await
+    }
+
+    async fn write_locked(writer: &mut Writer, frames: &[Frame]) -> std::io::Result<()> {
+        if writer.closed {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "WebSocket is closed",
+            ));
+        }
+        for frame in frames {
+            writer.io.write_all(&frame.encode()).await?;
+            if frame.opcode == OpCode::Close {
+                writer.closed = true;
+            }
+        }
+        writer.io.flush().await
+    }
+
+    async fn is_closed(&self) -> bool {
+        self.writer.lock().await.closed
+    }
+}
+
+fn control_frame(opcode: OpCode, payload: Vec<u8>) -> std::io::Result<Frame> {
+    if payload.len() > 125 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "control frame payloads are limited to 125 bytes",
+        ));
+    }
+    Ok(Frame::new(opcode, payload))
+}
+
+fn close_frame(frame: Option<&CloseFrame>) -> std::io::Result<Frame> {
+    let payload = match frame {
+        Some(frame) => {
+            let mut payload = frame.code.0.to_be_bytes().to_vec();
+            payload.extend_from_slice(frame.reason.as_bytes());
+            payload
+        }
+        None => Vec::new(),
+    };
+    control_frame(OpCode::Close, payload)
+}
+
+/// Receiving half of a `WebSocket`. Answers pings and close frames through
+/// the shared sender.
+pub struct WsReceiver {
+    io: ReadHalf<Box<dyn Upgraded>>,
+    buffer: Vec<u8>,
+    /// opcode, payload and whether it is compressed, for a fragmented
+    /// message still being received
+    partial: Option<(OpCode, Vec<u8>, bool)>,
+    closed: bool,
+    max_message_size: usize,
+    #[cfg(feature = "compression")]
+    inflater: Option<deflate::Inflater>,
+    sender: WsSender,
+}
+
+impl WsReceiver {
+    /// the next message, or `None` once the connection is closed
+    pub async fn recv(&mut self) -> std::io::Result<Option<Message>> {
+        while !self.closed {
+            let Some(frame) = self.read_frame().await? else {
+                self.closed = true;
+                return Ok(None);
+            };
+            if frame.mask.is_none() {
+                return Err(self.fail(CloseCode::PROTOCOL_ERROR, "unmasked client frame").await);
+            }
+            if frame.rsv1 && !(self.compressed() && matches!(frame.opcode, OpCode::Text | OpCode::Binary)) {
+                return Err(self.fail(CloseCode::PROTOCOL_ERROR, "unexpected reserved bit").await);
+            }
+            match frame.opcode {
+                OpCode::Ping => {
+                    // the pong may race a close we sent; that is not an error
+                    if !self.sender.is_closed().await {
+                        self.sender.send(Message::Pong(frame.payload.clone())).await?;
+                    }
+                    return Ok(Some(Message::Ping(frame.payload)));
+                }
+                OpCode::Pong => return Ok(Some(Message::Pong(frame.payload))),
+                OpCode::Close => return self.on_close(frame.payload).await.map(Some),
+                OpCode::Text | OpCode::Binary if self.partial.is_some() => {
+                    return Err(self.fail(CloseCode::PROTOCOL_ERROR, "expected a continuation frame").await);
+                }
+                OpCode::Continuation if self.partial.is_none() => {
+                    return Err(self.fail(CloseCode::PROTOCOL_ERROR, "unexpected continuation frame").await);
+                }
+                OpCode::Text | OpCode::Binary => self.partial = Some((frame.opcode, frame.payload, frame.rsv1)),
+                OpCode::Continuation => {
+                    let (_, data, _) = self.partial.as_mut().unwrap();
+                    if data.len() + frame.payload.len() > self.max_message_size {
+                        return Err(self.fail(CloseCode::MESSAGE_TOO_BIG, "message too big").await);
+                    }
+                    data.extend_from_slice(&frame.payload);
+                }
+            }
+            if frame.fin {
+                let (opcode, data, compressed) = self.partial.take().unwrap();
+                let data = if compressed { self.inflate(data).await? } else { data };
+                return match opcode {
+                    OpCode::Text => match String::from_utf8(data) {
+                        Ok(text) => Ok(Some(Message::Text(text))),
+                        Err(_) => Err(self.fail(CloseCode::INVALID_DATA, "text is not valid UTF-8").await),
+                    },
+                    _ => Ok(Some(Message::Binary(data))),
+                };
+            }
+        }
+        Ok(None)
+    }
+
+    /// whether permessage-deflate was negotiated
+    fn compressed(&self) -> bool {
+        #[cfg(feature = "compression")]
+        return self.inflater.is_some();
+        #[cfg(not(feature = "compression"))]
+        false
+    }
+
+    #[cfg(feature = "compression")]
+    async fn inflate(&mut self, data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        let inflater = self.inflater.as_mut().expect("compressed frames need permessage-deflate");
+        match inflater.decompress(&data, self.max_message_size) {
+            Ok(data) => Ok(data),
+            Err(e) if e.kind() == std::io::ErrorKind::FileTooLarge => {
+                Err(self.fail(CloseCode::MESSAGE_TOO_BIG, "message too big").await)
+            }
+            Err(_) => Err(self.fail(CloseCode::INVALID_DATA, "invalid compressed data").await),
+        }
+    }
+
+    /// never called: frames can't be marked compressed without permessage-deflate
+    #[cfg(not(feature = "compression"))]
+    async fn inflate(&mut self, data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        Ok(data)
+    }
+
+    /// `None` at EOF
+    async fn read_frame(&mut self) -> std::io::Result<Option<Frame>> {
+        loop {
+            match Frame::parse(&self.buffer, self.max_message_size) {
+                Ok(Some((frame, used))) => {
+                    self.buffer.drain(..used);
+                    return Ok(Some(frame));
+                }
+                Ok(None) => {}
+                Err(code) => return Err(self.fail(code, "invalid frame").await),
+            }
+            let mut chunk = [0; 8192];
+            let n = self.io.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// the peer's close frame: echo its code unless we closed first
+    async fn on_close(&mut self, payload: Vec<u8>) -> std::io::Result<Message> {
+        let frame = match payload.len() {
+            0 => None,
+            1 => return Err(self.fail(CloseCode::PROTOCOL_ERROR, "truncated close code").await),
+            _ => {
+                let code = CloseCode(u16::from_be_bytes([payload[0], payload[1]]));
+                if !code.is_valid() {
+                    return Err(self.fail(CloseCode::PROTOCOL_ERROR, "invalid close code").await);
+                }
+                let Ok(reason) = String::from_utf8(payload[2..].to_vec()) else {
+                    return Err(self.fail(CloseCode::INVALID_DATA, "close reason is not valid UTF-8").await);
+                };
+                Some(CloseFrame { code, reason })
+            }
+        };
+        self.closed = true;
+        if !self.sender.is_closed().await {
+            let code = frame.as_ref().map_or(CloseCode::NORMAL, |f| f.code);
+            self.sender.close(code, "").await?;
+        }
+        Ok(Message::Close(frame))
+    }
+
+    /// close the connection for a protocol violation, returning the error for `recv`
+    async fn fail(&mut self, code: CloseCode, reason: &str) -> std::io::Error {
+        self.closed = true;
+        if !self.sender.is_closed().await {
+            let _ = self.sender.close(code, reason).await;
+        }
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} ({})", reason, code))
+    }
+}
@@ -0,0 +1,181 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+/// every compressed message ends with an empty stored block, which is
+/// stripped on the wire (RFC 7692 7.2.1)
+const TAIL: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF];
+
+/// Negotiated `permessage-deflate` parameters (RFC 7692). "Server" and
+/// "client" name the sending side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerMessageDeflate {
+    /// reset the compressor after every message we send
+    pub server_no_context_takeover: bool,
+    /// the client resets its compressor after every message
+    pub client_no_context_takeover: bool,
+    /// LZ77 window of the client's compressor, 8 to 15
+    pub client_max_window_bits: u8,
+}
+
+impl PerMessageDeflate {
+    /// Pick the first acceptable offer from a client's
+    /// `Sec-WebSocket-Extensions`. Offers limiting our window below 15 bits
+    /// are declined, as the deflate backend always uses the full window.
+    pub fn negotiate(offers: &str) -> Option<Self> {
+        offers
+            .split(',')
+            .filter_map(|offer| {
+                let mut parts = offer.split(';').map(str::trim);
+                (parts.next()? == "permessage-deflate").then_some(parts)
+            })
+            .find_map(|params| Self::from_params(params, true))
+    }
+
+    /// the parameters our `Sec-WebSocket-Extensions` response header
+    /// settled on, e.g. to configure a socket after `handshake`
+    pub fn from_response(header: &str) -> Option<Self> {
+        let mut parts = header.split(';').map(str::trim);
+        if parts.next()? != "permessage-deflate" {
+            return None;
+        }
+        Self::from_params(parts, false)
+    }
+
+    fn from_params<'a>(params: impl Iterator<Item = &'a str>, offer: bool) -> Option<Self> {
+        let mut config = PerMessageDeflate {
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+            client_max_window_bits: 15,
+        };
+        let mut seen = Vec::new();
+        for param in params.filter(|p| !p.is_empty()) {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+            if seen.contains(&name) {
+                return None;
+            }
+            seen.push(name);
+            let bits = |value: Option<&str>| value?.parse::<u8>().ok().filter(|b| (8..=15).contains(b));
+            match (name, value) {
+                ("server_no_context_takeover", None) => config.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => config.client_no_context_takeover = true,
+                ("server_max_window_bits", value) => {
+                    if bits(value)? < 15 {
+                        return None;
+                    }
+                }
+                // a bare client_max_window_bits in an offer only says the
+                // client supports the parameter
+                ("client_max_window_bits", None) if offer => {}
+                ("client_max_window_bits", value) => config.client_max_window_bits = bits(value)?,
+                _ => return None,
+            }
+        }
+        Some(config)
+    }
+
+    /// value for the `Sec-WebSocket-Extensions` response header
+    pub fn response_header(&self) -> String {
+        let mut header = String::from("permessage-deflate");
+        if self.server_no_context_takeover {
+            header.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            header.push_str("; client_no_context_takeover");
+        }
+        if self.client_max_window_bits < 15 {
+            header.push_str(&format!("; client_max_window_bits={}", self.client_max_window_bits));
+        }
+        header
+    }
+}
+
+/// compressor for outgoing messages
+pub(crate) struct Deflater {
+    compress: Compress,
+    no_context_takeover: bool,
+}
+
+impl Deflater {
+    pub(crate) fn new(config: &PerMessageDeflate) -> Self {
+        Deflater {
+            compress: Compress::new(Compression::default(), false),
+            no_context_takeover: config.server_no_context_takeover,
+        }
+    }
+
+    pub(crate) fn compress(&mut self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let start = self.compress.total_in();
+        let mut out = Vec::with_capacity(data.len() / 2 + 64);
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            self.compress
+                .compress_vec(&data[consumed..], &mut out, FlushCompress::Sync)
+                .map_err(std::io::Error::other)?;
+            // the flush is complete once all input is in and output room is left
+            if (self.compress.total_in() - start) as usize == data.len() && out.len() < out.capacity() {
+                break;
+            }
+            out.reserve(out.capacity().max(64));
+        }
+        if out.ends_with(&TAIL) {
+            out.truncate(out.len() - TAIL.len());
+        }
+        if self.no_context_takeover {
+            self.compress.reset();
+        }
+        Ok(out)
+    }
+}
+
+/// decompressor for incoming messages
+pub(crate) struct Inflater {
+    decompress: Decompress,
+    no_context_takeover: bool,
+}
+
+impl Inflater {
+    pub(crate) fn new(config: &PerMessageDeflate) -> Self {
+        Inflater {
+            decompress: Decompress::new(false),
+            no_context_takeover: config.client_no_context_takeover,
+        }
+    }
+
+    /// Fails with `FileTooLarge` past `limit` bytes and `InvalidData` for
+    /// corrupt input.
+    pub(crate) fn decompress(&mut self, data: &[u8], limit: usize) -> std::io::Result<Vec<u8>> {
+        let mut input = Vec::with_capacity(data.len() + TAIL.len());
+        input.extend_from_slice(data);
+        input.extend_from_slice(&TAIL);
+
+        let start = self.decompress.total_in();
+        let mut out = Vec::with_capacity((data.len() * 4).clamp(64, limit.max(64)));
+        loop {
+            let consumed = (self.decompress.total_in() - start) as usize;
+            let status = self
+                .decompress
+                .decompress_vec(&input[consumed..], &mut out, FlushDecompress::Sync)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            if out.len() > limit {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::FileTooLarge,
+                    "decompressed message exceeds the limit",
+                ));
+            }
+            let done = (self.decompress.total_in() - start) as usize == input.len() && out.len() < out.capacity();
+            if done || status == Status::StreamEnd {
+                break;
+            }
+            if status == Status::BufError && out.len() < out.capacity() {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "truncated deflate data"));
+            }
+            out.reserve(out.capacity().min(limit + 1 - out.len()).max(64));
+        }
+        if self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+        Ok(out)
+    }
+}
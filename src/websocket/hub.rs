@@ -0,0 +1,180 @@
+use crate::websocket::{CloseCode, Message, WsSender};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// identifies a socket registered with a `Hub`
+pub type ClientId = u64;
+
+/// messages queued per client by default before it counts as too slow
+pub const DEFAULT_QUEUE_SIZE: usize = 64;
+
+/// Tracks connected sockets and the rooms they joined, for chat and
+/// notification fan-out. Every client gets a bounded send queue drained by
+/// its own task, so one slow reader can't hold up a broadcast; a client
+/// whose queue is full is closed with 1008 and removed.
+#[derive(Clone)]
+pub struct Hub {
+    state: Arc<Mutex<HubState>>,
+    queue_size: usize,
+}
+
+#[derive(Default)]
+struct HubState {
+    next_id: ClientId,
+    clients: HashMap<ClientId, Client>,
+    rooms: HashMap<String, HashSet<ClientId>>,
+}
+
+struct Client {
+    queue: mpsc::Sender<Message>,
+    sender: WsSender,
+    rooms: HashSet<String>,
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Self::with_queue_size(DEFAULT_QUEUE_SIZE)
+    }
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_queue_size(queue_size: usize) -> Self {
+        Hub {
+            state: Arc::new(Mutex::new(HubState::default())),
+            queue_size: queue_size.max(1),
+        }
+    }
+
+    /// Register a socket and start draining its queue into it. Call
+    /// `disconnect` when its receive loop ends.
+    pub fn connect(&self, sender: WsSender) -> ClientId {
+        let (queue, mut pending) = mpsc::channel::<Message>(self.queue_size);
+        let socket = sender.clone();
+        tokio::spawn(async move {
+            while let Some(message) = pending.recv().await {
+                if socket.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.clients.insert(
+            id,
+            Client {
+                queue,
+                sender,
+                rooms: HashSet::new(),
+            },
+        );
+        id
+    }
+
+    /// forget `id` and leave all its rooms; the socket itself stays open
+    pub fn disconnect(&self, id: ClientId) {
+        self.state.lock().unwrap().remove(id);
+    }
+
+    /// whether `id` is still registered, i.e. not disconnected or dropped for being slow
+    pub fn is_connected(&self, id: ClientId) -> bool {
+        self.state.lock().unwrap().clients.contains_key(&id)
+    }
+
+    pub fn join(&self, id: ClientId, room: &str) {
+        let mut state = self.state.lock().unwrap();
+        let Some(client) = state.clients.get_mut(&id) else {
+            return;
+        };
+        client.rooms.insert(room.to_string());
+        state.rooms.entry(room.to_string()).or_default().insert(id);
+    }
+
+    pub fn leave(&self, id: ClientId, room: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(client) = state.clients.get_mut(&id) {
+            client.rooms.remove(room);
+        }
+        state.leave(id, room);
+    }
+
+    /// clients in `room`, in no particular order
+    pub fn members(&self, room: &str) -> Vec<ClientId> {
+        let state = self.state.lock().unwrap();
+        state.rooms.get(room).map_or_else(Vec::new, |ids| ids.iter().copied().collect())
+    }
+
+    /// number of connected clients
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// queue `message` for one client; false if it is gone or was too slow
+    pub fn send_to(&self, id: ClientId, message: impl Into<Message>) -> bool {
+        self.deliver(&[id], message.into()) == 1
+    }
+
+    /// queue `message` for every member of `room`, returning how many got it
+    pub fn broadcast_to(&self, room: &str, message: impl Into<Message>) -> usize {
+        let members = self.members(room);
+        self.deliver(&members, message.into())
+    }
+
+    /// queue `message` for every connected client, returning how many got it
+    pub fn broadcast(&self, message: impl Into<Message>) -> usize {
+        let ids: Vec<ClientId> = self.state.lock().unwrap().clients.keys().copied().collect();
+        self.deliver(&ids, message.into())
+    }
+
+    fn deliver(&self, ids: &[ClientId], message: Message) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let mut delivered = 0;
+        for id in ids {
+            let Some(client) = state.clients.get(id) else {
+                continue;
+            };
+            match client.queue.try_send(message.clone()) {
+                Ok(()) => delivered += 1,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    if let Some(client) = state.remove(*id) {
+                        tokio::spawn(async move {
+                            let _ = client.sender.close(CloseCode::POLICY_VIOLATION, "too slow").await;
+                        });
+                    }
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    state.remove(*id);
+                }
+            }
+        }
+        delivered
+    }
+}
+
+impl HubState {
+    fn remove(&mut self, id: ClientId) -> Option<Client> {
+        let client = self.clients.remove(&id)?;
+        for room in &client.rooms {
+            self.leave(id, room);
+        }
+        Some(client)
+    }
+
+    fn leave(&mut self, id: ClientId, room: &str) {
+        if let Some(members) = self.rooms.get_mut(room) {
+            members.remove(&id);
+            if members.is_empty() {
+                self.rooms.remove(room);
+            }
+        }
+    }
+}
@@ -41,11 +41,12 @@ fn test_router_with_params() {
         version: web::models::http::HTTPVersion::HTTP1_1,
         headers: HashMap::new(),
         body: None,
+        ..Default::default()
     };
 
     let mut res = HTTPResponse::default();
 
-    router.handle(HTTPMethod::GET, &req, &mut res).unwrap();
+    router.handle(&HTTPMethod::GET, &req, &mut res).unwrap();
     assert_eq!(res.body, Some("Post 42".to_string()));
 }
 
@@ -55,4 +56,5291 @@ fn test_http_request_parsing() {
     let req = HTTPRequest::new(request_str.to_string());
     assert_eq!(req.method, HTTPMethod::GET);
     assert_eq!(req.url, "/posts/123?name=test");
-}
\ No newline at end of file
+}
+#[cfg(feature = "http2")]
+#[tokio::test]
+async fn test_http2_stream_dispatch() {
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/posts/{id}".to_string()), |req, res, pattern| {
+        let path_params = req.path_params(pattern).unwrap();
+        res.body = Some(format!("Post {} over {:?}", path_params["id"], req.version));
+    });
+
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let pipeline = web::middleware::Pipeline::new(std::sync::Arc::new(router));
+    tokio::spawn(web::http2::serve_connection(server_io, std::sync::Arc::new(pipeline)));
+
+    let (mut client, connection) = h2::client::handshake(client_io).await.unwrap();
+    tokio::spawn(connection);
+
+    let request = http::Request::get("http://localhost/posts/7").body(()).unwrap();
+    let (response, _) = client.send_request(request, true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    let mut body = response.into_body();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(String::from_utf8(data).unwrap(), "Post 7 over HTTP2");
+}
+
+#[test]
+fn test_typed_headers() {
+    use web::models::headers::{EntityTag, MediaType};
+    use web::models::http::HTTPHeaderType;
+
+    let request_str = "POST /posts HTTP/1.1\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: 2\r\nIf-None-Match: W/\"a\", \"b\"\r\n\r\n{}";
+    let req = HTTPRequest::new(request_str.to_string());
+    assert_eq!(req.content_length(), Some(2));
+    let content_type = req.content_type().unwrap();
+    assert_eq!(content_type.essence(), "application/json");
+    assert_eq!(content_type.charset(), Some("utf-8"));
+    assert_eq!(req.if_none_match(), vec![EntityTag::weak("a"), EntityTag::strong("b")]);
+    assert!("text".parse::<MediaType>().is_err());
+
+    let mut res = HTTPResponse::default();
+    res.set_location("/posts/1");
+    res.set_etag(&EntityTag::strong("v1"));
+    assert_eq!(res.headers.get(&HTTPHeaderType::Location), Some(&"/posts/1".to_string()));
+    assert_eq!(res.header(&HTTPHeaderType::ETag), Some("\"v1\""));
+}
+
+#[test]
+fn test_preserve_raw_headers() {
+    let request_str = "GET /echo HTTP/1.1\r\nX-Trace-ID: abc\r\nhost: localhost\r\nX-TRACE-id: def\r\n\r\n";
+    let req = HTTPRequest::new_preserving_headers(request_str.to_string());
+    assert_eq!(
+        req.raw_headers,
+        vec![
+            ("X-Trace-ID".to_string(), "abc".to_string()),
+            ("host".to_string(), "localhost".to_string()),
+            ("X-TRACE-id".to_string(), "def".to_string()),
+        ]
+    );
+    assert_eq!(
+        req.head_to_string(),
+        "GET /echo HTTP/1.1\r\nX-Trace-ID: abc\r\nhost: localhost\r\nX-TRACE-id: def\r\n\r\n"
+    );
+    assert!(HTTPRequest::new(request_str.to_string()).raw_headers.is_empty());
+}
+
+#[cfg(feature = "http2")]
+async fn start_h2c_server(port: i32) {
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/hello".to_string()), |req, res, _pattern| {
+        res.body = Some(format!("hello over {:?}", req.version));
+    });
+    let config = web::config::ServerConfig {
+        h2c: true,
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::new(port, router, HashMap::new()).with_config(config);
+    tokio::spawn(async move { server.start().await });
+}
+
+#[cfg(feature = "http2")]
+async fn connect(port: i32) -> tokio::net::TcpStream {
+    for _ in 0..50 {
+        if let Ok(stream) = tokio::net::TcpStream::connect(format!("127.0.0.1:{}", port)).await {
+            return stream;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("server on port {} did not start", port);
+}
+
+#[cfg(feature = "http2")]
+#[tokio::test]
+async fn test_h2c_prior_knowledge() {
+    start_h2c_server(38431).await;
+    let stream = connect(38431).await;
+
+    let (mut client, connection) = h2::client::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let request = http::Request::get("http://localhost/hello").body(()).unwrap();
+    let (response, _) = client.send_request(request, true).unwrap();
+    let mut body = response.await.unwrap().into_body();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(String::from_utf8(data).unwrap(), "hello over HTTP2");
+}
+
+#[cfg(feature = "http2")]
+#[tokio::test]
+async fn test_h2c_upgrade_answers_on_stream_one() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    start_h2c_server(38432).await;
+    let mut stream = connect(38432).await;
+    stream
+        .write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAARAAAAAAAIAAAAA\r\n\r\n")
+        .await
+        .unwrap();
+    let mut switching = vec![0; web::http2::SWITCHING_PROTOCOLS.len()];
+    stream.read_exact(&mut switching).await.unwrap();
+    assert_eq!(switching, web::http2::SWITCHING_PROTOCOLS);
+
+    // preface + empty SETTINGS, then look for a DATA frame on stream 1
+    stream.write_all(web::http2::PREFACE).await.unwrap();
+    stream.write_all(&[0, 0, 0, 0x4, 0, 0, 0, 0, 0]).await.unwrap();
+    loop {
+        let mut header = [0; 9];
+        stream.read_exact(&mut header).await.unwrap();
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let stream_id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+        let mut payload = vec![0; length];
+        stream.read_exact(&mut payload).await.unwrap();
+        if header[3] == 0x4 && header[4] & 0x1 == 0 {
+            // ack the server SETTINGS
+            stream.write_all(&[0, 0, 0, 0x4, 0x1, 0, 0, 0, 0]).await.unwrap();
+        }
+        if header[3] == 0x0 && stream_id == 1 {
+            assert_eq!(String::from_utf8(payload).unwrap(), "hello over HTTP2");
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_canonical_host_redirect() {
+    use web::middleware::canonical_host::CanonicalHost;
+    use web::middleware::Pipeline;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/users".to_string()), |_req, res, _pattern| {
+        res.body = Some("users".to_string());
+    });
+    let pipeline = Pipeline::with_layers(
+        std::sync::Arc::new(router),
+        vec![std::sync::Arc::new(CanonicalHost::new("example.com", "https"))],
+    );
+
+    let req = HTTPRequest::new("GET /users?page=2 HTTP/1.1\r\nHost: www.example.com\r\n\r\n".to_string());
+    let res = pipeline.dispatch(req).await;
+    assert_eq!(res.status, HTTPStatus::MovedPermanently);
+    assert_eq!(res.header(&HTTPHeaderType::Location), Some("https://example.com/users?page=2"));
+
+    let req = HTTPRequest::new("GET /users HTTP/1.1\r\nHost: Example.com:8443\r\n\r\n".to_string());
+    let res = pipeline.dispatch(req).await;
+    assert_eq!(res.status, HTTPStatus::Ok);
+    assert_eq!(res.body, Some("users".to_string()));
+}
+
+#[test]
+fn test_retry_transient_errors() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use web::models::error::HTTPError;
+    use web::models::http::HTTPStatus;
+    use web::retry::RetryPolicy;
+
+    let attempts = Arc::new(AtomicU32::new(0));
+    let counter = Arc::clone(&attempts);
+    let retry = RetryPolicy::new(2);
+    let mut router = Router::new();
+    router.try_bind(
+        (HTTPMethod::GET, "/flaky".to_string()),
+        retry.wrap(move |_req, res, _pattern| {
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                res.body = Some("partial".to_string());
+                return Err(HTTPError::transient("store unavailable"));
+            }
+            res.body = Some("ok".to_string());
+            Ok(())
+        }),
+    );
+    router.try_bind(
+        (HTTPMethod::POST, "/flaky".to_string()),
+        retry.wrap(|_req, _res, _pattern| Err(HTTPError::transient("store unavailable"))),
+    );
+
+    let req = HTTPRequest::new("GET /flaky HTTP/1.1\r\n\r\n".to_string());
+    let mut res = HTTPResponse::default();
+    router.handle(&HTTPMethod::GET, &req, &mut res).unwrap();
+    assert_eq!(res.body, Some("ok".to_string()));
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+    // POST is not idempotent, so it fails straight away
+    let req = HTTPRequest::new("POST /flaky HTTP/1.1\r\n\r\n".to_string());
+    let err = router.handle(&HTTPMethod::POST, &req, &mut res).unwrap_err();
+    assert_eq!(err.status, HTTPStatus::InternalServerError);
+
+    let stats = retry.stats();
+    assert_eq!((stats.calls(), stats.retries(), stats.recovered(), stats.exhausted()), (2, 1, 1, 0));
+}
+
+#[cfg(feature = "metrics")]
+#[tokio::test]
+async fn test_drain_long_lived_connections() {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use web::stats::{DrainPolicy, LongLivedKind, ServerStats};
+
+    let stats = Arc::new(ServerStats::default());
+    let socket = stats.register(LongLivedKind::WebSocket);
+    let events = stats.register(LongLivedKind::EventStream);
+    let _connection = stats.connection();
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.active_connections, 1);
+    assert_eq!(snapshot.long_lived.get(&LongLivedKind::WebSocket), Some(&1));
+
+    // nothing has been idle for an hour yet
+    let idle = DrainPolicy {
+        idle_for: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    };
+    assert_eq!(stats.drain(&idle), 0);
+
+    let websockets = DrainPolicy {
+        kind: Some(LongLivedKind::WebSocket),
+        ..Default::default()
+    };
+    assert_eq!(stats.drain(&websockets), 1);
+    tokio::time::timeout(Duration::from_secs(1), socket.closed()).await.unwrap();
+    assert!(!events.is_closing());
+
+    drop(socket);
+    assert_eq!(stats.snapshot().long_lived.get(&LongLivedKind::WebSocket), None);
+}
+
+#[cfg(all(feature = "metrics", feature = "ws"))]
+#[tokio::test]
+async fn test_drain_served_connections() {
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::sse::{Event, Sse};
+    use web::stats::{DrainPolicy, LongLivedKind};
+    use web::websocket::{Frame, Message, OpCode};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind_ws("/ws", |mut ws, _req| async move {
+        while let Ok(Some(message)) = ws.recv().await {
+            if let Message::Text(text) = message {
+                ws.send(text).await.unwrap();
+            }
+        }
+    });
+    router.bind((HTTPMethod::GET, "/events".to_string()), |_req, res, _pattern| {
+        let (events, rx) = tokio::sync::mpsc::channel(8);
+        tokio::spawn(async move {
+            events.send(Event::new("hello")).await.unwrap();
+            // never ends by itself
+            events.closed().await;
+        });
+        Sse::from_channel(rx).with_keep_alive(None).respond(res);
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    let stats = server.stats();
+    tokio::spawn(async move { server.start().await });
+
+    let mut socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+    socket
+        .write_all(b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n")
+        .await
+        .unwrap();
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(socket.read_u8().await.unwrap());
+    }
+    assert!(head.starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+    let frame = Frame { mask: Some([1, 2, 3, 4]), ..Frame::new(OpCode::Text, b"ping".to_vec()) };
+    socket.write_all(&frame.encode()).await.unwrap();
+    let mut echo = [0; 6];
+    socket.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo[2..], b"ping");
+
+    let mut events = tokio::net::TcpStream::connect(addr).await.unwrap();
+    events.write_all(b"GET /events HTTP/1.1\r\n\r\n").await.unwrap();
+    let mut received = Vec::new();
+    while !received.ends_with(b"data: hello\n\n") {
+        received.push(events.read_u8().await.unwrap());
+    }
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.long_lived.get(&LongLivedKind::WebSocket), Some(&1));
+    assert_eq!(snapshot.long_lived.get(&LongLivedKind::EventStream), Some(&1));
+    // both just had traffic
+    let idle = DrainPolicy {
+        idle_for: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    };
+    assert_eq!(stats.drain(&idle), 0);
+
+    let event_streams = DrainPolicy {
+        kind: Some(LongLivedKind::EventStream),
+        ..Default::default()
+    };
+    assert_eq!(stats.drain(&event_streams), 1);
+    let mut rest = Vec::new();
+    tokio::time::timeout(Duration::from_secs(1), events.read_to_end(&mut rest)).await.unwrap().unwrap();
+    assert_eq!(stats.drain(&DrainPolicy::default()), 1);
+    let read = tokio::time::timeout(Duration::from_secs(1), socket.read(&mut [0; 16])).await.unwrap();
+    assert_eq!(read.unwrap(), 0);
+    // the registration goes just after the connection does
+    for _ in 0..100 {
+        if stats.snapshot().long_lived.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(stats.snapshot().long_lived.is_empty());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_serve_on_prebound_listener() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/ping".to_string()), |_req, res, _pattern| {
+        res.body = Some("pong".to_string());
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("pong"));
+}
+
+#[cfg(all(feature = "server", unix))]
+#[tokio::test]
+async fn test_multiple_listeners() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let socket_path = std::env::temp_dir().join(format!("web-test-{}.sock", std::process::id()));
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/ping".to_string()), |_req, res, _pattern| {
+        res.body = Some("pong".to_string());
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .add_unix_listener(&socket_path);
+    tokio::spawn(async move { server.start().await });
+
+    let mut tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    tcp.write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    tcp.read_to_string(&mut response).await.unwrap();
+    assert!(response.ends_with("pong"));
+
+    let mut unix = None;
+    for _ in 0..50 {
+        if let Ok(stream) = tokio::net::UnixStream::connect(&socket_path).await {
+            unix = Some(stream);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let mut unix = unix.expect("unix listener did not start");
+    unix.write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    unix.read_to_string(&mut response).await.unwrap();
+    assert!(response.ends_with("pong"));
+    let _ = std::fs::remove_file(&socket_path);
+}
+
+#[test]
+fn test_default_bodies_and_teapot() {
+    use web::models::http::HTTPStatus;
+
+    assert_eq!(HTTPStatus::NotFound.default_body(), "404 Not Found");
+
+    let mut router = Router::new();
+    router.enable_teapot();
+    let req = HTTPRequest::new("GET /teapot HTTP/1.1\r\n\r\n".to_string());
+    let res = router.route(&req);
+    assert_eq!(res.status, HTTPStatus::ImATeapot);
+    assert_eq!(res.body, Some("418 I'm a teapot".to_string()));
+
+    router.on_error(|_req, err| HTTPResponse::error(err.status.clone(), "short and stout"));
+    let res = router.route(&req);
+    assert_eq!(res.status, HTTPStatus::ImATeapot);
+    assert_eq!(res.body, Some("short and stout".to_string()));
+}
+
+#[tokio::test]
+async fn test_https_redirect() {
+    use web::middleware::https_redirect::HttpsRedirect;
+    use web::middleware::Pipeline;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+
+    let pipeline = Pipeline::with_layers(
+        std::sync::Arc::new(Router::new()),
+        vec![std::sync::Arc::new(HttpsRedirect::new(8443))],
+    );
+    let req = HTTPRequest::new("GET /a/b?c=d HTTP/1.1\r\nHost: example.com:8080\r\n\r\n".to_string());
+    let res = pipeline.dispatch(req).await;
+    assert_eq!(res.status, HTTPStatus::MovedPermanently);
+    assert_eq!(res.header(&HTTPHeaderType::Location), Some("https://example.com:8443/a/b?c=d"));
+
+    let redirect = HttpsRedirect::new(443);
+    let req = HTTPRequest::new("GET / HTTP/1.1\r\nHost: [::1]\r\n\r\n".to_string());
+    assert_eq!(redirect.location(&req), Some("https://[::1]/".to_string()));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_load_shedding_when_saturated() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = web::config::ServerConfig {
+        max_connections: Some(1),
+        load_shed_retry_after: Some(5),
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::from_listener(listener, Router::new(), HashMap::new())
+        .unwrap()
+        .with_config(config);
+    tokio::spawn(async move { server.start().await });
+
+    // holds the only slot: connected but never sends a request
+    let _idle = tokio::net::TcpStream::connect(addr).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(response.contains("Retry-After: 5\r\n"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_slow_clients_time_out() {
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = web::config::ServerConfig {
+        first_byte_timeout: Some(Duration::from_millis(100)),
+        header_read_timeout: Some(Duration::from_millis(100)),
+        body_read_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::from_listener(listener, Router::new(), HashMap::new())
+        .unwrap()
+        .with_config(config);
+    tokio::spawn(async move { server.start().await });
+
+    // never sends anything: closed without a response
+    let mut idle = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(2), idle.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(response.is_empty());
+
+    // head trickles in too slowly
+    let mut slow_head = tokio::net::TcpStream::connect(addr).await.unwrap();
+    slow_head.write_all(b"GET / HTTP/1.1\r\nHost: local").await.unwrap();
+    let mut response = String::new();
+    slow_head.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+
+    // body never completes
+    let mut slow_body = tokio::net::TcpStream::connect(addr).await.unwrap();
+    slow_body
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    slow_body.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_request_size_limits() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::POST, "/".to_string()), |req, res, _pattern| {
+        res.body = req.body.clone();
+    });
+    let config = web::config::ServerConfig {
+        max_header_bytes: Some(64),
+        max_body_bytes: Some(8),
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .with_config(config);
+    tokio::spawn(async move { server.start().await });
+
+    let send = |request: String| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    let response = send("POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nping".to_string()).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("ping"));
+
+    let response = send(format!("POST / HTTP/1.1\r\nCookie: {}\r\n\r\n", "a".repeat(100))).await;
+    assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+    let response = send("POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\n123456789".to_string()).await;
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    // a chunked body counts by what it decodes to, not its framing
+    let chunked = |body: &str| format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{}0\r\n\r\n", body);
+    let response = send(chunked("4;x=y\r\n1234\r\n4\r\n5678\r\n")).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n") && response.ends_with("12345678"), "{}", response);
+    let response = send(chunked("4\r\n1234\r\n5\r\n56789\r\n")).await;
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{}", response);
+
+    // limited out of the box too
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = web::httpserver::HTTPServer::from_listener(listener, Router::new(), HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    let send = |request: String| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+    let cookie = "a".repeat(web::config::DEFAULT_MAX_HEADER_BYTES);
+    let response = send(format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", cookie)).await;
+    assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"), "{}", response);
+    let length = web::config::DEFAULT_MAX_BODY_BYTES + 1;
+    let response = send(format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", length)).await;
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{}", response);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_response_buffering_policy() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::models::http::Buffering;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/page".to_string()), |_req, res, _pattern| {
+        res.body = Some("buffered".to_string());
+    });
+    router.bind((HTTPMethod::GET, "/feed".to_string()), |_req, res, _pattern| {
+        res.body = Some("streamed".to_string());
+    });
+    router.buffering((HTTPMethod::GET, "/feed".to_string()), Buffering::Streamed);
+    router.bind((HTTPMethod::GET, "/override".to_string()), |_req, res, _pattern| {
+        res.set_buffering(Buffering::Streamed);
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let get = |path: &'static str| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    let response = get("/page").await;
+    assert!(response.contains("Content-Length: 8\r\n"));
+    assert!(response.ends_with("\r\n\r\nbuffered"));
+
+    let response = get("/feed").await;
+    assert!(!response.contains("Content-Length"));
+    assert!(response.contains("Connection: close\r\n"));
+    assert!(response.ends_with("\r\n\r\nstreamed"));
+
+    let response = get("/override").await;
+    assert!(!response.contains("Content-Length"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_connection_info() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/whoami".to_string()), |req, res, _pattern| {
+        let info = req.connection_info().unwrap();
+        res.body = Some(format!(
+            "{} {} {} {}",
+            req.peer_addr().unwrap(),
+            info.local_addr.unwrap(),
+            info.version,
+            req.is_tls()
+        ));
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let peer = stream.local_addr().unwrap();
+    stream.write_all(b"GET /whoami HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.ends_with(&format!("{} {} HTTP/1.1 false", peer, addr)));
+
+    assert!(HTTPRequest::new("GET / HTTP/1.1\r\n\r\n".to_string()).connection_info().is_none());
+}
+
+#[cfg(feature = "sessions")]
+#[test]
+fn test_login_session_cookie() {
+    use web::auth::LoginManager;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::session::Sessions;
+
+    let logins = LoginManager::new(Sessions::new(b"0123456789abcdef0123456789abcdef"));
+    let mut router = Router::new();
+    let guard = logins.clone();
+    router.try_bind(
+        (HTTPMethod::GET, "/account".to_string()),
+        logins.require_login(move |req, res, _pattern| {
+            res.body = guard.current_user(req);
+            Ok(())
+        }),
+    );
+
+    let mut res = HTTPResponse::default();
+    let req = HTTPRequest::new("POST /login HTTP/1.1\r\n\r\n".to_string());
+    logins.login(&req, &mut res, "alice");
+    let set_cookie = res.header(&HTTPHeaderType::SetCookie).unwrap().to_string();
+    assert!(set_cookie.contains("HttpOnly"));
+    let cookie = set_cookie.split(';').next().unwrap();
+
+    let req = HTTPRequest::new(format!("GET /account HTTP/1.1\r\nCookie: theme=dark; {}\r\n\r\n", cookie));
+    let res = router.route(&req);
+    assert_eq!(res.body, Some("alice".to_string()));
+
+    // a tampered cookie is treated as anonymous
+    let forged = cookie.replacen('.', "x.", 1);
+    let req = HTTPRequest::new(format!("GET /account HTTP/1.1\r\nCookie: {}\r\n\r\n", forged));
+    assert_eq!(logins.current_user(&req), None);
+    assert_eq!(router.route(&req).status, HTTPStatus::Unauthorized);
+
+    let req = HTTPRequest::new("GET /account?tab=1 HTTP/1.1\r\nAccept: text/html\r\n\r\n".to_string());
+    let res = router.route(&req);
+    assert_eq!(res.status, HTTPStatus::SeeOther);
+    assert_eq!(res.header(&HTTPHeaderType::Location), Some("/login?next=%2Faccount%3Ftab%3D1"));
+
+    let mut res = HTTPResponse::default();
+    logins.logout(&mut res);
+    assert!(res.header(&HTTPHeaderType::SetCookie).unwrap().contains("Max-Age=0"));
+}
+
+#[test]
+fn test_route_content_type_allowlist() {
+    use web::models::http::HTTPStatus;
+
+    let mut router = Router::new();
+    let route = (HTTPMethod::POST, "/users".to_string());
+    router.bind(route.clone(), |_req, res, _pattern| {
+        res.body = Some("created".to_string());
+    });
+    router.accept(route.clone(), &["application/json", "text/*"]).unwrap();
+    assert_eq!(router.accepted_types(&route).unwrap()[0].essence(), "application/json");
+
+    let req = HTTPRequest::new("POST /users HTTP/1.1\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: 2\r\n\r\n{}".to_string());
+    assert_eq!(router.route(&req).body, Some("created".to_string()));
+
+    let req = HTTPRequest::new("POST /users HTTP/1.1\r\nContent-Type: text/csv\r\nContent-Length: 3\r\n\r\na,b".to_string());
+    assert_eq!(router.route(&req).status, HTTPStatus::Ok);
+
+    let req = HTTPRequest::new("POST /users HTTP/1.1\r\nContent-Type: application/xml\r\nContent-Length: 4\r\n\r\n<a/>".to_string());
+    let res = router.route(&req);
+    assert_eq!(res.status, HTTPStatus::UnsupportedMediaType);
+    assert_eq!(res.body, Some("Expected one of: application/json, text/*".to_string()));
+
+    let req = HTTPRequest::new("POST /users HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}".to_string());
+    assert_eq!(router.route(&req).status, HTTPStatus::UnsupportedMediaType);
+
+    let req = HTTPRequest::new("POST /users HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!(router.route(&req).status, HTTPStatus::Ok);
+}
+
+#[test]
+fn test_seeded_store() {
+    use web::store::Store;
+
+    let store = Store::seeded(["alice", "bob"]);
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get(2), Some("bob"));
+    assert_eq!(store.insert("carol"), 3);
+    assert!(store.update(1, "alicia"));
+    assert!(!store.update(9, "nobody"));
+    assert_eq!(store.remove(2), Some("bob"));
+    assert_eq!(store.list(), vec![(1, "alicia"), (3, "carol")]);
+}
+
+#[tokio::test]
+async fn test_real_ip_behind_trusted_proxies() {
+    use std::net::IpAddr;
+    use web::middleware::real_ip::{RealIp, TrustedProxies};
+    use web::middleware::Pipeline;
+    use web::models::connection::ConnectionInfo;
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/".to_string()), |req, res, _pattern| {
+        res.body = req.real_ip().map(|ip| ip.to_string());
+    });
+    let proxies = TrustedProxies::new(&["10.0.0.0/8", "::1"]).unwrap();
+    let pipeline = Pipeline::with_layers(
+        std::sync::Arc::new(router),
+        vec![std::sync::Arc::new(RealIp::new(proxies))],
+    );
+    let request = |peer: &str, headers: &str| {
+        let mut req = HTTPRequest::new(format!("GET / HTTP/1.1\r\n{}\r\n", headers));
+        req.connection = Some(ConnectionInfo {
+            peer_addr: Some(peer.parse().unwrap()),
+            ..Default::default()
+        });
+        req
+    };
+    let resolve = |req| async { pipeline.dispatch(req).await.body.unwrap() };
+
+    // untrusted peers can't claim another address
+    let req = request("203.0.113.9:4000", "X-Forwarded-For: 1.1.1.1\r\n");
+    assert_eq!(resolve(req).await, "203.0.113.9");
+
+    // spoofed leftmost entry is skipped, the first untrusted hop wins
+    let req = request("10.0.0.2:4000", "X-Forwarded-For: 1.1.1.1, 198.51.100.7, 10.0.0.5\r\n");
+    assert_eq!(resolve(req).await, "198.51.100.7");
+
+    let req = request("[::1]:4000", "Forwarded: for=\"[2001:db8::1]:4711\";proto=https\r\n");
+    assert_eq!(resolve(req).await, "2001:db8::1");
+
+    let req = request("10.1.2.3:4000", "X-Real-IP: 192.0.2.44\r\n");
+    assert_eq!(resolve(req).await, "192.0.2.44");
+
+    let req = request("10.1.2.3:4000", "");
+    assert_eq!(resolve(req).await, "10.1.2.3");
+
+    let cidr: web::middleware::real_ip::Cidr = "192.168.0.0/16".parse().unwrap();
+    assert!(cidr.contains("192.168.40.1".parse::<IpAddr>().unwrap()));
+    assert!(cidr.contains("::ffff:192.168.0.1".parse::<IpAddr>().unwrap()));
+    assert!(!cidr.contains("192.169.0.1".parse::<IpAddr>().unwrap()));
+    assert!("10.0.0.0/33".parse::<web::middleware::real_ip::Cidr>().is_err());
+}
+
+#[tokio::test]
+async fn test_request_extensions() {
+    use web::middleware::{BoxFuture, Middleware, Next, Pipeline};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct CurrentUser(String);
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct RequestId(u64);
+
+    struct Authenticate;
+    impl Middleware for Authenticate {
+        fn handle<'a>(&'a self, mut req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+            req.extensions.insert(RequestId(7));
+            if let Some(user) = req.query_params().get("as") {
+                req.extensions.insert(CurrentUser(user.clone()));
+            }
+            next.run(req)
+        }
+    }
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/me".to_string()), |req, res, _pattern| {
+        let id = req.extensions.get::<RequestId>().unwrap();
+        res.body = Some(match req.extensions.get::<CurrentUser>() {
+            Some(CurrentUser(name)) => format!("{} #{}", name, id.0),
+            None => format!("anonymous #{}", id.0),
+        });
+    });
+    let pipeline = Pipeline::with_layers(std::sync::Arc::new(router), vec![std::sync::Arc::new(Authenticate)]);
+
+    let req = HTTPRequest::new("GET /me?as=alice HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!(pipeline.dispatch(req).await.body, Some("alice #7".to_string()));
+    let req = HTTPRequest::new("GET /me HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!(pipeline.dispatch(req).await.body, Some("anonymous #7".to_string()));
+
+    let mut extensions = web::models::extensions::Extensions::new();
+    assert_eq!(extensions.insert(RequestId(1)), None);
+    assert_eq!(extensions.insert(RequestId(2)), Some(RequestId(1)));
+    extensions.get_mut::<RequestId>().unwrap().0 += 1;
+    let copy = extensions.clone();
+    assert_eq!(extensions.remove::<RequestId>(), Some(RequestId(3)));
+    assert!(extensions.is_empty());
+    assert_eq!(copy.get::<RequestId>(), Some(&RequestId(3)));
+}
+
+#[test]
+fn test_static_files_with_ranges() {
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+
+    let root = std::env::temp_dir().join(format!("web-static-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("media")).unwrap();
+    let data: Vec<u8> = (0..=255).collect();
+    std::fs::write(root.join("media/clip.mp4"), &data).unwrap();
+    std::fs::write(root.join("index.html"), "<h1>home</h1>").unwrap();
+
+    let mut router = Router::new();
+    router.serve_dir("/static", &root);
+    let get = |path: &str, range: Option<&str>| {
+        let range = range.map(|r| format!("Range: {}\r\n", r)).unwrap_or_default();
+        router.route(&HTTPRequest::new(format!("GET {} HTTP/1.1\r\n{}\r\n", path, range)))
+    };
+
+    let res = get("/static/media/clip.mp4", None);
+    assert_eq!(res.status, HTTPStatus::Ok);
+    assert_eq!(res.body_bytes(), &data[..]);
+    assert_eq!(res.header(&HTTPHeaderType::ContentType), Some("video/mp4"));
+    assert_eq!(res.header(&HTTPHeaderType::AcceptRanges), Some("bytes"));
+
+    let res = get("/static/media/clip.mp4", Some("bytes=10-19"));
+    assert_eq!(res.status, HTTPStatus::PartialContent);
+    assert_eq!(res.body_bytes(), &data[10..20]);
+    assert_eq!(res.header(&HTTPHeaderType::ContentRange), Some("bytes 10-19/256"));
+
+    let res = get("/static/media/clip.mp4", Some("bytes=-6"));
+    assert_eq!(res.body_bytes(), &data[250..]);
+    assert_eq!(res.header(&HTTPHeaderType::ContentRange), Some("bytes 250-255/256"));
+
+    let res = get("/static/media/clip.mp4", Some("bytes=200-"));
+    assert_eq!(res.body_bytes().len(), 56);
+
+    let res = get("/static/media/clip.mp4", Some("bytes=300-400"));
+    assert_eq!(res.status, HTTPStatus::RangeNotSatisfiable);
+    assert_eq!(res.header(&HTTPHeaderType::ContentRange), Some("bytes */256"));
+
+    let etag = res.header(&HTTPHeaderType::ETag).unwrap().to_string();
+    let res = router.route(&HTTPRequest::new(format!(
+        "GET /static/media/clip.mp4 HTTP/1.1\r\nIf-None-Match: {}\r\n\r\n",
+        etag
+    )));
+    assert_eq!(res.status, HTTPStatus::NotModified);
+    // weak validators can't satisfy If-Range, so the whole file is sent
+    let res = router.route(&HTTPRequest::new(format!(
+        "GET /static/media/clip.mp4 HTTP/1.1\r\nRange: bytes=0-1\r\nIf-Range: {}\r\n\r\n",
+        etag
+    )));
+    assert_eq!(res.status, HTTPStatus::Ok);
+
+    let res = get("/static/", None);
+    assert_eq!(res.body_bytes(), b"<h1>home</h1>");
+    assert_eq!(get("/static/../Cargo.toml", None).status, HTTPStatus::NotFound);
+    assert_eq!(get("/static/%2e%2e/Cargo.toml", None).status, HTTPStatus::NotFound);
+    assert_eq!(get("/static/missing.txt", None).status, HTTPStatus::NotFound);
+
+    // big files and ranges stream from disk instead of being read whole
+    #[cfg(feature = "server")]
+    {
+        let big: Vec<u8> = (0..200 * 1024).map(|i| (i % 251) as u8).collect();
+        std::fs::write(root.join("media/big.bin"), &big).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let mut res = get("/static/media/big.bin", None);
+        assert!(res.body_stream.is_set() && res.body_bytes().is_empty());
+        assert_eq!(res.content_length(), Some(big.len() as u64));
+        runtime.block_on(res.collect_body());
+        assert!(res.body_bytes() == &big[..]);
+        let mut res = get("/static/media/big.bin", Some("bytes=1000-150999"));
+        assert_eq!(res.status, HTTPStatus::PartialContent);
+        assert_eq!(res.content_length(), Some(150_000));
+        runtime.block_on(res.collect_body());
+        assert!(res.body_bytes() == &big[1000..151_000]);
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_conditional_requests() {
+    use std::time::{Duration, UNIX_EPOCH};
+    use web::conditional::{evaluate, ConditionalGet, Precondition};
+    use web::middleware::Pipeline;
+    use web::models::headers::EntityTag;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+
+    let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/doc".to_string()), move |_req, res, _pattern| {
+        res.body = Some("v1".to_string());
+        res.set_etag(&EntityTag::strong("v1"));
+        res.set_last_modified(modified);
+    });
+    let pipeline = Pipeline::with_layers(std::sync::Arc::new(router), vec![std::sync::Arc::new(ConditionalGet)]);
+    let get = |headers: &str| {
+        let req = HTTPRequest::new(format!("GET /doc HTTP/1.1\r\n{}\r\n", headers));
+        pipeline.dispatch(req)
+    };
+
+    let res = get("If-None-Match: \"v0\", W/\"v1\"\r\n").await;
+    assert_eq!(res.status, HTTPStatus::NotModified);
+    assert_eq!(res.body_bytes(), b"");
+    assert_eq!(res.header(&HTTPHeaderType::ETag), Some("\"v1\""));
+
+    assert_eq!(get("If-None-Match: \"v0\"\r\n").await.status, HTTPStatus::Ok);
+    assert_eq!(get("If-Modified-Since: Tue, 14 Nov 2023 22:13:20 GMT\r\n").await.status, HTTPStatus::NotModified);
+    assert_eq!(get("If-Modified-Since: Tue, 14 Nov 2023 22:13:19 GMT\r\n").await.status, HTTPStatus::Ok);
+    // If-None-Match takes precedence over If-Modified-Since
+    let res = get("If-None-Match: \"v0\"\r\nIf-Modified-Since: Tue, 14 Nov 2023 22:13:20 GMT\r\n").await;
+    assert_eq!(res.status, HTTPStatus::Ok);
+    assert_eq!(get("If-Match: \"v2\"\r\n").await.status, HTTPStatus::PreconditionFailed);
+
+    // unsafe methods: evaluated by the handler before changing anything
+    let etag = EntityTag::strong("v1");
+    let put = |headers: &str| HTTPRequest::new(format!("PUT /doc HTTP/1.1\r\n{}\r\n", headers));
+    assert_eq!(evaluate(&put("If-Match: \"v1\"\r\n"), Some(&etag), None), Precondition::Proceed);
+    assert_eq!(evaluate(&put("If-Match: W/\"v1\"\r\n"), Some(&etag), None), Precondition::Failed);
+    assert_eq!(evaluate(&put("If-Match: *\r\n"), None, None), Precondition::Failed);
+    assert_eq!(evaluate(&put("If-None-Match: *\r\n"), Some(&etag), None), Precondition::Failed);
+    assert_eq!(evaluate(&put("If-None-Match: *\r\n"), None, None), Precondition::Proceed);
+    let stale = put("If-Unmodified-Since: Tue, 14 Nov 2023 22:13:19 GMT\r\n");
+    assert_eq!(evaluate(&stale, Some(&etag), Some(modified)), Precondition::Failed);
+}
+
+#[test]
+fn test_mime_and_content_type_helpers() {
+    use web::mime::{ContentType, Mime};
+    use web::models::http::HTTPHeaderType;
+
+    assert_eq!(Mime::from_extension("PNG"), Some(Mime::IMAGE_PNG));
+    assert_eq!(Mime::from_path("assets/app.min.js"), Mime::TEXT_JAVASCRIPT);
+    assert_eq!(Mime::from_path("archive.unknown"), Mime::APPLICATION_OCTET_STREAM);
+    assert_eq!(Mime::TEXT_HTML.media_type().charset(), Some("utf-8"));
+
+    let content_type: ContentType = "application/json; charset=utf-8".parse().unwrap();
+    assert!(content_type.is(Mime::APPLICATION_JSON));
+    assert!(!content_type.is(Mime::TEXT_PLAIN));
+
+    let mut res = HTTPResponse::default();
+    res.set_content_type(Mime::APPLICATION_PDF);
+    assert_eq!(res.header(&HTTPHeaderType::ContentType), Some("application/pdf"));
+
+    res.json(&serde_json::json!({ "ok": true })).unwrap();
+    assert_eq!(res.body, Some("{\"ok\":true}".to_string()));
+    assert_eq!(res.content_type(), Some(ContentType::json().0));
+
+    res.html("<p>hi</p>");
+    assert_eq!(res.header(&HTTPHeaderType::ContentType), Some("text/html; charset=utf-8"));
+    res.text("hi");
+    assert!(ContentType::from(res.content_type().unwrap()).is(Mime::TEXT_PLAIN));
+}
+
+#[test]
+fn test_precompressed_static_files() {
+    use web::models::http::HTTPHeaderType;
+
+    let root = std::env::temp_dir().join(format!("web-precompressed-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("app.js"), "console.log('plain')").unwrap();
+    std::fs::write(root.join("app.js.gz"), b"gzip bytes").unwrap();
+    std::fs::write(root.join("app.js.br"), b"brotli bytes").unwrap();
+    std::fs::write(root.join("style.css"), "body { color: red }".repeat(20)).unwrap();
+
+    let mut router = Router::new();
+    router.serve_dir("/", &root);
+    let get = |path: &str, accept: &str| {
+        router.route(&HTTPRequest::new(format!("GET {} HTTP/1.1\r\nAccept-Encoding: {}\r\n\r\n", path, accept)))
+    };
+
+    let res = get("/app.js", "gzip, br");
+    assert_eq!(res.body_bytes(), b"brotli bytes");
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), Some("br"));
+    assert_eq!(res.header(&HTTPHeaderType::ContentType), Some("text/javascript; charset=utf-8"));
+    assert_eq!(res.header(&HTTPHeaderType::Vary), Some("Accept-Encoding"));
+
+    let res = get("/app.js", "gzip;q=1, br;q=0");
+    assert_eq!(res.body_bytes(), b"gzip bytes");
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), Some("gzip"));
+
+    let res = router.route(&HTTPRequest::new("GET /app.js HTTP/1.1\r\n\r\n".to_string()));
+    assert_eq!(res.body_bytes(), b"console.log('plain')");
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), None);
+    assert_eq!(res.header(&HTTPHeaderType::Vary), Some("Accept-Encoding"));
+
+    #[cfg(feature = "compression")]
+    {
+        use std::io::Read;
+        use web::static_files::StaticFiles;
+
+        let mut files = StaticFiles::new(&root);
+        files.compress = true;
+        let req = HTTPRequest::new("GET /style.css HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n".to_string());
+        let mut res = HTTPResponse::default();
+        files.serve(&req, &mut res, "/{*path}", "path").unwrap();
+        assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), Some("gzip"));
+        assert!(res.body_bytes().len() < 400);
+        let mut css = String::new();
+        flate2::read::GzDecoder::new(res.body_bytes()).read_to_string(&mut css).unwrap();
+        assert_eq!(css, "body { color: red }".repeat(20));
+
+        // too big to gzip in memory: sent as it is
+        std::fs::write(root.join("big.css"), "a { b: c }\n".repeat(100_000)).unwrap();
+        let req = HTTPRequest::new("GET /big.css HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n".to_string());
+        let mut res = HTTPResponse::default();
+        files.serve(&req, &mut res, "/{*path}", "path").unwrap();
+        assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), None);
+        assert_eq!(res.content_length(), Some(1_100_000));
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(feature = "compression")]
+#[tokio::test]
+async fn test_response_compression() {
+    use web::compression::Coding;
+    use web::middleware::compression::Compression;
+    use web::middleware::Pipeline;
+    use web::models::http::HTTPHeaderType;
+
+    let page = "<p>hello</p>".repeat(200);
+    let mut router = Router::new();
+    let body = page.clone();
+    router.bind(
+        (HTTPMethod::GET, "/page".to_string()),
+        move |_req, res, _pattern| {
+            res.html(body.as_str());
+            res.set_header(HTTPHeaderType::ETag, "\"v1\"");
+        },
+    );
+    router.bind(
+        (HTTPMethod::GET, "/small".to_string()),
+        |_req, res, _pattern| {
+            res.text("tiny");
+        },
+    );
+    router.bind(
+        (HTTPMethod::GET, "/image".to_string()),
+        |_req, res, _pattern| {
+            res.set_raw_body(vec![0; 4096]);
+            res.set_content_type(web::mime::Mime::IMAGE_PNG);
+        },
+    );
+    let pipeline = Pipeline::with_layers(std::sync::Arc::new(router), vec![std::sync::Arc::new(Compression::new())]);
+    let get = |path: &str, accept: &str| {
+        pipeline.dispatch(HTTPRequest::new(format!(
+            "GET {} HTTP/1.1\r\nAccept-Encoding: {}\r\n\r\n",
+            path, accept
+        )))
+    };
+
+    let res = get("/page", "gzip;q=0.8, br").await;
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), Some("br"));
+    assert_eq!(res.header(&HTTPHeaderType::Vary), Some("Accept-Encoding"));
+    assert_eq!(res.header(&HTTPHeaderType::ETag), Some("W/\"v1\""));
+    assert!(res.body_bytes().len() < page.len());
+    assert_eq!(Coding::Brotli.decode(res.body_bytes(), 1 << 20).unwrap(), page.as_bytes());
+
+    let res = get("/page", "br;q=0.5, gzip, deflate").await;
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), Some("gzip"));
+    assert_eq!(Coding::Gzip.decode(res.body_bytes(), 1 << 20).unwrap(), page.as_bytes());
+
+    let res = get("/page", "identity").await;
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), None);
+    assert_eq!(res.header(&HTTPHeaderType::Vary), Some("Accept-Encoding"));
+    assert_eq!(res.body_bytes(), page.as_bytes());
+
+    let res = get("/page", "*;q=0").await;
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), None);
+
+    let res = get("/small", "gzip").await;
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), None);
+    assert_eq!(res.header(&HTTPHeaderType::Vary), None);
+
+    let res = get("/image", "gzip").await;
+    assert_eq!(res.header(&HTTPHeaderType::ContentEncoding), None);
+
+    assert_eq!(Compression::new().negotiate("deflate, *;q=0.1"), Some(Coding::Deflate));
+    assert!(Coding::Gzip.decode(&Coding::Gzip.encode(&[0; 10_000], 9).unwrap(), 1000).is_err());
+}
+
+#[cfg(all(feature = "server", feature = "compression"))]
+#[tokio::test]
+async fn test_request_decompression() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::compression::Coding;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::POST, "/".to_string()), |req, res, _pattern| {
+        res.body = req.body.clone();
+    });
+    let config = web::config::ServerConfig {
+        max_decompressed_body_bytes: Some(1000),
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .with_config(config);
+    tokio::spawn(async move { server.start().await });
+
+    let send = |encoding: &'static str, body: Vec<u8>| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let head = format!("POST / HTTP/1.1\r\nContent-Encoding: {}\r\nContent-Length: {}\r\n\r\n", encoding, body.len());
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(&body).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    let body = Coding::Gzip.encode(b"{\"name\":\"bob\"}", 6).unwrap();
+    let response = send("gzip", body).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("{\"name\":\"bob\"}"));
+
+    // applied deflate first, then br
+    let body = Coding::Brotli.encode(&Coding::Deflate.encode(b"layered", 6).unwrap(), 6).unwrap();
+    assert!(send("deflate, br", body).await.ends_with("layered"));
+
+    let bomb = Coding::Gzip.encode(&[b'a'; 100_000], 9).unwrap();
+    assert!(bomb.len() < 1000);
+    assert!(send("gzip", bomb).await.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    let response = send("compress", b"data".to_vec()).await;
+    assert!(response.starts_with("HTTP/1.1 415 Unsupported Media Type\r\n"));
+
+    let response = send("gzip", b"not gzip".to_vec()).await;
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
+
+#[cfg(feature = "ws")]
+#[tokio::test]
+async fn test_websocket_echo() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::websocket::{accept_key, Frame, Message, OpCode};
+
+    assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind_ws("/ws", |mut ws, _req| async move {
+        while let Ok(Some(message)) = ws.recv().await {
+            if let Message::Text(text) = message {
+                ws.send(text.to_uppercase()).await.unwrap();
+            }
+        }
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n")
+        .await
+        .unwrap();
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(stream.read_u8().await.unwrap());
+    }
+    let head = String::from_utf8(head).unwrap();
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+    assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+    async fn next_frame(stream: &mut tokio::net::TcpStream, buffer: &mut Vec<u8>) -> Frame {
+        loop {
+            if let Some((frame, used)) = Frame::parse(buffer, 1 << 20).unwrap() {
+                buffer.drain(..used);
+                return frame;
+            }
+            let mut chunk = [0; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed");
+            buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+    let masked = |fin: bool, opcode: OpCode, payload: &[u8]| {
+        Frame {
+            fin,
+            mask: Some([1, 2, 3, 4]),
+            ..Frame::new(opcode, payload.to_vec())
+        }
+        .encode()
+    };
+    let mut buffer = Vec::new();
+
+    // a fragmented message with a ping in between
+    let mut frames = masked(false, OpCode::Text, b"hel");
+    frames.extend(masked(true, OpCode::Ping, b"are you there"));
+    frames.extend(masked(true, OpCode::Continuation, b"lo"));
+    stream.write_all(&frames).await.unwrap();
+    assert_eq!(next_frame(&mut stream, &mut buffer).await, Frame::new(OpCode::Pong, b"are you there".to_vec()));
+    assert_eq!(next_frame(&mut stream, &mut buffer).await, Frame::new(OpCode::Text, b"HELLO".to_vec()));
+
+    stream.write_all(&masked(true, OpCode::Close, &[0x03, 0xE8, b'b', b'y', b'e'])).await.unwrap();
+    let close = next_frame(&mut stream, &mut buffer).await;
+    assert_eq!(close.opcode, OpCode::Close);
+    assert_eq!(close.payload, vec![0x03, 0xE8]);
+    assert_eq!(stream.read(&mut [0; 16]).await.unwrap(), 0);
+
+    // frames from clients must be masked
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: AAAAAAAAAAAAAAAAAAAAAA==\r\nSec-WebSocket-Version: 13\r\n\r\n")
+        .await
+        .unwrap();
+    stream.write_all(&Frame::new(OpCode::Text, b"hi".to_vec()).encode()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let body_start = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let (close, _) = Frame::parse(&response[body_start..], 1024).unwrap().unwrap();
+    assert_eq!(close.opcode, OpCode::Close);
+    assert_eq!(close.payload[..2], [0x03, 0xEA]);
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /ws HTTP/1.1\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 426 Upgrade Required\r\n"));
+    assert!(response.contains("Upgrade: websocket\r\n"));
+}
+
+#[cfg(all(feature = "ws", feature = "compression"))]
+#[tokio::test]
+async fn test_websocket_permessage_deflate() {
+    use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::websocket::deflate::PerMessageDeflate;
+    use web::websocket::{Frame, Message, OpCode};
+
+    // our window can't be shrunk, so that offer is passed over for the next
+    let negotiated = PerMessageDeflate::negotiate(
+        "permessage-deflate; server_max_window_bits=10, permessage-deflate; client_max_window_bits; server_no_context_takeover",
+    )
+    .unwrap();
+    assert!(negotiated.server_no_context_takeover);
+    assert_eq!(negotiated.response_header(), "permessage-deflate; server_no_context_takeover");
+    assert_eq!(PerMessageDeflate::negotiate("permessage-deflate; mystery=1"), None);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind_ws("/feed", |mut ws, _req| async move {
+        while let Ok(Some(message)) = ws.recv().await {
+            if let Message::Text(text) = message {
+                ws.send(text.repeat(3)).await.unwrap();
+            }
+        }
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /feed HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n")
+        .await
+        .unwrap();
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(stream.read_u8().await.unwrap());
+    }
+    let head = String::from_utf8(head).unwrap();
+    assert!(head.contains("Sec-WebSocket-Extensions: permessage-deflate\r\n"));
+
+    let mut compress = Compress::new(Compression::default(), false);
+    let mut decompress = Decompress::new(false);
+    let mut buffer = Vec::new();
+    for text in ["price update: 101.5", "price update: 101.5"] {
+        let mut payload = Vec::with_capacity(256);
+        compress.compress_vec(text.as_bytes(), &mut payload, FlushCompress::Sync).unwrap();
+        payload.truncate(payload.len() - 4);
+        let frame = Frame {
+            rsv1: true,
+            mask: Some([9, 8, 7, 6]),
+            ..Frame::new(OpCode::Text, payload)
+        };
+        stream.write_all(&frame.encode()).await.unwrap();
+
+        let reply = loop {
+            if let Some((frame, used)) = Frame::parse(&buffer, 1 << 20).unwrap() {
+                buffer.drain(..used);
+                break frame;
+            }
+            let mut chunk = [0; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0);
+            buffer.extend_from_slice(&chunk[..n]);
+        };
+        assert!(reply.rsv1);
+        assert!(reply.payload.len() < text.len() * 3);
+        let mut input = reply.payload.clone();
+        input.extend_from_slice(&[0, 0, 0xFF, 0xFF]);
+        let mut plain = Vec::with_capacity(1024);
+        decompress.decompress_vec(&input, &mut plain, FlushDecompress::Sync).unwrap();
+        assert_eq!(plain, text.repeat(3).as_bytes());
+    }
+}
+
+#[cfg(feature = "ws")]
+#[tokio::test]
+async fn test_websocket_hub_rooms() {
+    use tokio::io::{AsyncReadExt, DuplexStream};
+    use web::websocket::{Frame, Hub, OpCode, WebSocket};
+
+    async fn next_text(client: &mut DuplexStream) -> String {
+        let mut buffer = Vec::new();
+        loop {
+            if let Some((frame, _)) = Frame::parse(&buffer, 1 << 20).unwrap() {
+                assert_eq!(frame.opcode, OpCode::Text);
+                return String::from_utf8(frame.payload).unwrap();
+            }
+            buffer.push(client.read_u8().await.unwrap());
+        }
+    }
+
+    let hub = Hub::new();
+    let mut clients = Vec::new();
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        let (client, server) = tokio::io::duplex(4096);
+        let (sender, receiver) = WebSocket::from_upgraded(Box::new(server)).split();
+        ids.push(hub.connect(sender));
+        // keep the receiving half alive so the connection stays open
+        clients.push((client, receiver));
+    }
+    hub.join(ids[0], "news");
+    hub.join(ids[1], "news");
+    hub.join(ids[2], "sport");
+
+    assert_eq!(hub.broadcast_to("news", "headline"), 2);
+    assert_eq!(hub.broadcast("everyone"), 3);
+    assert_eq!(next_text(&mut clients[0].0).await, "headline");
+    assert_eq!(next_text(&mut clients[1].0).await, "headline");
+    assert_eq!(next_text(&mut clients[2].0).await, "everyone");
+    assert!(hub.send_to(ids[0], "direct"));
+
+    hub.leave(ids[1], "news");
+    assert_eq!(hub.members("news"), vec![ids[0]]);
+    hub.disconnect(ids[2]);
+    assert_eq!(hub.members("sport"), Vec::<u64>::new());
+    assert_eq!(hub.len(), 2);
+    assert!(!hub.send_to(ids[2], "gone"));
+
+    // a client that stops reading is dropped once its queue is full
+    let slow_hub = Hub::with_queue_size(2);
+    let (_client, server) = tokio::io::duplex(64);
+    let (sender, _receiver) = WebSocket::from_upgraded(Box::new(server)).split();
+    let slow = slow_hub.connect(sender);
+    let mut sent = 0;
+    while slow_hub.send_to(slow, "x".repeat(100)) {
+        sent += 1;
+        tokio::task::yield_now().await;
+    }
+    assert!(sent >= 2);
+    assert!(!slow_hub.is_connected(slow));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_server_sent_events() {
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::sse::{Event, History, Sse};
+
+    let event = Event::new("line one\nline two").with_event("update").with_id("7");
+    assert_eq!(event.to_string(), "event: update\nid: 7\ndata: line one\ndata: line two\n\n");
+    assert_eq!(Event::comment("hi").to_string(), ": hi\n\n");
+
+    let history = std::sync::Arc::new(History::new(2));
+    for n in 1..=3 {
+        history.record(Event::new(format!("tick {}", n)));
+    }
+    assert_eq!(history.since(None), vec![]);
+    assert_eq!(history.since(Some("2")), vec![Event::new("tick 3").with_id("3")]);
+    // id 1 was evicted, so everything still kept is replayed
+    assert_eq!(history.since(Some("1")).len(), 2);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    let replay = std::sync::Arc::clone(&history);
+    router.bind((HTTPMethod::GET, "/events".to_string()), move |req, res, _pattern| {
+        let (events, rx) = tokio::sync::mpsc::channel(8);
+        tokio::spawn(async move {
+            events.send(Event::new("first")).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            events.send(Event::new("second").with_event("late")).await.unwrap();
+        });
+        Sse::from_channel(rx)
+            .with_keep_alive(Some(Duration::from_millis(50)))
+            .with_retry(Duration::from_secs(3))
+            .with_replay(replay.since(req.last_event_id()))
+            .respond(res);
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /events HTTP/1.1\r\nLast-Event-ID: 2\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.lines().any(|line| line == "Content-Type: text/event-stream"));
+    assert!(head.lines().any(|line| line == "Cache-Control: no-cache"));
+    assert!(!head.contains("Content-Length"));
+    assert!(body.starts_with("retry: 3000\n\nid: 3\ndata: tick 3\n\ndata: first\n\n:\n\n"));
+    assert!(body.ends_with("event: late\ndata: second\n\n"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_reverse_proxy() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::proxy::ReverseProxy;
+
+    // the upstream answers in chunks and echoes what it was sent
+    let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.ends_with(b"hello") {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        let request = String::from_utf8(request).unwrap();
+        let (head, _) = request.split_once("\r\n\r\n").unwrap();
+        let echoed: String = head.lines().map(|line| format!("{}\n", line)).collect();
+        let response = format!(
+            "HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\nKeep-Alive: timeout=5\r\nX-Upstream: yes\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            echoed.len(),
+            echoed
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    // nothing listens on a port that was just released
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    assert!(ReverseProxy::new(Vec::<String>::new()).is_err());
+    assert!(ReverseProxy::new(["https://example.com"]).is_err());
+    let proxy = ReverseProxy::new([format!("http://{}/v1", upstream_addr)]).unwrap();
+    assert_eq!(proxy.upstreams()[0].base_path, "/v1");
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.proxy("/api", proxy);
+    router.proxy("/down", ReverseProxy::new([format!("http://{}", dead)]).unwrap());
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"POST /api/items?x=1 HTTP/1.1\r\nHost: example.test\r\nConnection: close, X-Hop\r\nX-Hop: 1\r\nContent-Length: 5\r\n\r\nhello")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 201 Created\r\n"));
+    assert!(head.lines().any(|line| line == "X-Upstream: yes"));
+    assert!(!head.contains("Transfer-Encoding") && !head.contains("Keep-Alive"));
+    assert!(body.starts_with("POST /v1/api/items?x=1 HTTP/1.1\n"));
+    let sent: Vec<&str> = body.lines().collect();
+    assert!(sent.contains(&format!("Host: {}", upstream_addr).as_str()));
+    assert!(sent.contains(&"X-Forwarded-For: 127.0.0.1"));
+    assert!(sent.contains(&"X-Forwarded-Host: example.test"));
+    assert!(sent.contains(&"X-Forwarded-Proto: http"));
+    assert!(sent.contains(&"Content-Length: 5"));
+    assert!(!body.contains("X-Hop"));
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /down/anything HTTP/1.1\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+
+    // binary uploads reach the upstream byte for byte
+    let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    let received = tokio::spawn(async move {
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.ends_with(&[0xff, 0x00, 0xfe]) {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+        request
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.proxy("/upload", ReverseProxy::new([format!("http://{}", upstream_addr)]).unwrap());
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"POST /upload HTTP/1.1\r\nContent-Length: 4\r\n\r\na\xff\x00\xfe").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", response);
+    let request = received.await.unwrap();
+    assert!(request.ends_with(b"\r\n\r\na\xff\x00\xfe"));
+
+    // header casing and order survive when the server keeps them
+    let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    let received = tokio::spawn(async move {
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.proxy("/cased", ReverseProxy::new([format!("http://{}", upstream_addr)]).unwrap());
+    let config = web::config::ServerConfig {
+        preserve_header_case: true,
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .with_config(config);
+    tokio::spawn(async move { server.start().await });
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /cased HTTP/1.1\r\nx-zeta: 1\r\nHost: a\r\nX-ALPHA: 2\r\nkeep-alive: 5\r\nx-Mid: 3\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", response);
+    let request = received.await.unwrap();
+    let forwarded: Vec<&str> = request.lines().filter(|line| line.to_ascii_lowercase().starts_with("x-")).collect();
+    assert_eq!(forwarded[..3], ["x-zeta: 1", "X-ALPHA: 2", "x-Mid: 3"], "{}", request);
+    assert!(!request.to_ascii_lowercase().contains("keep-alive"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_proxy_balancing_and_health() {
+    use std::time::Duration;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::proxy::{Balance, HashKey, HealthCheck, PassiveHealth, ReverseProxy};
+
+    // each upstream names itself; "b" fails its health check
+    let mut upstreams = Vec::new();
+    for name in ["a", "b"] {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        upstreams.push(format!("http://{}", listener.local_addr().unwrap()));
+        let mut router = Router::new();
+        router.bind((HTTPMethod::GET, "/who".to_string()), move |_req, res, _pattern| {
+            res.set_header(HTTPHeaderType::Other("X-Name".to_string()), name);
+        });
+        router.bind((HTTPMethod::GET, "/health".to_string()), move |_req, res, _pattern| {
+            if name == "b" {
+                res.status = HTTPStatus::InternalServerError;
+            }
+        });
+        let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+        tokio::spawn(async move { server.start().await });
+    }
+    let who = |key: &str| {
+        let mut req = HTTPRequest::new("GET /who HTTP/1.1\r\n\r\n".to_string());
+        req.headers.insert(HTTPHeaderType::Other("X-User".to_string()), key.to_string());
+        req
+    };
+    let name = |res: &HTTPResponse| res.header(&HTTPHeaderType::Other("X-Name".to_string())).unwrap().to_string();
+
+    let proxy = ReverseProxy::new(&upstreams).unwrap();
+    let mut seen = Vec::new();
+    for _ in 0..4 {
+        seen.push(name(&proxy.forward(who("u")).await));
+    }
+    assert_eq!(seen, ["a", "b", "a", "b"]);
+
+    let proxy = ReverseProxy::new(&upstreams)
+        .unwrap()
+        .with_balance(Balance::ConsistentHash(HashKey::Header(HTTPHeaderType::Other("X-User".to_string()))));
+    for key in ["alice", "bob", "carol"] {
+        let first = name(&proxy.forward(who(key)).await);
+        for _ in 0..3 {
+            assert_eq!(name(&proxy.forward(who(key)).await), first);
+        }
+    }
+
+    // a dead upstream is ejected after one failure and skipped until it may recover
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let proxy = ReverseProxy::new([format!("http://{}", dead), upstreams[0].clone()])
+        .unwrap()
+        .with_balance(Balance::LeastConnections)
+        .with_passive_health(PassiveHealth {
+            max_failures: 1,
+            eject_for: Duration::from_millis(200),
+        });
+    assert_eq!(proxy.forward(who("u")).await.status, HTTPStatus::BadGateway);
+    assert_eq!(proxy.healthy().len(), 1);
+    for _ in 0..3 {
+        assert_eq!(name(&proxy.forward(who("u")).await), "a");
+    }
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(proxy.healthy().len(), 2);
+
+    let proxy = ReverseProxy::new(&upstreams)
+        .unwrap()
+        .with_health_check(HealthCheck::new("/health").with_interval(Duration::from_millis(50)));
+    let checks = proxy.spawn_health_checks().unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(proxy.healthy(), vec![&proxy.upstreams()[0]]);
+    for _ in 0..3 {
+        assert_eq!(name(&proxy.forward(who("u")).await), "a");
+    }
+    checks.abort();
+}
+
+#[cfg(feature = "ws")]
+#[tokio::test]
+async fn test_proxy_websocket_passthrough() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::proxy::ReverseProxy;
+    use web::websocket::{Frame, Message, OpCode};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream = format!("http://{}", listener.local_addr().unwrap());
+    let mut router = Router::new();
+    router.bind_ws("/api/ws", |mut ws, req| async move {
+        let forwarded = req.header(&web::models::http::HTTPHeaderType::XForwardedFor).unwrap_or("").to_string();
+        while let Ok(Some(Message::Text(text))) = ws.recv().await {
+            ws.send(format!("{} via {}", text, forwarded)).await.unwrap();
+        }
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.proxy("/api", ReverseProxy::new([upstream]).unwrap());
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let hello = Frame {
+        mask: Some([9, 8, 7, 6]),
+        ..Frame::new(OpCode::Text, b"hello".to_vec())
+    }
+    .encode();
+    // the first frame travels with the handshake
+    let mut handshake = b"GET /api/ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n".to_vec();
+    handshake.extend_from_slice(&hello);
+    stream.write_all(&handshake).await.unwrap();
+
+    let mut buffer = Vec::new();
+    let head_end = loop {
+        if let Some(i) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert!(n > 0, "connection closed");
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+    let head = String::from_utf8(buffer.drain(..head_end).collect()).unwrap();
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+    assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    assert!(head.lines().any(|line| line == "Upgrade: websocket"));
+    assert!(head.lines().any(|line| line == "Connection: Upgrade"));
+
+    let frame = loop {
+        if let Some((frame, _)) = Frame::parse(&buffer, 1 << 20).unwrap() {
+            break frame;
+        }
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert!(n > 0, "connection closed");
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+    assert_eq!(frame.opcode, OpCode::Text);
+    assert_eq!(frame.payload, b"hello via 127.0.0.1");
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_connect_tunnel() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::models::http::HTTPHeaderType;
+    use web::proxy::ForwardProxy;
+
+    // the tunnel target echoes everything back
+    let target = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target_addr = target.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = target.accept().await.unwrap();
+        let (mut read, mut write) = stream.split();
+        tokio::io::copy(&mut read, &mut write).await.unwrap();
+    });
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.layer(
+        ForwardProxy::new()
+            .with_allowed_ports([target_addr.port()])
+            .with_auth("proxy", |req| req.header(&HTTPHeaderType::ProxyAuthorization) == Some("Basic dXNlcjpwYXNz")),
+    );
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    async fn connect(addr: std::net::SocketAddr, request: String) -> (String, tokio::net::TcpStream) {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        (String::from_utf8(head).unwrap(), stream)
+    }
+
+    let (head, _) = connect(addr, format!("CONNECT {} HTTP/1.1\r\nHost: {0}\r\n\r\n", target_addr)).await;
+    assert!(head.starts_with("HTTP/1.1 407 Proxy Authentication Required\r\n"));
+    assert!(head.contains("Proxy-Authenticate: Basic realm=\"proxy\"\r\n"));
+
+    let auth = "Proxy-Authorization: Basic dXNlcjpwYXNz\r\n";
+    let (head, _) = connect(addr, format!("CONNECT 127.0.0.1:25 HTTP/1.1\r\n{}\r\n", auth)).await;
+    assert!(head.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+
+    let (head, mut stream) = connect(addr, format!("CONNECT {} HTTP/1.1\r\n{}\r\n", target_addr, auth)).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(!head.contains("Content-Length"));
+    stream.write_all(b"ping").await.unwrap();
+    let mut echoed = [0u8; 4];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"ping");
+}
+
+#[cfg(all(feature = "client", feature = "tls"))]
+#[tokio::test]
+async fn test_http_client() {
+    use std::time::Duration;
+    use web::client::{ClientError, HTTPClient};
+    use web::models::body::ChannelStream;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::POST, "/echo".to_string()), |req, res, _pattern| {
+        let agent = req.header(&HTTPHeaderType::UserAgent).unwrap_or("").to_string();
+        res.set_header(HTTPHeaderType::Other("X-Agent".to_string()), agent);
+        res.body = Some(format!("{} {}", req.url, req.body.clone().unwrap_or_default()));
+    });
+    router.bind((HTTPMethod::GET, "/stream".to_string()), |_req, res, _pattern| {
+        let (chunks, rx) = tokio::sync::mpsc::channel(4);
+        tokio::spawn(async move {
+            for part in ["one ", "two ", "three"] {
+                chunks.send(part.as_bytes().to_vec()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+        res.body = None;
+        res.set_body_stream(ChannelStream::new(rx));
+    });
+    router.bind((HTTPMethod::GET, "/slow".to_string()), |_req, res, _pattern| {
+        res.defer(async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            HTTPResponse::default()
+        });
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let client = HTTPClient::builder().user_agent("tester").build();
+    let res = client
+        .post(&format!("http://{}/echo?x=1", addr))
+        .body("hello")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status, HTTPStatus::Ok);
+    assert_eq!(res.body.as_deref(), Some("/echo?x=1 hello"));
+    assert_eq!(res.header(&HTTPHeaderType::Other("X-Agent".to_string())), Some("tester"));
+
+    // streamed uploads go out chunked
+    let upload = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upload_addr = upload.local_addr().unwrap();
+    let received = tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (mut stream, _) = upload.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.ends_with(b"0\r\n\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    let (parts, rx) = tokio::sync::mpsc::channel(4);
+    tokio::spawn(async move {
+        parts.send(b"stre".to_vec()).await.unwrap();
+        parts.send(b"amed".to_vec()).await.unwrap();
+    });
+    let res = client
+        .put(&format!("http://{}/upload", upload_addr))
+        .body_stream(ChannelStream::new(rx))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status, HTTPStatus::NoContent);
+    let request = received.await.unwrap();
+    assert!(request.starts_with("PUT /upload HTTP/1.1\r\n"));
+    assert!(request.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(request.ends_with("\r\n\r\n4\r\nstre\r\n4\r\named\r\n0\r\n\r\n"));
+    // and the server decodes them
+    let (parts, rx) = tokio::sync::mpsc::channel(4);
+    tokio::spawn(async move {
+        parts.send(b"stre".to_vec()).await.unwrap();
+        parts.send(b"amed".to_vec()).await.unwrap();
+    });
+    let res = client
+        .post(&format!("http://{}/echo", addr))
+        .body_stream(ChannelStream::new(rx))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.body.as_deref(), Some("/echo streamed"));
+
+    let mut res = client.get(&format!("http://{}/stream", addr)).send_streaming().await.unwrap();
+    assert_eq!(res.chunk().await.as_deref(), Some(&b"one "[..]));
+    res.collect_body().await;
+    assert_eq!(res.body_bytes(), b"two three");
+
+    let timed_out = client
+        .get(&format!("http://{}/slow", addr))
+        .timeout(Duration::from_millis(100))
+        .send()
+        .await;
+    assert!(matches!(timed_out, Err(ClientError::Timeout)));
+    assert!(matches!(client.get("ftp://example.com/").send().await, Err(ClientError::InvalidUrl(_))));
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    assert!(matches!(client.get(&format!("http://{}/", dead)).send().await, Err(ClientError::Connect(_))));
+
+    // HTTPS against a server whose certificate a private CA signed
+    let tls = web::tls::TlsConfig::from_pem(
+        include_bytes!("data/localhost.pem"),
+        include_bytes!("data/localhost-key.pem"),
+    )
+    .unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/secure".to_string()), |req, res, _pattern| {
+        res.body = Some(format!("tls: {}", req.is_tls()));
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .with_tls(tls);
+    tokio::spawn(async move { server.start().await });
+
+    let url = format!("https://localhost:{}/secure", port);
+    assert!(matches!(client.get(&url).send().await, Err(ClientError::Connect(_))));
+    let trusting = HTTPClient::builder()
+        .add_root_certificates_pem(include_bytes!("data/ca.pem"))
+        .unwrap()
+        .build();
+    let res = trusting.get(&url).send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("tls: true"));
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+#[tokio::test]
+async fn test_client_redirects() {
+    use web::client::{ClientError, HTTPClient, RedirectPolicy};
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+
+    fn start(router: Router) -> std::net::SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+        tokio::spawn(async move { server.start().await });
+        addr
+    }
+    let describe = |req: &HTTPRequest, res: &mut HTTPResponse, _params: &web::router::PathParams| {
+        let auth = req.header(&HTTPHeaderType::Authorization).unwrap_or("-");
+        res.body = Some(format!("{} {} auth={}", req.method, req.body.clone().unwrap_or_default(), auth));
+    };
+
+    let mut other = Router::new();
+    other.bind((HTTPMethod::GET, "/landing".to_string()), describe);
+    let other = start(other);
+
+    let mut router = Router::new();
+    let redirect = |status: HTTPStatus, location: String| {
+        move |_req: &HTTPRequest, res: &mut HTTPResponse, _params: &web::router::PathParams| {
+            res.status = status.clone();
+            res.set_location(&location);
+        }
+    };
+    router.bind((HTTPMethod::POST, "/old/form".to_string()), redirect(HTTPStatus::Found, "landing".to_string()));
+    router.bind((HTTPMethod::POST, "/keep".to_string()), redirect(HTTPStatus::TemporaryRedirect, "/old/landing".to_string()));
+    router.bind((HTTPMethod::GET, "/away".to_string()), redirect(HTTPStatus::MovedPermanently, format!("http://{}/landing", other)));
+    router.bind((HTTPMethod::GET, "/loop".to_string()), redirect(HTTPStatus::Found, "/loop".to_string()));
+    router.bind((HTTPMethod::GET, "/old/landing".to_string()), describe);
+    router.bind((HTTPMethod::POST, "/old/landing".to_string()), describe);
+    let addr = start(router);
+
+    let client = HTTPClient::new();
+    // 302 after POST becomes a GET without the body, relative to the old path
+    let res = client.post(&format!("http://{}/old/form", addr)).body("data").send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("GET  auth=-"));
+    assert_eq!(res.redirects().len(), 1);
+    assert_eq!(res.redirects()[0].status, HTTPStatus::Found);
+    assert_eq!(res.redirects()[0].location, format!("http://{}/old/landing", addr));
+
+    // 307 repeats the method and body
+    let res = client.post(&format!("http://{}/keep", addr)).body("data").send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("POST data auth=-"));
+
+    // credentials stay on their origin
+    let res = client
+        .get(&format!("http://{}/away", addr))
+        .header(HTTPHeaderType::Authorization, "Bearer secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.body.as_deref(), Some("GET  auth=-"));
+    assert_eq!(res.redirects()[0].url, format!("http://{}/away", addr));
+
+    let looping = client.get(&format!("http://{}/loop", addr)).send().await;
+    assert!(matches!(looping, Err(ClientError::TooManyRedirects(10))));
+
+    let manual = HTTPClient::builder().redirect(RedirectPolicy::None).build();
+    let res = manual.get(&format!("http://{}/loop", addr)).send().await.unwrap();
+    assert_eq!(res.status, HTTPStatus::Found);
+    assert!(res.redirects().is_empty());
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+#[tokio::test]
+async fn test_client_retries() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use web::client::{ClientError, HTTPClient, Retry};
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+
+    // every third request succeeds, the others are 503 with Retry-After: 0
+    let hits = Arc::new(AtomicUsize::new(0));
+    let flaky = {
+        let hits = hits.clone();
+        move |req: &HTTPRequest, res: &mut HTTPResponse, _params: &web::router::PathParams| {
+            if hits.fetch_add(1, Ordering::SeqCst) % 3 == 2 {
+                res.body = Some(format!("ok {}", req.body.clone().unwrap_or_default()));
+            } else {
+                res.status = HTTPStatus::ServiceUnavailable;
+                res.set_header(HTTPHeaderType::RetryAfter, "0");
+            }
+        }
+    };
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/flaky".to_string()), flaky.clone());
+    router.bind((HTTPMethod::POST, "/flaky".to_string()), flaky);
+    router.bind((HTTPMethod::GET, "/busy".to_string()), |_req: &HTTPRequest, res: &mut HTTPResponse, _params: &web::router::PathParams| {
+        res.status = HTTPStatus::TooManyRequests;
+        res.set_header(HTTPHeaderType::RetryAfter, "60");
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let retry = Retry::new(3).with_base_delay(Duration::from_millis(10)).with_max_delay(Duration::from_secs(1));
+    let client = HTTPClient::builder().retry(retry.clone()).build();
+    let url = format!("http://{}/flaky", addr);
+
+    let res = client.get(&url).send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("ok "));
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    // POST isn't retried unless opted in
+    let res = client.post(&url).body("data").send().await.unwrap();
+    assert_eq!(res.status, HTTPStatus::ServiceUnavailable);
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+    let res = client.post(&url).body("data").retry(retry.clone().retry_non_idempotent()).send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("ok data"));
+    assert_eq!(hits.load(Ordering::SeqCst), 6);
+
+    // a Retry-After beyond max_delay gives up right away
+    let res = client.get(&format!("http://{}/busy", addr)).send().await.unwrap();
+    assert_eq!(res.status, HTTPStatus::TooManyRequests);
+
+    // connect errors are retried, then reported
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let started = std::time::Instant::now();
+    let err = client.post(&format!("http://{}/", closed)).send().await.unwrap_err();
+    assert!(matches!(err, ClientError::Connect(_)));
+    assert!(started.elapsed() >= Duration::from_millis(35));
+}
+
+#[cfg(all(feature = "client", feature = "tls"))]
+#[tokio::test]
+async fn test_client_proxy() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::client::{ClientError, HTTPClient, Proxy};
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::proxy::ForwardProxy;
+
+    // plain HTTP goes to the proxy in absolute form, credentials attached
+    let plain = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let plain_addr = plain.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = plain.accept().await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap();
+        let line = head.lines().next().unwrap().to_string();
+        let auth = head.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n");
+        let body = format!("{} auth={}", line, auth);
+        let res = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+        stream.write_all(res.as_bytes()).await.unwrap();
+    });
+    let client = HTTPClient::builder()
+        .proxy(Proxy::new(&format!("http://user:pass@{}", plain_addr)).unwrap())
+        .build();
+    let res = client.get("http://example.invalid:8080/a?b=c").send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("GET http://example.invalid:8080/a?b=c HTTP/1.1 auth=true"));
+
+    // HTTPS tunnels through CONNECT, here to a TLS server behind a ForwardProxy
+    let tls = web::tls::TlsConfig::from_pem(
+        include_bytes!("data/localhost.pem"),
+        include_bytes!("data/localhost-key.pem"),
+    )
+    .unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/secure".to_string()), |req, res, _pattern| {
+        res.body = Some(format!("tls: {}", req.is_tls()));
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .with_tls(tls);
+    tokio::spawn(async move { server.start().await });
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.layer(
+        ForwardProxy::new()
+            .with_allowed_ports([port])
+            .with_auth("proxy", |req| req.header(&HTTPHeaderType::ProxyAuthorization) == Some("Basic dXNlcjpwYXNz")),
+    );
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let url = format!("https://localhost:{}/secure", port);
+    let tunneling = |proxy: Proxy| {
+        HTTPClient::builder()
+            .add_root_certificates_pem(include_bytes!("data/ca.pem"))
+            .unwrap()
+            .proxy(proxy)
+            .build()
+    };
+    let proxy = Proxy::new(&format!("http://{}", proxy_addr)).unwrap();
+    let res = tunneling(proxy.clone().with_basic_auth("user", "pass")).get(&url).send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("tls: true"));
+    let refused = tunneling(proxy).get(&url).send().await;
+    assert!(matches!(refused, Err(ClientError::ProxyRefused(HTTPStatus::ProxyAuthenticationRequired))));
+
+    // bypassed hosts are connected to directly
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let dead = Proxy::new(&format!("http://{}", closed)).unwrap();
+    let res = tunneling(dead.clone().with_no_proxy("example.com, .localhost")).get(&url).send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("tls: true"));
+    let res = tunneling(dead.with_no_proxy("*")).get(&url).send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("tls: true"));
+    assert!(matches!(Proxy::new("socks5://127.0.0.1:1080"), Err(ClientError::InvalidUrl(_))));
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn test_client_uploads() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::client::{Form, HTTPClient};
+
+    // answers with the request's head and raw body
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            while !received.ends_with(b"\r\n\r\n") {
+                received.push(stream.read_u8().await.unwrap());
+            }
+            let head = String::from_utf8(received.clone()).unwrap();
+            let length = head
+                .lines()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .map(|n| n.parse::<usize>().unwrap());
+            match length {
+                Some(length) => {
+                    let mut body = vec![0; length];
+                    stream.read_exact(&mut body).await.unwrap();
+                    received.extend(body);
+                }
+                None => {
+                    let mut body = Vec::new();
+                    while !body.ends_with(b"0\r\n\r\n") {
+                        body.push(stream.read_u8().await.unwrap());
+                    }
+                    received.extend(body);
+                }
+            }
+            let res = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", received.len());
+            stream.write_all(res.as_bytes()).await.unwrap();
+            stream.write_all(&received).await.unwrap();
+        }
+    });
+    let client = HTTPClient::new();
+    let url = format!("http://{}/upload", addr);
+
+    let path = std::env::temp_dir().join(format!("web-upload-{}.txt", std::process::id()));
+    std::fs::write(&path, "file contents").unwrap();
+    let form = Form::new()
+        .text("title", "a \"quoted\" title")
+        .bytes("raw", "data.bin", vec![0xff, 0x00])
+        .file("doc", &path)
+        .await
+        .unwrap();
+    let boundary = form.boundary().to_string();
+    let res = client.post(&url).multipart(form).send().await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let received = res.raw_body.unwrap();
+    let split = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&received[..split]);
+    assert!(head.contains(&format!("Content-Type: multipart/form-data; boundary={}\r\n", boundary)));
+    let mut expected = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\na \"quoted\" title\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"raw\"; filename=\"data.bin\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+        b = boundary
+    )
+    .into_bytes();
+    expected.extend([0xff, 0x00]);
+    expected.extend(
+        format!(
+            "\r\n--{b}\r\nContent-Disposition: form-data; name=\"doc\"; filename=\"{f}\"\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nfile contents\r\n--{b}--\r\n",
+            b = boundary,
+            f = path.file_name().unwrap().to_string_lossy()
+        )
+        .into_bytes(),
+    );
+    assert!(head.contains(&format!("Content-Length: {}\r\n", expected.len())));
+    assert_eq!(&received[split..], &expected[..]);
+
+    // a reader of unknown length goes out chunked
+    let res = client.put(&url).body_reader(&b"streamed"[..]).send().await.unwrap();
+    let received = res.body.unwrap();
+    assert!(received.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(received.ends_with("\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn test_test_client() {
+    use web::middleware::{BoxFuture, Middleware, Next};
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::testing::TestClient;
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct User {
+        id: u32,
+        name: String,
+    }
+
+    struct Tag;
+    impl Middleware for Tag {
+        fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+            Box::pin(async move {
+                let mut res = next.run(req).await;
+                res.set_header(HTTPHeaderType::Server, "tagged");
+                res
+            })
+        }
+    }
+
+    let mut router = Router::new();
+    router.layer(Tag);
+    router.bind((HTTPMethod::GET, "/users/{id}".to_string()), |req, res, pattern| {
+        let params = web::router::match_route(pattern, &web::router::parse_url(&req.url).0).unwrap();
+        let id = params["id"].parse().unwrap();
+        let name = req.query_params().get("name").cloned().unwrap_or_default();
+        res.json(&User { id, name }).unwrap();
+    });
+    router.bind((HTTPMethod::POST, "/users".to_string()), |req, res, _pattern| {
+        let user: User = serde_json::from_str(req.body.as_deref().unwrap_or("")).unwrap();
+        res.status = HTTPStatus::Created;
+        res.set_header(HTTPHeaderType::Location, format!("/users/{}", user.id));
+        res.body = Some(format!("{} from {}", user.name, req.real_ip().unwrap()));
+    });
+    let client = TestClient::new(router);
+
+    let res = client.get("/users/3?name=ann").send().await;
+    res.assert_status(HTTPStatus::Ok)
+        .assert_header(HTTPHeaderType::Server, "tagged")
+        .assert_no_header(HTTPHeaderType::Location);
+    assert_eq!(res.json::<User>(), User { id: 3, name: "ann".to_string() });
+
+    client
+        .post("/users")
+        .json(&User { id: 4, name: "bo".to_string() })
+        .send()
+        .await
+        .assert_status(HTTPStatus::Created)
+        .assert_header(HTTPHeaderType::Location, "/users/4")
+        .assert_body("bo from 127.0.0.1");
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+#[tokio::test]
+async fn test_mock_server() {
+    use web::client::HTTPClient;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::testing::{Mock, MockServer};
+
+    let server = MockServer::start().await;
+    server
+        .mock(Mock::new(HTTPMethod::GET, "/users/1").respond(HTTPStatus::Ok, "ann"))
+        .mock(
+            Mock::new(HTTPMethod::POST, "/users")
+                .with_header(HTTPHeaderType::ContentType, "application/json")
+                .with_body("{\"name\":\"bo\"}")
+                .respond(HTTPStatus::Created, "created")
+                .times(2),
+        );
+
+    let client = HTTPClient::new();
+    let res = client.get(&server.url("/users/1?full=1")).send().await.unwrap();
+    assert_eq!(res.body.as_deref(), Some("ann"));
+    for _ in 0..2 {
+        let res = client
+            .post(&server.url("/users"))
+            .json(&serde_json::json!({ "name": "bo" }))
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status, HTTPStatus::Created);
+    }
+    assert_eq!(server.received().len(), 3);
+    assert_eq!(server.received()[0].url, "/users/1?full=1");
+    server.verify();
+
+    // unmet expectations and unmatched requests fail when the server drops
+    let unmet = tokio::spawn(async {
+        let server = MockServer::start().await;
+        server.mock(Mock::new(HTTPMethod::GET, "/never"));
+    });
+    assert!(unmet.await.unwrap_err().is_panic());
+    let unexpected = tokio::spawn(async {
+        let server = MockServer::start().await;
+        let res = HTTPClient::new().delete(&server.url("/users/1")).send().await.unwrap();
+        assert_eq!(res.status, HTTPStatus::NotFound);
+    });
+    assert!(unexpected.await.unwrap_err().is_panic());
+}
+
+#[cfg(feature = "macros")]
+#[tokio::test]
+async fn test_route_macros() {
+    use web::models::error::HTTPError;
+    use web::models::http::HTTPStatus;
+    use web::testing::TestClient;
+
+    #[web::get("/posts/{id}")]
+    fn show(req: &HTTPRequest, res: &mut HTTPResponse, pattern: &str) {
+        let params = web::router::match_route(pattern, &web::router::parse_url(&req.url).0).unwrap();
+        res.body = Some(format!("post {}", params["id"]));
+    }
+
+    #[web::post("/posts")]
+    fn create(req: &HTTPRequest, res: &mut HTTPResponse, _pattern: &str) -> Result<(), HTTPError> {
+        let title = req.body.as_deref().filter(|t| !t.is_empty());
+        let title = title.ok_or_else(|| HTTPError::client(HTTPStatus::BadRequest, "a title is required"))?;
+        res.status = HTTPStatus::Created;
+        res.body = Some(format!("created {}", title));
+        Ok(())
+    }
+
+    let mut router = Router::new();
+    router.mount(web::routes![show, create]);
+    let client = TestClient::new(router);
+
+    client.get("/posts/7").send().await.assert_body("post 7");
+    client.post("/posts").body("hello").send().await.assert_status(HTTPStatus::Created).assert_body("created hello");
+    client.post("/posts").send().await.assert_status(HTTPStatus::BadRequest);
+    // the handlers stay plain functions
+    let mut res = HTTPResponse::default();
+    show(&HTTPRequest::new("GET /posts/1 HTTP/1.1\r\n\r\n".to_string()), &mut res, "/posts/{id}");
+    assert_eq!(res.body.as_deref(), Some("post 1"));
+}
+
+#[tokio::test]
+async fn test_openapi() {
+    use serde_json::json;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::openapi::{DocsUi, OpenApi, Operation};
+    use web::testing::TestClient;
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/posts/{id}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::POST, "/posts".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::PUT, "/files/{*path}".to_string()), |_req, _res, _pattern| {});
+    router.accept((HTTPMethod::PUT, "/files/{*path}".to_string()), &["text/*"]).unwrap();
+    let post = json!({"type": "object", "properties": {"title": {"type": "string"}}});
+    router.document(
+        (HTTPMethod::POST, "/posts".to_string()),
+        Operation::new()
+            .summary("Create a post")
+            .tag("posts")
+            .json_body(post.clone())
+            .json_response(201, "Created", post.clone())
+            .response(400, "Invalid post"),
+    );
+    let openapi = OpenApi::new("Blog", "1.2.0").with_server("https://blog.example.com");
+    let document = openapi.generate(&router);
+    assert_eq!(document["openapi"], "3.1.0");
+    assert_eq!(document["info"], json!({"title": "Blog", "version": "1.2.0"}));
+    assert_eq!(document["servers"][0]["url"], "https://blog.example.com");
+    let show = &document["paths"]["/posts/{id}"]["get"];
+    assert_eq!(show["parameters"][0], json!({"name": "id", "in": "path", "required": true, "schema": {"type": "string"}}));
+    assert_eq!(show["responses"]["200"]["description"], "OK");
+    let create = &document["paths"]["/posts"]["post"];
+    assert_eq!(create["summary"], "Create a post");
+    assert_eq!(create["tags"], json!(["posts"]));
+    assert_eq!(create["requestBody"]["content"]["application/json"]["schema"], post);
+    assert_eq!(create["responses"]["201"]["content"]["application/json"]["schema"], post);
+    assert_eq!(create["responses"]["400"], json!({"description": "Invalid post"}));
+    let upload = &document["paths"]["/files/{path}"]["put"];
+    assert_eq!(upload["parameters"][0]["name"], "path");
+    assert!(upload["requestBody"]["content"]["text/*"].is_object());
+
+    // served by the layer, alongside a docs page
+    router.layer(OpenApi::new("Blog", "1.2.0").with_ui("/docs", DocsUi::SwaggerUi));
+    let client = TestClient::new(router);
+    let res = client.get("/openapi.json").send().await;
+    res.assert_status(HTTPStatus::Ok).assert_header(HTTPHeaderType::ContentType, "application/json");
+    assert_eq!(res.json::<serde_json::Value>()["paths"]["/posts"]["post"]["summary"], "Create a post");
+    let res = client.get("/docs").send().await;
+    res.assert_header(HTTPHeaderType::ContentType, "text/html; charset=utf-8");
+    assert!(res.text().contains("SwaggerUIBundle({url: \"/openapi.json\""));
+}
+
+#[test]
+fn test_route_introspection() {
+    use web::models::http::Buffering;
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::POST, "/posts".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/posts".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/posts/{id}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/static/{*file}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/".to_string()), |_req, _res, _pattern| {});
+    router.accept((HTTPMethod::POST, "/posts".to_string()), &["application/json"]).unwrap();
+    router.buffering((HTTPMethod::GET, "/static/{*file}".to_string()), Buffering::Streamed);
+    router.layer(web::middleware::request_log::RequestLog);
+
+    let routes = router.routes();
+    let listed: Vec<(String, &str)> = routes.iter().map(|r| (r.method.to_string(), r.pattern)).collect();
+    assert_eq!(
+        listed,
+        [
+            ("GET".to_string(), "/"),
+            ("GET".to_string(), "/posts"),
+            ("POST".to_string(), "/posts"),
+            ("GET".to_string(), "/posts/{id}"),
+            ("GET".to_string(), "/static/{*file}"),
+        ]
+    );
+    assert_eq!(routes[2].accepts.unwrap()[0].essence(), "application/json");
+    assert_eq!(routes[4].buffering, Some(Buffering::Streamed));
+    assert_eq!(routes[0].middleware, ["web::middleware::request_log::RequestLog"]);
+
+    assert_eq!(
+        router.tree(),
+        "/  [GET]\n\
+         ├── posts  [GET, POST]\n\
+         │   └── {id}  [GET]\n\
+         └── static\n    \
+             └── {*file}  [GET]\n\
+         layers: web::middleware::request_log::RequestLog\n"
+    );
+}
+
+#[test]
+fn test_named_routes() {
+    let mut router = Router::new();
+    router.bind_named("post_detail", (HTTPMethod::GET, "/posts/{id}".to_string()), |_req, _res, _pattern| {});
+    router.bind_named("asset", (HTTPMethod::GET, "/static/{*file}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/".to_string()), |_req, _res, _pattern| {});
+
+    assert_eq!(router.url_for("post_detail", &[("id", "7")]).unwrap(), "/posts/7");
+    assert_eq!(
+        router.url_for("post_detail", &[("page", "2"), ("id", "a b"), ("q", "x&y")]).unwrap(),
+        "/posts/a%20b?page=2&q=x%26y"
+    );
+    assert_eq!(router.url_for("asset", &[("file", "css/site.css")]).unwrap(), "/static/css/site.css");
+    assert!(router.url_for("post_detail", &[]).unwrap_err().contains("id"));
+    assert!(router.url_for("missing", &[]).is_err());
+
+    let routes = router.routes();
+    assert_eq!(routes[0].name, None);
+    assert_eq!(routes[1].name, Some("post_detail"));
+    assert_eq!(routes[2].name, Some("asset"));
+}
+
+#[tokio::test]
+async fn test_route_conflicts() {
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/posts/{id}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/posts/new".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::DELETE, "/posts/{slug}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/files/{*path}".to_string()), |_req, _res, _pattern| {});
+    assert_eq!(router.finalize(), Ok(()));
+
+    router.bind((HTTPMethod::GET, "/posts/{slug}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/files/{*rest}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/posts/new".to_string()), |_req, _res, _pattern| {});
+    assert_eq!(
+        router.finalize().unwrap_err(),
+        "conflicting routes:\n\
+         GET /files/{*path} and /files/{*rest} match the same paths\n\
+         GET /posts/new is bound more than once\n\
+         GET /posts/{id} and /posts/{slug} match the same paths"
+    );
+
+    #[cfg(feature = "server")]
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+        let err = server.start().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn test_route_precedence() {
+    let patterns = ["/posts/{id}", "/posts/new", "/posts/{*rest}", "/{*all}", "/posts/{id}/comments", "/{section}/new"];
+    // route storage is unordered, so build the router a few times over
+    for _ in 0..8 {
+        let mut router = Router::new();
+        for pattern in patterns {
+            router.bind((HTTPMethod::GET, pattern.to_string()), |_req, res, pattern| {
+                res.body = Some(pattern.to_string());
+            });
+        }
+        let matched = |path: &str| router.route(&HTTPRequest::new(format!("GET {} HTTP/1.1\r\n\r\n", path))).body.unwrap();
+        assert_eq!(matched("/posts/new"), "/posts/new");
+        assert_eq!(matched("/posts/7"), "/posts/{id}");
+        assert_eq!(matched("/posts/7/comments"), "/posts/{id}/comments");
+        assert_eq!(matched("/posts/7/likes"), "/posts/{*rest}");
+        assert_eq!(matched("/pages/new"), "/{section}/new");
+        assert_eq!(matched("/pages/about"), "/{*all}");
+    }
+}
+
+#[tokio::test]
+async fn test_host_routing() {
+    use web::middleware::{BoxFuture, Middleware, Next};
+    use web::models::connection::{ConnectionInfo, TlsInfo};
+    use web::models::http::HTTPHeaderType;
+    use web::testing::TestClient;
+
+    struct Tag(&'static str);
+    impl Middleware for Tag {
+        fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+            Box::pin(async move {
+                let mut res = next.run(req).await;
+                let tags = res.header(&HTTPHeaderType::Via).map(|v| format!("{}, {}", v, self.0));
+                res.set_header(HTTPHeaderType::Via, tags.unwrap_or_else(|| self.0.to_string()));
+                res
+            })
+        }
+    }
+
+    let mut api = Router::new();
+    api.bind((HTTPMethod::GET, "/".to_string()), |_req, res, _pattern| res.body = Some("api".to_string()));
+    api.layer(Tag("api"));
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/".to_string()), |_req, res, _pattern| res.body = Some("site".to_string()));
+    router.layer(Tag("outer"));
+    router.host("API.example.com", api);
+
+    // without a Host header, the TLS server name decides
+    let mut req = HTTPRequest::new("GET / HTTP/1.1\r\n\r\n".to_string());
+    assert!(req.hostname().is_none());
+    req.connection = Some(ConnectionInfo {
+        tls: Some(TlsInfo {
+            server_name: Some("api.example.com".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    assert_eq!(req.hostname(), Some("api.example.com"));
+    assert_eq!(router.route(&req).body.as_deref(), Some("api"));
+
+    let client = TestClient::new(router);
+    let res = client.get("/").header(HTTPHeaderType::Host, "api.example.com:8443").send().await;
+    res.assert_body("api").assert_header(HTTPHeaderType::Via, "api, outer");
+    let res = client.get("/").header(HTTPHeaderType::Host, "www.example.com").send().await;
+    res.assert_body("site").assert_header(HTTPHeaderType::Via, "outer");
+}
+
+#[tokio::test]
+async fn test_subdomain_routing() {
+    use web::models::http::HTTPHeaderType;
+    use web::testing::TestClient;
+
+    let mut admin = Router::new();
+    admin.bind((HTTPMethod::GET, "/".to_string()), |_req, res, _pattern| res.body = Some("admin".to_string()));
+    let mut tenants = Router::new();
+    tenants.bind((HTTPMethod::GET, "/whoami".to_string()), |req, res, _pattern| {
+        res.body = req.host_param("tenant").map(str::to_string);
+    });
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/".to_string()), |_req, res, _pattern| res.body = Some("site".to_string()));
+    router.host("{tenant}.example.com", tenants);
+    router.host("admin.example.com", admin);
+
+    // outside the pipeline too
+    let req = HTTPRequest::new("GET /whoami HTTP/1.1\r\nHost: acme.example.com\r\n\r\n".to_string());
+    assert_eq!(router.route(&req).body.as_deref(), Some("acme"));
+    assert_eq!(req.host_param("tenant"), None);
+
+    let client = TestClient::new(router);
+    let res = client.get("/whoami").header(HTTPHeaderType::Host, "Globex.example.com:8080").send().await;
+    res.assert_body("globex");
+    // exact names win over patterns added before them
+    let res = client.get("/").header(HTTPHeaderType::Host, "admin.example.com").send().await;
+    res.assert_body("admin");
+    // a label matches exactly one label
+    let res = client.get("/").header(HTTPHeaderType::Host, "a.b.example.com").send().await;
+    res.assert_body("site");
+    let res = client.get("/").header(HTTPHeaderType::Host, "example.com").send().await;
+    res.assert_body("site");
+}
+
+#[test]
+fn test_route_constraints() {
+    let mut router = Router::new();
+    for pattern in ["/posts/{id:\\d+}", "/posts/{slug}", "/files/{name:[a-z-]+}.{ext}", "/years/{year:\\d{4}}"] {
+        router.bind((HTTPMethod::GET, pattern.to_string()), |req, res, pattern| {
+            let mut params: Vec<_> = req.path_params(pattern).unwrap().into_iter().collect();
+            params.sort();
+            res.body = Some(format!("{} {:?}", pattern, params));
+        });
+    }
+    assert_eq!(router.finalize(), Ok(()));
+
+    let matched = |path: &str| {
+        let res = router.route(&HTTPRequest::new(format!("GET {} HTTP/1.1\r\n\r\n", path)));
+        res.body.unwrap_or_default()
+    };
+    assert_eq!(matched("/posts/42"), "/posts/{id:\\d+} [(\"id\", \"42\")]");
+    assert_eq!(matched("/posts/hello"), "/posts/{slug} [(\"slug\", \"hello\")]");
+    assert_eq!(
+        matched("/files/read-me.tar.gz"),
+        "/files/{name:[a-z-]+}.{ext} [(\"ext\", \"tar.gz\"), (\"name\", \"read-me\")]"
+    );
+    assert_eq!(matched("/years/2024"), "/years/{year:\\d{4}} [(\"year\", \"2024\")]");
+    // rejected before any handler runs
+    assert!(!matched("/files/README.md").starts_with('/'));
+    assert!(!matched("/years/24").starts_with('/'));
+
+    let mut router = Router::new();
+    router.bind_named("post", (HTTPMethod::GET, "/posts/{id:\\d+}".to_string()), |_req, _res, _pattern| {});
+    assert_eq!(router.url_for("post", &[("id", "7")]), Ok("/posts/7".to_string()));
+    assert!(router.url_for("post", &[("id", "seven")]).is_err());
+    router.bind((HTTPMethod::GET, "/posts/{n:\\d+}".to_string()), |_req, _res, _pattern| {});
+    router.bind((HTTPMethod::GET, "/tags/{tag:(}".to_string()), |_req, _res, _pattern| {});
+    let err = router.finalize().unwrap_err();
+    assert!(err.contains("GET /posts/{id:\\d+} and /posts/{n:\\d+} match the same paths"), "{}", err);
+    assert!(err.contains("GET /tags/{tag:(} has an invalid constraint"), "{}", err);
+}
+
+#[test]
+fn test_trailing_slash_policy() {
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::router::TrailingSlash;
+
+    let router_with = |policy: TrailingSlash| {
+        let mut router = Router::new();
+        router.trailing_slash(policy);
+        for pattern in ["/users", "/docs/", "/static/{*file}"] {
+            router.bind((HTTPMethod::GET, pattern.to_string()), |_req, res, pattern| res.body = Some(pattern.to_string()));
+        }
+        router.bind((HTTPMethod::POST, "/users".to_string()), |_req, res, _pattern| res.body = Some("created".to_string()));
+        router
+    };
+    let send = |router: &Router, method: &str, url: &str| {
+        router.route(&HTTPRequest::new(format!("{} {} HTTP/1.1\r\n\r\n", method, url)))
+    };
+
+    let router = router_with(TrailingSlash::Merge);
+    assert_eq!(send(&router, "GET", "/users/").body.as_deref(), Some("/users"));
+    assert_eq!(send(&router, "GET", "/docs").body.as_deref(), Some("/docs/"));
+
+    let router = router_with(TrailingSlash::Strict);
+    assert_eq!(send(&router, "GET", "/users").body.as_deref(), Some("/users"));
+    assert_ne!(send(&router, "GET", "/users/").status, HTTPStatus::Ok);
+    assert_ne!(send(&router, "GET", "/docs").status, HTTPStatus::Ok);
+    assert_eq!(send(&router, "GET", "/static/css/").body.as_deref(), Some("/static/{*file}"));
+
+    let router = router_with(TrailingSlash::Redirect);
+    let res = send(&router, "GET", "/users/?page=2");
+    assert_eq!(res.status, HTTPStatus::MovedPermanently);
+    assert_eq!(res.header(&HTTPHeaderType::Location), Some("/users?page=2"));
+    let res = send(&router, "GET", "/docs");
+    assert_eq!(res.header(&HTTPHeaderType::Location), Some("/docs/"));
+    let res = send(&router, "POST", "/users/");
+    assert_eq!(res.status, HTTPStatus::PermanentRedirect);
+    assert_eq!(send(&router, "POST", "/users").body.as_deref(), Some("created"));
+}
+
+#[tokio::test]
+async fn test_uri_normalization() {
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::router::normalize_path;
+    use web::testing::TestClient;
+
+    assert_eq!(normalize_path("/a//b/./%63/../d").as_deref(), Some("/a/b/d"));
+    assert_eq!(normalize_path("/a/b/..").as_deref(), Some("/a/"));
+    assert_eq!(normalize_path("/docs/").as_deref(), Some("/docs/"));
+    assert_eq!(normalize_path("/%7euser/%2f%zz").as_deref(), Some("/~user/%2F%zz"));
+    assert_eq!(normalize_path("/..").as_deref(), None);
+    assert_eq!(normalize_path("/static/%2e%2e/%2E%2E/etc/passwd"), None);
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/static/{*file}".to_string()), |req, res, _pattern| {
+        res.body = Some(format!("{} {}", req.url, req.host().unwrap_or_default()));
+    });
+    let client = TestClient::new(router);
+    let res = client.get("/static//css/../app.js?v=1").header(HTTPHeaderType::Host, "example.com:80").send().await;
+    res.assert_body("/static/app.js?v=1 example.com");
+    client.get("/static/../../secret").send().await.assert_status(HTTPStatus::BadRequest);
+    // not origin-form, so left alone
+    let mut req = HTTPRequest::new("OPTIONS * HTTP/1.1\r\n\r\n".to_string());
+    assert!(req.normalize().is_ok());
+    assert_eq!(req.url, "*");
+}
+
+#[tokio::test]
+async fn test_request_target_forms() {
+    use web::models::http::{HTTPHeaderType, HTTPStatus, TargetForm};
+    use web::testing::TestClient;
+
+    let req = HTTPRequest::new("GET http://Example.com:8080/a/b?x=1 HTTP/1.1\r\nHost: other\r\n\r\n".to_string());
+    assert_eq!(req.target_form(), TargetForm::Absolute);
+    assert_eq!(req.scheme(), Some("http"));
+    assert_eq!(req.authority(), Some("Example.com:8080"));
+    assert_eq!((req.path(), req.query()), ("/a/b", Some("x=1")));
+    let req = HTTPRequest::new("GET http://example.com HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!((req.path_and_query(), req.path()), ("/", "/"));
+    let req = HTTPRequest::new("CONNECT example.com:443 HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!(req.target_form(), TargetForm::Authority);
+    assert_eq!((req.authority(), req.path()), (Some("example.com:443"), ""));
+    let req = HTTPRequest::new("OPTIONS * HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!(req.target_form(), TargetForm::Asterisk);
+
+    // an absolute-form target routes by its path, and its authority wins over Host
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/a/{id}".to_string()), |req, res, pattern| {
+        let id = req.path_params(pattern).unwrap()["id"].clone();
+        res.body = Some(format!("{} {} {}", id, req.host().unwrap_or_default(), req.query_params()["x"]));
+    });
+    let client = TestClient::new(router);
+    let res = client.get("http://api.example.com/a/./7?x=1").header(HTTPHeaderType::Host, "other").send().await;
+    res.assert_body("7 api.example.com 1");
+    client.get("example.com:443").send().await.assert_status(HTTPStatus::BadRequest);
+    client.get("*").send().await.assert_status(HTTPStatus::BadRequest);
+    client.request(HTTPMethod::CONNECT, "/a/7").send().await.assert_status(HTTPStatus::BadRequest);
+}
+
+#[test]
+fn test_url() {
+    use web::models::url::Url;
+
+    let url: Url = "HTTPS://Example.com:8443/files/a%20b/c?q=x%26y&tag=a+b&flag#top".parse().unwrap();
+    assert_eq!(url.scheme.as_deref(), Some("https"));
+    assert_eq!(url.host.as_deref(), Some("example.com"));
+    assert_eq!(url.port, Some(8443));
+    assert_eq!(url.segments(), ["files", "a b", "c"]);
+    assert_eq!(
+        url.query_pairs(),
+        [("q".to_string(), "x&y".to_string()), ("tag".to_string(), "a b".to_string()), ("flag".to_string(), String::new())]
+    );
+    assert_eq!(url.fragment.as_deref(), Some("top"));
+    assert_eq!(url.to_string(), "https://example.com:8443/files/a%20b/c?q=x%26y&tag=a+b&flag#top");
+
+    let url = Url::parse("http://[::1]/").unwrap();
+    assert_eq!((url.host.as_deref(), url.port_or_default()), (Some("[::1]"), Some(80)));
+    let url = Url::parse("/search?q=rust").unwrap();
+    assert_eq!((url.host.as_deref(), url.path_and_query()), (None, "/search?q=rust".to_string()));
+    for bad in ["http://user:pw@example.com/", "http://example.com:99999/", "http://exa mple.com/", "ftp:/x", "http:///path"] {
+        assert!(Url::parse(bad).is_err(), "{}", bad);
+    }
+
+    let built = Url::new("https", "api.example.com")
+        .with_port(8080)
+        .with_segment("users")
+        .with_segment("a/b c")
+        .with_query("q", "x&y")
+        .with_fragment("top");
+    assert_eq!(built.to_string(), "https://api.example.com:8080/users/a%2Fb%20c?q=x%26y#top");
+    assert_eq!(Url::parse(&built.to_string()).unwrap(), built);
+
+    let (path, query) = web::router::parse_url("/hello?name=J%C3%BCrgen+S");
+    assert_eq!((path.as_str(), query["name"].as_str()), ("/hello", "Jürgen S"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_early_hints() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/".to_string()), |_req, res, _pattern| {
+        assert!(res.early_hints(&["</app.css>; rel=preload; as=style", "</app.js>; rel=preload; as=script"]));
+        res.body = Some("page".to_string());
+    });
+    // outside a server there is nowhere to send them
+    let res = router.route(&HTTPRequest::new("GET /x HTTP/1.1\r\n\r\n".to_string()));
+    assert!(!res.early_hints(&["</app.css>; rel=preload"]));
+
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let hints = "HTTP/1.1 103 Early Hints\r\nLink: </app.css>; rel=preload; as=style\r\nLink: </app.js>; rel=preload; as=script\r\n\r\n";
+    assert!(response.starts_with(hints), "{}", response);
+    assert!(response[hints.len()..].starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("page"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_http_1_0_clients() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let req = HTTPRequest::new("GET /old HTTP/1.0\r\n\r\n".to_string());
+    assert_eq!(req.version, web::models::http::HTTPVersion::HTTP1_0);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/old".to_string()), |_req, res, _pattern| {
+        assert!(!res.early_hints(&["</app.css>; rel=preload"]));
+        res.set_header(web::models::http::HTTPHeaderType::TransferEncoding, "chunked");
+        res.body = Some("still here".to_string());
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /old HTTP/1.0\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.0 200 OK\r\n"), "{}", response);
+    assert!(response.contains("Connection: close\r\n"));
+    assert!(!response.to_ascii_lowercase().contains("transfer-encoding"));
+    assert!(response.ends_with("still here"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_request_smuggling_defenses() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::POST, "/".to_string()), |req, res, _pattern| {
+        res.body = Some(req.body.clone().unwrap_or_default());
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    let send = |head: &'static str| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    let ok = send("POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3\r\n\r\nabc").await;
+    assert!(ok.starts_with("HTTP/1.1 200 OK\r\n") && ok.ends_with("abc"), "{}", ok);
+    let ok = send("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n2\r\nab\r\n1\r\nc\r\n0\r\nX-T: 1\r\n\r\n").await;
+    assert!(ok.starts_with("HTTP/1.1 200 OK\r\n") && ok.ends_with("abc"), "{}", ok);
+    for head in [
+        "POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        "POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\nabcd",
+        "POST / HTTP/1.1\r\nContent-Length: 3, 4\r\n\r\nabcd",
+        "POST / HTTP/1.1\r\nContent-Length: +3\r\n\r\nabc",
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked, identity\r\n\r\n",
+        "POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        "POST / HTTP/1.1\r\nContent-Length : 3\r\n\r\nabc",
+        "POST / HTTP/1.1\r\nX-Folded: a\r\n b\r\nContent-Length: 3\r\n\r\nabc",
+        // chunks are framed strictly too
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\nabc\r\n0\r\n\r\n",
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n-3\r\nabc\r\n0\r\n\r\n",
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabcd\r\n0\r\n\r\n",
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\n0\r\n\r\n",
+    ] {
+        let response = send(head).await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{:?}: {}", head, response);
+        assert!(response.contains("Connection: close\r\n"));
+    }
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_header_injection() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::models::headers::{valid_header_name, valid_header_value};
+    use web::models::http::HTTPHeaderType;
+
+    assert!(valid_header_name("X-Request-Id") && !valid_header_name("X Id") && !valid_header_name(""));
+    assert!(valid_header_value("a\tb ü") && !valid_header_value("a\r\nSet-Cookie: x") && !valid_header_value("a\0"));
+
+    let mut res = HTTPResponse::error(web::models::http::HTTPStatus::Found, "");
+    res.set_header(HTTPHeaderType::Location, "/next\r\nSet-Cookie: session=stolen");
+    res.set_header(HTTPHeaderType::Other("X-Bad\r\nName".to_string()), "1");
+    res.set_header(HTTPHeaderType::Other("X-Good".to_string()), "1");
+    let head = res.head_to_string();
+    assert!(!head.contains("Set-Cookie") && !head.contains("X-Bad"), "{}", head);
+    assert!(head.contains("X-Good: 1\r\n"));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = web::httpserver::HTTPServer::from_listener(listener, Router::new(), HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    for head in ["GET / HTTP/1.1\r\nX-Bad: a\0b\r\n\r\n", "GET / HTTP/1.1\r\nBad Name: 1\r\n\r\n", "GET / HTTP/1.1\r\nNoColon\r\n\r\n"] {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{:?}: {}", head, response);
+    }
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_repeated_headers() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::models::http::HTTPHeaderType;
+
+    let req = HTTPRequest::new(String::from(
+        "GET / HTTP/1.1\r\nHost: a.test\r\nHost: b.test\r\nVia: 1.1 a\r\nvia: 1.1 b\r\nCookie: a=1\r\nCookie: b=2\r\n\
+         X-Tags: \"x, y\", z\r\nContent-Length: 0\r\nContent-Length: 5\r\nX-Long: one\r\n two\r\n\r\n",
+    ));
+    let via = HTTPHeaderType::Via;
+    assert_eq!(req.header(&via), Some("1.1 a, 1.1 b"));
+    assert_eq!(req.header_values(&via), vec!["1.1 a", "1.1 b"]);
+    assert_eq!(req.header(&HTTPHeaderType::Cookie), Some("a=1; b=2"));
+    assert_eq!(req.header_values(&HTTPHeaderType::Cookie), vec!["a=1", "b=2"]);
+    assert_eq!(req.header_values(&HTTPHeaderType::Other("X-Tags".to_string())), vec!["\"x, y\"", "z"]);
+    assert_eq!(req.header(&HTTPHeaderType::Host), Some("a.test"));
+    assert_eq!(req.header(&HTTPHeaderType::ContentLength), Some("0"));
+    assert_eq!(req.header(&HTTPHeaderType::Other("X-Long".to_string())), Some("one two"));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = web::httpserver::HTTPServer::from_listener(listener, Router::new(), HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: a.test\r\nHost: b.test\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", response);
+}
+
+#[test]
+fn test_status_codes_and_classes() {
+    use web::models::http::HTTPStatus;
+
+    assert_eq!(HTTPStatus::from_code(404), Some(HTTPStatus::NotFound));
+    assert_eq!(HTTPStatus::from_code(103), Some(HTTPStatus::EarlyHints));
+    assert_eq!(HTTPStatus::from_code(299), None);
+    for code in [100, 200, 226, 308, 418, 451, 511] {
+        assert_eq!(HTTPStatus::from_code(code).map(|status| status.code()), Some(code));
+    }
+
+    assert!(HTTPStatus::Continue.is_informational() && !HTTPStatus::Continue.is_success());
+    assert!(HTTPStatus::NoContent.is_success());
+    assert!(HTTPStatus::NotModified.is_redirect());
+    assert!(HTTPStatus::TooManyRequests.is_client_error() && !HTTPStatus::TooManyRequests.is_server_error());
+    assert!(HTTPStatus::GatewayTimeout.is_server_error());
+}
+
+#[test]
+fn test_custom_status() {
+    use web::models::http::HTTPStatus;
+
+    let status = HTTPStatus::custom(499, "Client Closed Request").unwrap();
+    assert_eq!(status, HTTPStatus::Custom(499, "Client Closed Request".to_string()));
+    assert_eq!(status.code(), 499);
+    assert!(status.is_client_error());
+    assert_eq!(HTTPStatus::custom(404, "Nope").unwrap(), HTTPStatus::NotFound);
+    assert!(HTTPStatus::custom(1000, "").is_err() && HTTPStatus::custom(99, "").is_err());
+    assert!(HTTPStatus::custom(599, "Bad\r\nSet-Cookie: x").is_err());
+
+    let res = HTTPResponse::error(HTTPStatus::Custom(599, "Network\r\nTimeout".to_string()), "");
+    assert!(res.head_to_string().starts_with("HTTP/1.1 599 NetworkTimeout\r\n"));
+}
+
+#[tokio::test]
+async fn test_content_negotiation() {
+    use web::mime::Mime;
+    use web::models::headers::MediaRange;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::testing::TestClient;
+
+    let ranges = MediaRange::parse_list("text/*;q=0.5, application/json, text/html;level=1;q=0.5;ext=1, bad, */*;q=0.1");
+    let listed: Vec<(String, f32)> = ranges.iter().map(|r| (r.media_type.to_string(), r.quality)).collect();
+    assert_eq!(
+        listed,
+        vec![
+            ("application/json".to_string(), 1.0),
+            ("text/html; level=1".to_string(), 0.5),
+            ("text/*".to_string(), 0.5),
+            ("*/*".to_string(), 0.1),
+        ]
+    );
+
+    let offered = [Mime::APPLICATION_JSON, Mime::TEXT_HTML, Mime::TEXT_CSV];
+    let negotiate = |accept: Option<&str>| {
+        let mut req = HTTPRequest::new(String::from("GET / HTTP/1.1\r\n\r\n"));
+        if let Some(accept) = accept {
+            req.headers.insert(HTTPHeaderType::Accept, accept.to_string());
+        }
+        req.negotiate(&offered).map_err(|err| err.status)
+    };
+    assert_eq!(negotiate(None), Ok(Mime::APPLICATION_JSON));
+    assert_eq!(negotiate(Some("text/html,application/xhtml+xml,*/*;q=0.8")), Ok(Mime::TEXT_HTML));
+    assert_eq!(negotiate(Some("text/*, application/json;q=0.9")), Ok(Mime::TEXT_HTML));
+    assert_eq!(negotiate(Some("text/*, text/html;q=0")), Ok(Mime::TEXT_CSV));
+    assert_eq!(negotiate(Some("*/*;q=0.1, text/csv")), Ok(Mime::TEXT_CSV));
+    assert_eq!(negotiate(Some("image/png")), Err(HTTPStatus::NotAcceptable));
+
+    let mut router = Router::new();
+    router.try_bind((HTTPMethod::GET, "/report".to_string()), |req, res, _| {
+        let mime = req.negotiate(&[Mime::APPLICATION_JSON, Mime::TEXT_CSV])?;
+        res.set_content_type(mime);
+        res.body = Some(if mime == Mime::TEXT_CSV { "a,b\n1,2\n" } else { "{\"a\":1,\"b\":2}" }.to_string());
+        Ok(())
+    });
+    let client = TestClient::new(router);
+    client.get("/report").header(HTTPHeaderType::Accept, "text/csv").send().await.assert_body("a,b\n1,2\n");
+    client.get("/report").send().await.assert_header(HTTPHeaderType::ContentType, "application/json");
+    client.get("/report").header(HTTPHeaderType::Accept, "text/html").send().await.assert_status(HTTPStatus::NotAcceptable);
+}
+
+#[tokio::test]
+async fn test_language_negotiation() {
+    use web::middleware::locale::Localization;
+    use web::models::headers::language_matches;
+    use web::models::http::HTTPHeaderType;
+    use web::testing::TestClient;
+
+    assert!(language_matches("de", "de-CH") && language_matches("*", "fr") && language_matches("EN", "en"));
+    assert!(!language_matches("de", "den") && !language_matches("de-CH", "de"));
+
+    let supported = ["en", "de", "fr-CA"];
+    let preferred = |accept: &str| {
+        let mut req = HTTPRequest::new(String::from("GET / HTTP/1.1\r\n\r\n"));
+        req.headers.insert(HTTPHeaderType::AcceptLanguage, accept.to_string());
+        req.preferred_language(&supported)
+    };
+    assert_eq!(preferred("de-DE, de;q=0.9, en;q=0.5"), Some("de"));
+    assert_eq!(preferred("fr;q=0.9, en;q=0.8"), Some("fr-CA"));
+    assert_eq!(preferred("en;q=0.1, de;q=0.7"), Some("de"));
+    assert_eq!(preferred("*, en;q=0"), Some("de"));
+    assert_eq!(preferred("ja"), None);
+    assert_eq!(HTTPRequest::new(String::from("GET / HTTP/1.1\r\n\r\n")).preferred_language(&supported), None);
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/hello".to_string()), |req, res, _| {
+        res.body = Some(match req.locale() {
+            Some("de") => "Hallo".to_string(),
+            _ => "Hello".to_string(),
+        });
+    });
+    router.bind((HTTPMethod::GET, "/logo".to_string()), |_req, res, _| {
+        res.set_header(HTTPHeaderType::ContentLanguage, "mul");
+    });
+    router.layer(Localization::new(&supported));
+    let client = TestClient::new(router);
+    client
+        .get("/hello")
+        .header(HTTPHeaderType::AcceptLanguage, "de-AT, en;q=0.5")
+        .send()
+        .await
+        .assert_body("Hallo")
+        .assert_header(HTTPHeaderType::ContentLanguage, "de")
+        .assert_header(HTTPHeaderType::Vary, "Accept-Language");
+    client.get("/hello").send().await.assert_body("Hello").assert_header(HTTPHeaderType::ContentLanguage, "en");
+    client.get("/logo").send().await.assert_header(HTTPHeaderType::ContentLanguage, "mul");
+}
+
+#[tokio::test]
+async fn test_automatic_vary() {
+    use web::mime::Mime;
+    use web::models::http::HTTPHeaderType;
+    use web::testing::TestClient;
+
+    let mut router = Router::new();
+    router.try_bind((HTTPMethod::GET, "/data".to_string()), |req, res, _| {
+        res.set_header(HTTPHeaderType::Vary, "Cookie");
+        let mime = req.negotiate(&[Mime::APPLICATION_JSON, Mime::TEXT_HTML])?;
+        req.vary_on(&HTTPHeaderType::Origin);
+        req.vary_on(&HTTPHeaderType::Accept);
+        res.set_content_type(mime);
+        Ok(())
+    });
+    router.bind((HTTPMethod::GET, "/plain".to_string()), |_req, res, _| {
+        res.body = Some("same for everyone".to_string());
+    });
+    let client = TestClient::new(router);
+    client.get("/data").send().await.assert_header(HTTPHeaderType::Vary, "Cookie, Accept, Origin");
+    client
+        .get("/data")
+        .header(HTTPHeaderType::Accept, "image/png")
+        .send()
+        .await
+        .assert_header(HTTPHeaderType::Vary, "Accept");
+    client.get("/plain").send().await.assert_no_header(HTTPHeaderType::Vary);
+}
+
+#[tokio::test]
+async fn test_response_cache() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use web::middleware::cache::ResponseCache;
+    use web::models::http::HTTPHeaderType;
+    use web::testing::TestClient;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut router = Router::new();
+    for (path, cache_control) in [("/posts/{id}", None), ("/fresh", Some("max-age=60")), ("/secret", Some("private, max-age=60"))] {
+        let calls = calls.clone();
+        router.bind((HTTPMethod::GET, path.to_string()), move |req, res, _| {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if let Some(cache_control) = cache_control {
+                res.set_header(HTTPHeaderType::CacheControl, cache_control);
+            }
+            req.accepts_encoding("gzip");
+            res.body = Some(format!("{} #{}", req.path(), n));
+        });
+    }
+    let cache = ResponseCache::new(2).route("/posts/{id}", Some(Duration::from_millis(200))).route("/fresh", None).route("/secret", None);
+    router.layer(cache);
+    let client = TestClient::new(router);
+
+    client.get("/posts/1").send().await.assert_body("/posts/1 #1").assert_no_header(HTTPHeaderType::Age);
+    let hit = client.get("/posts/1").send().await;
+    hit.assert_body("/posts/1 #1").assert_header(HTTPHeaderType::Age, "0").assert_header(HTTPHeaderType::Vary, "Accept-Encoding");
+    // another Accept-Encoding is another variant
+    client.get("/posts/1").header(HTTPHeaderType::AcceptEncoding, "gzip").send().await.assert_body("/posts/1 #2");
+    client.get("/posts/1").header(HTTPHeaderType::CacheControl, "no-cache").send().await.assert_body("/posts/1 #3");
+    client.get("/posts/1").send().await.assert_body("/posts/1 #3");
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    client.get("/posts/1").send().await.assert_body("/posts/1 #4");
+
+    client.get("/fresh").send().await.assert_body("/fresh #5");
+    client.get("/fresh").send().await.assert_body("/fresh #5");
+    client.get("/fresh").header(HTTPHeaderType::Authorization, "Bearer x").send().await.assert_body("/fresh #6");
+    client.get("/secret").send().await.assert_body("/secret #7");
+    client.get("/secret").send().await.assert_body("/secret #8");
+
+    // capacity 2: the gzip variant of /posts/1, used least recently, made room
+    client.get("/posts/1").header(HTTPHeaderType::AcceptEncoding, "gzip").send().await.assert_body("/posts/1 #9");
+    client.get("/fresh").send().await.assert_body("/fresh #5");
+}
+
+#[tokio::test]
+async fn test_auto_etag() {
+    use web::conditional::AutoETag;
+    use web::models::headers::EntityTag;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::testing::TestClient;
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/report".to_string()), |_req, res, _| {
+        res.text("quarterly numbers");
+    });
+    router.bind((HTTPMethod::GET, "/tagged".to_string()), |_req, res, _| {
+        res.text("tagged");
+        res.set_etag(&EntityTag::weak("v7"));
+    });
+    router.bind((HTTPMethod::POST, "/report".to_string()), |_req, res, _| {
+        res.text("created");
+    });
+    router.layer(AutoETag);
+    let client = TestClient::new(router);
+
+    let res = client.get("/report").send().await;
+    let etag = res.header(&HTTPHeaderType::ETag).unwrap().to_string();
+    assert!(etag.starts_with('"') && etag.len() == 18, "{}", etag);
+    assert_eq!(client.get("/report").send().await.header(&HTTPHeaderType::ETag), Some(etag.as_str()));
+
+    client
+        .get("/report")
+        .header(HTTPHeaderType::IfNoneMatch, etag.clone())
+        .send()
+        .await
+        .assert_status(HTTPStatus::NotModified)
+        .assert_body("")
+        .assert_header(HTTPHeaderType::ETag, &etag);
+    client.get("/report").header(HTTPHeaderType::IfNoneMatch, "\"stale\"").send().await.assert_body("quarterly numbers");
+    client.get("/tagged").send().await.assert_header(HTTPHeaderType::ETag, "W/\"v7\"");
+    client.get("/tagged").header(HTTPHeaderType::IfNoneMatch, "W/\"v7\"").send().await.assert_status(HTTPStatus::NotModified);
+    client.post("/report").send().await.assert_no_header(HTTPHeaderType::ETag);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_problem_details() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::models::error::HTTPError;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::models::problem::ProblemDetails;
+    use web::testing::TestClient;
+
+    let problem = ProblemDetails::new(&HTTPStatus::Forbidden)
+        .with_type("https://example.com/probs/out-of-credit", "You do not have enough credit.")
+        .with_detail("Your current balance is 30, but that costs 50.")
+        .with_extension("balance", serde_json::json!(30));
+    let res = problem.to_response();
+    assert_eq!(res.status, HTTPStatus::Forbidden);
+    assert_eq!(res.header(&HTTPHeaderType::ContentType), Some("application/problem+json"));
+    let body: serde_json::Value = serde_json::from_str(res.body.as_deref().unwrap()).unwrap();
+    assert_eq!(body["type"], "https://example.com/probs/out-of-credit");
+    assert_eq!(body["balance"], 30);
+    assert_eq!(serde_json::from_value::<ProblemDetails>(body).unwrap(), problem);
+
+    let mut router = Router::new();
+    router.try_bind((HTTPMethod::POST, "/posts".to_string()), |_req, _res, _| {
+        Err(HTTPError::client(HTTPStatus::BadRequest, "title is required"))
+    });
+    router.bind((HTTPMethod::GET, "/posts/{id}".to_string()), |_req, _res, _| {});
+    router.bind((HTTPMethod::DELETE, "/posts/{id}".to_string()), |_req, _res, _| {});
+    router.enable_problem_details();
+    let client = TestClient::new(router);
+
+    let res = client.post("/posts").send().await;
+    res.assert_status(HTTPStatus::BadRequest).assert_header(HTTPHeaderType::ContentType, "application/problem+json");
+    assert_eq!(
+        res.json::<serde_json::Value>(),
+        serde_json::json!({"type": "about:blank", "title": "Bad Request", "status": 400, "detail": "title is required", "instance": "/posts"})
+    );
+    let res = client.get("/nope").send().await;
+    res.assert_status(HTTPStatus::NotFound);
+    assert_eq!(res.json::<ProblemDetails>().instance.as_deref(), Some("/nope"));
+    let res = client.put("/posts/1").send().await;
+    res.assert_status(HTTPStatus::MethodNotAllowed).assert_header(HTTPHeaderType::Allow, "DELETE, GET");
+    assert_eq!(res.json::<ProblemDetails>().title, "Method Not Allowed");
+
+    // requests the server turns away before routing
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.enable_problem_details();
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nBad Name: 1\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", response);
+    assert!(response.contains("Content-Type: application/problem+json\r\n"), "{}", response);
+    assert!(response.ends_with("{\"type\":\"about:blank\",\"title\":\"Bad Request\",\"status\":400}"), "{}", response);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_panic_isolation() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::middleware::{BoxFuture, Middleware, Next};
+    use web::models::http::HTTPStatus;
+    use web::testing::TestClient;
+
+    struct PanicOn(&'static str);
+    impl Middleware for PanicOn {
+        fn handle<'a>(&'a self, req: HTTPRequest, next: Next<'a>) -> BoxFuture<'a, HTTPResponse> {
+            if req.path() == self.0 {
+                panic!("layer gave up");
+            }
+            next.run(req)
+        }
+    }
+
+    let router = || {
+        let mut router = Router::new();
+        router.bind((HTTPMethod::GET, "/boom".to_string()), |_req, _res, _| panic!("handler gave up"));
+        router.bind((HTTPMethod::GET, "/later".to_string()), |_req, res, _| {
+            res.defer(async { panic!("deferred response gave up") });
+        });
+        router.bind((HTTPMethod::GET, "/fine".to_string()), |_req, res, _| {
+            res.body = Some("fine".to_string());
+        });
+        router.layer(PanicOn("/layer"));
+        router
+    };
+    let client = TestClient::new(router());
+    for path in ["/boom", "/later", "/layer"] {
+        client
+            .get(path)
+            .send()
+            .await
+            .assert_status(HTTPStatus::InternalServerError)
+            .assert_body("500 Internal Server Error");
+    }
+    client.get("/fine").send().await.assert_body("fine");
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = web::httpserver::HTTPServer::from_listener(listener, router(), HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    for (path, status) in [("/boom", "500 Internal Server Error"), ("/fine", "200 OK")] {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with(&format!("HTTP/1.1 {}\r\n", status)), "{}", response);
+    }
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_request_timeout() {
+    use std::time::Duration;
+    use web::middleware::timeout::Timeout;
+    use web::models::http::HTTPStatus;
+    use web::testing::TestClient;
+
+    let slow = |millis: u64| {
+        move |_req: &HTTPRequest, res: &mut HTTPResponse, _: &web::router::PathParams| {
+            res.defer(async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                HTTPResponse::error(HTTPStatus::Ok, "done")
+            });
+        }
+    };
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/quick".to_string()), slow(10));
+    router.bind((HTTPMethod::GET, "/slow".to_string()), slow(300));
+    router.bind((HTTPMethod::GET, "/reports/{id}".to_string()), slow(300));
+    let timeout = Timeout::new(Duration::from_millis(100)).route("/reports/{id}", Duration::from_secs(5));
+    assert_eq!(timeout.deadline_for("/reports/7"), Duration::from_secs(5));
+    assert_eq!(timeout.deadline_for("/slow"), Duration::from_millis(100));
+    router.layer(timeout);
+    let client = TestClient::new(router);
+
+    client.get("/quick").send().await.assert_status(HTTPStatus::Ok).assert_body("done");
+    client.get("/slow").send().await.assert_status(HTTPStatus::GatewayTimeout).assert_body("504 Gateway Timeout");
+    client.get("/reports/7").send().await.assert_body("done");
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/slow".to_string()), slow(300));
+    router.layer(Timeout::new(Duration::from_millis(50)).with_status(HTTPStatus::ServiceUnavailable));
+    TestClient::new(router).get("/slow").send().await.assert_status(HTTPStatus::ServiceUnavailable);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_cancellation_on_disconnect() {
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+    use web::models::cancellation::CancellationToken;
+    use web::models::http::HTTPStatus;
+
+    let token = CancellationToken::new();
+    assert_eq!(token.run_until_cancelled(async { 7 }).await, Some(7));
+    token.cancel();
+    assert!(token.is_cancelled());
+    assert_eq!(token.run_until_cancelled(std::future::pending::<()>()).await, None);
+    assert!(!HTTPRequest::default().cancellation().is_cancelled());
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/report".to_string()), move |req: &HTTPRequest, res: &mut HTTPResponse, _: &web::router::PathParams| {
+        let token = req.cancellation();
+        let tx = tx.clone();
+        // work spawned off the request, which outlives its future
+        tokio::spawn(async move {
+            token.cancelled().await;
+            tx.send("cancelled").unwrap();
+        });
+        res.defer(async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            HTTPResponse::error(HTTPStatus::Ok, "report")
+        });
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /report HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    drop(stream);
+    let fired = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await;
+    assert_eq!(fired.unwrap(), Some("cancelled"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_concurrency_limit() {
+    use std::time::Duration;
+    use web::middleware::concurrency::ConcurrencyLimit;
+    use web::models::http::HTTPHeaderType;
+    use web::models::http::HTTPStatus;
+    use web::testing::TestClient;
+
+    let slow = |_req: &HTTPRequest, res: &mut HTTPResponse, _: &web::router::PathParams| {
+        res.defer(async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            HTTPResponse::error(HTTPStatus::Ok, "done")
+        });
+    };
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/slow".to_string()), slow);
+    router.bind((HTTPMethod::GET, "/reports/{id}".to_string()), slow);
+    router.layer(
+        ConcurrencyLimit::new(1)
+            .with_queue(1)
+            .route("/reports/{id}", 2, 0)
+            .with_retry_after(5),
+    );
+    let client = TestClient::new(router);
+
+    // one runs, one waits its turn, one is turned away
+    let (a, b, c) = tokio::join!(
+        client.get("/slow").send(),
+        client.get("/slow").send(),
+        client.get("/slow").send()
+    );
+    let mut statuses = [a.status.code(), b.status.code(), c.status.code()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 200, 503]);
+    let rejected = [a, b, c].into_iter().find(|res| res.status.code() == 503).unwrap();
+    rejected.assert_header(HTTPHeaderType::RetryAfter, "5");
+
+    // reports have slots of their own and no queue
+    let (a, b, c) = tokio::join!(
+        client.get("/reports/1").send(),
+        client.get("/reports/2").send(),
+        client.get("/reports/3").send()
+    );
+    let mut statuses = [a.status.code(), b.status.code(), c.status.code()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 200, 503]);
+    client.get("/slow").send().await.assert_status(HTTPStatus::Ok);
+}
+
+#[tokio::test]
+async fn test_dynamic_routes() {
+    use web::models::http::HTTPStatus;
+    use web::testing::TestClient;
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/hello".to_string()), |_req, res, _| {
+        res.body = Some("static".to_string());
+    });
+    let dynamic = router.dynamic_routes();
+    let client = TestClient::new(router);
+
+    client.get("/plugins/echo").send().await.assert_status(HTTPStatus::NotFound);
+    dynamic.bind((HTTPMethod::GET, "/plugins/{name}".to_string()), |req, res, pattern| {
+        res.body = Some(format!("plugin {}", req.path_params(pattern).unwrap()["name"]));
+    });
+    client.get("/plugins/echo").send().await.assert_body("plugin echo");
+    client
+        .post("/plugins/echo")
+        .send()
+        .await
+        .assert_status(HTTPStatus::MethodNotAllowed)
+        .assert_header(web::models::http::HTTPHeaderType::Allow, "GET");
+
+    // replacing a route, dynamic or bound at startup
+    dynamic.bind((HTTPMethod::GET, "/plugins/{name}".to_string()), |_req, res, _| {
+        res.body = Some("v2".to_string());
+    });
+    client.get("/plugins/echo").send().await.assert_body("v2");
+    dynamic.bind((HTTPMethod::GET, "/hello".to_string()), |_req, res, _| {
+        res.body = Some("dynamic".to_string());
+    });
+    client.get("/hello").send().await.assert_body("dynamic");
+    assert_eq!(
+        dynamic.routes(),
+        vec![
+            (HTTPMethod::GET, "/hello".to_string()),
+            (HTTPMethod::GET, "/plugins/{name}".to_string())
+        ]
+    );
+
+    assert!(dynamic.remove(&(HTTPMethod::GET, "/hello".to_string())));
+    assert!(!dynamic.remove(&(HTTPMethod::GET, "/hello".to_string())));
+    client.get("/hello").send().await.assert_body("static");
+    dynamic.clear();
+    client.get("/plugins/echo").send().await.assert_status(HTTPStatus::NotFound);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_lifecycle_hooks() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let log = Arc::new(Mutex::new(Vec::<String>::new()));
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/ok".to_string()), |_req, res, _| {
+        res.body = Some("ok".to_string());
+    });
+    router.try_bind((HTTPMethod::GET, "/fail".to_string()), |_req, _res, _| {
+        Err(web::models::error::HTTPError::internal("db down"))
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (start, request, response, error, shutdown) = (log.clone(), log.clone(), log.clone(), log.clone(), log.clone());
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .on_start(move || {
+            let log = start.clone();
+            async move { log.lock().unwrap().push("start".to_string()) }
+        })
+        .on_request(move |req| {
+            let log = request.clone();
+            Box::pin(async move { log.lock().unwrap().push(format!("request {}", req.path())) })
+        })
+        .on_response(move |req, res| {
+            let log = response.clone();
+            Box::pin(async move { log.lock().unwrap().push(format!("response {} {}", req.path(), res.status.code())) })
+        })
+        .on_error(move |req, res| {
+            let log = error.clone();
+            Box::pin(async move { log.lock().unwrap().push(format!("error {} {}", req.path(), res.status.code())) })
+        })
+        .on_shutdown(move || {
+            let log = shutdown.clone();
+            async move { log.lock().unwrap().push("shutdown".to_string()) }
+        });
+    let token = server.shutdown_token();
+    let serving = tokio::spawn(async move { server.start().await });
+
+    for path in ["/ok", "/fail"] {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+    }
+    token.cancel();
+    tokio::time::timeout(Duration::from_secs(2), serving).await.unwrap().unwrap().unwrap();
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "start",
+            "request /ok",
+            "response /ok 200",
+            "request /fail",
+            "response /fail 500",
+            "error /fail 500",
+            "shutdown"
+        ]
+    );
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_background_tasks() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = web::config::ServerConfig {
+        background_shutdown_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::from_listener(listener, Router::new(), HashMap::new())
+        .unwrap()
+        .with_config(config);
+    let stopped = Arc::new(AtomicBool::new(false));
+    let stop = server.background_tasks().cancellation();
+    let flag = stopped.clone();
+    server.spawn_background(async move {
+        stop.cancelled().await;
+        flag.store(true, Ordering::SeqCst);
+    });
+    // one that never looks at its token
+    let stubborn = server.spawn_background(std::future::pending());
+    let finished = server.spawn_background(async {});
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(finished.is_finished());
+    assert_eq!(server.background_tasks().len(), 2);
+
+    let token = server.shutdown_token();
+    let tasks = server.background_tasks();
+    let serving = tokio::spawn(async move { server.start().await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    token.cancel();
+    tokio::time::timeout(Duration::from_secs(2), serving).await.unwrap().unwrap().unwrap();
+    assert!(stopped.load(Ordering::SeqCst));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(stubborn.is_finished());
+    assert!(tasks.is_empty());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_scheduled_jobs() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+    use web::scheduler::{CronSchedule, Job};
+
+    // Friday 2024-03-15 10:07:30 UTC
+    let now = UNIX_EPOCH + Duration::from_secs(1710497250);
+    let next = |expr: &str| {
+        expr.parse::<CronSchedule>()
+            .unwrap()
+            .next_after(now)
+            .map(|at| at.duration_since(UNIX_EPOCH).unwrap().as_secs())
+    };
+    assert_eq!(next("*/15 * * * *"), Some(1710497700));
+    assert_eq!(next("0 3 * * 1-5"), Some(1710730800));
+    assert_eq!(next("0 0 13 * 6"), Some(1710547200));
+    assert_eq!(next("@monthly"), Some(1711929600));
+    assert_eq!(next("@yearly"), Some(1735689600));
+    assert_eq!(next("0 0 29 2 *"), Some(1835395200));
+    assert_eq!(next("0 0 31 2 *"), None);
+    for invalid in ["60 * * * *", "* * *", "*/0 * * * *", "5-1 * * * *", "* * * * mon"] {
+        assert!(invalid.parse::<CronSchedule>().is_err(), "{}", invalid);
+    }
+    assert!(Job::cron("bad", "* * *", || async {}).is_err());
+
+    let (runs, slow_runs) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let (counter, slow_counter) = (runs.clone(), slow_runs.clone());
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = web::httpserver::HTTPServer::from_listener(listener, Router::new(), HashMap::new())
+        .unwrap()
+        .schedule(Job::every("tick", Duration::from_millis(20), move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }))
+        // outlasts its period, so most ticks are skipped
+        .schedule(
+            Job::every("slow", Duration::from_millis(20), move || {
+                let counter = slow_counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(150)).await;
+                }
+            })
+            .with_jitter(Duration::from_millis(5)),
+        );
+    let token = server.shutdown_token();
+    let serving = tokio::spawn(async move { server.start().await });
+    tokio::time::sleep(Duration::from_millis(210)).await;
+    token.cancel();
+    tokio::time::timeout(Duration::from_secs(2), serving).await.unwrap().unwrap().unwrap();
+    let ticks = runs.load(Ordering::SeqCst);
+    assert!((4..=11).contains(&ticks), "{}", ticks);
+    assert!((1..=2).contains(&slow_runs.load(Ordering::SeqCst)));
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(runs.load(Ordering::SeqCst), ticks);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_health_endpoints() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use web::health::{Health, HealthReport, HealthStatus};
+    use web::models::http::HTTPHeaderType;
+    use web::models::http::HTTPStatus;
+    use web::testing::TestClient;
+
+    let db_up = Arc::new(AtomicBool::new(true));
+    let db = db_up.clone();
+    let checks = Health::new()
+        .with_timeout(Duration::from_millis(50))
+        .check("db", move || {
+            let up = db.load(Ordering::SeqCst);
+            async move {
+                if up {
+                    Ok(())
+                } else {
+                    Err("connection refused".to_string())
+                }
+            }
+        })
+        .check("cache", || async { Ok(()) });
+    let mut router = Router::new();
+    router.health("/healthz", checks);
+    let client = TestClient::new(router);
+
+    client.get("/healthz").send().await.assert_status(HTTPStatus::Ok).assert_body("ok");
+    let res = client.get("/healthz/ready").send().await;
+    res.assert_status(HTTPStatus::Ok).assert_header(HTTPHeaderType::CacheControl, "no-store");
+    let report: HealthReport = res.json();
+    assert_eq!(report.status, HealthStatus::Ok);
+    assert_eq!(report.checks.len(), 2);
+
+    db_up.store(false, Ordering::SeqCst);
+    let res = client.get("/healthz/ready").send().await;
+    res.assert_status(HTTPStatus::ServiceUnavailable);
+    let report: HealthReport = res.json();
+    assert_eq!(report.status, HealthStatus::Fail);
+    assert_eq!(report.checks["db"].error.as_deref(), Some("connection refused"));
+    assert_eq!(report.checks["cache"].status, HealthStatus::Ok);
+    // liveness doesn't run the checks
+    client.get("/healthz").send().await.assert_status(HTTPStatus::Ok);
+
+    let hung = Health::new()
+        .with_timeout(Duration::from_millis(20))
+        .check("upstream", std::future::pending);
+    let report = hung.run().await;
+    assert_eq!(report.checks["upstream"].error.as_deref(), Some("timed out"));
+}
+
+#[cfg(all(feature = "toml", feature = "compression"))]
+#[tokio::test]
+async fn test_config_from_file_and_env() {
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::config::ServerConfig;
+
+    let config = ServerConfig::from_toml(
+        r#"
+        first_byte_timeout = "1m"
+        write_timeout = 10
+        header_read_timeout = "500ms"
+        max_body_bytes = 1048576
+        trusted_proxies = ["10.0.0.0/8", "127.0.0.1"]
+        request_log = true
+
+        [tls]
+        cert = "/etc/web/cert.pem"
+        key = "/etc/web/key.pem"
+
+        [compression]
+        level = 9
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.first_byte_timeout, Some(Duration::from_secs(60)));
+    assert_eq!(config.write_timeout, Some(Duration::from_secs(10)));
+    assert_eq!(config.header_read_timeout, Some(Duration::from_millis(500)));
+    assert_eq!(config.max_body_bytes, Some(1048576));
+    assert!(config.trusted_proxies.is_trusted("10.1.2.3".parse().unwrap()));
+    assert!(config.request_log);
+    assert_eq!(config.tls_cert, Some("/etc/web/cert.pem".into()));
+    assert_eq!(config.compression.as_ref().map(|c| c.level), Some(9));
+
+    assert!(ServerConfig::from_toml("idle_timout = 5").unwrap_err().contains("Unknown setting: idle_timout"));
+    assert!(ServerConfig::from_toml("first_byte_timeout = \"soon\"").is_err());
+    assert!(ServerConfig::from_toml("max_connections = -1").is_err());
+    let unlimited = ServerConfig::from_toml("max_header_bytes = \"unlimited\"").unwrap();
+    assert_eq!(unlimited.max_header_bytes, None);
+    assert_eq!(unlimited.max_body_bytes, Some(web::config::DEFAULT_MAX_BODY_BYTES));
+    assert!(ServerConfig::from_file("/nonexistent/server.toml").is_err());
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    std::env::set_var("WEB_BIND", format!("127.0.0.1:{}", port));
+    std::env::set_var("WEB_MAX_HEADER_BYTES", "4096");
+    std::env::set_var("WEB_COMPRESSION", "true");
+    std::env::set_var("WEB_UNRELATED", "ignored");
+    let config = ServerConfig::from_toml("max_header_bytes = 100\ncompression_min_size = 10").unwrap().merge_env().unwrap();
+    std::env::remove_var("WEB_BIND");
+    std::env::remove_var("WEB_MAX_HEADER_BYTES");
+    std::env::remove_var("WEB_COMPRESSION");
+    std::env::remove_var("WEB_UNRELATED");
+    assert_eq!(config.max_header_bytes, Some(4096));
+    // `compression = true` starts again from the defaults
+    assert_eq!(config.compression.as_ref().map(|c| c.min_size), Some(1024));
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/".to_string()), |_req, res, _| {
+        res.text("hello ".repeat(400));
+    });
+    let server = web::httpserver::HTTPServer::new(0, router, HashMap::new()).with_config(config);
+    tokio::spawn(async move { server.start().await });
+    let mut stream = loop {
+        match tokio::net::TcpStream::connect(("127.0.0.1", port)).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let head = String::from_utf8_lossy(&response).to_ascii_lowercase();
+    assert!(head.starts_with("http/1.1 200 ok\r\n"), "{}", head);
+    assert!(head.contains("content-encoding: gzip"), "{}", head);
+}
+
+#[cfg(feature = "toml")]
+#[tokio::test]
+async fn test_config_reload() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let path = std::env::temp_dir().join(format!("web-reload-{}.toml", std::process::id()));
+    std::fs::write(&path, "max_body_bytes = 1000\n[upstreams]\napi = [\"http://10.0.0.1\"]\n").unwrap();
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::POST, "/".to_string()), |req, res, _pattern| {
+        res.body = req.body.clone();
+    });
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let record = Arc::clone(&seen);
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .with_config_file(&path)
+        .unwrap()
+        .on_reload(move |config| {
+            record.lock().unwrap().push(config.upstreams.get("api").cloned().unwrap_or_default());
+            Ok(())
+        });
+    let reloader = server.reloader();
+    tokio::spawn(async move { server.start().await });
+
+    let request = format!("POST / HTTP/1.1\r\nContent-Length: 100\r\n\r\n{}", "a".repeat(100));
+    let send = |mut stream: tokio::net::TcpStream, request: String| async move {
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+    let connect = || async {
+        loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+    };
+
+    let response = send(connect().await, request.clone()).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+
+    // accepted before the reload, so it keeps the old limits
+    let open = connect().await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::write(&path, "max_body_bytes = 10\nupstreams_api = [\"http://10.0.0.2\", \"http://10.0.0.3\"]\n").unwrap();
+    reloader.reload().unwrap();
+    assert_eq!(reloader.current().max_body_bytes, Some(10));
+
+    let response = send(connect().await, request.clone()).await;
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{}", response);
+    let response = send(open, request.clone()).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            vec!["http://10.0.0.1".to_string()],
+            vec!["http://10.0.0.2".to_string(), "http://10.0.0.3".to_string()],
+        ]
+    );
+
+    // a broken file leaves the running config alone
+    std::fs::write(&path, "max_body_bytes = \"lots\"\n").unwrap();
+    assert!(reloader.reload().is_err());
+    assert_eq!(reloader.current().max_body_bytes, Some(10));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn test_tower_interop() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use tower_layer::Layer;
+    use tower_service::Service;
+    use web::middleware::BoxFuture;
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+    use web::testing::TestClient;
+    use web::tower::{BoxError, TowerLayer};
+
+    // tags responses and counts requests across the clones it is served by
+    #[derive(Clone)]
+    struct Tag<S> {
+        inner: S,
+        seen: Arc<AtomicUsize>,
+    }
+
+    impl<S> Service<HTTPRequest> for Tag<S>
+    where
+        S: Service<HTTPRequest, Response = HTTPResponse, Error = BoxError> + Send + 'static,
+        S::Future: Send,
+    {
+        type Response = HTTPResponse;
+        type Error = BoxError;
+        type Future = BoxFuture<'static, Result<HTTPResponse, BoxError>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: HTTPRequest) -> Self::Future {
+            let seen = self.seen.fetch_add(1, Ordering::SeqCst) + 1;
+            if req.url == "/refused" {
+                return Box::pin(async { Err("refused".into()) });
+            }
+            let call = self.inner.call(req);
+            Box::pin(async move {
+                let mut res = call.await?;
+                res.set_header(HTTPHeaderType::Other("X-Seen".into()), seen.to_string());
+                Ok(res)
+            })
+        }
+    }
+
+    struct TagLayer(Arc<AtomicUsize>);
+
+    impl<S> Layer<S> for TagLayer {
+        type Service = Tag<S>;
+        fn layer(&self, inner: S) -> Tag<S> {
+            Tag {
+                inner,
+                seen: Arc::clone(&self.0),
+            }
+        }
+    }
+
+    let seen = Arc::new(AtomicUsize::new(0));
+    let mut router = Router::new();
+    router.layer(TowerLayer::new(TagLayer(Arc::clone(&seen))));
+    router.bind((HTTPMethod::GET, "/hello".to_string()), |_req, res, _| {
+        res.text("hello");
+    });
+    let mut inner = Router::new();
+    inner.bind((HTTPMethod::GET, "/nested".to_string()), |_req, res, _| {
+        res.text("from a service");
+    });
+    router.bind_service((HTTPMethod::GET, "/nested".to_string()), inner.into_service());
+
+    let client = TestClient::new(router);
+    client.get("/hello").send().await.assert_status(HTTPStatus::Ok).assert_header(HTTPHeaderType::Other("X-Seen".into()), "1").assert_body("hello");
+    client
+        .get("/nested")
+        .send()
+        .await
+        .assert_status(HTTPStatus::Ok)
+        .assert_header(HTTPHeaderType::Other("X-Seen".into()), "2")
+        .assert_body("from a service");
+    client.get("/refused").send().await.assert_status(HTTPStatus::InternalServerError);
+    assert_eq!(seen.load(Ordering::SeqCst), 3);
+
+    // and the other way round, the router as a service
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/".to_string()), |_req, res, _| {
+        res.text("served");
+    });
+    let mut service = router.into_service();
+    std::future::poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+    let req = HTTPRequest {
+        method: HTTPMethod::GET,
+        url: "/".to_string(),
+        ..Default::default()
+    };
+    let res = service.call(req).await.unwrap();
+    assert_eq!(res.status, HTTPStatus::Ok);
+    assert_eq!(res.body.as_deref(), Some("served"));
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn test_http_crate_conversions() {
+    use bytes::Bytes;
+    use web::models::http::{HTTPHeaderType, HTTPStatus, HTTPVersion};
+
+    assert_eq!(http::Method::from(HTTPMethod::PATCH), http::Method::PATCH);
+    assert_eq!(HTTPMethod::try_from(http::Method::DELETE), Ok(HTTPMethod::DELETE));
+    assert!(HTTPMethod::try_from(http::Method::TRACE).is_err());
+    assert_eq!(http::StatusCode::from(HTTPStatus::NotFound), http::StatusCode::NOT_FOUND);
+    assert_eq!(HTTPStatus::try_from(http::StatusCode::CREATED), Ok(HTTPStatus::Created));
+    assert!(HTTPStatus::try_from(http::StatusCode::from_u16(599).unwrap()).is_err());
+
+    let req = http::Request::builder()
+        .method("POST")
+        .uri("https://example.com/items?page=2")
+        .header("content-type", "application/json")
+        .header("accept", "text/html")
+        .header("accept", "application/json")
+        .body(Bytes::from_static(b"{\"name\":\"a\"}"))
+        .unwrap();
+    let req = HTTPRequest::try_from(req).unwrap();
+    assert_eq!(req.method, HTTPMethod::POST);
+    assert_eq!(req.url, "/items?page=2");
+    assert_eq!(req.version, HTTPVersion::HTTP1_1);
+    assert_eq!(req.headers.get(&HTTPHeaderType::Host).map(String::as_str), Some("example.com"));
+    assert_eq!(
+        req.headers.get(&HTTPHeaderType::Accept).map(String::as_str),
+        Some("text/html, application/json")
+    );
+    assert_eq!(req.body.as_deref(), Some("{\"name\":\"a\"}"));
+
+    let back = http::Request::<Bytes>::try_from(req).unwrap();
+    assert_eq!(back.method(), http::Method::POST);
+    assert_eq!(back.uri(), "/items?page=2");
+    assert_eq!(back.headers()["content-type"], "application/json");
+    assert_eq!(back.body(), &Bytes::from_static(b"{\"name\":\"a\"}"));
+
+    let binary = http::Request::new(Bytes::from_static(&[0xff, 0xfe]));
+    assert!(HTTPRequest::try_from(binary).is_err());
+
+    let mut res = HTTPResponse {
+        status: HTTPStatus::Accepted,
+        ..Default::default()
+    };
+    res.text("queued");
+    let converted = http::Response::<Bytes>::try_from(res).unwrap();
+    assert_eq!(converted.status(), http::StatusCode::ACCEPTED);
+    assert_eq!(converted.body(), &Bytes::from_static(b"queued"));
+    assert!(converted.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+
+    let upstream = http::Response::builder()
+        .status(200)
+        .header("content-type", "image/png")
+        .body(Bytes::from_static(&[0x89, b'P', b'N', b'G', 0xff]))
+        .unwrap();
+    let res = HTTPResponse::try_from(upstream).unwrap();
+    assert_eq!(res.status, HTTPStatus::Ok);
+    assert_eq!(res.body, None);
+    assert_eq!(res.body_bytes(), &[0x89, b'P', b'N', b'G', 0xff]);
+
+    let mut deferred = HTTPResponse::default();
+    deferred.defer(async { HTTPResponse::default() });
+    assert!(http::Response::<Bytes>::try_from(deferred).is_err());
+}
+
+#[cfg(all(feature = "hyper", feature = "http2"))]
+#[tokio::test]
+async fn test_hyper_backend() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/hello".to_string()), |req, res, _pattern| {
+        res.text(format!("hello over {:?}", req.version));
+    });
+    router.bind((HTTPMethod::POST, "/echo".to_string()), |req, res, _pattern| {
+        let body = req.body.clone().unwrap_or_default();
+        res.defer(async move {
+            let mut res = HTTPResponse::default();
+            res.text(body);
+            res
+        });
+    });
+    let config = web::config::ServerConfig {
+        hyper: true,
+        h2c: true,
+        max_body_bytes: Some(8),
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new())
+        .unwrap()
+        .with_config(config);
+    tokio::spawn(async move { server.start().await });
+
+    // two requests on one kept-alive connection
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 4\r\n\r\npingGET /hello HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("\r\n\r\npingHTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("hello over HTTP1_1"), "{}", response);
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 9\r\nConnection: close\r\n\r\n123456789")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{}", response);
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (mut client, connection) = h2::client::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let request = http::Request::get("http://localhost/hello").body(()).unwrap();
+    let (response, _) = client.send_request(request, true).unwrap();
+    let mut body = response.await.unwrap().into_body();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(String::from_utf8(data).unwrap(), "hello over HTTP2");
+}
+
+#[cfg(feature = "lambda")]
+#[tokio::test]
+async fn test_lambda_events() {
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use web::lambda::{handle_event, LambdaEvent};
+    use web::middleware::Pipeline;
+    use web::models::http::HTTPHeaderType;
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::POST, "/items/{id}".to_string()), |req, res, _pattern| {
+        res.set_header(HTTPHeaderType::SetCookie, "seen=1");
+        res.text(format!(
+            "{} {} {} {}",
+            req.url,
+            req.header(&HTTPHeaderType::Other("x-tenant".into())).unwrap_or("-"),
+            req.real_ip().map(|ip| ip.to_string()).unwrap_or_default(),
+            req.body.clone().unwrap_or_default(),
+        ));
+    });
+    router.bind((HTTPMethod::GET, "/logo.png".to_string()), |_req, res, _pattern| {
+        res.set_raw_body(vec![0x89, b'P', b'N', b'G', 0xff]);
+    });
+    let pipeline = Pipeline::new(Arc::new(router));
+
+    let v1 = json!({
+        "httpMethod": "POST",
+        "path": "/items/7",
+        "headers": {"x-tenant": "acme"},
+        "multiValueHeaders": null,
+        "queryStringParameters": {"q": "a b"},
+        "multiValueQueryStringParameters": null,
+        "body": "aGVsbG8=",
+        "isBase64Encoded": true,
+        "requestContext": {"identity": {"sourceIp": "203.0.113.9"}}
+    });
+    assert!(matches!(LambdaEvent::from_json(v1.clone()), Ok(LambdaEvent::ApiGatewayV1(_))));
+    let res = handle_event(&pipeline, v1).await.unwrap();
+    assert_eq!(res["statusCode"], 200);
+    assert_eq!(res["body"], "/items/7?q=a%20b acme 203.0.113.9 hello");
+    assert_eq!(res["headers"]["Set-Cookie"], "seen=1");
+    assert_eq!(res["isBase64Encoded"], false);
+
+    let v2 = json!({
+        "version": "2.0",
+        "rawPath": "/items/8",
+        "rawQueryString": "q=x%26y",
+        "cookies": ["a=1", "b=2"],
+        "headers": {"x-tenant": "globex"},
+        "body": "hi",
+        "isBase64Encoded": false,
+        "requestContext": {"http": {"method": "POST", "sourceIp": "198.51.100.4"}}
+    });
+    let res = handle_event(&pipeline, v2).await.unwrap();
+    assert_eq!(res["body"], "/items/8?q=x%26y globex 198.51.100.4 hi");
+    assert_eq!(res["cookies"], json!(["seen=1"]));
+    assert!(res["headers"].get("Set-Cookie").is_none());
+
+    let alb = json!({
+        "httpMethod": "GET",
+        "path": "/logo.png",
+        "multiValueHeaders": {"accept": ["image/png"]},
+        "multiValueQueryStringParameters": {},
+        "body": "",
+        "isBase64Encoded": false,
+        "requestContext": {"elb": {"targetGroupArn": "arn:aws:elasticloadbalancing:..."}}
+    });
+    let res = handle_event(&pipeline, alb).await.unwrap();
+    assert_eq!(res["statusDescription"], "200 OK");
+    assert_eq!(res["isBase64Encoded"], true);
+    assert_eq!(res["body"], "iVBOR/8=");
+    assert!(res["multiValueHeaders"].is_object());
+
+    assert!(handle_event(&pipeline, json!({"source": "aws.events"})).await.is_err());
+
+    // the runtime API, faked: one invocation, then the answer is recorded
+    let answers = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&answers);
+    let mut runtime = Router::new();
+    runtime.bind((HTTPMethod::GET, "/2018-06-01/runtime/invocation/next".to_string()), |_req, res, _pattern| {
+        res.set_header(HTTPHeaderType::Other("Lambda-Runtime-Aws-Request-Id".into()), "req-1");
+        res.text(json!({"version": "2.0", "rawPath": "/missing", "requestContext": {"http": {"method": "GET"}}}).to_string());
+    });
+    runtime.bind((HTTPMethod::POST, "/2018-06-01/runtime/invocation/{id}/response".to_string()), move |req, res, _pattern| {
+        recorded.lock().unwrap().push((req.url.clone(), req.body.clone().unwrap_or_default()));
+        res.status = web::models::http::HTTPStatus::Accepted;
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    std::env::set_var("AWS_LAMBDA_RUNTIME_API", listener.local_addr().unwrap().to_string());
+    let server = web::httpserver::HTTPServer::from_listener(listener, runtime, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    tokio::spawn(web::lambda::run(Router::new()));
+    for _ in 0..100 {
+        if !answers.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    std::env::remove_var("AWS_LAMBDA_RUNTIME_API");
+    let (url, body) = answers.lock().unwrap()[0].clone();
+    assert_eq!(url, "/2018-06-01/runtime/invocation/req-1/response");
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(response["statusCode"], 404);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_custom_transport() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio::sync::{mpsc, Mutex};
+    use web::middleware::{BoxFuture, Pipeline};
+    use web::models::connection::ConnectionInfo;
+    use web::transport::{Stream, Transport};
+
+    /// streams handed over through a channel, as a WASM host might
+    struct Handoff(Mutex<mpsc::Receiver<DuplexStream>>);
+
+    impl Transport for Handoff {
+        fn accept(&self) -> BoxFuture<'_, std::io::Result<(Box<dyn Stream>, ConnectionInfo)>> {
+            Box::pin(async move {
+                let stream = self.0.lock().await.recv().await.ok_or(std::io::ErrorKind::ConnectionAborted)?;
+                Ok((Box::new(stream) as Box<dyn Stream>, ConnectionInfo::default()))
+            })
+        }
+
+        fn address(&self) -> std::io::Result<String> {
+            Ok(String::from("handoff"))
+        }
+    }
+
+    let router = || {
+        let mut router = Router::new();
+        router.bind((HTTPMethod::GET, "/hello".to_string()), |req, res, _pattern| {
+            res.text(format!("hello {}", req.url));
+        });
+        router
+    };
+    let (handoff, streams) = mpsc::channel(1);
+    let server = web::httpserver::HTTPServer::from_transport(Handoff(Mutex::new(streams)), router(), HashMap::new());
+    tokio::spawn(async move { server.start().await });
+
+    let (mut client, stream) = tokio::io::duplex(4096);
+    handoff.send(stream).await.unwrap();
+    client.write_all(b"GET /hello?via=transport HTTP/1.1\r\nHost: a\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("hello /hello?via=transport"), "{}", response);
+
+    // a single stream, without a server
+    let pipeline = std::sync::Arc::new(Pipeline::new(std::sync::Arc::new(router())));
+    let (mut client, stream) = tokio::io::duplex(4096);
+    let served = tokio::spawn(web::httpserver::serve_connection(stream, pipeline));
+    client.write_all(b"GET /hello HTTP/1.1\r\nHost: a\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).await.unwrap();
+    assert!(response.ends_with("hello /hello"), "{}", response);
+    served.await.unwrap().unwrap();
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_tcp_server_with_codec() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::tcp::{Codec, Server};
+
+    /// frames end with `;`
+    #[derive(Clone)]
+    struct Semicolons;
+
+    impl Codec for Semicolons {
+        type Frame = String;
+
+        fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Option<String>, String> {
+            let Some(end) = buf.iter().position(|&b| b == b';') else {
+                return Ok(None);
+            };
+            let frame: Vec<u8> = buf.drain(..=end).collect();
+            String::from_utf8(frame[..end].to_vec()).map(Some).map_err(|e| e.to_string())
+        }
+
+        fn encode(&mut self, frame: String, buf: &mut Vec<u8>) -> Result<(), String> {
+            buf.extend_from_slice(frame.as_bytes());
+            buf.push(b';');
+            Ok(())
+        }
+    }
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::from_listener(listener, Semicolons, |frame: String, peer| async move {
+        match frame.as_str() {
+            "quiet" => None,
+            "peer" => Some(peer.peer_addr.is_some().to_string()),
+            _ => Some(frame.to_uppercase()),
+        }
+    });
+    let stop = server.shutdown_token();
+    let serving = tokio::spawn(async move { server.start().await });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    // frames split across writes and several in one
+    stream.write_all(b"hel").await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    stream.write_all(b"lo;quiet;peer;bye;").await.unwrap();
+    stream.shutdown().await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert_eq!(response, "HELLO;true;BYE;");
+
+    stop.cancel();
+    serving.await.unwrap().unwrap();
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_tcp_codecs() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::tcp::{Codec, LengthDelimitedCodec, LinesCodec, Server};
+
+    let mut lines = LinesCodec::with_max_length(5);
+    let mut buf = b"one\r\ntwo\nthr".to_vec();
+    assert_eq!(lines.decode(&mut buf), Ok(Some(String::from("one"))));
+    assert_eq!(lines.decode(&mut buf), Ok(Some(String::from("two"))));
+    assert_eq!(lines.decode(&mut buf), Ok(None));
+    buf.extend_from_slice(b"eeeee");
+    assert!(lines.decode(&mut buf).is_err());
+    let mut out = Vec::new();
+    lines.encode(String::from("hi"), &mut out).unwrap();
+    assert_eq!(out, b"hi\n");
+    assert!(lines.encode(String::from("a\nb"), &mut out).is_err());
+
+    let mut frames = LengthDelimitedCodec::with_max_frame_length(4).length_field_bytes(2);
+    let mut buf = vec![0, 3, b'a', b'b'];
+    assert_eq!(frames.decode(&mut buf), Ok(None));
+    buf.extend_from_slice(&[b'c', 0, 0]);
+    assert_eq!(frames.decode(&mut buf), Ok(Some(b"abc".to_vec())));
+    assert_eq!(frames.decode(&mut buf), Ok(Some(Vec::new())));
+    assert!(buf.is_empty());
+    assert!(frames.decode(&mut vec![0, 5]).is_err());
+    let mut out = Vec::new();
+    frames.encode(b"xy".to_vec(), &mut out).unwrap();
+    assert_eq!(out, [0, 2, b'x', b'y']);
+    assert!(frames.encode(b"too long".to_vec(), &mut out).is_err());
+
+    // a length-prefixed echo server
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::from_listener(listener, LengthDelimitedCodec::new(), |frame: Vec<u8>, _peer| async move {
+        Some(frame.into_iter().rev().collect())
+    });
+    tokio::spawn(async move { server.start().await });
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0, 0, 0, 3, 1, 2, 3, 0, 0, 0, 1, 9]).await.unwrap();
+    stream.shutdown().await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert_eq!(response, [0, 0, 0, 3, 3, 2, 1, 0, 0, 0, 1, 9]);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_udp_server_and_client() {
+    use std::time::Duration;
+    use web::udp::{Peer, UdpClient, UdpServer};
+
+    // replies `<id><datagrams seen from this peer>`, nothing to `q`
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap().to_string();
+    let server = UdpServer::from_socket(socket, |datagram: Vec<u8>, peer: Peer<u8>| async move {
+        let mut seen = peer.state.lock().await;
+        *seen += 1;
+        (datagram[1] != b'q').then(|| vec![datagram[0], *seen])
+    });
+    let stop = server.shutdown_token();
+    let serving = tokio::spawn(async move { server.start().await });
+
+    let client = UdpClient::connect(&addr).await.unwrap().timeout(Duration::from_millis(200));
+    assert_eq!(client.request(b"ax").await.unwrap(), [b'a', 1]);
+    assert_eq!(client.request(b"bx").await.unwrap(), [b'b', 2]);
+    let err = client.request(b"cq").await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+    // concurrent requests matched by their first byte, each peer counted apart
+    let client = UdpClient::connect(&addr)
+        .await
+        .unwrap()
+        .timeout(Duration::from_millis(200))
+        .retries(1)
+        .correlate(|datagram| datagram.first().map(|&id| id as u64));
+    let (x, y) = tokio::join!(client.request(b"xx"), client.request(b"yx"));
+    let mut replies = [x.unwrap(), y.unwrap()];
+    replies.sort();
+    assert_eq!(replies[0][0], b'x');
+    assert_eq!(replies[1][0], b'y');
+    let mut counts = [replies[0][1], replies[1][1]];
+    counts.sort();
+    assert_eq!(counts, [1, 2]);
+    // a quiet request is sent twice before giving up
+    let err = client.request(b"zq").await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    assert_eq!(client.request(b"wx").await.unwrap(), [b'w', 5]);
+
+    stop.cancel();
+    serving.await.unwrap().unwrap();
+}
+
+/// the RCODE and `(owner, type, rdata)` records a fake zone answers a
+/// name and type with
+#[cfg(all(feature = "client", feature = "server"))]
+type DnsZone = fn(&str, u16) -> (u8, Vec<(String, u16, Vec<u8>)>);
+
+/// `name` as DNS labels
+#[cfg(all(feature = "client", feature = "server"))]
+fn dns_name(name: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for label in name.split('.') {
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    encoded
+}
+
+/// The answer to `query` from `zone`, all records with a TTL of 60. With
+/// `truncate`, answers with records are cut to just the header and TC.
+#[cfg(all(feature = "client", feature = "server"))]
+fn dns_response(query: &[u8], zone: DnsZone, truncate: bool) -> Vec<u8> {
+    let mut at = 12;
+    let mut labels = Vec::new();
+    while query[at] != 0 {
+        let length = query[at] as usize;
+        labels.push(String::from_utf8_lossy(&query[at + 1..at + 1 + length]).to_string());
+        at += 1 + length;
+    }
+    let question = &query[12..at + 5];
+    let kind = u16::from_be_bytes([query[at + 1], query[at + 2]]);
+    let (rcode, records) = zone(&labels.join("."), kind);
+    let truncated = truncate && !records.is_empty();
+    let mut response = query[..2].to_vec();
+    let flags: u16 = 0x8180 | u16::from(rcode) | if truncated { 0x0200 } else { 0 };
+    response.extend_from_slice(&flags.to_be_bytes());
+    let count = if truncated { 0 } else { records.len() as u16 };
+    for count in [1, count, 0, 0] {
+        response.extend_from_slice(&count.to_be_bytes());
+    }
+    response.extend_from_slice(question);
+    for (owner, kind, data) in records.iter().take(count as usize) {
+        response.extend(dns_name(owner));
+        response.extend_from_slice(&kind.to_be_bytes());
+        response.extend_from_slice(&[0, 1, 0, 0, 0, 60]);
+        response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        response.extend_from_slice(data);
+    }
+    response
+}
+
+/// a nameserver answering from `zone` over UDP, truncating if `truncate`,
+/// and TCP on the same port; returns its address and the number of
+/// queries it got
+#[cfg(all(feature = "client", feature = "server"))]
+async fn fake_nameserver(
+    zone: DnsZone,
+    truncate: bool,
+) -> (std::net::SocketAddr, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use web::tcp::LengthDelimitedCodec;
+
+    let (udp, tcp) = loop {
+        let udp = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        if let Ok(tcp) = std::net::TcpListener::bind(udp.local_addr().unwrap()) {
+            break (udp, tcp);
+        }
+    };
+    let addr = udp.local_addr().unwrap();
+    let queries = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&queries);
+    let server = web::udp::UdpServer::from_socket(udp, move |query: Vec<u8>, _peer: web::udp::Peer| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        async move { Some(dns_response(&query, zone, truncate)) }
+    });
+    tokio::spawn(async move { server.start().await });
+    let codec = LengthDelimitedCodec::new().length_field_bytes(2);
+    let server = web::tcp::Server::from_listener(tcp, codec, move |query: Vec<u8>, _peer| async move {
+        Some(dns_response(&query, zone, false))
+    });
+    tokio::spawn(async move { server.start().await });
+    (addr, queries)
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+#[tokio::test]
+async fn test_dns_resolver() {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use web::dns::{RecordData, RecordType, Resolver, ResolverConfig};
+
+    let config = ResolverConfig::from_resolv_conf(
+        "# comment\nnameserver 10.0.0.1\nnameserver fe80::1%eth0\nnameserver ::1\nsearch corp.test test.\noptions ndots:2 timeout:1 attempts:3 rotate\n",
+    );
+    assert_eq!(config.nameservers, ["10.0.0.1:53".parse().unwrap(), "[::1]:53".parse().unwrap()]);
+    assert_eq!(config.search, ["corp.test", "test"]);
+    assert_eq!((config.ndots, config.timeout, config.attempts), (2, Duration::from_secs(1), 3));
+    assert_eq!(ResolverConfig::from_resolv_conf("").nameservers, ResolverConfig::default().nameservers);
+
+    fn zone(name: &str, kind: u16) -> (u8, Vec<(String, u16, Vec<u8>)>) {
+        let owner = name.to_string();
+        match (name, kind) {
+            ("www.test", 1) => (0, vec![
+                (owner, 5, dns_name("web.test")),
+                (String::from("web.test"), 1, vec![10, 0, 0, 1]),
+            ]),
+            // the alias's target has to be asked for separately
+            ("alias.test", 1) => (0, vec![(owner, 5, dns_name("web.test"))]),
+            ("web.test", 1) => (0, vec![(owner, 1, vec![10, 0, 0, 1])]),
+            ("host.test", 1) | ("app.test", 1) => (0, vec![(owner, 1, vec![127, 0, 0, 1])]),
+            ("big.test", 16) => (0, vec![(owner, 16, b"\x05hello\x05world".to_vec())]),
+            ("_http._tcp.test", 33) => {
+                let mut data = vec![0, 10, 0, 5, 0x1f, 0x90];
+                data.extend(dns_name("web.test"));
+                (0, vec![(owner, 33, data)])
+            }
+            (_, 1) | (_, 16) | (_, 33) if name.ends_with(".test") && !name.starts_with("missing") => (0, Vec::new()),
+            _ => (3, Vec::new()),
+        }
+    }
+    let (nameserver, queries) = fake_nameserver(zone, false).await;
+    let resolver = Resolver::new(ResolverConfig {
+        nameservers: vec![nameserver],
+        search: vec![String::from("test")],
+        timeout: Duration::from_millis(500),
+        ..Default::default()
+    });
+
+    let records = resolver.lookup("www.test", RecordType::A).await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].name, "web.test");
+    assert_eq!(records[0].ttl, 60);
+    assert_eq!(records[0].data, RecordData::A(Ipv4Addr::new(10, 0, 0, 1)));
+    let asked = queries.load(Ordering::SeqCst);
+    resolver.lookup("www.test", RecordType::A).await.unwrap();
+    assert_eq!(queries.load(Ordering::SeqCst), asked, "answered from the cache");
+    assert_eq!(resolver.lookup("alias.test", RecordType::A).await.unwrap(), records);
+
+    // no dot, so the search domain comes first
+    let records = resolver.lookup("host", RecordType::A).await.unwrap();
+    assert_eq!(records[0].name, "host.test");
+    let srv = resolver.lookup("_http._tcp.test", RecordType::SRV).await.unwrap();
+    assert_eq!(
+        srv[0].data,
+        RecordData::SRV { priority: 10, weight: 5, port: 8080, target: String::from("web.test") }
+    );
+    let err = resolver.lookup("missing.test", RecordType::A).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    assert_eq!(resolver.lookup_ip("[::1]").await.unwrap(), [IpAddr::from([0u16, 0, 0, 0, 0, 0, 0, 1])]);
+
+    // a truncated UDP answer is asked for again over TCP
+    let (nameserver, _) = fake_nameserver(zone, true).await;
+    let resolver = Resolver::new(ResolverConfig {
+        nameservers: vec![nameserver],
+        ..Default::default()
+    });
+    let txt = resolver.lookup("big.test", RecordType::TXT).await.unwrap();
+    assert_eq!(txt[0].data, RecordData::TXT(vec![String::from("hello"), String::from("world")]));
+
+    // an answer with the right id but another question is ignored
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let nameserver = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut query = [0; 512];
+        let (read, peer) = socket.recv_from(&mut query).await.unwrap();
+        let query = &query[..read];
+        let mut other = query[..12].to_vec();
+        other.extend(dns_name("web.test"));
+        other.extend_from_slice(&[0, 1, 0, 1]);
+        socket.send_to(&dns_response(&other, zone, false), peer).await.unwrap();
+        socket.send_to(&dns_response(query, zone, false), peer).await.unwrap();
+    });
+    let spoofed = Resolver::new(ResolverConfig {
+        nameservers: vec![nameserver],
+        ..Default::default()
+    });
+    let records = spoofed.lookup("host.test", RecordType::A).await.unwrap();
+    assert_eq!(records[0].data, RecordData::A(Ipv4Addr::LOCALHOST));
+
+    // the client looks hosts up with it
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/".to_string()), |req, res, _pattern| {
+        res.text(req.headers.get(&web::models::http::HTTPHeaderType::Host).cloned().unwrap_or_default());
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    let client = web::client::HTTPClient::builder().resolver(resolver).build();
+    let res = client.get(&format!("http://app.test:{}/", port)).send().await.unwrap();
+    assert_eq!(res.body, Some(format!("app.test:{}", port)));
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+#[tokio::test]
+async fn test_dns_over_https() {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::dns::{DohMethod, DohServer, RecordData, RecordType, Resolver, ResolverConfig};
+
+    fn zone(name: &str, kind: u16) -> (u8, Vec<(String, u16, Vec<u8>)>) {
+        match (name, kind) {
+            ("secret.test", 1) => (0, vec![(name.to_string(), 1, vec![127, 0, 0, 1])]),
+            (_, 28) if name == "secret.test" => (0, Vec::new()),
+            _ => (3, Vec::new()),
+        }
+    }
+
+    fn base64url(text: &str) -> Vec<u8> {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let bits: Vec<u8> = text.bytes().map(|c| ALPHABET.iter().position(|&a| a == c).unwrap() as u8).collect();
+        let mut bytes = Vec::new();
+        let (mut buffer, mut count) = (0u32, 0);
+        for value in bits {
+            buffer = buffer << 6 | u32::from(value);
+            count += 6;
+            if count >= 8 {
+                count -= 8;
+                bytes.push((buffer >> count) as u8);
+            }
+        }
+        bytes
+    }
+
+    // a DoH endpoint answering from `zone`, logging each request line and content type
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/dns-query", listener.local_addr().unwrap());
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&seen);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            let head_end = loop {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break end + 4;
+                }
+            };
+            let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+            let length: usize = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .map_or(0, |length| length.trim().parse().unwrap());
+            while request.len() < head_end + length {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            // the query parameter is case-sensitive
+            let line = String::from_utf8_lossy(&request[..head_end]).lines().next().unwrap().to_string();
+            let query = match line.split_once("?dns=") {
+                Some((_, rest)) => base64url(rest.split(' ').next().unwrap()),
+                None => request[head_end..].to_vec(),
+            };
+            assert_eq!(&query[..2], [0, 0], "DoH queries use id 0");
+            let content_type = head.lines().find_map(|l| l.strip_prefix("content-type: ")).map(str::to_string);
+            log.lock().unwrap().push((line.split(' ').next().unwrap().to_lowercase(), content_type));
+            let answer = dns_response(&query, zone, false);
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                answer.len()
+            )
+            .into_bytes();
+            response.extend(answer);
+            stream.write_all(&response).await.unwrap();
+        }
+    });
+
+    let resolver = Resolver::dns_over_https(&url);
+    assert_eq!(
+        resolver.lookup("secret.test", RecordType::A).await.unwrap()[0].data,
+        RecordData::A(Ipv4Addr::LOCALHOST)
+    );
+    assert!(seen.lock().unwrap().iter().all(|(method, _)| method == "get"));
+
+    let resolver = Resolver::new(ResolverConfig {
+        doh: Some(DohServer::new(&url).method(DohMethod::Post)),
+        ..Default::default()
+    });
+    seen.lock().unwrap().clear();
+    assert_eq!(resolver.lookup_ip("secret.test").await.unwrap(), [IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+    let err = resolver.lookup("nothing.test", RecordType::A).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    let seen = seen.lock().unwrap().clone();
+    assert_eq!(seen.len(), 3);
+    assert!(seen
+        .iter()
+        .all(|(method, content_type)| method == "post" && content_type.as_deref() == Some("application/dns-message")));
+
+    // chosen in the client's configuration
+    let client = web::client::HTTPClient::builder().dns_over_https(&url).build();
+    let err = client.get("http://nothing.test/").send().await.unwrap_err();
+    assert!(err.to_string().contains("no such host"), "{}", err);
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn test_happy_eyeballs() {
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+    use web::happy_eyeballs::{connect, connect_host, interleave};
+
+    let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+    assert_eq!(
+        interleave([addr("[::1]:1"), addr("[::2]:1"), addr("10.0.0.1:1"), addr("[::3]:1"), addr("10.0.0.2:1")]),
+        [addr("[::1]:1"), addr("10.0.0.1:1"), addr("[::2]:1"), addr("10.0.0.2:1"), addr("[::3]:1")]
+    );
+    assert_eq!(interleave([addr("10.0.0.1:1"), addr("[::1]:1")]), [addr("10.0.0.1:1"), addr("[::1]:1")]);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let open = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let _ = listener.accept().await;
+        }
+    });
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    // a refused address is skipped right away
+    let started = Instant::now();
+    let stream = connect([closed, open], Duration::from_secs(5)).await.unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), open);
+    assert!(started.elapsed() < Duration::from_secs(2));
+    // one that never answers gets the delay, then the next races it
+    let started = Instant::now();
+    let stream = connect([addr("192.0.2.1:80"), open], Duration::from_millis(100)).await.unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), open);
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    let err = connect([closed], Duration::from_millis(100)).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+    assert!(connect([], Duration::from_millis(100)).await.is_err());
+    let stream = connect_host("127.0.0.1", open.port()).await.unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), open);
+}
+
+#[cfg(all(feature = "client", feature = "toml", unix))]
+#[tokio::test]
+async fn test_socket_options() {
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use web::client::HTTPClient;
+    use web::config::ServerConfig;
+    use web::socket::{Keepalive, SocketOptions};
+    use web::tcp::{LinesCodec, Server};
+
+    let config = ServerConfig::from_toml(
+        r#"
+        [socket]
+        nodelay = true
+        keepalive_interval = "10s"
+        keepalive = "1m"
+        keepalive_retries = 3
+        backlog = 16
+        send_buffer_size = 65536
+        recv_buffer_size = 65536
+        "#,
+    )
+    .unwrap();
+    let keepalive = Keepalive::new(Duration::from_secs(60)).interval(Duration::from_secs(10)).retries(3);
+    assert_eq!(config.socket.keepalive, Some(keepalive));
+    assert_eq!(config.socket.nodelay, Some(true));
+    assert_eq!(config.socket.backlog, Some(16));
+    assert_eq!(config.socket.recv_buffer_size, Some(65536));
+    assert!(ServerConfig::from_toml("[socket]\nbacklog = -1").is_err());
+
+    // the HTTP server binds with them, and the client connects with its own
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/hello".to_string()), |_req, res, _pattern| {
+        res.body = Some(String::from("hello"));
+    });
+    let config = ServerConfig {
+        bind: Some(format!("127.0.0.1:{}", port)),
+        ..config
+    };
+    let server = web::httpserver::HTTPServer::new(0, router, HashMap::new()).with_config(config);
+    tokio::spawn(async move { server.start().await });
+    let client = HTTPClient::builder()
+        .socket_options(SocketOptions {
+            nodelay: Some(true),
+            keepalive: Some(Keepalive::new(Duration::from_secs(30))),
+            send_buffer_size: Some(32768),
+            ..Default::default()
+        })
+        .build();
+    let url = format!("http://127.0.0.1:{}/hello", port);
+    let mut res = client.get(&url).send().await;
+    for _ in 0..50 {
+        if res.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        res = client.get(&url).send().await;
+    }
+    assert_eq!(res.unwrap().body.as_deref(), Some("hello"));
+
+    // with SO_REUSEPORT two servers share one address
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let reuse = SocketOptions {
+        reuse_port: Some(true),
+        ..Default::default()
+    };
+    let mut servers = Vec::new();
+    for name in ["a", "b"] {
+        let answer = move |_line: String, _peer| async move { Some(name.to_string()) };
+        let server = Server::new(&addr, LinesCodec::new(), answer).socket_options(reuse);
+        let stop = server.shutdown_token();
+        servers.push((stop, tokio::spawn(async move { server.start().await })));
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+    stream.write_all(b"who\n").await.unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).await.unwrap();
+    assert!(line == "a\n" || line == "b\n", "{:?}", line);
+    // without it a third can't
+    let alone = Server::new(&addr, LinesCodec::new(), |line: String, _peer| async move { Some(line) });
+    assert!(alone.start().await.is_err());
+    for (stop, serving) in servers {
+        stop.cancel();
+        serving.await.unwrap().unwrap();
+    }
+}
+
+#[cfg(all(feature = "client", feature = "toml", unix))]
+#[tokio::test]
+async fn test_reuseport_acceptors() {
+    use web::client::HTTPClient;
+    use web::config::ServerConfig;
+
+    assert_eq!(ServerConfig::from_toml("acceptors = 4").unwrap().acceptors, Some(4));
+    assert!(ServerConfig::from_toml("acceptors = 0").is_err());
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/hello".to_string()), |_req, res, _pattern| {
+        res.body = Some(String::from("hello"));
+    });
+    let config = ServerConfig {
+        bind: Some(format!("127.0.0.1:{}", port)),
+        acceptors: Some(4),
+        ..Default::default()
+    };
+    let server = web::httpserver::HTTPServer::new(0, router, HashMap::new()).with_config(config);
+    let stop = server.shutdown_token();
+    let serving = tokio::spawn(async move { server.start().await });
+
+    let client = HTTPClient::new();
+    let url = format!("http://127.0.0.1:{}/hello", port);
+    for _ in 0..50 {
+        if client.get(&url).send().await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    // every connection is accepted, whichever socket the kernel picks
+    let requests = (0..32).map(|_| {
+        let (client, url) = (client.clone(), url.clone());
+        tokio::spawn(async move { client.get(&url).send().await.unwrap().body })
+    });
+    for request in requests.collect::<Vec<_>>() {
+        assert_eq!(request.await.unwrap().as_deref(), Some("hello"));
+    }
+    // the port is held with SO_REUSEPORT, but a listener without it is refused
+    assert!(std::net::TcpListener::bind(("127.0.0.1", port)).is_err());
+    stop.cancel();
+    serving.await.unwrap().unwrap();
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_request_head_parser() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use web::models::http::{HTTPHeaderType, HTTPVersion};
+    use web::models::parse::{parse_head, HeadScanner, ParseError};
+
+    let raw = b"POST /items?id=1 HTTP/1.1\r\nHost: example.com\r\nX-Folded: a\r\n  b\r\nContent-Length: 3\r\n\r\nabc";
+    let head = parse_head(raw).unwrap();
+    assert_eq!((head.method, head.target, head.version), (&b"POST"[..], &b"/items?id=1"[..], &b"HTTP/1.1"[..]));
+    assert_eq!(head.headers[0], (&b"Host"[..], &b"example.com"[..]));
+    assert_eq!(head.headers[2], (&b""[..], &b"b"[..]));
+    assert_eq!(&raw[head.len..], b"abc");
+    // the pieces point into the buffer rather than copies of it
+    assert!(raw.as_ptr_range().contains(&head.headers[1].1.as_ptr()));
+
+    assert_eq!(parse_head(b"GET /\r\n\r\n").unwrap_err(), ParseError::RequestLine);
+    assert_eq!(parse_head(b"GET  / HTTP/1.1\r\n\r\n").unwrap_err(), ParseError::RequestLine);
+    assert_eq!(parse_head(b"GET / HTTP/1.1\r\nNoColon\r\n\r\n").unwrap_err(), ParseError::HeaderLine);
+
+    // found across reads, wherever the terminator is cut
+    let mut scanner = HeadScanner::default();
+    let mut buffer = Vec::new();
+    let mut found = None;
+    for piece in [&b"GET / HTTP/1.1\r\nHost: a\r"[..], b"\n\r", b"\nbody"] {
+        buffer.extend_from_slice(piece);
+        found = scanner.scan(&buffer);
+    }
+    assert_eq!(found, Some(27));
+    // bare LF endings too, however the blank line is split
+    let mut scanner = HeadScanner::default();
+    assert_eq!(scanner.scan(b"GET / HTTP/1.1\nHost: a\n"), None);
+    assert_eq!(scanner.scan(b"GET / HTTP/1.1\nHost: a\n\nbody"), Some(24));
+    let mut scanner = HeadScanner::default();
+    assert_eq!(scanner.scan(b"GET / HTTP/1.1\n\r"), None);
+    assert_eq!(scanner.scan(b"GET / HTTP/1.1\n\r\n"), Some(17));
+
+    // bare LF line endings and folding still work for `HTTPRequest::new`
+    let req = HTTPRequest::new(String::from("GET / HTTP/1.0\nX-Folded: a\n b\n\nrest"));
+    assert_eq!(req.version, HTTPVersion::HTTP1_0);
+    assert_eq!(req.header(&HTTPHeaderType::Other(String::from("X-Folded"))), Some("a b"));
+    assert_eq!(req.body.as_deref(), Some("rest"));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.bind((HTTPMethod::POST, "/".to_string()), |req, res, _pattern| {
+        let host = req.header(&HTTPHeaderType::Host).unwrap_or_default();
+        res.body = Some(format!("{} {}", host, req.body.clone().unwrap_or_default()));
+    });
+    let server = web::httpserver::HTTPServer::from_listener(listener, router, HashMap::new()).unwrap();
+    tokio::spawn(async move { server.start().await });
+    let send = |pieces: Vec<&'static str>| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        for piece in pieces {
+            stream.write_all(piece.as_bytes()).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+    // a head and body trickling in
+    let response = send(vec!["POST / HTTP/1.1\r\nHo", "st: example.com\r\nContent-Length: 5\r", "\n\r\nhel", "lo"]).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n") && response.ends_with("example.com hello"), "{}", response);
+    // bare LF line endings are read as the parser reads them
+    let response = send(vec!["POST / HTTP/1.1\nHost: lf\nContent-Length: 2\n\nhi"]).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n") && response.ends_with("lf hi"), "{}", response);
+    // a head cut short by the client closing its side is refused, not dispatched
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Le").await.unwrap();
+    stream.shutdown().await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", response);
+    // a pipelined request behind the body isn't part of it
+    let response = send(vec!["POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\n\r\nhiGET / HTTP/1.1\r\n\r\n"]).await;
+    assert!(response.ends_with("a hi"), "{}", response);
+    // a body cut short by the client closing its side is refused
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nhalf").await.unwrap();
+    stream.shutdown().await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", response);
+    // methods and versions the server doesn't know are refused, not a panic
+    for head in ["BREW / HTTP/1.1\r\n\r\n", "GET / HTTP/9.9\r\n\r\n"] {
+        let response = send(vec![head]).await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{:?}: {}", head, response);
+    }
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_response_writes() {
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+    use web::middleware::Pipeline;
+
+    /// counts the writes the server makes
+    struct Counting(DuplexStream, Arc<AtomicUsize>);
+
+    impl AsyncRead for Counting {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.0).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Counting {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            Pin::new(&mut self.0).poll_write(cx, buf)
+        }
+
+        fn poll_write_vectored(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[std::io::IoSlice<'_>],
+        ) -> Poll<std::io::Result<usize>> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            let bytes: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+            Pin::new(&mut self.0).poll_write(cx, &bytes)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.0).poll_shutdown(cx)
+        }
+    }
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/small".to_string()), |_req, res, _pattern| {
+        res.text("small");
+    });
+    router.bind((HTTPMethod::GET, "/large".to_string()), |_req, res, _pattern| {
+        res.set_raw_body((0..100_000).map(|i| (i % 251) as u8).collect());
+    });
+    let pipeline = Arc::new(Pipeline::new(Arc::new(router)));
+    for (path, length) in [("/small", 5), ("/large", 100_000)] {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let (mut client, stream) = tokio::io::duplex(1 << 20);
+        let served = tokio::spawn(web::httpserver::serve_connection(
+            Counting(stream, Arc::clone(&writes)),
+            Arc::clone(&pipeline),
+        ));
+        client.write_all(format!("GET {} HTTP/1.1\r\nHost: a\r\n\r\n", path).as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        served.await.unwrap().unwrap();
+        let head_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&response[..head_end]);
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+        assert!(head.contains(&format!("Content-Length: {}\r\n", length)), "{}", head);
+        assert_eq!(response.len() - head_end, length);
+        if path == "/large" {
+            assert!(response[head_end..].iter().enumerate().all(|(i, &b)| b == (i % 251) as u8));
+        }
+        // head and body leave together, copied into one buffer or not
+        assert_eq!(writes.load(Ordering::SeqCst), 1, "{}", path);
+    }
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_head_serialization() {
+    use web::models::http::{HTTPHeaderType, HTTPStatus};
+
+    for code in 100..600 {
+        if let Some(status) = HTTPStatus::from_code(code) {
+            assert_eq!(status.status_line().unwrap(), format!("HTTP/1.1 {} {}\r\n", code, status).as_bytes());
+        }
+    }
+    assert_eq!(HTTPStatus::custom(499, "Client Closed Request").unwrap().status_line(), None);
+    for name in ["content-type", "access-control-allow-credentials", "x-request-id"] {
+        let header: HTTPHeaderType = name.parse().unwrap();
+        assert!(header.as_str().eq_ignore_ascii_case(name));
+        assert_eq!(header.as_str(), header.to_string());
+    }
+
+    let mut res = HTTPResponse::error(HTTPStatus::Ok, "");
+    res.set_header(HTTPHeaderType::ContentType, "application/json");
+    res.set_header(HTTPHeaderType::CacheControl, "no-store");
+    res.set_header(HTTPHeaderType::Other(String::from("X-Request-Id")), "f3a1");
+    res.set_content_length(2);
+    // how heads were built before the tables, compared in `benches/response.rs`
+    let formatted = |res: &HTTPResponse| {
+        use web::models::headers::{valid_header_name, valid_header_value};
+        let mut head = format!("HTTP/1.1 {} {}\r\n", res.status.code(), res.status);
+        for (key, value) in &res.headers {
+            let name = key.to_string();
+            if valid_header_name(&name) && valid_header_value(value) {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    };
+    let mut head = Vec::new();
+    res.write_head(&mut head);
+    assert_eq!(head, formatted(&res));
+    let mut old = res.clone();
+    old.version = web::models::http::HTTPVersion::HTTP1_0;
+    assert!(old.head_to_string().starts_with("HTTP/1.0 200 OK\r\n"));
+}
+
+#[test]
+fn test_handlers_get_matched_params() {
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/posts/{id}".to_string()), |_req, res, params| {
+        res.body = Some(format!("{} id={}", params, params.get("id").unwrap()));
+    });
+    router.bind((HTTPMethod::GET, "/posts/{id:\\d+}/comments/{comment}".to_string()), |_req, res, params| {
+        let mut captured: Vec<_> = params.params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        captured.sort();
+        res.body = Some(format!("{} {}", params.pattern, captured.join(" ")));
+    });
+    router.bind((HTTPMethod::GET, "/{*rest}".to_string()), |req, res, pattern| {
+        // the pattern still works where a `&str` is wanted
+        assert_eq!(req.path_params(pattern), Some(HashMap::from([("rest".to_string(), "a/b".to_string())])));
+        res.body = Some(pattern.get("rest").unwrap_or_default().to_string());
+    });
+    router.dynamic_routes().bind((HTTPMethod::GET, "/plugins/{name}".to_string()), |_req, res, params| {
+        res.body = Some(format!("plugin {}", params.get("name").unwrap()));
+    });
+
+    let get = |path: &str| router.route(&HTTPRequest::new(format!("GET {} HTTP/1.1\r\n\r\n", path))).body.unwrap();
+    assert_eq!(get("/posts/7"), "/posts/{id} id=7");
+    assert_eq!(get("/posts/7/comments/3"), "/posts/{id:\\d+}/comments/{comment} comment=3 id=7");
+    assert_eq!(get("/a/b"), "a/b");
+    assert_eq!(get("/plugins/search"), "plugin search");
+
+    let req = HTTPRequest::new("POST /posts/7 HTTP/1.1\r\n\r\n".to_string());
+    let err = router.handle(&req.method, &req, &mut HTTPResponse::default()).unwrap_err();
+    assert_eq!(err.status, web::models::http::HTTPStatus::MethodNotAllowed);
+}
+
+#[cfg(feature = "bench")]
+#[global_allocator]
+static ALLOC: web::alloc::CountingAllocator = web::alloc::CountingAllocator;
+
+#[cfg(feature = "bench")]
+#[test]
+fn test_alloc_counters() {
+    use std::hint::black_box;
+    use web::alloc::AllocStats;
+
+    let before = AllocStats::now();
+    let (len, stats) = AllocStats::measure(|| black_box(vec![0u8; 4096]).len());
+    assert_eq!(len, 4096);
+    // other tests allocate alongside, so only lower bounds hold
+    assert!(stats.allocations >= 1 && stats.deallocations >= 1);
+    assert!(stats.bytes >= 4096);
+    let (_, grown) = AllocStats::measure(|| {
+        let mut buf = Vec::with_capacity(8);
+        buf.extend_from_slice(black_box(&[1u8; 64]));
+        buf
+    });
+    assert!(grown.reallocations >= 1 || grown.allocations >= 2);
+    assert!(AllocStats::now().since(&before).allocations >= stats.allocations + grown.allocations);
+}
+
+#[test]
+fn test_parse_request_corpus() {
+    use web::models::parse::{parse_request, ChunkedDecoder, ParseError};
+
+    let (req, used) = parse_request(b"POST /users HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhelloGET / HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!((req.method, req.url.as_str(), req.body.as_deref(), used), (HTTPMethod::POST, "/users", Some("hello"), 57));
+    assert_eq!(parse_request(b"GET / HTTP/1.1\r\nHost: a\r\n\r").unwrap_err(), ParseError::Incomplete);
+    assert_eq!(parse_request(b"GET / HTTP/1.1\r\nContent-Length: 3\r\n\r\nab").unwrap_err(), ParseError::Incomplete);
+    assert_eq!(parse_request(b"GET /\r\n\r\n").unwrap_err(), ParseError::RequestLine);
+    assert_eq!(parse_request(b"BREW / HTTP/1.1\r\n\r\n").unwrap_err(), ParseError::Method);
+    assert_eq!(parse_request(b"GET / HTTP/1.1\r\nX-A b: c\r\n\r\n").unwrap_err(), ParseError::Header);
+    let smuggled = b"POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\nabc";
+    assert_eq!(parse_request(smuggled).unwrap_err(), ParseError::Framing);
+    let chunked = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+    let (req, used) = parse_request(chunked).unwrap();
+    assert_eq!((req.body.as_deref(), used), (Some("hello"), chunked.len() - 18));
+    assert_eq!(parse_request(&chunked[..60]).unwrap_err(), ParseError::Incomplete);
+    let bad = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+    assert_eq!(parse_request(bad).unwrap_err(), ParseError::Chunk);
+
+    // the decoder picks up where it stopped, however the bytes are split
+    let mut decoder = ChunkedDecoder::default();
+    let mut pending = Vec::new();
+    for piece in [&b"a"[..], b";ext\r", b"\n0123", b"456789\r\n0\r", b"\nTrailer: x\r\n\r\nnext"] {
+        pending.extend_from_slice(piece);
+        let used = decoder.decode(&pending).unwrap();
+        pending.drain(..used);
+    }
+    assert!(decoder.is_done());
+    assert_eq!((decoder.body(), &pending[..]), (&b"0123456789"[..], &b"next"[..]));
+
+    // the fuzz target's checks, over its seeds, every prefix of them and
+    // every single byte of them changed to a few troublesome values
+    let check = |data: &[u8]| {
+        let parsed = parse_request(data);
+        assert_eq!(parsed, parse_request(data));
+        match parsed {
+            Ok((request, used)) => {
+                assert!(used <= data.len());
+                assert_eq!(parse_request(&data[..used]), Ok((request, used)));
+            }
+            Err(ParseError::Incomplete) => {}
+            Err(e) => assert_eq!(parse_request(&[data, b"\r\n\r\n"].concat()).err(), Some(e)),
+        }
+    };
+    let seeds = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/seeds/parse_request");
+    let mut count = 0;
+    for seed in std::fs::read_dir(seeds).unwrap() {
+        let seed = std::fs::read(seed.unwrap().path()).unwrap();
+        for end in 0..=seed.len() {
+            check(&seed[..end]);
+        }
+        for i in 0..seed.len() {
+            for byte in [b'\r', b'\n', b' ', b':', 0, 0xff] {
+                let mut mutated = seed.clone();
+                mutated[i] = byte;
+                check(&mutated);
+            }
+        }
+        count += 1;
+    }
+    assert!(count >= 8);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_structured_logging() {
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Metadata, Subscriber};
+
+    /// each event's level and its fields, the message among them
+    type Events = Vec<(Level, HashMap<String, String>)>;
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Events>>);
+
+    struct Fields<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = HashMap::new();
+            event.record(&mut Fields(&mut fields));
+            self.0.lock().unwrap().push((*event.metadata().level(), fields));
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/users/{id}".to_string()), |_req, res, _params| res.text("ok"));
+    router.bind((HTTPMethod::GET, "/boom".to_string()), |_req, _res, _params| panic!("kaboom"));
+    let capture = Capture::default();
+    tracing::subscriber::with_default(capture.clone(), || {
+        for path in ["/users/7", "/missing", "/boom"] {
+            router.route(&HTTPRequest::new(format!("GET {} HTTP/1.1\r\n\r\n", path)));
+        }
+        router.route(&HTTPRequest::new("DELETE /users/7 HTTP/1.1\r\n\r\n".to_string()));
+    });
+
+    let events = capture.0.lock().unwrap();
+    let find = |message: &str| {
+        events
+            .iter()
+            .filter(|(_, fields)| fields["message"] == message)
+            .map(|(level, fields)| (*level, fields.clone()))
+            .collect::<Vec<_>>()
+    };
+    let finished = find("handler finished");
+    assert_eq!(finished.len(), 2);
+    assert_eq!(finished[0].0, Level::DEBUG);
+    assert_eq!((finished[0].1["method"].as_str(), finished[0].1["route"].as_str()), ("GET", "/users/{id}"));
+    assert!(finished[0].1["micros"].parse::<u128>().is_ok());
+    let misses = find("route miss");
+    assert_eq!(misses.len(), 2);
+    assert_eq!((misses[0].1["path"].as_str(), misses[0].1["status"].as_str()), ("/missing", "404"));
+    assert_eq!((misses[1].1["method"].as_str(), misses[1].1["status"].as_str()), ("DELETE", "405"));
+    let panicked = find("handler panicked");
+    assert_eq!(panicked[0].0, Level::ERROR);
+    assert_eq!(panicked[0].1["panic"], "kaboom");
+    assert_eq!(find("request failed").iter().filter(|(level, _)| *level == Level::WARN).count(), 1);
+    drop(events);
+
+    let mut router = Router::new();
+    router.bind((HTTPMethod::GET, "/users/{id}".to_string()), |_req, res, _params| res.text("ok"));
+    router.layer(web::middleware::request_log::RequestLog);
+    let client = web::testing::TestClient::new(router);
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    tracing::subscriber::with_default(capture.clone(), || runtime.block_on(client.get("/users/7?x=1").send()));
+    let events = capture.0.lock().unwrap();
+    let (level, fields) = events.iter().find(|(_, fields)| fields["message"] == "request").unwrap();
+    assert_eq!(*level, Level::INFO);
+    assert_eq!((fields["method"].as_str(), fields["url"].as_str()), ("GET", "/users/7?x=1"));
+    assert_eq!(fields["status"], "200");
+    assert!(fields["micros"].parse::<u128>().is_ok());
+}